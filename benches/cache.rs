@@ -0,0 +1,136 @@
+//! timings of the cache on a large synthetic database, `cargo bench --bench cache`;
+//! each case reports the fastest of a few runs
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, Instant};
+
+use wallpaper_slideshow::cache::{Cache, CacheRecord};
+use wallpaper_slideshow::exif::ExifInfo;
+
+const ROOT: &str = "/bench/photos";
+const RUNS: usize = 5;
+
+fn records(count: usize) -> Vec<CacheRecord> {
+    (0..count)
+        .map(|i| CacheRecord {
+            path: format!("{}/{:03}/{:06}.jpg", ROOT, i % 500, i),
+            mtime: 1_600_000_000 + i as i64,
+            size: 2_000_000 + i as u64,
+            hash: None,
+            dimensions: Some((6000, 4000)),
+            luminance: None,
+            info: ExifInfo {
+                hour: Some((i % 24) as u8),
+                minute_of_day: Some((i % 1440) as u16),
+                month: Some((i % 12) as u8 + 1),
+                camera: Some("Canon EOS R6".to_string()),
+                ..ExifInfo::default()
+            },
+            error: None,
+        })
+        .collect()
+}
+
+/// a new database under the temp dir, removed again by `done`
+fn temp_cache(name: &str) -> (PathBuf, Cache) {
+    let dir = env::temp_dir().join(format!(
+        "wallpaper_slideshow_bench_{}_{}",
+        name,
+        process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let cache = Cache::open(dir.join("cache.db")).unwrap();
+    (dir, cache)
+}
+
+fn done(dir: PathBuf, cache: Cache) {
+    drop(cache);
+    let _ = fs::remove_dir_all(dir);
+}
+
+/// the fastest of `RUNS` runs of `run`, each after an untimed `setup`
+fn fastest(mut setup: impl FnMut(), mut run: impl FnMut()) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            setup();
+            let start = Instant::now();
+            run();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn report(case: &str, took: Duration) {
+    println!("{:<48} {:>10.2} ms", case, took.as_secs_f64() * 1000.0);
+}
+
+/// removing 10k stale entries, one DELETE per path as before against the
+/// chunked statements of `cleanup_stale`
+fn stale_cleanup() {
+    let (dir, cache) = temp_cache("stale");
+    let stale = records(10_000);
+    let paths: Vec<&str> = stale.iter().map(|r| r.path.as_str()).collect();
+    env::set_var("WALLPAPER_DIR", ROOT);
+
+    let row_by_row = fastest(
+        || cache.upsert(&stale).unwrap(),
+        || {
+            let tx = cache.connection().unchecked_transaction().unwrap();
+            for table in ["exif_cache", "thumbnails"] {
+                let mut stmt = tx
+                    .prepare(&format!("DELETE FROM {} WHERE path = ?1", table))
+                    .unwrap();
+                for path in &paths {
+                    stmt.execute([path]).unwrap();
+                }
+            }
+            tx.commit().unwrap();
+        },
+    );
+    report("cleanup 10k stale, row by row", row_by_row);
+
+    let chunked = fastest(
+        || cache.upsert(&stale).unwrap(),
+        || assert_eq!(cache.cleanup_stale(&HashSet::new()).unwrap(), 10_000),
+    );
+    report("cleanup 10k stale, chunked (cleanup_stale)", chunked);
+
+    done(dir, cache);
+}
+
+/// what a run reads from a 30k entry cache to pick among 300 candidates: every
+/// full row as before against the key projection plus the pool's rows
+fn startup_load() {
+    let (dir, cache) = temp_cache("startup");
+    let all = records(30_000);
+    cache.upsert(&all).unwrap();
+    let pool: Vec<String> = all.iter().step_by(100).map(|r| r.path.clone()).collect();
+
+    let full = fastest(
+        || {},
+        || assert_eq!(cache.load_all().unwrap().len(), 30_000),
+    );
+    report("load 30k, every full row (load_all)", full);
+
+    let projected = fastest(
+        || {},
+        || {
+            assert_eq!(cache.load_keys().unwrap().len(), 30_000);
+            assert_eq!(cache.load_for_paths(&pool).unwrap().len(), 300);
+        },
+    );
+    report("load 30k, keys plus 300 pool rows", projected);
+
+    done(dir, cache);
+}
+
+fn main() {
+    stale_cleanup();
+    startup_load();
+}
@@ -0,0 +1,1003 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{config, history, hooks, state, theming};
+
+/// why a wallpaper couldn't be applied
+#[derive(Debug)]
+pub enum ApplyError {
+    /// no backend by that name, see `named`
+    UnknownBackend(String),
+    /// the backend has no output by that name
+    UnknownOutput(String),
+    /// `WallpaperBackend::is_available` said no, with why
+    Unavailable(String),
+    /// the program couldn't be run
+    Spawn(&'static str, io::Error),
+    /// the program ran and failed, with what it printed to stderr, or to
+    /// stdout if nothing
+    Failed(&'static str, ExitStatus, String),
+    /// the program was killed after running this long, see `run_with_timeout`
+    TimedOut(&'static str, Duration),
+    /// talking to a daemon over its socket failed
+    Socket(PathBuf, io::Error),
+    /// the daemon answered with an error, e.g. hyprpaper's "could not preload"
+    Rejected(&'static str, String),
+}
+
+impl fmt::Display for ApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownBackend(name) => write!(f, "unknown wallpaper backend {}", name),
+            Self::UnknownOutput(name) => write!(f, "no output {}", name),
+            Self::Unavailable(reason) => write!(f, "{}", reason),
+            Self::Spawn(program, e) => write!(f, "failed to run {}: {}", program, e),
+            Self::Failed(program, status, stderr) if stderr.is_empty() => {
+                write!(f, "{} failed ({})", program, status)
+            }
+            Self::Failed(program, status, stderr) => {
+                write!(f, "{} failed ({}): {}", program, status, stderr)
+            }
+            Self::TimedOut(program, timeout) => {
+                write!(f, "{} didn't finish within {:?}", program, timeout)
+            }
+            Self::Socket(path, e) => write!(f, "failed to talk to {}: {}", path.display(), e),
+            Self::Rejected(program, reply) => write!(f, "{}: {}", program, reply),
+        }
+    }
+}
+
+impl Error for ApplyError {}
+
+impl ApplyError {
+    /// whether it may pass by itself, as when the timer fires right after
+    /// login and the compositor or its wallpaper daemon is still starting
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Unavailable(_) => true,
+            Self::Socket(_, e) => matches!(
+                e.kind(),
+                io::ErrorKind::NotFound
+                    | io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+            ),
+            _ => false,
+        }
+    }
+}
+
+/// how long `retry` waits after the first failure, doubling after each one
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// `attempt` until it succeeds, fails in a way that isn't
+/// `ApplyError::is_transient`, or `config::apply_retry` has gone by
+pub fn retry<T>(mut attempt: impl FnMut() -> Result<T, ApplyError>) -> Result<T, ApplyError> {
+    let deadline = Instant::now() + config::apply_retry();
+    let mut delay = FIRST_RETRY_DELAY;
+    loop {
+        match attempt() {
+            Err(e) if e.is_transient() => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return Err(e);
+                }
+                let wait = delay.min(left);
+                eprintln!("{}, trying again in {:.1}s", e, wait.as_secs_f64());
+                thread::sleep(wait);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// what puts an image on the screen
+pub trait WallpaperBackend {
+    fn name(&self) -> &str;
+
+    /// show `path` on `monitor`, or on every output if None
+    fn apply(&self, path: &Path, monitor: Option<&str>) -> Result<(), ApplyError>;
+
+    /// whether it can apply anything right now, e.g. its daemon is reachable
+    fn is_available(&self) -> bool;
+
+    /// what to tell the user when it isn't available
+    fn unavailable_reason(&self) -> String {
+        format!("{} is not available", self.name())
+    }
+}
+
+/// hyprpaper, driven over its socket, or through hyprctl when the socket
+/// isn't where `hyprpaper_socket` looks. hyprpaper keeps every image it loaded
+/// in memory, so with `preload` each new one is preloaded and the ones no
+/// output shows any more are unloaded, as recorded in `hyprpaper_state`
+#[derive(Debug, Clone, Copy)]
+pub struct HyprpaperBackend {
+    /// preload and unload, or just set the wallpaper and leave memory to
+    /// hyprpaper
+    pub preload: bool,
+    /// `unload all` after this many wallpapers in case something was missed,
+    /// 0 never
+    pub unload_all_every: u32,
+}
+
+impl Default for HyprpaperBackend {
+    fn default() -> Self {
+        Self {
+            preload: true,
+            unload_all_every: config::DEFAULT_HYPRPAPER_UNLOAD_ALL_EVERY,
+        }
+    }
+}
+
+impl HyprpaperBackend {
+    pub fn from_config() -> Self {
+        Self {
+            preload: config::hyprpaper_preload(),
+            unload_all_every: config::hyprpaper_unload_all_every(),
+        }
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/hypr/<instance signature>/.hyprpaper.sock` if it exists
+fn hyprpaper_socket() -> Option<PathBuf> {
+    let runtime = std::env::var_os("XDG_RUNTIME_DIR")?;
+    let signature = std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE")?;
+    let socket = Path::new(&runtime)
+        .join("hypr")
+        .join(signature)
+        .join(".hyprpaper.sock");
+    socket.exists().then_some(socket)
+}
+
+/// send hyprpaper a request like `preload /some/image.jpg` and check that it
+/// answers "ok"
+fn hyprpaper(args: &[&str]) -> Result<(), ApplyError> {
+    let reply = match hyprpaper_socket() {
+        Some(socket) => hyprpaper_request(&socket, &args.join(" "))
+            .map_err(|e| ApplyError::Socket(socket, e))?,
+        None => {
+            let output = run_with_timeout(
+                "hyprctl",
+                Command::new("hyprctl").arg("hyprpaper").args(args),
+                config::command_timeout(),
+            )?;
+            let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            // hyprpaper isn't up yet, or not running at all
+            for message in [&stdout, &stderr] {
+                if message.starts_with("Couldn't connect") {
+                    return Err(ApplyError::Unavailable(message.clone()));
+                }
+            }
+            if !output.status.success() {
+                return Err(ApplyError::Failed("hyprctl", output.status, stderr));
+            }
+            // hyprctl passes hyprpaper's answer on, and exits 0 either way
+            stdout
+        }
+    };
+    match reply.trim() {
+        "" | "ok" => Ok(()),
+        error => Err(ApplyError::Rejected("hyprpaper", error.to_string())),
+    }
+}
+
+/// one request per connection, the way hyprctl does it: hyprpaper answers and
+/// hangs up
+fn hyprpaper_request(socket: &Path, request: &str) -> io::Result<String> {
+    let timeout = Some(config::command_timeout());
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+    stream.write_all(request.as_bytes())?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
+
+impl WallpaperBackend for HyprpaperBackend {
+    fn name(&self) -> &str {
+        "hyprpaper"
+    }
+
+    fn apply(&self, path: &Path, monitor: Option<&str>) -> Result<(), ApplyError> {
+        let image = path.display().to_string();
+        // an empty monitor before the comma means every output
+        let target = format!("{},{}", monitor.unwrap_or(""), image);
+        if !self.preload {
+            return hyprpaper(&["wallpaper", &target]);
+        }
+
+        let state = hyprpaper_state();
+        let (applied, old) = read_hyprpaper_state(&state);
+        if !old.iter().any(|(_, shown)| *shown == image) {
+            hyprpaper(&["preload", &image])?;
+        }
+        hyprpaper(&["wallpaper", &target])?;
+
+        let output = monitor.unwrap_or(ALL_OUTPUTS);
+        let mut shown: Vec<(String, String)> = match monitor {
+            Some(_) => old.iter().filter(|(o, _)| o != output).cloned().collect(),
+            None => Vec::new(),
+        };
+        shown.push((output.to_string(), image));
+        for (_, gone) in &old {
+            if !shown.iter().any(|(_, image)| image == gone) {
+                if let Err(e) = hyprpaper(&["unload", gone]) {
+                    eprintln!("Failed to unload {}: {}", gone, e);
+                }
+            }
+        }
+
+        let applied = applied + 1;
+        if self.unload_all_every > 0 && applied % self.unload_all_every == 0 {
+            if let Err(e) = hyprpaper(&["unload", "all"]) {
+                eprintln!("Failed to unload unused wallpapers: {}", e);
+            }
+        }
+        if let Err(e) = write_hyprpaper_state(&state, applied, &shown) {
+            eprintln!("Failed to save {}: {}", state.display(), e);
+        }
+        Ok(())
+    }
+
+    fn is_available(&self) -> bool {
+        match hyprpaper_socket() {
+            // a harmless request, as hyprpaper expects one on every connection
+            Some(socket) => hyprpaper_request(&socket, "listloaded").is_ok(),
+            None => succeeds(Command::new("hyprctl").arg("version")),
+        }
+    }
+}
+
+/// swww, whose daemon fades between wallpapers
+#[derive(Debug, Clone, Default)]
+pub struct SwwwBackend {
+    /// swww's --transition-type, e.g. "fade", "wipe" or "grow"
+    pub transition_type: Option<String>,
+    /// swww's --transition-duration, in seconds
+    pub transition_duration: Option<f64>,
+}
+
+impl SwwwBackend {
+    pub fn from_config() -> Self {
+        Self {
+            transition_type: config::swww_transition_type(),
+            transition_duration: config::swww_transition_duration(),
+        }
+    }
+}
+
+impl WallpaperBackend for SwwwBackend {
+    fn name(&self) -> &str {
+        "swww"
+    }
+
+    fn apply(&self, path: &Path, monitor: Option<&str>) -> Result<(), ApplyError> {
+        let mut command = Command::new("swww");
+        command.arg("img").arg(path);
+        if let Some(monitor) = monitor {
+            command.args(["--outputs", monitor]);
+        }
+        if let Some(kind) = &self.transition_type {
+            command.args(["--transition-type", kind]);
+        }
+        if let Some(seconds) = self.transition_duration {
+            command.args(["--transition-duration", &seconds.to_string()]);
+        }
+        run("swww", &mut command)
+    }
+
+    fn is_available(&self) -> bool {
+        succeeds(Command::new("swww").arg("query"))
+    }
+
+    fn unavailable_reason(&self) -> String {
+        "swww daemon not running".to_string()
+    }
+}
+
+/// how long a new swaybg gets to draw before the one it replaces is killed
+const SWAYBG_SETTLE: Duration = Duration::from_millis(300);
+
+/// swaybg, one process per output that shows the image until killed. The new
+/// one is started before the old one is killed so the background colour never
+/// shows, and only processes recorded in `swaybg_state` are ever killed
+#[derive(Debug, Clone)]
+pub struct SwaybgBackend {
+    /// swaybg's -m: fill, fit, center, tile or stretch
+    pub mode: String,
+}
+
+impl SwaybgBackend {
+    pub fn from_config() -> Self {
+        Self {
+            mode: config::swaybg_mode(),
+        }
+    }
+}
+
+impl WallpaperBackend for SwaybgBackend {
+    fn name(&self) -> &str {
+        "swaybg"
+    }
+
+    fn apply(&self, path: &Path, monitor: Option<&str>) -> Result<(), ApplyError> {
+        let output = monitor.unwrap_or(ALL_OUTPUTS);
+        let mut command = Command::new("swaybg");
+        command.arg("-i").arg(path).args(["-m", &self.mode]);
+        if let Some(monitor) = monitor {
+            command.args(["-o", monitor]);
+        }
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| ApplyError::Spawn("swaybg", e))?;
+
+        thread::sleep(SWAYBG_SETTLE);
+        if let Ok(Some(status)) = child.try_wait() {
+            return Err(ApplyError::Failed("swaybg", status, String::new()));
+        }
+
+        let state = swaybg_state();
+        let mut owned = read_swaybg_state(&state);
+        // one for every output replaces all of them; one started for every
+        // output is left under a new one for a single output, as the other
+        // outputs still show it
+        let (replaced, kept): (Vec<_>, Vec<_>) = owned
+            .drain(..)
+            .partition(|(_, o)| monitor.is_none() || o == output);
+        for (pid, _) in replaced {
+            stop_swaybg(pid);
+        }
+        owned = kept;
+        owned.push((child.id(), output.to_string()));
+        if let Err(e) = write_swaybg_state(&state, &owned) {
+            eprintln!("Failed to save {}: {}", state.display(), e);
+        }
+        Ok(())
+    }
+
+    fn is_available(&self) -> bool {
+        std::env::var_os("WAYLAND_DISPLAY").is_some() && succeeds(Command::new("swaybg").arg("-v"))
+    }
+
+    fn unavailable_reason(&self) -> String {
+        "swaybg isn't installed or there is no Wayland display".to_string()
+    }
+}
+
+/// GNOME's own background, set through gsettings. It has no per-output
+/// wallpaper, so every monitor shows the last one applied
+#[derive(Debug, Clone)]
+pub struct GnomeBackend {
+    /// picture-options: none, wallpaper, centered, scaled, stretched, zoom or
+    /// spanned
+    pub picture_options: String,
+}
+
+impl GnomeBackend {
+    pub fn from_config() -> Self {
+        Self {
+            picture_options: config::gnome_picture_options(),
+        }
+    }
+}
+
+/// the gsettings schema holding the desktop background
+const GNOME_SCHEMA: &str = "org.gnome.desktop.background";
+
+impl WallpaperBackend for GnomeBackend {
+    fn name(&self) -> &str {
+        "gnome"
+    }
+
+    fn apply(&self, path: &Path, _monitor: Option<&str>) -> Result<(), ApplyError> {
+        let uri = file_uri(path);
+        // picture-uri-dark is the one shown with the dark style
+        for (key, value) in [
+            ("picture-uri", uri.as_str()),
+            ("picture-uri-dark", uri.as_str()),
+            ("picture-options", self.picture_options.as_str()),
+        ] {
+            run(
+                "gsettings",
+                Command::new("gsettings").args(["set", GNOME_SCHEMA, key, value]),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn is_available(&self) -> bool {
+        succeeds(Command::new("gsettings").args(["list-keys", GNOME_SCHEMA]))
+    }
+
+    fn unavailable_reason(&self) -> String {
+        // say what gsettings itself said, e.g. "No such schema"
+        match run(
+            "gsettings",
+            Command::new("gsettings").args(["list-keys", GNOME_SCHEMA]),
+        ) {
+            Err(e) => format!("gsettings can't read {}: {}", GNOME_SCHEMA, e),
+            Ok(()) => format!("gsettings can't read {}", GNOME_SCHEMA),
+        }
+    }
+}
+
+/// KDE Plasma, through plasma-apply-wallpaperimage or, on Plasma before 5.24
+/// or for a single screen, a plasmashell script sent over qdbus. Plasma numbers
+/// its screens, so a monitor is a screen number
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlasmaBackend;
+
+const PLASMA_APPLY: &str = "plasma-apply-wallpaperimage";
+
+/// qdbus is named after the Qt it came with on some distributions
+const QDBUS: [&str; 3] = ["qdbus", "qdbus6", "qdbus-qt5"];
+
+impl PlasmaBackend {
+    fn qdbus() -> Option<&'static str> {
+        QDBUS.into_iter().find(|program| on_path(program))
+    }
+
+    /// set the image of every desktop on `screen`, or on all of them if None
+    fn evaluate_script(path: &Path, screen: Option<u32>) -> Result<(), ApplyError> {
+        let qdbus = Self::qdbus()
+            .ok_or_else(|| ApplyError::Unavailable(PlasmaBackend.unavailable_reason()))?;
+        let script = plasma_script(path, screen);
+        run(
+            qdbus,
+            Command::new(qdbus).args([
+                "org.kde.plasmashell",
+                "/PlasmaShell",
+                "org.kde.PlasmaShell.evaluateScript",
+                &script,
+            ]),
+        )
+    }
+}
+
+impl WallpaperBackend for PlasmaBackend {
+    fn name(&self) -> &str {
+        "plasma"
+    }
+
+    fn apply(&self, path: &Path, monitor: Option<&str>) -> Result<(), ApplyError> {
+        if let Some(monitor) = monitor {
+            let screen = monitor
+                .parse()
+                .map_err(|_| ApplyError::UnknownOutput(monitor.to_string()))?;
+            return Self::evaluate_script(path, Some(screen));
+        }
+        if on_path(PLASMA_APPLY) {
+            run(PLASMA_APPLY, Command::new(PLASMA_APPLY).arg(path))
+        } else {
+            Self::evaluate_script(path, None)
+        }
+    }
+
+    fn is_available(&self) -> bool {
+        on_path(PLASMA_APPLY) || Self::qdbus().is_some()
+    }
+
+    fn unavailable_reason(&self) -> String {
+        format!("neither {} nor qdbus is installed", PLASMA_APPLY)
+    }
+}
+
+/// a plasmashell script pointing the desktops on `screen` (all if None) at
+/// `path`
+fn plasma_script(path: &Path, screen: Option<u32>) -> String {
+    // the URI is plain ASCII, and quoted as JSON it's a JavaScript string
+    let uri = serde_json::to_string(&file_uri(path)).unwrap_or_default();
+    let screen = screen.map_or(-1, i64::from);
+    format!(
+        "var all = desktops();
+for (var i = 0; i < all.length; i++) {{
+    var d = all[i];
+    if ({screen} >= 0 && d.screen != {screen}) continue;
+    d.wallpaperPlugin = \"org.kde.image\";
+    d.currentConfigGroup = [\"Wallpaper\", \"org.kde.image\", \"General\"];
+    d.writeConfig(\"Image\", {uri});
+}}"
+    )
+}
+
+/// X11 through xwallpaper or feh, whichever `config::x11_tool` names or,
+/// if it names neither, the first installed. feh can't target one output, so
+/// with it every output shows the last image applied
+#[derive(Debug, Clone, Default)]
+pub struct X11Backend {
+    pub tool: Option<String>,
+}
+
+const XWALLPAPER: &str = "xwallpaper";
+const FEH: &str = "feh";
+
+/// marks the lines of ~/.fehbg that say what each output shows
+const FEHBG_MARK: &str = "# wallpaper_slideshow: ";
+
+impl X11Backend {
+    pub fn from_config() -> Self {
+        Self {
+            tool: config::x11_tool(),
+        }
+    }
+
+    fn tool(&self) -> Option<&'static str> {
+        match self.tool.as_deref() {
+            Some(XWALLPAPER) => on_path(XWALLPAPER).then_some(XWALLPAPER),
+            Some(FEH) => on_path(FEH).then_some(FEH),
+            _ => [XWALLPAPER, FEH].into_iter().find(|tool| on_path(tool)),
+        }
+    }
+
+    /// set `path` on `monitor` with xwallpaper, redrawing what the other
+    /// outputs show too, and save the command as ~/.fehbg the way feh does
+    fn xwallpaper(path: &Path, monitor: Option<&str>) -> Result<(), ApplyError> {
+        let script = fehbg();
+        let mut shown: Vec<(String, String)> = match monitor {
+            // one image for every output replaces the per-output ones
+            Some(_) => read_fehbg(script.as_deref())
+                .into_iter()
+                .filter(|(output, _)| output != ALL_OUTPUTS)
+                .collect(),
+            None => Vec::new(),
+        };
+        let output = monitor.unwrap_or(ALL_OUTPUTS);
+        shown.retain(|(o, _)| o != output);
+        shown.push((output.to_string(), path.display().to_string()));
+
+        let mut args: Vec<&str> = Vec::new();
+        for (output, image) in &shown {
+            if output != ALL_OUTPUTS {
+                args.extend(["--output", output]);
+            }
+            args.extend(["--zoom", image]);
+        }
+        run(XWALLPAPER, Command::new(XWALLPAPER).args(&args))?;
+
+        if let Some(script) = script {
+            if let Err(e) = write_fehbg(&script, &shown, &args) {
+                eprintln!("Failed to save {}: {}", script.display(), e);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WallpaperBackend for X11Backend {
+    fn name(&self) -> &str {
+        "x11"
+    }
+
+    fn apply(&self, path: &Path, monitor: Option<&str>) -> Result<(), ApplyError> {
+        match self.tool() {
+            Some(XWALLPAPER) => Self::xwallpaper(path, monitor),
+            // feh writes ~/.fehbg itself
+            Some(tool) => run(tool, Command::new(tool).arg("--bg-fill").arg(path)),
+            None => Err(ApplyError::Unavailable(self.unavailable_reason())),
+        }
+    }
+
+    fn is_available(&self) -> bool {
+        std::env::var_os("DISPLAY").is_some() && self.tool().is_some()
+    }
+
+    fn unavailable_reason(&self) -> String {
+        match self.tool.as_deref() {
+            Some(tool @ (XWALLPAPER | FEH)) => {
+                format!("{} isn't installed or there is no X display", tool)
+            }
+            _ => "neither xwallpaper nor feh is installed, or there is no X display".to_string(),
+        }
+    }
+}
+
+/// the script that restores the wallpaper, run from .xinitrc and the like
+fn fehbg() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| Path::new(&home).join(".fehbg"))
+}
+
+/// the (output, image) lines this tool wrote to `path`, none if feh wrote it
+fn read_fehbg(path: Option<&Path>) -> Vec<(String, String)> {
+    let Some(content) = path.and_then(|p| fs::read_to_string(p).ok()) else {
+        return Vec::new();
+    };
+    content
+        .lines()
+        .filter_map(|line| {
+            let (output, image) = line.strip_prefix(FEHBG_MARK)?.split_once('\t')?;
+            Some((output.to_string(), image.to_string()))
+        })
+        .collect()
+}
+
+fn write_fehbg(path: &Path, shown: &[(String, String)], args: &[&str]) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut script = String::from("#!/bin/sh\n");
+    for (output, image) in shown {
+        script.push_str(&format!("{}{}\t{}\n", FEHBG_MARK, output, image));
+    }
+    let quoted: Vec<String> = args.iter().map(|arg| shell_quote(arg)).collect();
+    script.push_str(&format!("{} {}\n", XWALLPAPER, quoted.join(" ")));
+    fs::write(path, script)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+}
+
+/// `arg` in single quotes for sh
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// whether `program` is an executable file in a PATH directory
+fn on_path(program: &str) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::env::var_os("PATH").is_some_and(|path| {
+        std::env::split_paths(&path).any(|dir| {
+            fs::metadata(dir.join(program))
+                .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+    })
+}
+
+/// `path` as a file:// URI, every byte but unreserved ones and / percent-encoded
+fn file_uri(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut uri = String::from("file://");
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+/// the output of a swaybg started without -o
+const ALL_OUTPUTS: &str = "*";
+
+/// `name` in the runtime directory, which is emptied when the user logs out
+fn runtime_file(name: &str) -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map_or_else(std::env::temp_dir, PathBuf::from)
+        .join(name)
+}
+
+/// the swaybg processes this tool started, one "pid\toutput" line each
+fn swaybg_state() -> PathBuf {
+    runtime_file("wallpaper_slideshow_swaybg")
+}
+
+/// how many wallpapers were applied through hyprpaper, then an
+/// "output\timage" line for each image it has loaded
+fn hyprpaper_state() -> PathBuf {
+    runtime_file("wallpaper_slideshow_hyprpaper")
+}
+
+fn read_hyprpaper_state(path: &Path) -> (u32, Vec<(String, String)>) {
+    let Ok(content) = fs::read_to_string(path) else {
+        return (0, Vec::new());
+    };
+    let mut lines = content.lines();
+    let applied = lines.next().and_then(|l| l.parse().ok()).unwrap_or(0);
+    let shown = lines
+        .filter_map(|line| {
+            let (output, image) = line.split_once('\t')?;
+            Some((output.to_string(), image.to_string()))
+        })
+        .collect();
+    (applied, shown)
+}
+
+fn write_hyprpaper_state(path: &Path, applied: u32, shown: &[(String, String)]) -> io::Result<()> {
+    let mut content = format!("{}\n", applied);
+    for (output, image) in shown {
+        content.push_str(&format!("{}\t{}\n", output, image));
+    }
+    fs::write(path, content)
+}
+
+fn read_swaybg_state(path: &Path) -> Vec<(u32, String)> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    content
+        .lines()
+        .filter_map(|line| {
+            let (pid, output) = line.split_once('\t')?;
+            Some((pid.parse().ok()?, output.to_string()))
+        })
+        .collect()
+}
+
+fn write_swaybg_state(path: &Path, owned: &[(u32, String)]) -> io::Result<()> {
+    let lines: Vec<String> = owned
+        .iter()
+        .map(|(pid, output)| format!("{}\t{}\n", pid, output))
+        .collect();
+    fs::write(path, lines.concat())
+}
+
+/// kill `pid` if it's still a swaybg, as the pid may have been reused since
+fn stop_swaybg(pid: u32) {
+    let comm = fs::read_to_string(format!("/proc/{}/comm", pid)).unwrap_or_default();
+    if comm.trim() != "swaybg" {
+        return;
+    }
+    // SAFETY: plain syscalls on a pid; waitpid only reaps it if it's our child,
+    // as it is when a daemon started it
+    unsafe {
+        libc::kill(pid as libc::pid_t, libc::SIGTERM);
+        libc::waitpid(pid as libc::pid_t, std::ptr::null_mut(), 0);
+    }
+}
+
+/// the backend called `name`
+pub fn named(name: &str) -> Result<Box<dyn WallpaperBackend>, ApplyError> {
+    match name.to_ascii_lowercase().as_str() {
+        "hyprpaper" => Ok(Box::new(HyprpaperBackend::from_config())),
+        "swww" => Ok(Box::new(SwwwBackend::from_config())),
+        "swaybg" => Ok(Box::new(SwaybgBackend::from_config())),
+        "gnome" => Ok(Box::new(GnomeBackend::from_config())),
+        "plasma" | "kde" => Ok(Box::new(PlasmaBackend)),
+        "x11" => Ok(Box::new(X11Backend::from_config())),
+        _ => Err(ApplyError::UnknownBackend(name.to_string())),
+    }
+}
+
+/// the variables `detect` looks at
+const DETECT_VARS: [&str; 5] = [
+    "XDG_CURRENT_DESKTOP",
+    "HYPRLAND_INSTANCE_SIGNATURE",
+    "SWAYSOCK",
+    "WAYLAND_DISPLAY",
+    "DISPLAY",
+];
+
+/// what `detect` decides from, gathered by `DesktopSnapshot::current`
+#[derive(Debug, Clone, Default)]
+pub struct DesktopSnapshot {
+    /// those of `DETECT_VARS` that are set and not empty
+    pub vars: HashMap<String, String>,
+    /// the socket WAYLAND_DISPLAY names exists, so it isn't just the wayland-0
+    /// the slideshow falls back to
+    pub wayland_socket: bool,
+    pub hyprpaper_running: bool,
+    pub swww_running: bool,
+}
+
+impl DesktopSnapshot {
+    pub fn current() -> Self {
+        let vars: HashMap<String, String> = DETECT_VARS
+            .iter()
+            .filter_map(|&name| {
+                let value = std::env::var(name).ok().filter(|v| !v.is_empty())?;
+                Some((name.to_string(), value))
+            })
+            .collect();
+        let wayland_socket = vars.get("WAYLAND_DISPLAY").is_some_and(|display| {
+            std::env::var_os("XDG_RUNTIME_DIR")
+                .map_or_else(PathBuf::new, PathBuf::from)
+                .join(display)
+                .exists()
+        });
+        Self {
+            vars,
+            wayland_socket,
+            hyprpaper_running: hyprpaper_socket().is_some(),
+            swww_running: wayland_socket && SwwwBackend::default().is_available(),
+        }
+    }
+}
+
+/// which backend suits the desktop in `snapshot`, and why: GNOME and Plasma by
+/// XDG_CURRENT_DESKTOP, hyprpaper on Hyprland unless only swww is running,
+/// swww or else swaybg on sway and other Wayland compositors, then X11
+pub fn detect(snapshot: &DesktopSnapshot) -> (&'static str, String) {
+    let var = |name: &str| snapshot.vars.get(name).map(String::as_str);
+    let desktops: Vec<String> = var("XDG_CURRENT_DESKTOP")
+        .unwrap_or("")
+        .split(':')
+        .map(|d| d.trim().to_ascii_lowercase())
+        .collect();
+    let on = |desktop: &str| desktops.iter().any(|d| d == desktop);
+    let wlroots = |session: &str| {
+        if snapshot.swww_running {
+            ("swww", format!("{} with the swww daemon running", session))
+        } else {
+            ("swaybg", session.to_string())
+        }
+    };
+
+    if on("gnome") {
+        return ("gnome", "XDG_CURRENT_DESKTOP is GNOME".to_string());
+    }
+    if on("kde") {
+        return ("plasma", "XDG_CURRENT_DESKTOP is KDE".to_string());
+    }
+    if var("HYPRLAND_INSTANCE_SIGNATURE").is_some() || on("hyprland") {
+        return match (snapshot.hyprpaper_running, snapshot.swww_running) {
+            (false, true) => (
+                "swww",
+                "Hyprland with the swww daemon running and hyprpaper not".to_string(),
+            ),
+            (true, _) => ("hyprpaper", "Hyprland with hyprpaper running".to_string()),
+            (false, false) => (
+                "hyprpaper",
+                "Hyprland, though neither hyprpaper nor swww is running yet".to_string(),
+            ),
+        };
+    }
+    if var("SWAYSOCK").is_some() || on("sway") {
+        return wlroots("sway");
+    }
+    if snapshot.wayland_socket {
+        return wlroots("a Wayland session");
+    }
+    if var("DISPLAY").is_some() {
+        return (
+            "x11",
+            "DISPLAY is set and there's no Wayland session".to_string(),
+        );
+    }
+    ("hyprpaper", "no desktop detected".to_string())
+}
+
+/// the name of the backend to use and why: `config::backend`, or if that's
+/// "auto" what `detect` makes of the current desktop
+pub fn choose() -> (String, String) {
+    let name = config::backend();
+    if !name.eq_ignore_ascii_case("auto") {
+        return (name, "set by WALLPAPER_BACKEND".to_string());
+    }
+    let (name, why) = detect(&DesktopSnapshot::current());
+    (name.to_string(), format!("detected: {}", why))
+}
+
+/// the backend `choose` picks
+pub fn from_config() -> Result<Box<dyn WallpaperBackend>, ApplyError> {
+    named(&choose().0)
+}
+
+/// the backend from `config::backend` if it's available right now
+pub fn available() -> Result<Box<dyn WallpaperBackend>, ApplyError> {
+    let backend = from_config()?;
+    if !backend.is_available() {
+        return Err(ApplyError::Unavailable(backend.unavailable_reason()));
+    }
+    Ok(backend)
+}
+
+/// show each of `picks` on its output through `backend`, retrying while it
+/// isn't up yet, and log the ones that worked to history. The first that
+/// worked becomes the current wallpaper, which the theme and post hooks follow.
+/// Returns the picks that failed with why
+pub fn apply_all<'a>(
+    backend: &dyn WallpaperBackend,
+    picks: &[(Option<&str>, &'a Path)],
+) -> Vec<(&'a Path, ApplyError)> {
+    let mut failed = Vec::new();
+    let mut current = None;
+    for &(monitor, path) in picks {
+        match retry(|| backend.apply(path, monitor)) {
+            Ok(()) => {
+                history::log(path, monitor);
+                current.get_or_insert((path, monitor));
+            }
+            Err(e) => failed.push((path, e)),
+        }
+    }
+    if let Some((path, monitor)) = current {
+        if let Err(e) = state::set_current(path, monitor) {
+            eprintln!("Failed to record the current wallpaper: {}", e);
+        }
+        if config::theming() {
+            match theming::generate(path) {
+                Ok(written) => println!("Wrote theme colours to {} files", written.len()),
+                Err(e) => eprintln!("Failed to work out theme colours: {}", e),
+            }
+        }
+        hooks::run_post_hooks(path);
+    }
+    failed
+}
+
+/// how often `run_with_timeout` checks whether the program has exited
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// run `command` (called `program` in errors) with its output captured,
+/// killing it and everything it started if it's still running after `timeout`
+pub fn run_with_timeout(
+    program: &'static str,
+    command: &mut Command,
+    timeout: Duration,
+) -> Result<Output, ApplyError> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // its own process group, so a timeout also kills what it started
+        .process_group(0)
+        .spawn()
+        .map_err(|e| ApplyError::Spawn(program, e))?;
+    // read both pipes while it runs so neither fills up and blocks it
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => thread::sleep(POLL_INTERVAL),
+            Ok(None) => {
+                // SAFETY: a plain syscall on the group we made it lead
+                unsafe {
+                    libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+                }
+                let _ = child.wait();
+                return Err(ApplyError::TimedOut(program, timeout));
+            }
+            Err(e) => return Err(ApplyError::Spawn(program, e)),
+        }
+    };
+    // something it left running in the background may hold the pipes open
+    let rest = deadline
+        .saturating_duration_since(Instant::now())
+        .max(POLL_INTERVAL);
+    Ok(Output {
+        status,
+        stdout: stdout.recv_timeout(rest).unwrap_or_default(),
+        stderr: stderr.recv_timeout(rest).unwrap_or_default(),
+    })
+}
+
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> mpsc::Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut content = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut content);
+        }
+        let _ = sender.send(content);
+    });
+    receiver
+}
+
+/// run `command` within `config::command_timeout`, failing unless it exits
+/// successfully
+pub fn run(program: &'static str, command: &mut Command) -> Result<(), ApplyError> {
+    let output = run_with_timeout(program, command, config::command_timeout())?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let mut message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    if message.is_empty() {
+        message = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    }
+    Err(ApplyError::Failed(program, output.status, message))
+}
+
+/// whether `command` runs and exits successfully within
+/// `config::command_timeout`, its output discarded
+fn succeeds(command: &mut Command) -> bool {
+    run("command", command).is_ok()
+}
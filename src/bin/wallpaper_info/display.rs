@@ -7,11 +7,15 @@ use base64::Engine;
 use crossterm::terminal;
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
-use image::ImageReader;
+use image::{DynamicImage, ImageReader};
 
-use wallpaper_slideshow::{exif, ExifInfo, WallpaperHistory};
-
-use crate::color::{self, ColorPalette, COLOR_RESET};
+use wallpaper_slideshow::cache::{self, Cache};
+use wallpaper_slideshow::color::{self, ColorPalette, COLOR_RESET};
+use wallpaper_slideshow::playlist;
+use wallpaper_slideshow::state;
+use wallpaper_slideshow::{
+    config, discovery, exif, history, ExifInfo, ImageFile, WallpaperHistory,
+};
 
 static IS_TMUX: LazyLock<bool> = LazyLock::new(|| {
     std::env::var("TMUX").is_ok_and(|v| !v.is_empty())
@@ -22,6 +26,11 @@ struct ImageMeta {
     width: u32,
     height: u32,
     file_size: u64,
+    hour_override: Option<u8>,
+    blocked: bool,
+    favorite: bool,
+    /// it's the wallpaper on screen right now
+    current: bool,
 }
 
 pub fn show_wallpaper(stdout: &mut io::Stdout, history: &WallpaperHistory) -> io::Result<ExifInfo> {
@@ -36,19 +45,6 @@ pub fn show_wallpaper(stdout: &mut io::Stdout, history: &WallpaperHistory) -> io
     let file_size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
     let (term_width, term_height) = terminal::size().unwrap_or((80, 24));
 
-    let image = ImageReader::new(Cursor::new(fs::read(&path)?))
-        .with_guessed_format()
-        .ok()
-        .and_then(|r| r.decode().ok())
-        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Failed to decode image"))?;
-
-    let palette = color::extract_palette(&image);
-    let meta = ImageMeta {
-        width: image.width(),
-        height: image.height(),
-        file_size,
-    };
-
     let window_size = terminal::window_size().unwrap_or(terminal::WindowSize {
         width: 1920,
         height: 1080,
@@ -61,12 +57,35 @@ pub fn show_wallpaper(stdout: &mut io::Stdout, history: &WallpaperHistory) -> io
     let panel_height: u16 = 12;
     let image_area_height = term_height.saturating_sub(panel_height + 1);
 
-    let scale = (term_width as f64 * cell_width / image.width() as f64)
-        .min(image_area_height as f64 * cell_height / image.height() as f64);
+    let area_w = term_width as f64 * cell_width;
+    let area_h = image_area_height as f64 * cell_height;
+    let (width, height, image) = if area_w.max(area_h) <= cache::THUMBNAIL_EDGE as f64 {
+        load_thumbnail(&path)?
+    } else {
+        let image = decode(&fs::read(&path)?)?;
+        (image.width(), image.height(), image)
+    };
+
+    let palette = color::extract_palette(&image);
+    let meta = ImageMeta {
+        width,
+        height,
+        file_size,
+        hour_override: Cache::open_default()
+            .ok()
+            .and_then(|cache| cache.override_for(&path).ok().flatten()),
+        blocked: history::load_blocklist().contains(&path),
+        favorite: history::load_favorites().contains(&path),
+        current: state::current().is_some_and(|current| {
+            std::path::absolute(&path).is_ok_and(|path| path == current.path)
+        }),
+    };
+
+    let scale = (area_w / width as f64).min(area_h / height as f64);
 
     let (target_w, target_h) = (
-        (image.width() as f64 * scale) as u32,
-        (image.height() as f64 * scale) as u32,
+        (width as f64 * scale) as u32,
+        (height as f64 * scale) as u32,
     );
     let resized = image.resize(target_w, target_h, image::imageops::FilterType::Lanczos3);
 
@@ -96,18 +115,87 @@ pub fn show_wallpaper(stdout: &mut io::Stdout, history: &WallpaperHistory) -> io
         term_width,
         term_height,
         panel_height,
-        &history.position_str(),
+        &position(history),
     )?;
 
     stdout.flush()?;
     Ok(exif_info)
 }
 
+/// the history position, and the playlist position when a playlist is set and
+/// the wallpaper is in it
+fn position(history: &WallpaperHistory) -> String {
+    let playlist = playlist::load().and_then(Result::ok);
+    let located = playlist.and_then(|p| {
+        p.position(history.current_entry())?;
+        Some((p.locate(&history.up_to_current())?, p.len()))
+    });
+    match located {
+        Some((index, len)) => format!(
+            "{} · playlist {}/{}",
+            history.position_str(),
+            index + 1,
+            len
+        ),
+        None => history.position_str(),
+    }
+}
+
+/// replace the help bar with `text`, e.g. the search prompt
+pub fn show_status(stdout: &mut io::Stdout, text: &str) -> io::Result<()> {
+    let (term_width, term_height) = terminal::size().unwrap_or((80, 24));
+    write!(
+        stdout,
+        "\x1b[{};1H\x1b[2K {}",
+        term_height,
+        truncate(text, term_width.saturating_sub(2) as usize)
+    )?;
+    stdout.flush()
+}
+
 /// cleanup kitty graphics state
 pub fn cleanup(stdout: &mut io::Stdout) -> io::Result<()> {
     write_kitty_escape(stdout, "\x1b_Ga=d,d=A,q=2\x1b\\")
 }
 
+fn decode(data: &[u8]) -> io::Result<DynamicImage> {
+    ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()
+        .and_then(|r| r.decode().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Failed to decode image"))
+}
+
+/// the cached thumbnail along with the full image's size, generating and storing
+/// the thumbnail on first use; cache errors only cost the full decode
+fn load_thumbnail(path: &Path) -> io::Result<(u32, u32, DynamicImage)> {
+    let img = ImageFile::from_path(path)?;
+    let mtime = img.mtime;
+    let key = path.to_string_lossy();
+    let cache = Cache::open_default().ok();
+
+    let cached = cache
+        .as_ref()
+        .and_then(|c| c.get_thumbnail(&key, mtime).ok().flatten())
+        .and_then(|data| decode(&data).ok());
+    let dimensions = match &cache {
+        Some(cache) => img.dimensions(cache),
+        None => discovery::probe_dimensions(path),
+    };
+
+    if let (Some(thumbnail), Some((width, height))) = (cached, dimensions) {
+        return Ok((width, height, thumbnail));
+    }
+
+    let image = decode(&fs::read(path)?)?;
+    if let (Some(cache), Some(data)) = (&cache, cache::encode_thumbnail(&image)) {
+        if let Err(e) = cache.put_thumbnail(&key, mtime, &data) {
+            eprintln!("Failed to cache thumbnail: {}", e);
+        }
+    }
+    Ok((image.width(), image.height(), image))
+}
+
 fn display_kitty_image(
     w: &mut impl Write,
     img: &image::DynamicImage,
@@ -225,6 +313,18 @@ fn display_panel(
         accent,
         truncate(filename, term_width as usize / 2)
     )?;
+    if meta.current {
+        write!(w, "  {}● current", accent)?;
+    }
+    if meta.favorite {
+        write!(w, "  {}★ favorite", accent)?;
+    }
+    if let Some(rating) = info.rating {
+        write!(w, "  {}{}", secondary, format_rating(rating))?;
+    }
+    if meta.blocked {
+        write!(w, "  {}⊘ blocked", secondary)?;
+    }
     let pos_text = format!("[{}]", position);
     write!(
         w,
@@ -237,10 +337,26 @@ fn display_panel(
     )?;
     row += 1;
 
+    // caption
+    if let Some(ref description) = info.description {
+        let caption = description.split_whitespace().collect::<Vec<_>>().join(" ");
+        write!(
+            w,
+            "\x1b[{};{}H{}{}{}{}",
+            row,
+            left,
+            bg,
+            dim,
+            truncate(&caption, term_width.saturating_sub(6) as usize),
+            COLOR_RESET
+        )?;
+        row += 1;
+    }
+
     // dimensions
     write!(
         w,
-        "\x1b[{};{}H{}{}{}x{}  {}{}{}",
+        "\x1b[{};{}H{}{}{}x{}  {}{}",
         row,
         left,
         bg,
@@ -249,27 +365,50 @@ fn display_panel(
         meta.height,
         secondary,
         format_size(meta.file_size),
-        COLOR_RESET
     )?;
+    if let Some(orientation) = info.orientation.filter(|o| !o.is_normal()) {
+        write!(w, "  {}{}", dim, orientation.description())?;
+    }
+    write!(w, "{}", COLOR_RESET)?;
     row += 2;
 
+    let body_start = row;
     let col2 = term_width / 2;
 
     // col1: when & where
-    if let Some(ref dt) = info.datetime {
+    if info.datetime.is_some() || meta.hour_override.is_some() {
         write!(
             w,
-            "\x1b[{};{}H{}{} When   {}{}{}",
-            row, left, bg, accent, text, dt, COLOR_RESET
+            "\x1b[{};{}H{}{} When   {}{}",
+            row,
+            left,
+            bg,
+            accent,
+            text,
+            info.datetime.as_deref().unwrap_or("")
         )?;
+        if let Some(hour) = meta.hour_override {
+            let gap = if info.datetime.is_some() { "  " } else { "" };
+            write!(w, "{}{}shown as {}:00 (override)", gap, dim, hour)?;
+        } else if let Some(source) = info.datetime_source.filter(|s| s.is_approximate()) {
+            write!(w, "  {}~ from {}", dim, source.tag_name())?;
+        }
+        write!(w, "{}", COLOR_RESET)?;
         row += 1;
     }
-    if let Some(ref loc) = info.location {
+    if let Some(loc) = info.location(config::gps_format()) {
         write!(
             w,
-            "\x1b[{};{}H{}{} Where  {}{}{}",
-            row, left, bg, accent, text, loc, COLOR_RESET
+            "\x1b[{};{}H{}{} Where  {}{}",
+            row, left, bg, accent, text, loc
         )?;
+        if let Some(bearing) = info.bearing() {
+            write!(w, "  {}{}", dim, bearing)?;
+        }
+        if let Some(altitude) = info.gps_altitude {
+            write!(w, "  {}{}", dim, format_altitude(altitude))?;
+        }
+        write!(w, "{}", COLOR_RESET)?;
         if info.has_gps() {
             row += 1;
             write!(
@@ -278,10 +417,37 @@ fn display_panel(
                 row, left, bg, dim, accent, dim, COLOR_RESET
             )?;
         }
+        row += 1;
+    }
+    if !info.keywords.is_empty() {
+        write!(
+            w,
+            "\x1b[{};{}H{}{} Tags   {}{}{}",
+            row,
+            left,
+            bg,
+            accent,
+            text,
+            truncate(&info.keywords.join(", "), (term_width / 2 - 12) as usize),
+            COLOR_RESET
+        )?;
+        row += 1;
+    }
+    if let Some(credit) = info.credit() {
+        write!(
+            w,
+            "\x1b[{};{}H{}{} {}{}",
+            row,
+            left,
+            bg,
+            dim,
+            truncate(&credit, (term_width / 2 - 5) as usize),
+            COLOR_RESET
+        )?;
     }
 
     // col2: camera & settings
-    row = panel_start + 3;
+    row = body_start;
     if let Some(ref cam) = info.camera {
         write!(
             w,
@@ -320,10 +486,43 @@ fn display_panel(
     .filter_map(|o| o.as_ref().map(|s| s.as_str()))
     .collect();
 
-    if !settings.is_empty() {
-        write!(w, "\x1b[{};{}H{}{} Settings  ", row, col2, bg, secondary)?;
-        for (i, s) in settings.iter().enumerate() {
-            if i > 0 {
+    let extras: Vec<&str> = [
+        &info.exposure_bias,
+        &info.metering_mode,
+        &info.white_balance,
+        &info.flash,
+    ]
+    .iter()
+    .filter_map(|o| o.as_ref().map(|s| s.as_str()))
+    .collect();
+
+    // extras go after the basic settings while they fit, then on one more line
+    let label = " Settings  ";
+    let available = term_width.saturating_sub(col2 + label.len() as u16 + 2) as usize;
+    let mut lines = vec![settings];
+    for extra in extras {
+        let last = lines.len() - 1;
+        let fits = line_width(&lines[last]) + 2 + extra.chars().count() <= available;
+        if lines[last].is_empty() || fits {
+            lines[last].push(extra);
+        } else if lines.len() < 2 {
+            lines.push(vec![extra]);
+        }
+    }
+
+    for (i, line) in lines.iter().filter(|l| !l.is_empty()).enumerate() {
+        let label = if i == 0 { label } else { "           " };
+        write!(
+            w,
+            "\x1b[{};{}H{}{}{}",
+            row + i as u16,
+            col2,
+            bg,
+            secondary,
+            label
+        )?;
+        for (j, s) in line.iter().enumerate() {
+            if j > 0 {
                 write!(w, "{}  ", dim)?;
             }
             write!(w, "{}{}", text, s)?;
@@ -340,11 +539,28 @@ fn display_panel(
     if info.has_gps() {
         write!(w, "   {}m{}Maps   {}c{}Copy", accent, dim, accent, dim)?;
     }
+    let favorite = if meta.favorite {
+        "Unfavorite"
+    } else {
+        "Favorite"
+    };
+    let block = if meta.blocked { "Unblock" } else { "Block" };
+    write!(
+        w,
+        "   {}f{}{}   {}x{}{}",
+        accent, dim, favorite, accent, dim, block
+    )?;
     write!(w, "{}", COLOR_RESET)?;
 
     Ok(())
 }
 
+/// printed width of items separated by two spaces
+fn line_width(items: &[&str]) -> usize {
+    let chars: usize = items.iter().map(|s| s.chars().count()).sum();
+    chars + 2 * items.len().saturating_sub(1)
+}
+
 fn truncate(s: &str, max: usize) -> String {
     if s.chars().count() <= max {
         s.to_string()
@@ -355,6 +571,27 @@ fn truncate(s: &str, max: usize) -> String {
     }
 }
 
+/// "1,420 m"
+fn format_altitude(meters: f64) -> String {
+    let rounded = meters.round() as i64;
+    let digits = rounded.unsigned_abs().to_string();
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    let sign = if rounded < 0 { "-" } else { "" };
+    format!("{}{} m", sign, grouped)
+}
+
+/// filled and hollow stars out of 5
+fn format_rating(rating: u8) -> String {
+    let filled = rating.min(5) as usize;
+    format!("{}{}", "★".repeat(filled), "☆".repeat(5 - filled))
+}
+
 fn format_size(bytes: u64) -> String {
     const KB: u64 = 1024;
     const MB: u64 = KB * 1024;
@@ -1,4 +1,3 @@
-mod color;
 mod display;
 
 use std::env;
@@ -8,7 +7,9 @@ use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
 use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
 use crossterm::ExecutableCommand;
 
-use wallpaper_slideshow::{WallpaperHistory, DEFAULT_HISTORY_LOG, DEFAULT_WALLPAPER_DIR};
+use wallpaper_slideshow::{
+    config, history, ExifInfo, WallpaperHistory, DEFAULT_HISTORY_LOG, DEFAULT_WALLPAPER_DIR,
+};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -46,13 +47,27 @@ ENVIRONMENT VARIABLES:
                            Default: {}
     WALLPAPER_HISTORY_LOG  Path to wallpaper history log file
                            Default: {}
+    WALLPAPER_HISTORY_BACKEND
+                           file, or sqlite to read history from the cache database
+                           Default: file
+    WALLPAPER_GPS_FORMAT   Coordinates on the Where line: dms, decimal or both
+                           Default: dms
+    WALLPAPER_GPS_COPY_FORMAT
+                           Coordinates copied with c: dms, decimal or both
+                           Default: decimal
 
 KEYBINDINGS:
     q, Esc    Quit the application
     m         Open location in Google Maps (if GPS data available)
     c         Copy GPS coordinates to clipboard (if available)
+    /         Search history by file name; Enter jumps to the newest match
+    n, N      Jump to the next older / newer match of the last search
+    f         Mark the shown wallpaper as a favorite, or unmark it
+    x         Block the shown wallpaper from being selected again, or unblock it
     Left/Up   Show previous wallpaper from history
     Right/Down Show next wallpaper from history
+    g, G      Jump to the oldest / newest wallpaper in history
+    N g, N G  Jump to the Nth wallpaper in history, e.g. 5G
 "#,
         env!("CARGO_PKG_VERSION"),
         DEFAULT_WALLPAPER_DIR,
@@ -61,7 +76,7 @@ KEYBINDINGS:
 }
 
 fn run() -> io::Result<()> {
-    let mut history = WallpaperHistory::load()
+    let mut history = WallpaperHistory::load_existing()
         .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No wallpaper history found"))?;
 
     let mut stdout = io::stdout();
@@ -69,10 +84,51 @@ fn run() -> io::Result<()> {
     terminal::enable_raw_mode()?;
 
     let mut current_exif = display::show_wallpaper(&mut stdout, &history)?;
+    // digits typed before g or G
+    let mut count: Option<usize> = None;
+    // the query being typed after /, and the last one searched for n and N
+    let mut search: Option<String> = None;
+    let mut last_search: Option<String> = None;
 
     loop {
         if event::poll(std::time::Duration::from_millis(100))? {
             if let Event::Key(key) = event::read()? {
+                if let Some(query) = search.as_mut() {
+                    match key.code {
+                        KeyCode::Esc => {
+                            search = None;
+                            current_exif = display::show_wallpaper(&mut stdout, &history)?;
+                        }
+                        KeyCode::Enter => {
+                            let query = search.take().unwrap_or_default();
+                            let found = history.search(&query).first().copied();
+                            if let Some(info) =
+                                show_match(&mut stdout, &mut history, found, &query)?
+                            {
+                                current_exif = info;
+                            }
+                            last_search = Some(query);
+                        }
+                        KeyCode::Backspace => {
+                            query.pop();
+                            display::show_status(&mut stdout, &format!("/{}", query))?;
+                        }
+                        KeyCode::Char(c) => {
+                            query.push(c);
+                            display::show_status(&mut stdout, &format!("/{}", query))?;
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+
+                if let KeyCode::Char(c @ '0'..='9') = key.code {
+                    let digit = c.to_digit(10).unwrap_or(0) as usize;
+                    count = Some(count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                    continue;
+                }
+                let count = count.take();
+
                 match key {
                     KeyEvent {
                         code: KeyCode::Char('q'),
@@ -100,30 +156,84 @@ fn run() -> io::Result<()> {
                         code: KeyCode::Char('c'),
                         ..
                     } => {
-                        if let (Some(lat), Some(lon)) =
-                            (current_exif.gps_latitude, current_exif.gps_longitude)
-                        {
-                            let _ = copy_to_clipboard(&format!("{:.6}, {:.6}", lat, lon));
+                        if let Some(mut text) = current_exif.location(config::gps_copy_format()) {
+                            if let Some(bearing) = current_exif.bearing() {
+                                text.push_str(&format!(" # facing {}", bearing));
+                            }
+                            let _ = copy_to_clipboard(&text);
                         }
                     }
 
                     KeyEvent {
-                        code: KeyCode::Left | KeyCode::Up | KeyCode::Char('h') | KeyCode::Char('k'),
+                        code: KeyCode::Char('/'),
                         ..
                     } => {
-                        if history.go_previous() {
-                            current_exif = display::show_wallpaper(&mut stdout, &history)?;
+                        search = Some(String::new());
+                        display::show_status(&mut stdout, "/")?;
+                    }
+
+                    KeyEvent {
+                        code: KeyCode::Char(c @ ('n' | 'N')),
+                        ..
+                    } => {
+                        if let Some(query) = &last_search {
+                            let found = next_match(&history, query, c == 'n');
+                            if let Some(info) = show_match(&mut stdout, &mut history, found, query)?
+                            {
+                                current_exif = info;
+                            }
                         }
                     }
 
                     KeyEvent {
-                        code:
-                            KeyCode::Right | KeyCode::Down | KeyCode::Char('l') | KeyCode::Char('j'),
+                        code: KeyCode::Char('f'),
                         ..
                     } => {
-                        if history.go_next() {
-                            current_exif = display::show_wallpaper(&mut stdout, &history)?;
+                        let entry = history.current_entry().to_string();
+                        if let Ok(false) = history::favorite(&entry) {
+                            let _ = history::unfavorite(&entry);
+                        }
+                        current_exif = display::show_wallpaper(&mut stdout, &history)?;
+                    }
+
+                    KeyEvent {
+                        code: KeyCode::Char('x'),
+                        ..
+                    } => {
+                        let entry = history.current_entry().to_string();
+                        if let Ok(false) = history::block(&entry) {
+                            let _ = history::unblock(&entry);
                         }
+                        current_exif = display::show_wallpaper(&mut stdout, &history)?;
+                    }
+
+                    KeyEvent {
+                        code: KeyCode::Left | KeyCode::Up | KeyCode::Char('h') | KeyCode::Char('k'),
+                        ..
+                    } if history.go_previous() => {
+                        current_exif = display::show_wallpaper(&mut stdout, &history)?;
+                    }
+
+                    KeyEvent {
+                        code:
+                            KeyCode::Right | KeyCode::Down | KeyCode::Char('l') | KeyCode::Char('j'),
+                        ..
+                    } if history.go_next() => {
+                        current_exif = display::show_wallpaper(&mut stdout, &history)?;
+                    }
+
+                    KeyEvent {
+                        code: KeyCode::Char('g'),
+                        ..
+                    } if jump(&mut history, count, WallpaperHistory::jump_first) => {
+                        current_exif = display::show_wallpaper(&mut stdout, &history)?;
+                    }
+
+                    KeyEvent {
+                        code: KeyCode::Char('G'),
+                        ..
+                    } if jump(&mut history, count, WallpaperHistory::jump_last) => {
+                        current_exif = display::show_wallpaper(&mut stdout, &history)?;
                     }
 
                     _ => {}
@@ -138,6 +248,54 @@ fn run() -> io::Result<()> {
     Ok(())
 }
 
+/// the match after the current entry, going to older entries for `older`,
+/// wrapping around at the end
+fn next_match(history: &WallpaperHistory, query: &str, older: bool) -> Option<usize> {
+    let matches = history.search(query);
+    let current = history.current_index();
+    if older {
+        let next = matches.iter().find(|&&i| i < current);
+        next.or(matches.first()).copied()
+    } else {
+        let next = matches.iter().rev().find(|&&i| i > current);
+        next.or(matches.last()).copied()
+    }
+}
+
+/// jump to `found` and redraw, or say nothing matched `query`
+fn show_match(
+    stdout: &mut io::Stdout,
+    history: &mut WallpaperHistory,
+    found: Option<usize>,
+    query: &str,
+) -> io::Result<Option<ExifInfo>> {
+    match found {
+        Some(index) => {
+            history.jump_to(index);
+            display::show_wallpaper(stdout, history).map(Some)
+        }
+        None => {
+            display::show_status(stdout, &format!("No match for \"{}\"", query))?;
+            Ok(None)
+        }
+    }
+}
+
+/// jump to the `count`th entry, counting from 1 as shown in the panel, or use
+/// `default` without a count
+fn jump(
+    history: &mut WallpaperHistory,
+    count: Option<usize>,
+    default: fn(&mut WallpaperHistory) -> bool,
+) -> bool {
+    let before = history.current_index();
+    let moved = match count {
+        Some(n) => n.checked_sub(1).is_some_and(|i| history.jump_to(i)),
+        None => default(history),
+    };
+    moved && history.current_index() != before
+}
+
 fn open_url(url: &str) -> io::Result<()> {
     #[cfg(target_os = "linux")]
     std::process::Command::new("xdg-open")
@@ -1,23 +1,543 @@
-use rusqlite::{params, Connection};
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use image::{DynamicImage, ImageFormat};
+use rusqlite::{
+    params, Connection, DatabaseName, OpenFlags, OptionalExtension, Transaction,
+    TransactionBehavior,
+};
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::error::Error;
 use std::fs;
-use std::path::Path;
+use std::io::{self, BufRead, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::config;
+use crate::discovery::{Dimensions, ImageFile};
+use crate::exif::{self, ExifInfo, ExifOrientation, Extracted};
+use crate::places::Point;
+use crate::selection;
+use crate::solar::Light;
+
+type Migration = fn(&Transaction) -> Result<(), rusqlite::Error>;
+
+/// step `i` upgrades a cache at `user_version` i to i + 1; append a step for every
+/// schema change instead of editing an existing one
+const MIGRATIONS: &[Migration] = &[
+    create_tables,
+    reparse_for_offset_time,
+    reparse_for_datetime_fallback,
+    add_rating,
+    add_keywords,
+    add_month,
+    add_error,
+    add_failures,
+    add_thumbnails,
+    add_updated_at,
+    add_size,
+    add_hash,
+    move_dimensions,
+    add_overrides,
+    add_history,
+    add_luminance,
+    add_location,
+    add_captured_on,
+    add_minute_of_day,
+    add_captured_at,
+    add_utc_offset,
+    add_settings,
+];
+
+/// the mtime `reparse_all` gives entries, see `CacheKey::is_fresh`
+const REPARSE_MTIME: i64 = 0;
+/// the updated_at of an entry `insert_dimensions` created, which holds only the
+/// dimensions until the EXIF is read
+const UNPARSED: i64 = 0;
+/// how long a statement waits for another process to release the database
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// how long closing waits to checkpoint before leaving it to the next run
+const CLOSE_TIMEOUT: Duration = Duration::from_millis(250);
+/// a locked write is tried this many times in total
+const WRITE_ATTEMPTS: u32 = 3;
+/// wait before the first retry, doubled each time
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// paths bound per statement, well below SQLite's variable limit
+const PATHS_PER_STATEMENT: usize = 500;
+
+/// long edge of stored thumbnails in pixels
+pub const THUMBNAIL_EDGE: u32 = 512;
+
+/// a file whose EXIF failed this many times in a row isn't parsed again until it changes
+pub const MAX_PARSE_ATTEMPTS: u32 = 3;
+
+/// keywords are stored as one column, split on this
+const KEYWORD_SEPARATOR: char = '\n';
+
+/// capture dates and times are stored as text in these formats
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// path, cache mtime, size, content hash, header dimensions, what could be read,
+/// and why the EXIF couldn't be
+#[deprecated(note = "use `CacheRecord`")]
+pub type NewEntry = (
+    String,
+    i64,
+    u64,
+    Option<u64>,
+    Option<Dimensions>,
+    ExifInfo,
+    Option<String>,
+);
+
+/// one freshly parsed file, written with `Cache::upsert`
+#[derive(Debug, Clone)]
+pub struct CacheRecord {
+    pub path: String,
+    /// `ImageFile::cache_mtime`, covering the sidecar too
+    pub mtime: i64,
+    pub size: u64,
+    pub hash: Option<u64>,
+    pub dimensions: Option<Dimensions>,
+    pub luminance: Option<f32>,
+    pub info: ExifInfo,
+    /// why the EXIF couldn't be read, if it couldn't
+    pub error: Option<String>,
+}
+
+impl CacheRecord {
+    pub fn new(img: &ImageFile, extracted: Extracted) -> Self {
+        Self {
+            path: extracted.path.to_string_lossy().to_string(),
+            mtime: img.cache_mtime(),
+            size: img.size,
+            hash: img.hash,
+            dimensions: extracted.dimensions,
+            luminance: extracted.luminance,
+            info: extracted.info,
+            error: extracted.error.map(|e| e.to_string()),
+        }
+    }
+}
+
+/// the EXIF cache database
+pub struct Cache {
+    conn: Connection,
+    /// writes are skipped, see `open`
+    read_only: bool,
+}
+
+impl Cache {
+    /// open the database at `path`, creating and migrating it as needed; when it
+    /// can't be written but an up to date one exists it is opened read-only
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, rusqlite::Error> {
+        let path = path.as_ref();
+        let opened = match open_at(path) {
+            Err(e) if is_corrupt(&e) => match move_aside(path) {
+                Ok(()) => open_at(path),
+                Err(move_error) => {
+                    eprintln!(
+                        "Cache database is corrupt but couldn't be moved: {}",
+                        move_error
+                    );
+                    Err(e)
+                }
+            },
+            opened => opened,
+        };
+        match opened {
+            Ok(conn) if !conn.is_readonly(DatabaseName::Main)? => Ok(Self {
+                conn,
+                read_only: false,
+            }),
+            Ok(_) => Self::open_read_only(path),
+            Err(e) => Self::open_read_only(path).map_err(|_| e),
+        }
+    }
+
+    fn open_read_only(path: &Path) -> Result<Self, rusqlite::Error> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        let read_version = |conn: &Connection| {
+            conn.query_row("PRAGMA user_version", [], |row| row.get::<_, usize>(0))
+        };
+
+        let mut conn = Connection::open_with_flags(path, flags)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        // a WAL database needs its shared memory file, which can't be created in a
+        // read-only directory; immutable mode reads the main file without it
+        let version = match read_version(&conn) {
+            Ok(version) => version,
+            Err(_) => {
+                conn = Connection::open_with_flags(
+                    immutable_uri(path),
+                    flags | OpenFlags::SQLITE_OPEN_URI,
+                )?;
+                read_version(&conn)?
+            }
+        };
+        if version != MIGRATIONS.len() {
+            return Err(read_only_error("read-only cache has an outdated schema"));
+        }
+        Ok(Self {
+            conn,
+            read_only: true,
+        })
+    }
+
+    /// whether writes are silently skipped because the database isn't writable;
+    /// those asked for explicitly, like `prune` or `set_override`, fail instead
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// fail unless the database is writable, for writes that mustn't be
+    /// skipped silently
+    fn check_writable(&self) -> Result<(), rusqlite::Error> {
+        if self.read_only {
+            return Err(read_only_error("cache is read-only"));
+        }
+        Ok(())
+    }
+
+    /// the database at `config::cache_db`
+    pub fn open_default() -> Result<Self, rusqlite::Error> {
+        Self::open(config::cache_db())
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    pub fn load_all(&self) -> Result<HashMap<String, CachedEntry>, rusqlite::Error> {
+        load_entries(&self.conn)
+    }
+
+    /// (path, (latitude, longitude)) of every entry with GPS, by path
+    pub fn load_locations(&self) -> Result<Vec<(String, Point)>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT path, latitude, longitude FROM exif_cache
+             WHERE latitude IS NOT NULL AND longitude IS NOT NULL
+             ORDER BY path",
+        )?;
+        let locations = stmt.query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?;
+        locations.collect()
+    }
+
+    pub fn load_for_paths(
+        &self,
+        paths: &[String],
+    ) -> Result<HashMap<String, CachedEntry>, rusqlite::Error> {
+        load_for_paths(&self.conn, paths)
+    }
+
+    pub fn load_keys(&self) -> Result<HashMap<String, CacheKey>, rusqlite::Error> {
+        load_keys(&self.conn)
+    }
+
+    /// insert or update the entries for these files
+    pub fn upsert(&self, records: &[CacheRecord]) -> Result<(), rusqlite::Error> {
+        if self.read_only {
+            return Ok(());
+        }
+        with_retry(|| upsert_once(&self.conn, records))
+    }
+
+    /// remove entries under the wallpaper directory that aren't in `current_paths`,
+    /// returning how many were removed
+    pub fn cleanup_stale(&self, current_paths: &HashSet<String>) -> Result<usize, rusqlite::Error> {
+        if self.read_only {
+            return Ok(0);
+        }
+        let paths: Vec<String> = self
+            .conn
+            .prepare("SELECT path FROM exif_cache")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        remove_stale(&self.conn, current_paths, paths.iter())
+    }
+
+    pub fn remove(&self, paths: &[String]) -> Result<usize, rusqlite::Error> {
+        if self.read_only {
+            return Ok(0);
+        }
+        remove(&self.conn, paths)
+    }
+
+    pub fn stats(&self) -> Result<CacheStats, rusqlite::Error> {
+        stats(&self.conn)
+    }
+
+    pub fn prune(&self, keep: &HashSet<String>) -> Result<PruneReport, rusqlite::Error> {
+        self.check_writable()?;
+        prune(&self.conn, keep)
+    }
+
+    pub fn export(&self, writer: impl Write) -> Result<usize, Box<dyn Error>> {
+        export(&self.conn, writer)
+    }
+
+    pub fn import(&self, reader: impl BufRead) -> Result<(usize, usize), Box<dyn Error>> {
+        self.check_writable()?;
+        import(&self.conn, reader)
+    }
+
+    pub fn get_thumbnail(
+        &self,
+        path: &str,
+        mtime: i64,
+    ) -> Result<Option<Vec<u8>>, rusqlite::Error> {
+        read_thumbnail(&self.conn, path, mtime, !self.read_only)
+    }
+
+    pub fn put_thumbnail(
+        &self,
+        path: &str,
+        mtime: i64,
+        data: &[u8],
+    ) -> Result<(), rusqlite::Error> {
+        if self.read_only {
+            return Ok(());
+        }
+        with_retry(|| put_thumbnail(&self.conn, path, mtime, data))
+    }
+
+    pub fn prune_thumbnails(
+        &self,
+        budget: u64,
+        keep: Option<&str>,
+    ) -> Result<usize, rusqlite::Error> {
+        self.check_writable()?;
+        with_retry(|| prune_thumbnails(&self.conn, budget, keep))
+    }
+
+    pub fn set_override(&self, key: &str, hour: u8) -> Result<(), rusqlite::Error> {
+        self.check_writable()?;
+        set_override(&self.conn, key, hour)
+    }
+
+    pub fn remove_override(&self, key: &str) -> Result<bool, rusqlite::Error> {
+        self.check_writable()?;
+        remove_override(&self.conn, key)
+    }
+
+    /// see `get_dimensions`
+    pub fn get_dimensions(
+        &self,
+        path: &str,
+        mtime: i64,
+    ) -> Result<Option<Option<Dimensions>>, rusqlite::Error> {
+        get_dimensions(&self.conn, path, mtime)
+    }
+
+    /// see `load_dimensions`
+    pub fn load_dimensions(
+        &self,
+    ) -> Result<HashMap<String, (i64, Option<Dimensions>)>, rusqlite::Error> {
+        load_dimensions(&self.conn)
+    }
+
+    /// record probed dimensions, see `insert_dimensions`
+    pub fn insert_dimensions(
+        &self,
+        entries: &[(String, i64, Option<Dimensions>)],
+    ) -> Result<(), rusqlite::Error> {
+        if self.read_only {
+            return Ok(());
+        }
+        with_retry(|| insert_dimensions(&self.conn, entries))
+    }
+
+    pub fn get_overrides(&self) -> Result<HashMap<String, u8>, rusqlite::Error> {
+        get_overrides(&self.conn)
+    }
+
+    /// the override for this file, by full path or else by basename
+    pub fn override_for(&self, path: &Path) -> Result<Option<u8>, rusqlite::Error> {
+        let basename = path.file_name().map(|n| n.to_string_lossy().to_string());
+        self.conn
+            .query_row(
+                "SELECT hour FROM overrides WHERE key IN (?1, ?2)
+                 ORDER BY key = ?1 DESC LIMIT 1",
+                params![path.to_string_lossy(), basename],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    /// checkpoint and close; dropping the cache does the same
+    pub fn close(self) {}
+
+    /// fold the WAL back into the database if this process wrote anything, giving
+    /// up quickly when another process holds it
+    fn checkpoint(&self) {
+        if self.read_only || self.conn.total_changes() == 0 {
+            return;
+        }
+        let _ = self.conn.busy_timeout(CLOSE_TIMEOUT);
+        let _ = self
+            .conn
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()));
+        let _ = self.conn.execute_batch("PRAGMA optimize");
+    }
+}
+
+impl Drop for Cache {
+    fn drop(&mut self) {
+        self.checkpoint();
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct CachedEntry {
     pub mtime: i64,
+    /// unknown for entries written before sizes were stored
+    pub size: Option<u64>,
+    /// set when the entry was written with content hashing on
+    pub hash: Option<u64>,
+    /// `None` when the header couldn't be read
+    pub dimensions: Option<Dimensions>,
+    /// mean luminance from 0 to 1, only stored with `config::brightness`
+    pub luminance: Option<f32>,
     pub hour: Option<u8>,
+    /// minutes after midnight; None for entries cached before minutes were,
+    /// see `minute`
+    pub minute_of_day: Option<u16>,
+    pub month: Option<u8>,
+    /// local capture date, from the same datetime as the hour
+    pub captured_on: Option<NaiveDate>,
+    /// local capture time to the second
+    pub captured_at: Option<NaiveDateTime>,
+    /// minutes east of UTC the capture time was written with
+    pub utc_offset: Option<i32>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub orientation: Option<ExifOrientation>,
+    pub rating: Option<u8>,
+    pub keywords: Vec<String>,
+    /// set when the EXIF couldn't be read; such entries are parsed again next run
+    pub error: Option<String>,
+    /// consecutive failed parses of the same file
+    pub failures: u32,
+    /// false for an entry holding only the dimensions, see `insert_dimensions`
+    pub parsed: bool,
+}
+
+impl CachedEntry {
+    /// minutes after midnight, the middle of the hour until an entry cached
+    /// with only the hour is parsed again
+    pub fn minute(&self) -> Option<u16> {
+        self.minute_of_day
+            .or_else(|| self.hour.map(selection::middle_of_hour))
+    }
+
+    /// the light it was taken in, if it has a position and a capture time
+    pub fn light(&self) -> Option<Light> {
+        let location = self.latitude.zip(self.longitude)?;
+        Some(Light::of_photo(
+            self.captured_at?,
+            self.utc_offset,
+            location,
+        ))
+    }
+
+    pub fn key(&self) -> CacheKey {
+        CacheKey {
+            mtime: self.mtime,
+            size: self.size,
+            hash: self.hash,
+            failed: self.error.is_some(),
+            failures: self.failures,
+            has_luminance: self.luminance.is_some(),
+            has_minute: self.hour.is_none() || self.minute_of_day.is_some(),
+            parsed: self.parsed,
+        }
+    }
+
+    /// see `CacheKey::is_fresh`
+    pub fn is_fresh(&self, img: &ImageFile) -> bool {
+        self.key().is_fresh(img)
+    }
+
+    /// see `CacheKey::should_retry`
+    pub fn should_retry(&self) -> bool {
+        self.key().should_retry()
+    }
+}
+
+/// the columns of an entry that decide whether it is still valid, cheap to load
+/// for every file
+#[derive(Debug, Clone, Copy)]
+pub struct CacheKey {
+    pub mtime: i64,
+    pub size: Option<u64>,
+    pub hash: Option<u64>,
+    /// whether the EXIF couldn't be read
+    pub failed: bool,
+    pub failures: u32,
+    pub has_luminance: bool,
+    /// false for an entry with an hour cached before minutes were
+    pub has_minute: bool,
+    /// see `CachedEntry::parsed`
+    pub parsed: bool,
+}
+
+impl CacheKey {
+    /// whether the entry can be used as is for `img`; with content hashes on both
+    /// sides only the hash is compared, so a changed mtime alone doesn't count.
+    /// An mtime of 0 is `reparse_all` asking for a reparse, hash or not, and an
+    /// entry that only holds dimensions was never parsed
+    pub fn is_fresh(&self, img: &ImageFile) -> bool {
+        if self.mtime == REPARSE_MTIME || !self.parsed {
+            return false;
+        }
+        let unchanged = match (self.hash, img.hash) {
+            (Some(cached), Some(current)) => cached == current,
+            _ => self.mtime == img.cache_mtime() && self.size.is_none_or(|s| s == img.size),
+        };
+        unchanged && !self.should_retry()
+    }
+
+    /// whether a failed entry should be parsed again even though the file is unchanged
+    pub fn should_retry(&self) -> bool {
+        self.failed && self.failures < MAX_PARSE_ATTEMPTS
+    }
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub entries: u64,
+    pub with_hour: u64,
+    pub without_hour: u64,
+    pub failed: u64,
+    pub thumbnails: u64,
+    pub db_bytes: u64,
+    /// unix time of the most recent insert
+    pub last_updated: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PruneReport {
+    /// entries whose file is gone
+    pub missing: usize,
+    /// entries older than the configured maximum age
+    pub expired: usize,
+    pub bytes_reclaimed: u64,
+}
+
+#[deprecated(note = "use `Cache::open_default`")]
 pub fn open() -> Result<Connection, rusqlite::Error> {
-    let db_path = config::cache_db();
-    if let Some(parent) = Path::new(&db_path).parent() {
+    open_at(Path::new(&config::cache_db()))
+}
+
+fn open_at(db_path: &Path) -> Result<Connection, rusqlite::Error> {
+    if let Some(parent) = db_path.parent() {
         let _ = fs::create_dir_all(parent);
     }
 
-    let conn = Connection::open(&db_path)?;
+    let mut conn = Connection::open(db_path)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
 
     conn.execute_batch(
         "
@@ -28,56 +548,440 @@ pub fn open() -> Result<Connection, rusqlite::Error> {
         ",
     )?;
 
+    migrate(&mut conn)?;
+    match_exif_utc(&conn)?;
+    Ok(conn)
+}
+
+/// reparse every entry with a UTC offset when `config::exif_utc` changed
+/// since they were parsed, as their hours depend on it
+fn match_exif_utc(conn: &Connection) -> Result<(), rusqlite::Error> {
+    let current = if config::exif_utc() { "1" } else { "0" };
+    let cached: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = 'exif_utc'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if cached.as_deref().unwrap_or("0") == current {
+        return Ok(());
+    }
     conn.execute(
+        "UPDATE exif_cache SET mtime = ?1 WHERE utc_offset IS NOT NULL",
+        [REPARSE_MTIME],
+    )?;
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (key, value) VALUES ('exif_utc', ?1)",
+        [current],
+    )?;
+    Ok(())
+}
+
+/// bring the schema up to date, rebuilding it when it was written by a newer build
+fn migrate(conn: &mut Connection) -> Result<(), rusqlite::Error> {
+    let latest = MIGRATIONS.len() as i32;
+    let mut version: i32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    if version > latest {
+        eprintln!(
+            "Cache schema version {} is newer than this build understands ({}), rebuilding it",
+            version, latest
+        );
+        conn.execute_batch(
+            "DROP TABLE IF EXISTS exif_cache;
+             DROP TABLE IF EXISTS dimensions;
+             DROP TABLE IF EXISTS thumbnails;
+             PRAGMA user_version = 0;",
+        )?;
+        version = 0;
+    }
+
+    for (from, step) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let tx = conn.transaction()?;
+        step(&tx)?;
+        tx.pragma_update(None, "user_version", from as i32 + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// the table as it was before versioning, possibly already there from an old build
+fn create_tables(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute_batch(
         "CREATE TABLE IF NOT EXISTS exif_cache (
+             path TEXT PRIMARY KEY,
+             mtime INTEGER NOT NULL,
+             hour INTEGER
+         );
+         CREATE INDEX IF NOT EXISTS idx_path ON exif_cache(path);
+         CREATE TABLE IF NOT EXISTS dimensions (
+             path TEXT PRIMARY KEY,
+             mtime INTEGER NOT NULL,
+             width INTEGER,
+             height INTEGER
+         );
+         ALTER TABLE exif_cache ADD COLUMN orientation INTEGER;",
+    )?;
+    reparse_all(tx)
+}
+
+fn reparse_for_offset_time(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    reparse_all(tx)
+}
+
+fn reparse_for_datetime_fallback(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    reparse_all(tx)
+}
+
+fn add_rating(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute("ALTER TABLE exif_cache ADD COLUMN rating INTEGER", [])?;
+    reparse_all(tx)
+}
+
+fn add_keywords(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute("ALTER TABLE exif_cache ADD COLUMN keywords TEXT", [])?;
+    reparse_all(tx)
+}
+
+fn add_month(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute("ALTER TABLE exif_cache ADD COLUMN month INTEGER", [])?;
+    reparse_all(tx)
+}
+
+fn add_error(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute("ALTER TABLE exif_cache ADD COLUMN error TEXT", [])?;
+    Ok(())
+}
+
+fn add_failures(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute(
+        "ALTER TABLE exif_cache ADD COLUMN failures INTEGER NOT NULL DEFAULT 0",
+        [],
+    )?;
+    Ok(())
+}
+
+fn add_thumbnails(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute(
+        "CREATE TABLE thumbnails (
             path TEXT PRIMARY KEY,
             mtime INTEGER NOT NULL,
-            hour INTEGER
+            data BLOB NOT NULL,
+            last_used INTEGER NOT NULL
         )",
         [],
     )?;
+    Ok(())
+}
 
-    conn.execute(
-        "CREATE INDEX IF NOT EXISTS idx_path ON exif_cache(path)",
+/// hours set by hand, keyed by full path or basename; no cleanup touches them,
+/// nor does rebuilding a cache from a newer build
+fn add_overrides(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS overrides (
+            key TEXT PRIMARY KEY,
+            hour INTEGER NOT NULL
+        )",
         [],
     )?;
+    Ok(())
+}
 
-    Ok(conn)
+/// shown wallpapers for `history::SqliteHistory`, kept like the overrides;
+/// `shown_at` is unknown for lines imported from the flat log
+fn add_history(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS history (
+             id INTEGER PRIMARY KEY AUTOINCREMENT,
+             shown_at INTEGER,
+             path TEXT NOT NULL,
+             monitor TEXT
+         );
+         CREATE INDEX IF NOT EXISTS idx_history_path ON history(path);",
+    )
 }
 
+/// no reparse: hour-only entries stand in for the middle of their hour until
+/// they change or `--warm-cache` fills them in
+fn add_minute_of_day(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute(
+        "ALTER TABLE exif_cache ADD COLUMN minute_of_day INTEGER",
+        [],
+    )?;
+    Ok(())
+}
+
+/// settings the cached values depend on, see `match_exif_utc`. Entries so far
+/// had their offset added to DateTimeOriginal, as `config::exif_utc` does now
+fn add_settings(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS settings (
+             key TEXT PRIMARY KEY,
+             value TEXT NOT NULL
+         );
+         INSERT OR REPLACE INTO settings (key, value) VALUES ('exif_utc', '1');",
+    )
+}
+
+fn add_utc_offset(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute("ALTER TABLE exif_cache ADD COLUMN utc_offset INTEGER", [])?;
+    reparse_all(tx)
+}
+
+fn add_captured_at(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute("ALTER TABLE exif_cache ADD COLUMN captured_at TEXT", [])?;
+    reparse_all(tx)
+}
+
+fn add_captured_on(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute("ALTER TABLE exif_cache ADD COLUMN captured_on TEXT", [])?;
+    reparse_all(tx)
+}
+
+fn add_location(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute_batch(
+        "ALTER TABLE exif_cache ADD COLUMN latitude REAL;
+         ALTER TABLE exif_cache ADD COLUMN longitude REAL;",
+    )?;
+    reparse_all(tx)
+}
+
+fn add_luminance(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute("ALTER TABLE exif_cache ADD COLUMN luminance REAL", [])?;
+    Ok(())
+}
+
+/// dimensions live next to the EXIF data instead of in their own table
+fn move_dimensions(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute_batch(
+        "ALTER TABLE exif_cache ADD COLUMN width INTEGER;
+         ALTER TABLE exif_cache ADD COLUMN height INTEGER;
+         UPDATE exif_cache SET (width, height) = (
+             SELECT width, height FROM dimensions
+             WHERE dimensions.path = exif_cache.path AND dimensions.mtime = exif_cache.mtime
+         );
+         DROP TABLE dimensions;",
+    )
+}
+
+fn add_hash(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute("ALTER TABLE exif_cache ADD COLUMN hash INTEGER", [])?;
+    Ok(())
+}
+
+fn add_size(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute("ALTER TABLE exif_cache ADD COLUMN size INTEGER", [])?;
+    Ok(())
+}
+
+fn add_updated_at(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute("ALTER TABLE exif_cache ADD COLUMN updated_at INTEGER", [])?;
+    Ok(())
+}
+
+/// make every entry look stale so new columns get filled in on the next run, while
+/// keeping the rows themselves
+fn reparse_all(tx: &Transaction) -> Result<(), rusqlite::Error> {
+    tx.execute("UPDATE exif_cache SET mtime = ?1", [REPARSE_MTIME])?;
+    Ok(())
+}
+
+#[deprecated(note = "use `Cache::load_all`")]
 pub fn load_all(conn: &Connection) -> Result<HashMap<String, CachedEntry>, rusqlite::Error> {
-    let mut stmt = conn.prepare("SELECT path, mtime, hour FROM exif_cache")?;
-    let entries = stmt.query_map([], |row| {
+    load_entries(conn)
+}
+
+fn load_entries(conn: &Connection) -> Result<HashMap<String, CachedEntry>, rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM exif_cache", entry_columns()))?;
+    let entries = stmt.query_map([], entry_from_row)?;
+    entries.collect()
+}
+
+/// the entries for these paths, looked up in chunks instead of reading the table
+pub fn load_for_paths(
+    conn: &Connection,
+    paths: &[String],
+) -> Result<HashMap<String, CachedEntry>, rusqlite::Error> {
+    let mut map = HashMap::new();
+    for chunk in paths.chunks(PATHS_PER_STATEMENT) {
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT {} FROM exif_cache WHERE path IN ({})",
+            entry_columns(),
+            vec!["?"; chunk.len()].join(",")
+        ))?;
+        for entry in stmt.query_map(rusqlite::params_from_iter(chunk), entry_from_row)? {
+            let (path, cached) = entry?;
+            map.insert(path, cached);
+        }
+    }
+    Ok(map)
+}
+
+/// just the validity columns of every entry
+pub fn load_keys(conn: &Connection) -> Result<HashMap<String, CacheKey>, rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT path, mtime, size, hash, error IS NOT NULL, failures, luminance IS NOT NULL,
+             hour IS NULL OR minute_of_day IS NOT NULL, updated_at IS NOT {}
+         FROM exif_cache",
+        UNPARSED
+    ))?;
+    let keys = stmt.query_map([], |row| {
         Ok((
-            row.get::<_, String>(0)?,
-            CachedEntry {
+            row.get(0)?,
+            CacheKey {
                 mtime: row.get(1)?,
-                hour: row.get(2)?,
+                size: row.get(2)?,
+                hash: row.get::<_, Option<i64>>(3)?.map(|h| h as u64),
+                failed: row.get(4)?,
+                failures: row.get(5)?,
+                has_luminance: row.get(6)?,
+                has_minute: row.get(7)?,
+                parsed: row.get(8)?,
             },
         ))
     })?;
+    keys.collect()
+}
 
-    let mut map = HashMap::new();
-    for entry in entries {
-        let (path, cached) = entry?;
-        map.insert(path, cached);
-    }
-    Ok(map)
+/// what `entry_from_row` reads; the last is `CachedEntry::parsed`
+fn entry_columns() -> String {
+    format!(
+        "path, mtime, size, hour, month, orientation, rating, keywords, error, failures, hash, \
+         width, height, luminance, captured_on, minute_of_day, captured_at, utc_offset, \
+         latitude, longitude, updated_at IS NOT {}",
+        UNPARSED
+    )
 }
 
-pub fn insert(
-    conn: &Connection,
-    entries: &[(String, i64, Option<u8>)],
-) -> Result<(), rusqlite::Error> {
-    let tx = conn.unchecked_transaction()?;
+fn entry_from_row(row: &rusqlite::Row) -> Result<(String, CachedEntry), rusqlite::Error> {
+    Ok((
+        row.get(0)?,
+        CachedEntry {
+            mtime: row.get(1)?,
+            size: row.get(2)?,
+            hour: row.get(3)?,
+            month: row.get(4)?,
+            orientation: row
+                .get::<_, Option<u16>>(5)?
+                .and_then(ExifOrientation::from_exif),
+            rating: row.get(6)?,
+            keywords: row
+                .get::<_, Option<String>>(7)?
+                .map(|k| k.split(KEYWORD_SEPARATOR).map(String::from).collect())
+                .unwrap_or_default(),
+            error: row.get(8)?,
+            failures: row.get(9)?,
+            // stored as the signed integer SQLite holds
+            hash: row.get::<_, Option<i64>>(10)?.map(|h| h as u64),
+            dimensions: row
+                .get::<_, Option<u32>>(11)?
+                .zip(row.get::<_, Option<u32>>(12)?),
+            luminance: row.get(13)?,
+            captured_on: row
+                .get::<_, Option<String>>(14)?
+                .and_then(|d| NaiveDate::parse_from_str(&d, DATE_FORMAT).ok()),
+            minute_of_day: row.get(15)?,
+            captured_at: row
+                .get::<_, Option<String>>(16)?
+                .and_then(|t| NaiveDateTime::parse_from_str(&t, DATETIME_FORMAT).ok()),
+            utc_offset: row.get(17)?,
+            latitude: row.get(18)?,
+            longitude: row.get(19)?,
+            parsed: row.get(20)?,
+        },
+    ))
+}
+
+#[deprecated(note = "use `Cache::upsert`")]
+#[allow(deprecated)]
+pub fn insert(conn: &Connection, entries: &[NewEntry]) -> Result<(), rusqlite::Error> {
+    let records: Vec<CacheRecord> = entries
+        .iter()
+        .map(
+            |(path, mtime, size, hash, dimensions, info, error)| CacheRecord {
+                path: path.clone(),
+                mtime: *mtime,
+                size: *size,
+                hash: *hash,
+                dimensions: *dimensions,
+                luminance: None,
+                info: info.clone(),
+                error: error.clone(),
+            },
+        )
+        .collect();
+    with_retry(|| upsert_once(conn, &records))
+}
+
+fn upsert_once(conn: &Connection, records: &[CacheRecord]) -> Result<(), rusqlite::Error> {
+    let updated_at = now();
+    let tx = write_transaction(conn)?;
 
     {
         let mut stmt = tx.prepare_cached(
-            "INSERT OR REPLACE INTO exif_cache (path, mtime, hour) VALUES (?1, ?2, ?3)",
+            "INSERT INTO exif_cache
+                 (path, mtime, size, hash, width, height, hour, month, orientation, rating,
+                  keywords, error, failures, updated_at, luminance, latitude, longitude,
+                  captured_on, minute_of_day, captured_at, utc_offset)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?12 IS NOT NULL, ?13,
+                     ?14, ?15, ?16, ?17, ?18, ?19, ?20)
+             ON CONFLICT(path) DO UPDATE SET
+                 hour = excluded.hour,
+                 minute_of_day = excluded.minute_of_day,
+                 month = excluded.month,
+                 captured_on = excluded.captured_on,
+                 captured_at = excluded.captured_at,
+                 utc_offset = excluded.utc_offset,
+                 orientation = excluded.orientation,
+                 rating = excluded.rating,
+                 keywords = excluded.keywords,
+                 error = excluded.error,
+                 failures = CASE
+                     WHEN excluded.error IS NULL THEN 0
+                     WHEN exif_cache.hash = excluded.hash
+                         OR (exif_cache.mtime = excluded.mtime AND exif_cache.size IS excluded.size)
+                         THEN exif_cache.failures + 1
+                     ELSE 1
+                 END,
+                 mtime = excluded.mtime,
+                 size = excluded.size,
+                 hash = excluded.hash,
+                 width = excluded.width,
+                 height = excluded.height,
+                 luminance = excluded.luminance,
+                 latitude = excluded.latitude,
+                 longitude = excluded.longitude,
+                 updated_at = excluded.updated_at",
         )?;
 
-        for (path, mtime, hour) in entries {
-            stmt.execute(params![path, mtime, hour])?;
+        for record in records {
+            let info = &record.info;
+            stmt.execute(params![
+                record.path,
+                record.mtime,
+                record.size,
+                record.hash.map(|h| h as i64),
+                record.dimensions.map(|(w, _)| w),
+                record.dimensions.map(|(_, h)| h),
+                info.hour,
+                info.month,
+                info.orientation.map(ExifOrientation::as_exif),
+                info.rating,
+                (!info.keywords.is_empty())
+                    .then(|| info.keywords.join(&KEYWORD_SEPARATOR.to_string())),
+                record.error,
+                updated_at,
+                record.luminance,
+                info.gps_latitude,
+                info.gps_longitude,
+                info.captured_at
+                    .map(|t| t.date().format(DATE_FORMAT).to_string()),
+                info.minute_of_day,
+                info.captured_at
+                    .map(|t| t.format(DATETIME_FORMAT).to_string()),
+                info.offset.as_deref().and_then(exif::parse_offset_minutes)
+            ])?;
         }
     }
 
@@ -85,31 +989,1026 @@ pub fn insert(
     Ok(())
 }
 
+/// totals over the whole cache, each table read in a single aggregate query
+pub fn stats(conn: &Connection) -> Result<CacheStats, rusqlite::Error> {
+    let (entries, with_hour, failed, last_updated) = conn.query_row(
+        "SELECT COUNT(*), COUNT(hour), COUNT(error), MAX(updated_at) FROM exif_cache",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )?;
+    let thumbnails = conn.query_row("SELECT COUNT(*) FROM thumbnails", [], |row| row.get(0))?;
+    let db_bytes = conn.query_row(
+        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+        [],
+        |row| row.get(0),
+    )?;
+
+    Ok(CacheStats {
+        entries,
+        with_hour,
+        without_hour: entries - with_hour,
+        failed,
+        thumbnails,
+        db_bytes,
+        last_updated,
+    })
+}
+
+/// drop entries for files that no longer exist or that weren't refreshed within
+/// `config::cache_max_age_days`, then shrink the file; paths in `keep` are never
+/// dropped
+pub fn prune(conn: &Connection, keep: &HashSet<String>) -> Result<PruneReport, rusqlite::Error> {
+    let size_before = file_size(conn);
+
+    let paths: Vec<String> = conn
+        .prepare("SELECT path FROM exif_cache UNION SELECT path FROM thumbnails")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    let missing: Vec<String> = paths
+        .into_iter()
+        .filter(|path| !keep.contains(path) && !Path::new(path).exists())
+        .collect();
+
+    // rows holding just dimensions were never parsed, so they have no age
+    let expired: Vec<String> = match config::cache_max_age_days() {
+        Some(days) => conn
+            .prepare("SELECT path FROM exif_cache WHERE updated_at < ?1 AND updated_at IS NOT ?2")?
+            .query_map(
+                params![now() - days as i64 * 24 * 60 * 60, UNPARSED],
+                |row| row.get(0),
+            )?
+            .filter(|path| {
+                path.as_ref()
+                    .map_or(true, |p| !keep.contains(p) && Path::new(p).exists())
+            })
+            .collect::<Result<_, _>>()?,
+        None => Vec::new(),
+    };
+
+    remove(conn, &missing)?;
+    remove(conn, &expired)?;
+
+    // VACUUM rewrites the database into the WAL, the checkpoint moves it back and
+    // truncates the log
+    conn.execute_batch("VACUUM")?;
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+
+    Ok(PruneReport {
+        missing: missing.len(),
+        expired: expired.len(),
+        bytes_reclaimed: size_before.saturating_sub(file_size(conn)),
+    })
+}
+
+/// run a write again with backoff while another process holds the database
+fn with_retry<T>(
+    mut write: impl FnMut() -> Result<T, rusqlite::Error>,
+) -> Result<T, rusqlite::Error> {
+    let mut delay = RETRY_DELAY;
+    for _ in 1..WRITE_ATTEMPTS {
+        match write() {
+            Err(e) if is_locked(&e) => {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    write()
+}
+
+/// takes the write lock up front so `busy_timeout` applies, instead of failing
+/// when a read transaction can't be upgraded
+fn write_transaction(conn: &Connection) -> Result<Transaction<'_>, rusqlite::Error> {
+    Transaction::new_unchecked(conn, TransactionBehavior::Immediate)
+}
+
+/// whether the database file is damaged or isn't a database at all
+pub fn is_corrupt(e: &rusqlite::Error) -> bool {
+    matches!(
+        e.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::DatabaseCorrupt | rusqlite::ErrorCode::NotADatabase)
+    )
+}
+
+/// rename a corrupt database and its WAL to `<name>.corrupt-<timestamp>` so the
+/// next open starts from scratch
+pub fn move_aside(path: &Path) -> io::Result<()> {
+    let suffix = format!(".corrupt-{}", Local::now().format("%Y%m%d-%H%M%S"));
+    let mut moved = PathBuf::from(path);
+    moved.as_mut_os_string().push(&suffix);
+
+    for extension in ["", "-wal", "-shm"] {
+        let mut from = path.as_os_str().to_owned();
+        from.push(extension);
+        let mut to = moved.as_os_str().to_owned();
+        to.push(extension);
+        match fs::rename(&from, &to) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+
+    eprintln!(
+        "Cache database is corrupt, moved it to {} and starting a new one",
+        moved.display()
+    );
+    Ok(())
+}
+
+/// whether an operation failed because another process holds the database
+pub fn is_locked(e: &rusqlite::Error) -> bool {
+    matches!(
+        e.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+    )
+}
+
+fn read_only_error(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_READONLY),
+        Some(message.to_string()),
+    )
+}
+
+/// a `file:` URI opening `path` read-only without locking or a WAL
+fn immutable_uri(path: &Path) -> String {
+    let mut uri = String::from("file:");
+    for c in path.to_string_lossy().chars() {
+        match c {
+            '?' | '#' | '%' => uri.push_str(&format!("%{:02X}", c as u32)),
+            c => uri.push(c),
+        }
+    }
+    uri.push_str("?mode=ro&immutable=1");
+    uri
+}
+
+/// bytes on disk for the database and its write-ahead log
+fn file_size(conn: &Connection) -> u64 {
+    let Some(path) = conn.path() else {
+        return 0;
+    };
+    [path.to_string(), format!("{}-wal", path)]
+        .iter()
+        .filter_map(|p| fs::metadata(p).ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// one line of `export`, with the path relative to the wallpaper directory so it
+/// can be imported on a machine where the directory lives elsewhere
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportedEntry {
+    path: String,
+    mtime: i64,
+    #[serde(default)]
+    size: Option<u64>,
+    #[serde(default)]
+    hash: Option<i64>,
+    #[serde(default)]
+    hour: Option<u8>,
+    #[serde(default)]
+    month: Option<u8>,
+    #[serde(default)]
+    orientation: Option<u16>,
+    #[serde(default)]
+    rating: Option<u8>,
+    #[serde(default)]
+    keywords: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    failures: u32,
+    #[serde(default)]
+    luminance: Option<f32>,
+    #[serde(default)]
+    latitude: Option<f64>,
+    #[serde(default)]
+    longitude: Option<f64>,
+    #[serde(default)]
+    captured_on: Option<String>,
+    #[serde(default)]
+    minute_of_day: Option<u16>,
+    #[serde(default)]
+    captured_at: Option<String>,
+    #[serde(default)]
+    utc_offset: Option<i32>,
+}
+
+/// write every entry under the wallpaper directory as a JSON line, returning how
+/// many were written
+pub fn export(conn: &Connection, mut writer: impl Write) -> Result<usize, Box<dyn Error>> {
+    let root = PathBuf::from(config::wallpaper_dir());
+    let mut stmt = conn.prepare(
+        "SELECT path, mtime, size, hash, hour, month, orientation, rating, keywords, error,
+             failures, luminance, latitude, longitude, captured_on, minute_of_day, captured_at,
+             utc_offset
+         FROM exif_cache",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(ExportedEntry {
+            path: row.get(0)?,
+            mtime: row.get(1)?,
+            size: row.get(2)?,
+            hash: row.get(3)?,
+            hour: row.get(4)?,
+            month: row.get(5)?,
+            orientation: row.get(6)?,
+            rating: row.get(7)?,
+            keywords: row.get(8)?,
+            error: row.get(9)?,
+            failures: row.get(10)?,
+            luminance: row.get(11)?,
+            latitude: row.get(12)?,
+            longitude: row.get(13)?,
+            captured_on: row.get(14)?,
+            minute_of_day: row.get(15)?,
+            captured_at: row.get(16)?,
+            utc_offset: row.get(17)?,
+        })
+    })?;
+
+    let mut written = 0;
+    for row in rows {
+        let mut entry = row?;
+        let Ok(relative) = Path::new(&entry.path).strip_prefix(&root) else {
+            continue;
+        };
+        entry.path = relative.to_string_lossy().into_owned();
+        serde_json::to_writer(&mut writer, &entry)?;
+        writeln!(writer)?;
+        written += 1;
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+/// read lines written by `export`, keeping those whose file exists under the
+/// wallpaper directory with the same mtime and size; returns (imported, skipped)
+pub fn import(conn: &Connection, reader: impl BufRead) -> Result<(usize, usize), Box<dyn Error>> {
+    let root = PathBuf::from(config::wallpaper_dir());
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut entry: ExportedEntry = serde_json::from_str(&line)?;
+        let path = root.join(&entry.path);
+        let matches = ImageFile::from_path(&path).is_ok_and(|img| {
+            img.cache_mtime() == entry.mtime && entry.size.is_none_or(|s| s == img.size)
+        });
+        if !matches {
+            skipped += 1;
+            continue;
+        }
+        entry.path = path.to_string_lossy().into_owned();
+        entries.push(entry);
+    }
+
+    with_retry(|| import_once(conn, &entries))?;
+    Ok((entries.len(), skipped))
+}
+
+fn import_once(conn: &Connection, entries: &[ExportedEntry]) -> Result<(), rusqlite::Error> {
+    let updated_at = now();
+    let tx = write_transaction(conn)?;
+    {
+        let mut stmt = tx.prepare_cached(
+            "INSERT OR REPLACE INTO exif_cache
+                 (path, mtime, size, hash, hour, month, orientation, rating, keywords, error,
+                  failures, updated_at, luminance, latitude, longitude, captured_on,
+                  minute_of_day, captured_at, utc_offset)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16,
+                     ?17, ?18, ?19)",
+        )?;
+        for e in entries {
+            stmt.execute(params![
+                e.path,
+                e.mtime,
+                e.size,
+                e.hash,
+                e.hour,
+                e.month,
+                e.orientation,
+                e.rating,
+                e.keywords,
+                e.error,
+                e.failures,
+                updated_at,
+                e.luminance,
+                e.latitude,
+                e.longitude,
+                e.captured_on,
+                e.minute_of_day,
+                e.captured_at,
+                e.utc_offset
+            ])?;
+        }
+    }
+    tx.commit()
+}
+
+#[deprecated(note = "use `Cache::cleanup_stale`")]
 pub fn cleanup_stale(
     conn: &Connection,
     current_paths: &HashSet<String>,
     cache: &HashMap<String, CachedEntry>,
-) -> Result<(), rusqlite::Error> {
-    let stale_paths: Vec<&String> = cache
-        .keys()
-        .filter(|path| !current_paths.contains(*path))
+) -> Result<usize, rusqlite::Error> {
+    remove_stale(conn, current_paths, cache.keys())
+}
+
+/// remove entries for paths that are no longer in the wallpaper directory,
+/// returning how many were removed; entries outside the directory belong to
+/// other collections and are left alone
+fn remove_stale<'a>(
+    conn: &Connection,
+    current_paths: &HashSet<String>,
+    cached_paths: impl Iterator<Item = &'a String>,
+) -> Result<usize, rusqlite::Error> {
+    let root = PathBuf::from(config::wallpaper_dir());
+    let stale_paths: Vec<&str> = cached_paths
+        .filter(|path| !current_paths.contains(*path) && Path::new(path).starts_with(&root))
+        .map(String::as_str)
         .collect();
 
     if stale_paths.is_empty() {
-        return Ok(());
+        return Ok(0);
     }
+    with_retry(|| remove_once(conn, &stale_paths))
+}
 
-    println!("Removing {} stale cache entries", stale_paths.len());
+/// remove the entries for these paths from every table, returning how many EXIF
+/// entries were removed
+pub fn remove(conn: &Connection, paths: &[String]) -> Result<usize, rusqlite::Error> {
+    let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+    with_retry(|| remove_once(conn, &paths))
+}
 
-    let tx = conn.unchecked_transaction()?;
+fn remove_once(conn: &Connection, paths: &[&str]) -> Result<usize, rusqlite::Error> {
+    let tx = write_transaction(conn)?;
+    let mut removed = 0;
+
+    for chunk in paths.chunks(PATHS_PER_STATEMENT) {
+        let placeholders = vec!["?"; chunk.len()].join(",");
+        for table in ["exif_cache", "thumbnails"] {
+            let count = tx
+                .prepare_cached(&format!(
+                    "DELETE FROM {} WHERE path IN ({})",
+                    table, placeholders
+                ))?
+                .execute(rusqlite::params_from_iter(chunk))?;
+            if table == "exif_cache" {
+                removed += count;
+            }
+        }
+    }
+
+    tx.commit()?;
+    Ok(removed)
+}
+
+/// cached (mtime, dimensions) per path; `None` dimensions mean the header could not be read
+pub fn load_dimensions(
+    conn: &Connection,
+) -> Result<HashMap<String, (i64, Option<Dimensions>)>, rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT path, mtime, width, height FROM exif_cache")?;
+    let entries = stmt.query_map([], |row| {
+        let width: Option<u32> = row.get(2)?;
+        let height: Option<u32> = row.get(3)?;
+        Ok((
+            row.get::<_, String>(0)?,
+            (row.get::<_, i64>(1)?, width.zip(height)),
+        ))
+    })?;
+
+    let mut map = HashMap::new();
+    for entry in entries {
+        let (path, dims) = entry?;
+        map.insert(path, dims);
+    }
+    Ok(map)
+}
+
+/// dimensions cached for this version of the file, `Some(None)` when its header
+/// couldn't be read and `None` when there is no entry
+pub fn get_dimensions(
+    conn: &Connection,
+    path: &str,
+    mtime: i64,
+) -> Result<Option<Option<Dimensions>>, rusqlite::Error> {
+    let mut stmt =
+        conn.prepare_cached("SELECT width, height FROM exif_cache WHERE path = ?1 AND mtime = ?2")?;
+    let mut rows = stmt.query(params![path, mtime])?;
+    match rows.next()? {
+        Some(row) => {
+            let width: Option<u32> = row.get(0)?;
+            let height: Option<u32> = row.get(1)?;
+            Ok(Some(width.zip(height)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// record probed dimensions on existing entries for the same version of the file;
+/// files without an entry get one holding just them, which the next run parses
+/// like a new file
+fn insert_dimensions(
+    conn: &Connection,
+    entries: &[(String, i64, Option<Dimensions>)],
+) -> Result<(), rusqlite::Error> {
+    let tx = write_transaction(conn)?;
 
     {
-        let mut stmt = tx.prepare_cached("DELETE FROM exif_cache WHERE path = ?1")?;
-        for path in &stale_paths {
-            stmt.execute([path])?;
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO exif_cache (path, mtime, width, height, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(path) DO UPDATE SET
+                 mtime = excluded.mtime,
+                 width = excluded.width,
+                 height = excluded.height
+             WHERE exif_cache.mtime = excluded.mtime OR exif_cache.updated_at IS ?5",
+        )?;
+
+        for (path, mtime, dims) in entries {
+            stmt.execute(params![
+                path,
+                mtime,
+                dims.map(|(w, _)| w),
+                dims.map(|(_, h)| h),
+                UNPARSED
+            ])?;
         }
     }
 
     tx.commit()?;
     Ok(())
 }
+
+/// treat the file at `key`, a full path or a basename, as taken at `hour`
+pub fn set_override(conn: &Connection, key: &str, hour: u8) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "INSERT OR REPLACE INTO overrides (key, hour) VALUES (?1, ?2)",
+        params![key, hour],
+    )?;
+    Ok(())
+}
+
+/// whether there was an override to remove
+pub fn remove_override(conn: &Connection, key: &str) -> Result<bool, rusqlite::Error> {
+    Ok(conn.execute("DELETE FROM overrides WHERE key = ?1", [key])? > 0)
+}
+
+pub fn get_overrides(conn: &Connection) -> Result<HashMap<String, u8>, rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT key, hour FROM overrides")?;
+    let overrides = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    overrides.collect()
+}
+
+/// the hour from `overrides` for this file, by full path or else by basename
+pub fn lookup_override(overrides: &HashMap<String, u8>, path: &Path) -> Option<u8> {
+    overrides
+        .get(path.to_string_lossy().as_ref())
+        .or_else(|| overrides.get(path.file_name()?.to_str()?))
+        .copied()
+}
+
+/// the JPEG thumbnail stored for this version of the file, marking it as recently used
+pub fn get_thumbnail(
+    conn: &Connection,
+    path: &str,
+    mtime: i64,
+) -> Result<Option<Vec<u8>>, rusqlite::Error> {
+    read_thumbnail(conn, path, mtime, true)
+}
+
+fn read_thumbnail(
+    conn: &Connection,
+    path: &str,
+    mtime: i64,
+    touch: bool,
+) -> Result<Option<Vec<u8>>, rusqlite::Error> {
+    let data: Option<Vec<u8>> = conn
+        .query_row(
+            "SELECT data FROM thumbnails WHERE path = ?1 AND mtime = ?2",
+            params![path, mtime],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    if data.is_some() && touch {
+        conn.execute(
+            "UPDATE thumbnails SET last_used = ?2 WHERE path = ?1",
+            params![path, now()],
+        )?;
+    }
+    Ok(data)
+}
+
+/// store a thumbnail, then evict the least recently used others past the budget
+pub fn put_thumbnail(
+    conn: &Connection,
+    path: &str,
+    mtime: i64,
+    data: &[u8],
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "INSERT OR REPLACE INTO thumbnails (path, mtime, data, last_used)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![path, mtime, data, now()],
+    )?;
+    prune_thumbnails(conn, config::thumbnail_budget(), Some(path))?;
+    Ok(())
+}
+
+/// drop least recently used thumbnails until they fit in `budget` bytes, never
+/// dropping `keep`; returns how many were removed
+pub fn prune_thumbnails(
+    conn: &Connection,
+    budget: u64,
+    keep: Option<&str>,
+) -> Result<usize, rusqlite::Error> {
+    // sizes are read under the write lock, so another process storing a
+    // thumbnail meanwhile can't push the total past the budget
+    let tx = write_transaction(conn)?;
+    let mut total = 0;
+    let mut evict = Vec::new();
+    {
+        let mut stmt =
+            tx.prepare("SELECT path, length(data) FROM thumbnails ORDER BY last_used DESC")?;
+        let sizes = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+        })?;
+        for entry in sizes {
+            let (path, size) = entry?;
+            if keep == Some(path.as_str()) {
+                total += size;
+            } else if total + size > budget {
+                evict.push(path);
+            } else {
+                total += size;
+            }
+        }
+    }
+
+    {
+        let mut stmt = tx.prepare_cached("DELETE FROM thumbnails WHERE path = ?1")?;
+        for path in &evict {
+            stmt.execute([path])?;
+        }
+    }
+    tx.commit()?;
+    Ok(evict.len())
+}
+
+/// a JPEG no larger than `THUMBNAIL_EDGE` on its long side
+pub fn encode_thumbnail(image: &DynamicImage) -> Option<Vec<u8>> {
+    let thumbnail = image.thumbnail(THUMBNAIL_EDGE, THUMBNAIL_EDGE).to_rgb8();
+    let mut data = Vec::new();
+    thumbnail
+        .write_to(&mut Cursor::new(&mut data), ImageFormat::Jpeg)
+        .ok()?;
+    Some(data)
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a cache as a build that knew only the first `version` migrations left it
+    fn at_version(version: usize) -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        for step in 0..version {
+            run_step(&mut conn, step);
+        }
+        conn
+    }
+
+    fn run_step(conn: &mut Connection, step: usize) {
+        let tx = conn.transaction().unwrap();
+        MIGRATIONS[step](&tx).unwrap();
+        tx.pragma_update(None, "user_version", step as i32 + 1)
+            .unwrap();
+        tx.commit().unwrap();
+    }
+
+    fn user_version(conn: &Connection) -> i32 {
+        conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    fn version_of(migration: Migration) -> usize {
+        MIGRATIONS
+            .iter()
+            .position(|step| *step as usize == migration as usize)
+            .unwrap()
+    }
+
+    /// path, mtime and hour are the columns every version has
+    fn insert_photo(conn: &Connection, path: &str, mtime: i64, hour: u8) {
+        conn.execute(
+            "INSERT INTO exif_cache (path, mtime, hour) VALUES (?1, ?2, ?3)",
+            params![path, mtime, hour],
+        )
+        .unwrap();
+    }
+
+    fn photo(conn: &Connection, path: &str) -> Option<(i64, u8)> {
+        conn.query_row(
+            "SELECT mtime, hour FROM exif_cache WHERE path = ?1",
+            [path],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .unwrap()
+    }
+
+    #[test]
+    fn every_migration_keeps_the_rows_before_it() {
+        for step in 1..MIGRATIONS.len() {
+            let mut conn = at_version(step);
+            insert_photo(&conn, "/photos/a.jpg", 1_700_000_000, 7);
+
+            run_step(&mut conn, step);
+
+            let (mtime, hour) = photo(&conn, "/photos/a.jpg")
+                .unwrap_or_else(|| panic!("migration {} dropped the row", step));
+            assert_eq!(hour, 7, "migration {}", step);
+            assert!(
+                mtime == 1_700_000_000 || mtime == REPARSE_MTIME,
+                "migration {} left mtime {}",
+                step,
+                mtime
+            );
+            assert_eq!(user_version(&conn), step as i32 + 1);
+        }
+    }
+
+    #[test]
+    fn create_tables_keeps_a_cache_from_before_versioning() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE exif_cache (
+                 path TEXT PRIMARY KEY,
+                 mtime INTEGER NOT NULL,
+                 hour INTEGER
+             );",
+        )
+        .unwrap();
+        insert_photo(&conn, "/photos/a.jpg", 1_700_000_000, 7);
+
+        run_step(&mut conn, 0);
+
+        assert_eq!(photo(&conn, "/photos/a.jpg"), Some((REPARSE_MTIME, 7)));
+    }
+
+    #[test]
+    fn move_dimensions_carries_only_matching_mtimes() {
+        let step = version_of(move_dimensions);
+        let mut conn = at_version(step);
+        insert_photo(&conn, "/photos/same.jpg", 100, 7);
+        insert_photo(&conn, "/photos/changed.jpg", 200, 8);
+        conn.execute_batch(
+            "INSERT INTO dimensions (path, mtime, width, height)
+                 VALUES ('/photos/same.jpg', 100, 4000, 3000);
+             INSERT INTO dimensions (path, mtime, width, height)
+                 VALUES ('/photos/changed.jpg', 150, 1920, 1080);",
+        )
+        .unwrap();
+
+        run_step(&mut conn, step);
+
+        let size = |path: &str| -> (Option<u32>, Option<u32>) {
+            conn.query_row(
+                "SELECT width, height FROM exif_cache WHERE path = ?1",
+                [path],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap()
+        };
+        assert_eq!(size("/photos/same.jpg"), (Some(4000), Some(3000)));
+        assert_eq!(size("/photos/changed.jpg"), (None, None));
+        let dimensions: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE name = 'dimensions'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(dimensions, 0);
+    }
+
+    #[test]
+    fn add_month_adds_an_empty_column_and_reparses() {
+        let step = version_of(add_month);
+        let mut conn = at_version(step);
+        insert_photo(&conn, "/photos/a.jpg", 100, 7);
+
+        run_step(&mut conn, step);
+
+        let month: Option<u8> = conn
+            .query_row(
+                "SELECT month FROM exif_cache WHERE path = '/photos/a.jpg'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(month, None);
+        // the month is only known once the file is parsed again
+        assert_eq!(photo(&conn, "/photos/a.jpg"), Some((REPARSE_MTIME, 7)));
+    }
+
+    #[test]
+    fn add_failures_starts_existing_rows_at_zero() {
+        let step = version_of(add_failures);
+        let mut conn = at_version(step);
+        insert_photo(&conn, "/photos/a.jpg", 100, 7);
+
+        run_step(&mut conn, step);
+
+        let failures: i64 = conn
+            .query_row(
+                "SELECT failures FROM exif_cache WHERE path = '/photos/a.jpg'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(failures, 0);
+    }
+
+    #[test]
+    fn add_settings_records_the_offset_already_applied() {
+        let step = version_of(add_settings);
+        let mut conn = at_version(step);
+
+        run_step(&mut conn, step);
+
+        let exif_utc: String = conn
+            .query_row(
+                "SELECT value FROM settings WHERE key = 'exif_utc'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(exif_utc, "1");
+    }
+
+    #[test]
+    fn migrate_reaches_the_latest_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        assert_eq!(user_version(&conn), MIGRATIONS.len() as i32);
+
+        // nothing left to run the second time
+        migrate(&mut conn).unwrap();
+        assert_eq!(user_version(&conn), MIGRATIONS.len() as i32);
+    }
+
+    #[test]
+    fn migrate_rebuilds_a_newer_cache_but_keeps_overrides() {
+        let mut conn = at_version(MIGRATIONS.len());
+        insert_photo(&conn, "/photos/a.jpg", 100, 7);
+        set_override(&conn, "a.jpg", 9).unwrap();
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() as i32 + 3)
+            .unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        assert_eq!(user_version(&conn), MIGRATIONS.len() as i32);
+        assert_eq!(photo(&conn, "/photos/a.jpg"), None);
+        let hour: u8 = conn
+            .query_row(
+                "SELECT hour FROM overrides WHERE key = 'a.jpg'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(hour, 9);
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "wallpaper_slideshow_cache_{}_{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn dimension_only_rows_are_not_pruned_for_age() {
+        let dir = temp_dir("prune_age");
+        let probed = dir.join("probed.jpg").to_string_lossy().into_owned();
+        let parsed = dir.join("parsed.jpg").to_string_lossy().into_owned();
+        fs::write(&probed, b"").unwrap();
+        fs::write(&parsed, b"").unwrap();
+
+        let conn = at_version(MIGRATIONS.len());
+        insert_dimensions(&conn, &[(probed.clone(), 100, Some((4000, 3000)))]).unwrap();
+        // parsed long ago
+        conn.execute(
+            "INSERT INTO exif_cache (path, mtime, hour, updated_at) VALUES (?1, 100, 7, 1)",
+            [&parsed],
+        )
+        .unwrap();
+
+        std::env::set_var("WALLPAPER_CACHE_MAX_AGE_DAYS", "30");
+        let report = prune(&conn, &HashSet::new()).unwrap();
+        assert_eq!((report.missing, report.expired), (0, 1));
+        let keys = load_keys(&conn).unwrap();
+        assert!(!keys[&probed].parsed);
+        assert!(!keys.contains_key(&parsed));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    fn record(path: &str, mtime: i64) -> CacheRecord {
+        CacheRecord {
+            path: path.to_string(),
+            mtime,
+            size: 1,
+            hash: None,
+            dimensions: None,
+            luminance: None,
+            info: ExifInfo::default(),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn two_writers_on_one_database_both_commit() {
+        let dir = temp_dir("two_writers");
+        let db = dir.join("cache.db");
+        drop(Cache::open(&db).unwrap());
+
+        let start = std::sync::Arc::new(std::sync::Barrier::new(2));
+        let writers: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|name| {
+                let (db, start) = (db.clone(), start.clone());
+                thread::spawn(move || {
+                    let cache = Cache::open(&db).unwrap();
+                    start.wait();
+                    (0..50)
+                        .map(|batch| {
+                            let records: Vec<_> = (0..20)
+                                .map(|i| {
+                                    record(&format!("/photos/{}/{}-{}.jpg", name, batch, i), 100)
+                                })
+                                .collect();
+                            cache.upsert(&records)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        for writer in writers {
+            for result in writer.join().unwrap() {
+                assert!(result.is_ok(), "{:?}", result);
+            }
+        }
+        assert_eq!(Cache::open(&db).unwrap().load_keys().unwrap().len(), 2000);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_cache_in_a_read_only_directory_opens_read_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("read_only_dir");
+        let db = dir.join("cache.db");
+        Cache::open(&db)
+            .unwrap()
+            .upsert(&[record("/photos/a.jpg", 100)])
+            .unwrap();
+        let set_mode = |path: &Path, mode| {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap()
+        };
+        set_mode(&db, 0o444);
+        set_mode(&dir, 0o555);
+
+        // root ignores the permissions, so `open` only falls back for other users;
+        // the fallback itself is checked either way
+        let writable = fs::write(dir.join("probe"), b"").is_ok();
+        let opened = Cache::open(&db).map(|cache| cache.is_read_only());
+        let cache = Cache::open_read_only(&db);
+        set_mode(&dir, 0o755);
+        set_mode(&db, 0o644);
+
+        if !writable {
+            assert_eq!(opened.ok(), Some(true));
+        }
+        let cache = cache.unwrap();
+        assert!(cache.is_read_only());
+        assert!(cache.load_keys().unwrap().contains_key("/photos/a.jpg"));
+
+        // skipped quietly or refused, but never written
+        cache.upsert(&[record("/photos/b.jpg", 100)]).unwrap();
+        assert!(cache.set_override("a.jpg", 7).is_err());
+        assert!(cache.prune(&HashSet::new()).is_err());
+        assert!(cache
+            .connection()
+            .execute("DELETE FROM exif_cache", [])
+            .is_err());
+        let paths: Vec<String> = cache.load_keys().unwrap().into_keys().collect();
+        assert_eq!(paths, ["/photos/a.jpg"]);
+
+        drop(cache);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_corrupt_database_is_moved_aside_and_recreated() {
+        let dir = temp_dir("corrupt");
+        let db = dir.join("cache.db");
+        let garbage = b"this was never a SQLite database".repeat(200);
+        fs::write(&db, &garbage).unwrap();
+
+        let cache = Cache::open(&db).unwrap();
+        assert!(!cache.is_read_only());
+        cache.upsert(&[record("/photos/a.jpg", 100)]).unwrap();
+        assert_eq!(cache.load_keys().unwrap().len(), 1);
+        drop(cache);
+
+        let moved: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.to_string_lossy().contains("cache.db.corrupt-"))
+            .collect();
+        assert_eq!(moved.len(), 1, "{:?}", moved);
+        assert_eq!(fs::read(&moved[0]).unwrap(), garbage);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn freshness_compares_mtime_and_size_or_else_the_hash() {
+        let key = |mtime, size, hash| CacheKey {
+            mtime,
+            size,
+            hash,
+            failed: false,
+            failures: 0,
+            has_luminance: false,
+            has_minute: true,
+            parsed: true,
+        };
+        let file = |mtime, size, hash| ImageFile {
+            hash,
+            ..ImageFile::new(PathBuf::from("/photos/a.jpg"), mtime, size)
+        };
+
+        assert!(key(100, Some(2048), None).is_fresh(&file(100, 2048, None)));
+        // replaced by a file of another size within the same second
+        assert!(!key(100, Some(2048), None).is_fresh(&file(100, 4096, None)));
+        // touched or rewritten to the same length
+        assert!(!key(100, Some(2048), None).is_fresh(&file(200, 2048, None)));
+        // cached before sizes were stored
+        assert!(key(100, None, None).is_fresh(&file(100, 4096, None)));
+
+        // with hashes on both sides only the content counts
+        assert!(key(100, Some(2048), Some(7)).is_fresh(&file(200, 4096, Some(7))));
+        assert!(!key(100, Some(2048), Some(7)).is_fresh(&file(100, 2048, Some(8))));
+        // hashing switched on or off since: back to mtime and size
+        assert!(key(100, Some(2048), None).is_fresh(&file(100, 2048, Some(7))));
+        assert!(!key(100, Some(2048), Some(7)).is_fresh(&file(200, 2048, None)));
+
+        // `reparse_all` wins over a matching hash
+        assert!(!key(REPARSE_MTIME, Some(2048), Some(7)).is_fresh(&file(200, 2048, Some(7))));
+    }
+
+    #[test]
+    fn a_read_only_cache_keeps_probed_dimensions_to_itself() {
+        let mut cache = Cache {
+            conn: at_version(MIGRATIONS.len()),
+            read_only: true,
+        };
+        cache
+            .insert_dimensions(&[("/photos/a.jpg".to_string(), 100, Some((4000, 3000)))])
+            .unwrap();
+        assert_eq!(cache.get_dimensions("/photos/a.jpg", 100).unwrap(), None);
+
+        cache.read_only = false;
+        cache
+            .insert_dimensions(&[("/photos/a.jpg".to_string(), 100, Some((4000, 3000)))])
+            .unwrap();
+        assert_eq!(
+            cache.get_dimensions("/photos/a.jpg", 100).unwrap(),
+            Some(Some((4000, 3000)))
+        );
+    }
+}
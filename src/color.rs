@@ -18,6 +18,18 @@ impl Rgb {
         format!("\x1b[48;2;{};{};{}m", self.r, self.g, self.b)
     }
 
+    /// like "#1a2b3c"
+    pub fn hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+
+    /// how far apart two colours are, summed over the channels
+    fn distance(&self, other: &Rgb) -> i32 {
+        (self.r as i32 - other.r as i32).abs()
+            + (self.g as i32 - other.g as i32).abs()
+            + (self.b as i32 - other.b as i32).abs()
+    }
+
     fn luminance(&self) -> f64 {
         0.299 * self.r as f64 / 255.0
             + 0.587 * self.g as f64 / 255.0
@@ -101,7 +113,8 @@ impl Default for ColorPalette {
     }
 }
 
-pub fn extract_palette(image: &image::DynamicImage) -> ColorPalette {
+/// the image's colours rounded to steps of 16, most common first
+fn common_colors(image: &image::DynamicImage) -> Vec<((u8, u8, u8), u32)> {
     let small = image.resize(64, 64, image::imageops::FilterType::Nearest);
     let rgb_image = small.to_rgb8();
 
@@ -112,7 +125,12 @@ pub fn extract_palette(image: &image::DynamicImage) -> ColorPalette {
     }
 
     let mut colors: Vec<((u8, u8, u8), u32)> = color_counts.into_iter().collect();
-    colors.sort_by(|a, b| b.1.cmp(&a.1));
+    colors.sort_by_key(|c| std::cmp::Reverse(c.1));
+    colors
+}
+
+pub fn extract_palette(image: &image::DynamicImage) -> ColorPalette {
+    let colors = common_colors(image);
 
     let accent = colors
         .iter()
@@ -147,10 +165,7 @@ pub fn extract_palette(image: &image::DynamicImage) -> ColorPalette {
                 g: *g,
                 b: *b,
             };
-            let diff = (accent.r as i32 - rgb.r as i32).abs()
-                + (accent.g as i32 - rgb.g as i32).abs()
-                + (accent.b as i32 - rgb.b as i32).abs();
-            if diff > 100 && rgb.saturation() > 0.2 && rgb.luminance() > 0.15 {
+            if accent.distance(&rgb) > 100 && rgb.saturation() > 0.2 && rgb.luminance() > 0.15 {
                 Some(rgb)
             } else {
                 None
@@ -208,3 +223,39 @@ pub fn extract_palette(image: &image::DynamicImage) -> ColorPalette {
         },
     }
 }
+
+/// the 16 terminal colours the way pywal lays them out: the background, six
+/// of the image's colours that are neither too dark nor too light, and the
+/// foreground, then the same eight brightened
+pub fn terminal_colors(image: &image::DynamicImage, palette: &ColorPalette) -> [Rgb; 16] {
+    let mut picked: Vec<Rgb> = Vec::new();
+    for ((r, g, b), _) in common_colors(image) {
+        let rgb = Rgb { r, g, b };
+        let lum = rgb.luminance();
+        if lum > 0.15 && lum < 0.85 && picked.iter().all(|p| p.distance(&rgb) > 48) {
+            picked.push(rgb);
+            if picked.len() == 6 {
+                break;
+            }
+        }
+    }
+    // a nearly single coloured image: shades of the accent and secondary
+    let mut shade = 0.0;
+    while picked.len() < 6 {
+        shade += 0.15;
+        picked.push(palette.accent.lighten(shade));
+        if picked.len() < 6 {
+            picked.push(palette.secondary.darken(shade));
+        }
+    }
+
+    let mut colors = [palette.background; 16];
+    colors[1..7].copy_from_slice(&picked);
+    colors[7] = palette.text.darken(0.1);
+    colors[8] = palette.background.lighten(0.25);
+    for i in 9..15 {
+        colors[i] = colors[i - 8].lighten(0.2);
+    }
+    colors[15] = palette.text;
+    colors
+}
@@ -1,10 +1,46 @@
 use std::env;
+use std::time::Duration;
+
+use crate::exif::{GpsFormat, Hemisphere, SeasonMode};
+use crate::history::HistoryBackend;
+use crate::selection::{self, ScoreWeights, SelectionMode, Strategy, Tiebreak};
 
 pub const DEFAULT_WALLPAPER_DIR: &str =
     "/home/simon/dotfiles/wallpaper_slideshow/wallpapers/norway";
 pub const DEFAULT_HISTORY_LOG: &str = "/home/simon/.cache/wallpaper_history.log";
 pub const DEFAULT_CACHE_DB: &str = "/home/simon/.cache/wallpaper_exif_cache.db";
+pub const DEFAULT_BLOCKLIST: &str = "/home/simon/.config/wallpaper_slideshow/blocklist";
+pub const DEFAULT_FAVORITES: &str = "/home/simon/.config/wallpaper_slideshow/favorites";
+pub const DEFAULT_SCHEDULE: &str = "/home/simon/.config/wallpaper_slideshow/schedule";
+pub const DEFAULT_POST_HOOKS: &str = "/home/simon/.config/wallpaper_slideshow/post_hooks";
+pub const DEFAULT_STATE_DIR: &str = "/home/simon/.cache/wallpaper_slideshow";
+pub const DEFAULT_THEME_TEMPLATES: &str = "/home/simon/.config/wallpaper_slideshow/templates";
+pub const DEFAULT_BACKEND: &str = "auto";
+pub const DEFAULT_HYPRPAPER_UNLOAD_ALL_EVERY: u32 = 50;
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+pub const DEFAULT_APPLY_RETRY: Duration = Duration::from_secs(30);
+pub const DEFAULT_FAVORITE_WEIGHT: f64 = 3.0;
 pub const HISTORY_SIZE: usize = 25;
+pub const DEFAULT_SELECTION_TAU: f64 = 1.5;
+pub const DEFAULT_MAX_WINDOW: i32 = 3;
+pub const DEFAULT_SCORE_WEIGHTS: ScoreWeights = ScoreWeights {
+    time: 1.0,
+    season: 0.5,
+    favorite: 0.5,
+    unseen: 0.25,
+    recent: 1.0,
+};
+pub const DEFAULT_SCORE_TOP_K: usize = 5;
+pub const DEFAULT_WINDOW_MINUTES: i32 = 60;
+pub const DEFAULT_ASPECT_TOLERANCE: f64 = 0.25;
+pub const DEFAULT_PLACE_SIZE_KM: f64 = 25.0;
+pub const DEFAULT_ANNIVERSARY_DAYS: u32 = 3;
+pub const DEFAULT_BURST_SECONDS: i64 = 10;
+pub const DEFAULT_DAEMON_INTERVAL: Duration = Duration::from_secs(20 * 60);
+pub const DEFAULT_HISTORY_MAX_LINES: usize = 5000;
+pub const DEFAULT_THUMBNAIL_BUDGET_MB: u64 = 64;
+pub const DEFAULT_NIGHT_MAX_LUMINANCE: f32 = 0.35;
+pub const DEFAULT_DAY_MIN_LUMINANCE: f32 = 0.15;
 
 pub fn wallpaper_dir() -> String {
     env::var("WALLPAPER_DIR").unwrap_or_else(|_| DEFAULT_WALLPAPER_DIR.to_string())
@@ -14,6 +50,507 @@ pub fn history_log() -> String {
     env::var("WALLPAPER_HISTORY_LOG").unwrap_or_else(|_| DEFAULT_HISTORY_LOG.to_string())
 }
 
+/// where the current wallpaper is recorded for other tools, see `state`
+pub fn state_dir() -> String {
+    env::var("XDG_CACHE_HOME")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .or_else(|| env::var("HOME").ok().map(|home| format!("{}/.cache", home)))
+        .map_or_else(
+            || DEFAULT_STATE_DIR.to_string(),
+            |cache| format!("{}/wallpaper_slideshow", cache),
+        )
+}
+
+/// write the applied wallpaper's colours out for theming, see `theming`
+pub fn theming() -> bool {
+    env_flag("WALLPAPER_THEMING", false)
+}
+
+/// where colors.json, colors.sh and relative template outputs are written
+pub fn theme_dir() -> String {
+    env::var("WALLPAPER_THEME_DIR").unwrap_or_else(|_| state_dir())
+}
+
+/// a file of `template = output` lines, see `theming::templates`
+pub fn theme_templates() -> String {
+    env::var("WALLPAPER_THEME_TEMPLATES").unwrap_or_else(|_| DEFAULT_THEME_TEMPLATES.to_string())
+}
+
+/// the history log is trimmed to roughly this many of its newest lines
+pub fn history_max_lines() -> usize {
+    env::var("WALLPAPER_HISTORY_MAX_LINES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_HISTORY_MAX_LINES)
+        .max(HISTORY_SIZE)
+}
+
+/// wallpapers never to select, see `history::PathList`
+pub fn blocklist() -> String {
+    env::var("WALLPAPER_BLOCKLIST").unwrap_or_else(|_| DEFAULT_BLOCKLIST.to_string())
+}
+
+/// wallpapers picked more often, in the same format as the blocklist
+pub fn favorites() -> String {
+    env::var("WALLPAPER_FAVORITES").unwrap_or_else(|_| DEFAULT_FAVORITES.to_string())
+}
+
+/// pick from one place a day, see `places`
+pub fn place_of_the_day() -> bool {
+    env_flag("WALLPAPER_PLACE_OF_THE_DAY", false)
+}
+
+/// how close photos have to be to count as one place, in km
+pub fn place_size_km() -> f64 {
+    env::var("WALLPAPER_PLACE_SIZE_KM")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|k: &f64| k.is_finite() && *k > 0.0)
+        .unwrap_or(DEFAULT_PLACE_SIZE_KM)
+}
+
+/// prefer photos taken on this day in earlier years, see `anniversary_days`
+pub fn anniversary() -> bool {
+    env_flag("WALLPAPER_ANNIVERSARY", false)
+}
+
+/// how many days either side of today's date still count as an anniversary
+/// when nothing was taken on the day itself
+pub fn anniversary_days() -> u32 {
+    env::var("WALLPAPER_ANNIVERSARY_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_ANNIVERSARY_DAYS)
+}
+
+/// skip images shaped very differently from the monitor, see `aspect_tolerance`
+pub fn aspect_filter() -> bool {
+    env_flag("WALLPAPER_ASPECT_FILTER", false)
+}
+
+/// how far an image's aspect ratio may be from the monitor's, as a fraction
+pub fn aspect_tolerance() -> f64 {
+    env::var("WALLPAPER_ASPECT_TOLERANCE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|t: &f64| t.is_finite() && *t >= 0.0)
+        .unwrap_or(DEFAULT_ASPECT_TOLERANCE)
+}
+
+/// a file of shell commands to run after each wallpaper change, see `hooks`
+pub fn post_hooks() -> String {
+    env::var("WALLPAPER_POST_HOOKS").unwrap_or_else(|_| DEFAULT_POST_HOOKS.to_string())
+}
+
+/// a file of wallpapers to show in order instead of selecting by time
+pub fn playlist() -> Option<String> {
+    env::var("WALLPAPER_PLAYLIST")
+        .ok()
+        .filter(|v| !v.is_empty())
+}
+
+/// which wallpapers to pick from on which days of the week
+pub fn schedule() -> String {
+    env::var("WALLPAPER_SCHEDULE").unwrap_or_else(|_| DEFAULT_SCHEDULE.to_string())
+}
+
+/// preload each wallpaper into hyprpaper and unload the one it replaces
+pub fn hyprpaper_preload() -> bool {
+    env_flag("WALLPAPER_HYPRPAPER_PRELOAD", true)
+}
+
+/// how many wallpapers to apply between hyprpaper `unload all`s, 0 for never
+pub fn hyprpaper_unload_all_every() -> u32 {
+    env::var("WALLPAPER_HYPRPAPER_UNLOAD_ALL_EVERY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_HYPRPAPER_UNLOAD_ALL_EVERY)
+}
+
+/// swww's --transition-type, its own default if unset
+pub fn swww_transition_type() -> Option<String> {
+    env::var("WALLPAPER_SWWW_TRANSITION_TYPE")
+        .ok()
+        .filter(|v| !v.is_empty())
+}
+
+/// swww's --transition-duration in seconds, its own default if unset
+pub fn swww_transition_duration() -> Option<f64> {
+    env::var("WALLPAPER_SWWW_TRANSITION_DURATION")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|s: &f64| s.is_finite() && *s >= 0.0)
+}
+
+/// how swaybg fits the image to the output: fill, fit, center, tile or stretch
+pub fn swaybg_mode() -> String {
+    env::var("WALLPAPER_SWAYBG_MODE")
+        .ok()
+        .map(|v| v.to_ascii_lowercase())
+        .filter(|v| ["fill", "fit", "center", "tile", "stretch"].contains(&v.as_str()))
+        .unwrap_or_else(|| "fill".to_string())
+}
+
+/// how GNOME fits the image: none, wallpaper, centered, scaled, stretched,
+/// zoom or spanned
+pub fn gnome_picture_options() -> String {
+    env::var("WALLPAPER_GNOME_PICTURE_OPTIONS")
+        .ok()
+        .map(|v| v.to_ascii_lowercase())
+        .filter(|v| {
+            [
+                "none",
+                "wallpaper",
+                "centered",
+                "scaled",
+                "stretched",
+                "zoom",
+                "spanned",
+            ]
+            .contains(&v.as_str())
+        })
+        .unwrap_or_else(|| "zoom".to_string())
+}
+
+/// xwallpaper or feh for the x11 backend, None to use whichever is installed
+pub fn x11_tool() -> Option<String> {
+    env::var("WALLPAPER_X11_TOOL")
+        .ok()
+        .map(|v| v.to_ascii_lowercase())
+        .filter(|v| v == "xwallpaper" || v == "feh")
+}
+
+/// how long an external command like hyprctl or a theme hook may run before
+/// it's killed
+pub fn command_timeout() -> Duration {
+    env::var("WALLPAPER_COMMAND_TIMEOUT")
+        .ok()
+        .and_then(|v| parse_duration(&v))
+        .filter(|d| !d.is_zero())
+        .unwrap_or(DEFAULT_COMMAND_TIMEOUT)
+}
+
+/// how long to keep retrying when the backend isn't reachable yet, see
+/// `backend::retry`; zero to give up right away
+pub fn apply_retry() -> Duration {
+    env::var("WALLPAPER_APPLY_RETRY")
+        .ok()
+        .and_then(|v| parse_duration(&v))
+        .unwrap_or(DEFAULT_APPLY_RETRY)
+}
+
+/// what applies the wallpaper, see `backend::named`
+pub fn backend() -> String {
+    env::var("WALLPAPER_BACKEND").unwrap_or_else(|_| DEFAULT_BACKEND.to_string())
+}
+
+/// how many times as likely a favorite is to be picked as any other candidate
+pub fn favorite_weight() -> f64 {
+    env::var("WALLPAPER_FAVORITE_WEIGHT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|w: &f64| w.is_finite() && *w > 0.0)
+        .unwrap_or(DEFAULT_FAVORITE_WEIGHT)
+}
+
+/// weight candidates by how rarely they were shown over the whole history
+pub fn fairness() -> bool {
+    env_flag("WALLPAPER_FAIRNESS", false)
+}
+
+/// only select wallpapers rated at least this many stars, 1 to 5
+pub fn min_rating() -> Option<u8> {
+    env::var("WALLPAPER_MIN_RATING")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|r| (1..=5).contains(r))
+}
+
+/// whether unrated wallpapers pass `min_rating`: "include" (the default) or
+/// "exclude"
+pub fn include_unrated() -> bool {
+    !env::var("WALLPAPER_UNRATED").is_ok_and(|v| v.eq_ignore_ascii_case("exclude"))
+}
+
+/// photos taken within this many seconds of a recently shown one count as
+/// frames of its burst and are skipped; 0 turns that off
+pub fn burst_seconds() -> i64 {
+    env::var("WALLPAPER_BURST_SECONDS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&s: &i64| s >= 0)
+        .unwrap_or(DEFAULT_BURST_SECONDS)
+}
+
+/// pick from wallpapers never shown before while any match the time
+pub fn prefer_unseen() -> bool {
+    env_flag("WALLPAPER_PREFER_UNSEEN", false)
+}
+
+/// "soft" keeps recently shown wallpapers in the pool with a lower weight
+/// instead of dropping them
+pub fn soft_recency() -> bool {
+    env::var("WALLPAPER_RECENCY").is_ok_and(|v| v.eq_ignore_ascii_case("soft"))
+}
+
+/// "sqlite" keeps history in the cache database instead of the flat log
+pub fn history_backend() -> HistoryBackend {
+    match env::var("WALLPAPER_HISTORY_BACKEND") {
+        Ok(v) if v.eq_ignore_ascii_case("sqlite") => HistoryBackend::Sqlite,
+        _ => HistoryBackend::File,
+    }
+}
+
 pub fn cache_db() -> String {
     env::var("WALLPAPER_CACHE_DB").unwrap_or_else(|_| DEFAULT_CACHE_DB.to_string())
 }
+
+pub fn follow_links() -> bool {
+    env_flag("WALLPAPER_FOLLOW_LINKS", true)
+}
+
+/// files modified more recently than this are assumed to still be copying
+pub fn settle_seconds() -> i64 {
+    env::var("WALLPAPER_SETTLE_SECONDS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0)
+}
+
+/// the camera writes DateTimeOriginal in UTC rather than local time, so its
+/// OffsetTimeOriginal is added to get the hour the photo was taken
+pub fn exif_utc() -> bool {
+    env_flag("WALLPAPER_EXIF_UTC", false)
+}
+
+/// derive the hour from the file's mtime when EXIF has no date
+pub fn mtime_fallback() -> bool {
+    env_flag("WALLPAPER_MTIME_FALLBACK", false)
+}
+
+/// only this many bytes of each file are handed to the EXIF parser; unset reads
+/// the whole file
+pub fn exif_max_bytes() -> Option<u64> {
+    env::var("WALLPAPER_EXIF_MAX_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&n| n > 0)
+}
+
+/// coordinates on the panel's Where line
+pub fn gps_format() -> GpsFormat {
+    gps_format_var("WALLPAPER_GPS_FORMAT", GpsFormat::Dms)
+}
+
+/// coordinates copied to the clipboard
+pub fn gps_copy_format() -> GpsFormat {
+    gps_format_var("WALLPAPER_GPS_COPY_FORMAT", GpsFormat::Decimal)
+}
+
+fn gps_format_var(name: &str, default: GpsFormat) -> GpsFormat {
+    env::var(name)
+        .ok()
+        .and_then(|v| GpsFormat::parse(&v))
+        .unwrap_or(default)
+}
+
+/// key the EXIF cache on a hash of each file's head instead of its mtime, for
+/// sync tools that don't preserve mtimes
+pub fn content_hash() -> bool {
+    env_flag("WALLPAPER_CONTENT_HASH", false)
+}
+
+/// measure how bright each image is and prefer dark ones at night, bright
+/// ones by day; costs a full decode of every newly cached image
+pub fn brightness() -> bool {
+    env_flag("WALLPAPER_BRIGHTNESS", false)
+}
+
+/// mean luminance (0 to 1) above which an image is too bright for the night
+pub fn night_max_luminance() -> f32 {
+    luminance_var("WALLPAPER_NIGHT_MAX_LUMINANCE", DEFAULT_NIGHT_MAX_LUMINANCE)
+}
+
+/// mean luminance (0 to 1) below which an image is too dark for the day
+pub fn day_min_luminance() -> f32 {
+    luminance_var("WALLPAPER_DAY_MIN_LUMINANCE", DEFAULT_DAY_MIN_LUMINANCE)
+}
+
+fn luminance_var(name: &str, default: f32) -> f32 {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|l| (0.0..=1.0).contains(l))
+        .unwrap_or(default)
+}
+
+/// total bytes of thumbnails kept in the cache, set in MiB
+pub fn thumbnail_budget() -> u64 {
+    env::var("WALLPAPER_THUMBNAIL_BUDGET_MB")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_THUMBNAIL_BUDGET_MB)
+        * 1024
+        * 1024
+}
+
+/// `--prune-cache` also drops entries not refreshed for this many days
+pub fn cache_max_age_days() -> Option<u64> {
+    env::var("WALLPAPER_CACHE_MAX_AGE_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&n| n > 0)
+}
+
+/// decides which months count as winter, "north" unless set to "south"
+pub fn hemisphere() -> Hemisphere {
+    match env::var("WALLPAPER_HEMISPHERE") {
+        Ok(v) if v.to_ascii_lowercase().starts_with('s') => Hemisphere::Southern,
+        _ => Hemisphere::Northern,
+    }
+}
+
+/// whether selection prefers or requires photos from the current season
+pub fn season_mode() -> SeasonMode {
+    env::var("WALLPAPER_SEASON")
+        .ok()
+        .and_then(|v| SeasonMode::parse(&v))
+        .unwrap_or(SeasonMode::Boost)
+}
+
+/// how capture times are matched against now
+pub fn strategy() -> Strategy {
+    env::var("WALLPAPER_STRATEGY")
+        .ok()
+        .and_then(|v| Strategy::parse(&v))
+        .unwrap_or(Strategy::Hour)
+}
+
+/// window picks by default, weighted draws over every candidate if set
+pub fn selection_mode() -> SelectionMode {
+    env::var("WALLPAPER_SELECTION")
+        .ok()
+        .and_then(|v| SelectionMode::parse(&v))
+        .unwrap_or(SelectionMode::Window)
+}
+
+/// how much each factor counts with WALLPAPER_SELECTION=score, each from its
+/// own variable
+pub fn score_weights() -> ScoreWeights {
+    let weight = |name: &str, default: f64| {
+        env::var(name)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|w: &f64| w.is_finite() && *w >= 0.0)
+            .unwrap_or(default)
+    };
+    let default = DEFAULT_SCORE_WEIGHTS;
+    ScoreWeights {
+        time: weight("WALLPAPER_SCORE_TIME", default.time),
+        season: weight("WALLPAPER_SCORE_SEASON", default.season),
+        favorite: weight("WALLPAPER_SCORE_FAVORITE", default.favorite),
+        unseen: weight("WALLPAPER_SCORE_UNSEEN", default.unseen),
+        recent: weight("WALLPAPER_SCORE_RECENT", default.recent),
+    }
+}
+
+/// how many of the best scoring images the pick is drawn from
+pub fn score_top_k() -> usize {
+    env::var("WALLPAPER_SCORE_TOP_K")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&k: &usize| k > 0)
+        .unwrap_or(DEFAULT_SCORE_TOP_K)
+}
+
+/// minutes either side of now a photo's capture time may be to match
+pub fn window_minutes() -> i32 {
+    env::var("WALLPAPER_WINDOW_MINUTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&m: &i32| (0..=selection::MINUTES_PER_DAY / 2).contains(&m))
+        .unwrap_or(DEFAULT_WINDOW_MINUTES)
+}
+
+/// how far the window widens when nothing is in it, in hours or in phases
+/// with the solar strategy; past it the single closest image is taken
+pub fn max_window() -> i32 {
+    env::var("WALLPAPER_MAX_WINDOW")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&w: &i32| w >= 0)
+        .unwrap_or(DEFAULT_MAX_WINDOW)
+}
+
+/// how `select` picks among several images in the window, random by default
+pub fn tiebreak() -> Tiebreak {
+    env::var("WALLPAPER_TIEBREAK")
+        .ok()
+        .and_then(|v| Tiebreak::parse(&v))
+        .unwrap_or(Tiebreak::Random)
+}
+
+/// seed for the selection's random choices, so the same images and time pick
+/// the same wallpaper
+pub fn seed() -> Option<u64> {
+    env::var("WALLPAPER_SEED").ok().and_then(|v| v.parse().ok())
+}
+
+/// how fast weights fall off with distance in weighted selection, in hours
+/// (or phases for solar matching)
+pub fn selection_tau() -> f64 {
+    env::var("WALLPAPER_SELECTION_TAU")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|t: &f64| t.is_finite() && *t > 0.0)
+        .unwrap_or(DEFAULT_SELECTION_TAU)
+}
+
+/// (latitude, longitude) for sunrise and sunset, when both are set
+pub fn location() -> Option<(f64, f64)> {
+    let degrees = |name: &str, limit: f64| {
+        env::var(name)
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|d| d.abs() <= limit)
+    };
+    degrees("WALLPAPER_LATITUDE", 90.0).zip(degrees("WALLPAPER_LONGITUDE", 180.0))
+}
+
+/// a duration like "90s", "15m", "1h" or "1h30m"; a bare number is seconds
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    if let Ok(secs) = s.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            _ => return None,
+        };
+        let n: u64 = digits.parse().ok()?;
+        total = total.checked_add(n.checked_mul(unit)?)?;
+        digits.clear();
+    }
+    digits.is_empty().then(|| Duration::from_secs(total))
+}
+
+fn env_flag(name: &str, default: bool) -> bool {
+    match env::var(name) {
+        Ok(v) => !matches!(
+            v.to_ascii_lowercase().as_str(),
+            "0" | "false" | "no" | "off"
+        ),
+        Err(_) => default,
+    }
+}
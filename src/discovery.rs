@@ -1,14 +1,315 @@
-use std::fs;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
-use walkdir::WalkDir;
+use walkdir::{DirEntry, WalkDir};
+use xxhash_rust::xxh64::Xxh64;
 
-use crate::config;
+use crate::cache::Cache;
+use crate::{config, history, xmp};
+
+/// leading bytes of each file fed to `content_hash`; EXIF lives near the start
+const HASHED_BYTES: u64 = 64 * 1024;
+
+/// (width, height) in pixels
+pub type Dimensions = (u32, u32);
 
 #[derive(Debug, Clone)]
 pub struct ImageFile {
     pub path: PathBuf,
     pub mtime: i64,
+    /// length in bytes, checked alongside the mtime to spot replaced files
+    pub size: u64,
+    /// mtime of the XMP sidecar, if there is one
+    pub sidecar_mtime: Option<i64>,
+    /// `content_hash` of the file, only computed when `config::content_hash` is on
+    pub hash: Option<u64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl ImageFile {
+    pub fn new(path: PathBuf, mtime: i64, size: u64) -> Self {
+        Self {
+            path,
+            mtime,
+            size,
+            sidecar_mtime: None,
+            hash: None,
+            width: None,
+            height: None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> std::io::Result<Self> {
+        let (mtime, size) = get_mtime_and_size(path)?;
+        let mut img = Self::new(path.to_path_buf(), mtime, size);
+        img.sidecar_mtime = xmp::find_sidecar(path).and_then(|p| get_mtime(&p).ok());
+        Ok(img)
+    }
+
+    /// fill in `hash` if content hashing is enabled
+    pub fn hash_if_enabled(&mut self) {
+        if config::content_hash() {
+            self.hash = content_hash(&self.path).ok();
+        }
+    }
+
+    /// the mtime EXIF cache entries are keyed on, covering edits to the sidecar
+    pub fn cache_mtime(&self) -> i64 {
+        self.sidecar_mtime.map_or(self.mtime, |m| m.max(self.mtime))
+    }
+
+    /// dimensions from the struct, the cache, or a header probe, in that order
+    pub fn dimensions(&self, cache: &Cache) -> Option<Dimensions> {
+        if let Some(dims) = self.width.zip(self.height) {
+            return Some(dims);
+        }
+
+        let path_str = self.path.to_string_lossy();
+        if let Ok(Some(dims)) = cache.get_dimensions(&path_str, self.cache_mtime()) {
+            return dims;
+        }
+
+        let dims = probe_dimensions(&self.path);
+        if let Err(e) = cache.insert_dimensions(&[(path_str.to_string(), self.cache_mtime(), dims)])
+        {
+            eprintln!("Failed to cache dimensions: {}", e);
+        }
+        dims
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Landscape,
+    Portrait,
+    Square,
+}
+
+impl Orientation {
+    pub fn of((width, height): Dimensions) -> Self {
+        match width.cmp(&height) {
+            std::cmp::Ordering::Greater => Orientation::Landscape,
+            std::cmp::Ordering::Less => Orientation::Portrait,
+            std::cmp::Ordering::Equal => Orientation::Square,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Predicate {
+    ExcludeRecent(HashSet<String>),
+    ExcludeBlocked(history::PathList),
+    MinDimensions(u32, u32),
+    Orientation(Orientation),
+    /// the display's size and how far an image's aspect ratio may be off
+    AspectRatio(Dimensions, f64),
+}
+
+impl Predicate {
+    fn name(&self) -> &'static str {
+        match self {
+            Predicate::ExcludeRecent(_) => "recent history",
+            Predicate::ExcludeBlocked(_) => "blocklist",
+            Predicate::MinDimensions(..) => "minimum dimensions",
+            Predicate::Orientation(_) => "orientation",
+            Predicate::AspectRatio(..) => "aspect ratio",
+        }
+    }
+
+    /// `apply_relaxed` may drop it to find something to show
+    fn relaxable(&self) -> bool {
+        !matches!(self, Predicate::ExcludeBlocked(_))
+    }
+
+    /// images with unknown dimensions pass the dimension based predicates
+    fn matches(&self, img: &ImageFile) -> bool {
+        let dims = img.width.zip(img.height);
+        match self {
+            Predicate::ExcludeRecent(recent) => !history::is_recent(recent, &img.path),
+            Predicate::ExcludeBlocked(blocklist) => !blocklist.contains(&img.path),
+            Predicate::MinDimensions(min_w, min_h) => {
+                dims.is_none_or(|(w, h)| w >= *min_w && h >= *min_h)
+            }
+            Predicate::Orientation(orientation) => {
+                dims.is_none_or(|d| Orientation::of(d) == *orientation)
+            }
+            Predicate::AspectRatio(display, tolerance) => {
+                dims.is_none_or(|d| aspect_difference(d, *display) <= *tolerance)
+            }
+        }
+    }
+}
+
+/// builds a candidate pool from discovered images, e.g.
+/// `ImageFilter::new().exclude_recent(&recent).min_dimensions(1920, 1080)`
+#[derive(Debug, Clone, Default)]
+pub struct ImageFilter {
+    predicates: Vec<Predicate>,
+}
+
+impl ImageFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// drop images logged in `recent`, see `history::is_recent`
+    pub fn exclude_recent(mut self, recent: &HashSet<String>) -> Self {
+        self.predicates
+            .push(Predicate::ExcludeRecent(recent.clone()));
+        self
+    }
+
+    /// drop blocked images; unlike the other predicates this is never relaxed
+    pub fn exclude_blocked(mut self, blocklist: &history::PathList) -> Self {
+        self.predicates
+            .push(Predicate::ExcludeBlocked(blocklist.clone()));
+        self
+    }
+
+    pub fn min_dimensions(mut self, width: u32, height: u32) -> Self {
+        self.predicates
+            .push(Predicate::MinDimensions(width, height));
+        self
+    }
+
+    pub fn orientation(mut self, orientation: Orientation) -> Self {
+        self.predicates.push(Predicate::Orientation(orientation));
+        self
+    }
+
+    /// drop images whose aspect ratio is more than `tolerance` off that of a
+    /// `display` sized screen, see `aspect_difference`
+    pub fn aspect_ratio(mut self, display: Dimensions, tolerance: f64) -> Self {
+        self.predicates
+            .push(Predicate::AspectRatio(display, tolerance));
+        self
+    }
+
+    pub fn matches(&self, img: &ImageFile) -> bool {
+        self.predicates.iter().all(|p| p.matches(img))
+    }
+
+    pub fn apply(&self, images: &[ImageFile]) -> Vec<ImageFile> {
+        images
+            .iter()
+            .filter(|img| self.matches(img))
+            .cloned()
+            .collect()
+    }
+
+    /// how many of `images` each predicate rejects on its own, by name
+    pub fn rejections(&self, images: &[ImageFile]) -> Vec<(&'static str, usize)> {
+        self.predicates
+            .iter()
+            .map(|p| {
+                (
+                    p.name(),
+                    images.iter().filter(|img| !p.matches(img)).count(),
+                )
+            })
+            .collect()
+    }
+
+    /// like `apply`, but if nothing passes, drop predicates starting with the
+    /// last one added until something does; returns the names of the relaxed ones.
+    /// The blocklist always stays
+    pub fn apply_relaxed(&self, images: &[ImageFile]) -> (Vec<ImageFile>, Vec<&'static str>) {
+        let mut active = self.clone();
+        let mut relaxed = Vec::new();
+
+        loop {
+            let pool = active.apply(images);
+            if !pool.is_empty() {
+                return (pool, relaxed);
+            }
+            match active.predicates.iter().rposition(Predicate::relaxable) {
+                Some(i) => relaxed.push(active.predicates.remove(i).name()),
+                None => return (pool, relaxed),
+            }
+        }
+    }
+}
+
+/// how much wider one of two sizes is than the other for its height, as a
+/// fraction: 0 for the same shape, 0.25 for 20:9 against 16:9 either way round
+pub fn aspect_difference((w1, h1): Dimensions, (w2, h2): Dimensions) -> f64 {
+    let a = w1 as f64 / h1.max(1) as f64;
+    let b = w2 as f64 / h2.max(1) as f64;
+    a.max(b) / a.min(b).max(f64::MIN_POSITIVE) - 1.0
+}
+
+/// mean luminance from 0 (black) to 1 (white); decodes the whole image, but
+/// averages a 64x64 nearest-neighbour downscale like the info panel's palette
+pub fn mean_luminance(path: &Path) -> Option<f32> {
+    let image = image::ImageReader::open(path)
+        .ok()?
+        .with_guessed_format()
+        .ok()?
+        .decode()
+        .ok()?;
+    let small = image
+        .resize_exact(64, 64, image::imageops::FilterType::Nearest)
+        .to_luma8();
+    let pixels = small.width() * small.height();
+    let sum: u64 = small.pixels().map(|p| p.0[0] as u64).sum();
+    Some(sum as f32 / (pixels as f32 * 255.0))
+}
+
+/// read width and height from the image header without decoding the pixels
+pub fn probe_dimensions(path: &Path) -> Option<Dimensions> {
+    image::ImageReader::open(path)
+        .ok()?
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// fill in `width`/`height` for all images, probing uncached ones in parallel
+pub fn load_dimensions(images: &mut [ImageFile], cache: &Cache) -> Result<(), rusqlite::Error> {
+    let cached = cache.load_dimensions()?;
+
+    let probed: Vec<(String, i64, Option<Dimensions>)> = images
+        .par_iter()
+        .filter(
+            |img| match cached.get(img.path.to_string_lossy().as_ref()) {
+                Some((mtime, _)) => *mtime != img.cache_mtime(),
+                None => true,
+            },
+        )
+        .map(|img| {
+            (
+                img.path.to_string_lossy().to_string(),
+                img.cache_mtime(),
+                probe_dimensions(&img.path),
+            )
+        })
+        .collect();
+
+    if !probed.is_empty() {
+        cache.insert_dimensions(&probed)?;
+    }
+
+    let probed: HashMap<&str, Option<Dimensions>> = probed
+        .iter()
+        .map(|(path, _, dims)| (path.as_str(), *dims))
+        .collect();
+
+    for img in images.iter_mut() {
+        let path_str = img.path.to_string_lossy();
+        let dims = match probed.get(path_str.as_ref()) {
+            Some(dims) => *dims,
+            None => cached.get(path_str.as_ref()).and_then(|(_, dims)| *dims),
+        };
+        img.width = dims.map(|(w, _)| w);
+        img.height = dims.map(|(_, h)| h);
+    }
+
+    Ok(())
 }
 
 pub fn find_images() -> Vec<ImageFile> {
@@ -16,32 +317,144 @@ pub fn find_images() -> Vec<ImageFile> {
 }
 
 pub fn find_images_in(dir: &str) -> Vec<ImageFile> {
-    WalkDir::new(dir)
-        .follow_links(true)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file() && is_jpeg(e.path()))
+    let settled_before = now() - config::settle_seconds();
+    let mut unsettled = 0;
+
+    let mut walk = Walk::new(Path::new(dir));
+    let mut images = walk
+        .by_ref()
+        .filter(|e| e.file_type().is_file() && is_candidate(e.path()))
         .filter_map(|e| {
-            let mtime = get_mtime(e.path()).ok()?;
-            Some(ImageFile {
-                path: e.path().to_path_buf(),
-                mtime,
-            })
+            let img = ImageFile::from_path(e.path()).ok()?;
+            if img.mtime > settled_before {
+                unsettled += 1;
+                return None;
+            }
+            Some(img)
         })
-        .collect()
+        .collect::<Vec<_>>();
+
+    if config::content_hash() {
+        images.par_iter_mut().for_each(ImageFile::hash_if_enabled);
+    }
+
+    walk.stats.report();
+    if unsettled > 0 {
+        println!("Skipped {} recently modified images", unsettled);
+    }
+    images
 }
 
 pub fn find_by_basename(basename: &str) -> Option<PathBuf> {
     find_by_basename_in(basename, &config::wallpaper_dir())
 }
 
+/// the first file named `basename` under `dir`, reporting what the walk up to
+/// it had to skip like `find_images_in` does
 pub fn find_by_basename_in(basename: &str, dir: &str) -> Option<PathBuf> {
-    WalkDir::new(dir)
-        .follow_links(true)
-        .into_iter()
-        .filter_map(|e| e.ok())
+    let mut walk = Walk::new(Path::new(dir));
+    let found = walk
+        .by_ref()
         .find(|e| e.file_type().is_file() && e.file_name().to_str() == Some(basename))
-        .map(|e| e.path().to_path_buf())
+        .map(|e| e.path().to_path_buf());
+    walk.stats.report();
+    found
+}
+
+#[derive(Debug, Default)]
+pub struct WalkStats {
+    /// directories skipped because they were already reached through another link
+    pub cycles: usize,
+    /// entries that could not be read, e.g. broken symlinks
+    pub errors: Vec<walkdir::Error>,
+}
+
+impl WalkStats {
+    pub fn report(&self) {
+        if self.cycles > 0 {
+            eprintln!(
+                "Skipped {} already visited directories (symlink cycles)",
+                self.cycles
+            );
+        }
+        if !self.errors.is_empty() {
+            eprintln!("Skipped {} unreadable entries:", self.errors.len());
+            for e in self.errors.iter().take(5) {
+                eprintln!("  {}", e);
+            }
+        }
+    }
+}
+
+/// directory walk honoring `follow_links`, visiting each canonical directory once
+pub struct Walk {
+    it: walkdir::IntoIter,
+    follow_links: bool,
+    visited: HashSet<PathBuf>,
+    pub stats: WalkStats,
+}
+
+impl Walk {
+    pub fn new(dir: &Path) -> Self {
+        let follow_links = config::follow_links();
+        Self {
+            it: WalkDir::new(dir).follow_links(follow_links).into_iter(),
+            follow_links,
+            visited: HashSet::new(),
+            stats: WalkStats::default(),
+        }
+    }
+}
+
+impl Iterator for Walk {
+    type Item = DirEntry;
+
+    fn next(&mut self) -> Option<DirEntry> {
+        loop {
+            match self.it.next()? {
+                Ok(entry) => {
+                    if self.follow_links && entry.file_type().is_dir() {
+                        let canonical = fs::canonicalize(entry.path())
+                            .unwrap_or_else(|_| entry.path().to_path_buf());
+                        if !self.visited.insert(canonical) {
+                            self.stats.cycles += 1;
+                            self.it.skip_current_dir();
+                            continue;
+                        }
+                    }
+                    return Some(entry);
+                }
+                Err(e) if e.loop_ancestor().is_some() => self.stats.cycles += 1,
+                Err(e) => self.stats.errors.push(e),
+            }
+        }
+    }
+}
+
+/// a JPEG that is not a partial transfer
+pub fn is_candidate(path: &Path) -> bool {
+    is_jpeg(path) && !is_partial(path)
+}
+
+/// rsync's `--delay-updates` dir and temporaries, browser/curl downloads
+fn is_partial(path: &Path) -> bool {
+    const SUFFIXES: [&str; 4] = [".part", ".partial", ".tmp", ".crdownload"];
+
+    let in_tmp_dir = path.components().any(|c| c.as_os_str() == ".~tmp~");
+    let name = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
+    in_tmp_dir || is_rsync_temp(name) || SUFFIXES.iter().any(|s| name.ends_with(s))
+}
+
+/// rsync writes `name` to `.name.XXXXXX` and renames it when done; other
+/// hidden files are left alone
+fn is_rsync_temp(name: &str) -> bool {
+    name.strip_prefix('.')
+        .and_then(|rest| rest.rsplit_once('.'))
+        .is_some_and(|(base, random)| {
+            !base.is_empty()
+                && random.len() == 6
+                && random.chars().all(|c| c.is_ascii_alphanumeric())
+        })
 }
 
 fn is_jpeg(path: &Path) -> bool {
@@ -51,12 +464,40 @@ fn is_jpeg(path: &Path) -> bool {
         .unwrap_or(false)
 }
 
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// xxHash64 of the first `HASHED_BYTES`, the length, and any XMP sidecar; enough
+/// to notice an edited file without reading all of it
+pub fn content_hash(path: &Path) -> std::io::Result<u64> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut head = Vec::with_capacity(HASHED_BYTES as usize);
+    file.by_ref().take(HASHED_BYTES).read_to_end(&mut head)?;
+
+    let mut hasher = Xxh64::new(0);
+    hasher.update(&head);
+    hasher.update(&len.to_le_bytes());
+    if let Some(sidecar) = xmp::find_sidecar(path) {
+        hasher.update(&fs::read(sidecar)?);
+    }
+    Ok(hasher.digest())
+}
+
 pub fn get_mtime(path: &Path) -> std::io::Result<i64> {
+    get_mtime_and_size(path).map(|(mtime, _)| mtime)
+}
+
+pub fn get_mtime_and_size(path: &Path) -> std::io::Result<(i64, u64)> {
     let metadata = fs::metadata(path)?;
     let mtime = metadata
         .modified()?
         .duration_since(SystemTime::UNIX_EPOCH)
         .map(|d| d.as_secs() as i64)
         .unwrap_or(0);
-    Ok(mtime)
+    Ok((mtime, metadata.len()))
 }
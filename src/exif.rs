@@ -1,26 +1,322 @@
-use std::path::Path;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek};
+use std::ops::RangeInclusive;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::discovery::{self, Dimensions};
+use crate::{config, iptc, xmp};
+
+const TAG_OFFSET_TIME: u16 = 0x9010;
+const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+const TAG_OFFSET_TIME_DIGITIZED: u16 = 0x9012;
+const TAG_RATING: u16 = 0x4746;
+const TAG_ARTIST: u16 = 0x013b;
+const TAG_XP_TITLE: u16 = 0x9c9b;
+const TAG_RECOMMENDED_EXPOSURE_INDEX: u16 = 0x8832;
+const TAG_ISO_SPEED: u16 = 0x8833;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// keywords kept per image, enough for the panel and filtering
+const MAX_KEYWORDS: usize = 32;
 
 #[derive(Debug, Default, Clone)]
 pub struct ExifInfo {
+    /// ImageDescription, or the Windows XPTitle
+    pub description: Option<String>,
     pub datetime: Option<String>,
+    /// the tag value as written, before applying the offset
     pub datetime_raw: Option<String>,
+    /// local capture time, hour, month and `datetime` are derived from it
+    pub captured_at: Option<NaiveDateTime>,
+    pub datetime_source: Option<DatetimeSource>,
+    /// UTC offset of the capture time, e.g. "+02:00"
+    pub offset: Option<String>,
     pub hour: Option<u8>,
-    pub location: Option<String>,
+    /// minutes after midnight, from the same datetime as the hour
+    pub minute_of_day: Option<u16>,
+    /// 1-12, from the same datetime as the hour
+    pub month: Option<u8>,
     pub camera: Option<String>,
     pub lens: Option<String>,
     pub exposure: Option<String>,
     pub aperture: Option<String>,
     pub iso: Option<String>,
     pub focal_length: Option<String>,
+    pub artist: Option<String>,
+    pub copyright: Option<String>,
+    /// e.g. "-0.7 EV", None when zero
+    pub exposure_bias: Option<String>,
+    /// only set when the flash fired
+    pub flash: Option<String>,
+    pub white_balance: Option<String>,
+    pub metering_mode: Option<String>,
     pub gps_latitude: Option<f64>,
     pub gps_longitude: Option<f64>,
+    /// meters, negative below sea level
+    pub gps_altitude: Option<f64>,
+    /// degrees the camera faced, 0 up to but excluding 360
+    pub gps_direction: Option<f64>,
+    /// "T" for true north, "M" for magnetic north
+    pub gps_direction_ref: Option<String>,
+    pub orientation: Option<ExifOrientation>,
+    /// 0-5 stars; None when unrated, which is not the same as an explicit 0
+    pub rating: Option<u8>,
+    /// IPTC and XMP keywords, deduplicated ignoring case
+    pub keywords: Vec<String>,
+}
+
+/// which tag the capture time was taken from, in order of preference
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatetimeSource {
+    Original,
+    /// DateTimeOriginal from an XMP sidecar
+    Sidecar,
+    Digitized,
+    Modified,
+}
+
+impl DatetimeSource {
+    /// only DateTimeOriginal reliably records when the photo was taken
+    pub fn is_approximate(self) -> bool {
+        !matches!(self, Self::Original | Self::Sidecar)
+    }
+
+    pub fn tag_name(self) -> &'static str {
+        match self {
+            Self::Original => "DateTimeOriginal",
+            Self::Sidecar => "XMP sidecar",
+            Self::Digitized => "DateTimeDigitized",
+            Self::Modified => "DateTime",
+        }
+    }
+}
+
+/// the 8 values of the EXIF Orientation tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExifOrientation {
+    Normal = 1,
+    FlipHorizontal = 2,
+    Rotate180 = 3,
+    FlipVertical = 4,
+    Transpose = 5,
+    Rotate90Cw = 6,
+    Transverse = 7,
+    Rotate90Ccw = 8,
+}
+
+impl ExifOrientation {
+    pub fn from_exif(value: u16) -> Option<Self> {
+        match value {
+            1 => Some(Self::Normal),
+            2 => Some(Self::FlipHorizontal),
+            3 => Some(Self::Rotate180),
+            4 => Some(Self::FlipVertical),
+            5 => Some(Self::Transpose),
+            6 => Some(Self::Rotate90Cw),
+            7 => Some(Self::Transverse),
+            8 => Some(Self::Rotate90Ccw),
+            _ => None,
+        }
+    }
+
+    pub fn as_exif(self) -> u16 {
+        self as u16
+    }
+
+    pub fn is_normal(self) -> bool {
+        self == Self::Normal
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Normal => "Normal",
+            Self::FlipHorizontal => "Mirrored horizontally",
+            Self::Rotate180 => "Rotated 180°",
+            Self::FlipVertical => "Mirrored vertically",
+            Self::Transpose => "Mirrored, rotated 90° CCW",
+            Self::Rotate90Cw => "Rotated 90° CW",
+            Self::Transverse => "Mirrored, rotated 90° CW",
+            Self::Rotate90Ccw => "Rotated 90° CCW",
+        }
+    }
+}
+
+/// how coordinates are written for display or copying
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpsFormat {
+    /// degrees, minutes and seconds
+    Dms,
+    /// signed decimal degrees
+    Decimal,
+    Both,
+}
+
+impl GpsFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "dms" => Some(Self::Dms),
+            "decimal" => Some(Self::Decimal),
+            "both" => Some(Self::Both),
+            _ => None,
+        }
+    }
+
+    pub fn format(self, lat: f64, lon: f64) -> String {
+        let decimal = format!("{:.5}, {:.5}", lat, lon);
+        match self {
+            Self::Dms => format_dms(lat, lon),
+            Self::Decimal => decimal,
+            Self::Both => format!("{} ({})", format_dms(lat, lon), decimal),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hemisphere {
+    Northern,
+    Southern,
+}
+
+/// meteorological seasons, whole months
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Season {
+    pub fn from_month(month: u8, hemisphere: Hemisphere) -> Option<Self> {
+        let northern = match month {
+            3..=5 => Self::Spring,
+            6..=8 => Self::Summer,
+            9..=11 => Self::Autumn,
+            12 | 1 | 2 => Self::Winter,
+            _ => return None,
+        };
+        Some(match hemisphere {
+            Hemisphere::Northern => northern,
+            Hemisphere::Southern => northern.opposite(),
+        })
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Self::Spring => Self::Autumn,
+            Self::Summer => Self::Winter,
+            Self::Autumn => Self::Spring,
+            Self::Winter => Self::Summer,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Spring => "spring",
+            Self::Summer => "summer",
+            Self::Autumn => "autumn",
+            Self::Winter => "winter",
+        }
+    }
+}
+
+/// how selection treats the capture season, see `SeasonMatch`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeasonMode {
+    /// ignore it
+    Off,
+    /// prefer photos from the current season within the time window
+    Boost,
+    /// never pick photos known to be from another season
+    Strict,
+}
+
+impl SeasonMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Some(Self::Off),
+            "boost" => Some(Self::Boost),
+            "strict" => Some(Self::Strict),
+            _ => None,
+        }
+    }
+}
+
+/// how a photo's capture month relates to the season on a given day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeasonMatch {
+    Same,
+    Other,
+    /// no capture month to go by
+    Unknown,
+}
+
+impl SeasonMatch {
+    pub fn of(today: NaiveDate, month: Option<u8>, hemisphere: Hemisphere) -> Self {
+        let current = Season::from_month(today.month() as u8, hemisphere);
+        match month.and_then(|m| Season::from_month(m, hemisphere)) {
+            Some(season) if Some(season) == current => Self::Same,
+            Some(_) => Self::Other,
+            None => Self::Unknown,
+        }
+    }
 }
 
 impl ExifInfo {
+    pub fn season(&self, hemisphere: Hemisphere) -> Option<Season> {
+        Season::from_month(self.month?, hemisphere)
+    }
+
+    /// the coordinates written in the given format
+    pub fn location(&self, format: GpsFormat) -> Option<String> {
+        let (lat, lon) = self.gps_latitude.zip(self.gps_longitude)?;
+        Some(format.format(lat, lon))
+    }
+
     pub fn has_gps(&self) -> bool {
         self.gps_latitude.is_some() && self.gps_longitude.is_some()
     }
 
+    /// "© Jane Doe", naming the artist separately when the copyright doesn't
+    pub fn credit(&self) -> Option<String> {
+        let copyright = self.copyright.as_deref().map(|c| {
+            c.trim_start_matches('©')
+                .trim_start_matches("(c)")
+                .trim_start_matches("(C)")
+                .trim()
+        });
+        match (self.artist.as_deref(), copyright) {
+            (Some(artist), Some(c)) if !c.contains(artist) => Some(format!("{}  © {}", artist, c)),
+            (_, Some(c)) => Some(format!("© {}", c)),
+            (Some(artist), None) => Some(format!("© {}", artist)),
+            (None, None) => None,
+        }
+    }
+
+    /// e.g. "NW (312°)", marked when measured against magnetic north
+    pub fn bearing(&self) -> Option<String> {
+        let degrees = self.gps_direction?;
+        let magnetic = if self.gps_direction_ref.as_deref() == Some("M") {
+            " magnetic"
+        } else {
+            ""
+        };
+        Some(format!(
+            "{} ({:.0}°{})",
+            compass_point(degrees),
+            degrees,
+            magnetic
+        ))
+    }
+
     pub fn maps_url(&self) -> Option<String> {
         match (self.gps_latitude, self.gps_longitude) {
             (Some(lat), Some(lon)) => {
@@ -31,25 +327,262 @@ impl ExifInfo {
     }
 }
 
-pub fn extract(path: &Path) -> ExifInfo {
-    let mut info = ExifInfo::default();
+/// why a file's embedded EXIF couldn't be read; a file without EXIF is not an error
+#[derive(Debug)]
+pub enum ExifError {
+    Io(io::Error),
+    Parse(String),
+    Unsupported,
+}
+
+impl fmt::Display for ExifError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Parse(msg) => write!(f, "malformed EXIF: {}", msg),
+            Self::Unsupported => write!(f, "unsupported file type"),
+        }
+    }
+}
+
+impl Error for ExifError {}
 
-    let exif = match rexif::parse_file(path) {
-        Ok(e) => e,
-        Err(_) => return info,
+impl ExifError {
+    /// None when rexif only reports that there is no EXIF to read
+    fn from_rexif(e: rexif::ExifError, buffer: &[u8]) -> Option<Self> {
+        match e {
+            rexif::ExifError::IoError(e) => Some(Self::Io(e)),
+            rexif::ExifError::JpegWithoutExif(ref msg)
+                if msg.starts_with("Last mark found") || msg.starts_with("Scan past EOF") =>
+            {
+                None
+            }
+            // rexif only recognises JPEGs that start with an Exif or JFIF segment
+            rexif::ExifError::FileTypeUnknown if buffer.starts_with(&[0xFF, 0xD8]) => None,
+            rexif::ExifError::FileTypeUnknown => Some(Self::Unsupported),
+            e => Some(Self::Parse(e.to_string())),
+        }
+    }
+}
+
+/// the file's EXIF entries; a panic inside rexif only fails this file
+fn read_entries(path: &Path) -> (Vec<rexif::ExifEntry>, Option<ExifError>) {
+    let buffer = match read_exif_bytes(path) {
+        Ok(Some(buffer)) => buffer,
+        Ok(None) => return (Vec::new(), None),
+        Err(e) => return (Vec::new(), Some(ExifError::Io(e))),
     };
 
+    match panic::catch_unwind(|| rexif::parse_buffer(&buffer)) {
+        Ok(Ok(exif)) => (exif.entries, None),
+        Ok(Err(e)) => (Vec::new(), ExifError::from_rexif(e, &buffer)),
+        Err(_) => (
+            Vec::new(),
+            Some(ExifError::Parse("the EXIF parser panicked".to_string())),
+        ),
+    }
+}
+
+/// what to hand to rexif: a PNG's eXIf payload, otherwise the file itself;
+/// None for a PNG without one
+fn read_exif_bytes(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    let mut file = File::open(path)?;
+    let mut buffer = Vec::new();
+    file.by_ref()
+        .take(PNG_SIGNATURE.len() as u64)
+        .read_to_end(&mut buffer)?;
+
+    if buffer == PNG_SIGNATURE {
+        return png_exif_chunk(&mut file);
+    }
+
+    match config::exif_max_bytes() {
+        Some(max) => file
+            .take(max.saturating_sub(buffer.len() as u64))
+            .read_to_end(&mut buffer)?,
+        None => file.read_to_end(&mut buffer)?,
+    };
+    Ok(Some(buffer))
+}
+
+/// walk the chunks after the signature, giving up at the image data since
+/// eXIf has to come before it
+fn png_exif_chunk(r: &mut (impl Read + Seek)) -> io::Result<Option<Vec<u8>>> {
+    loop {
+        let mut header = [0u8; 8];
+        match r.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+
+        match &header[4..] {
+            b"eXIf" => {
+                let mut data = Vec::new();
+                r.take(len as u64).read_to_end(&mut data)?;
+                return Ok(Some(data));
+            }
+            b"IDAT" | b"IEND" => return Ok(None),
+            // skip the data and its CRC
+            _ => {
+                r.seek_relative(len as i64 + 4)?;
+            }
+        }
+    }
+}
+
+/// byte values longer than this are summarized rather than printed
+const MAX_DUMPED_BYTES: usize = 32;
+
+/// one tag from `dump`
+#[derive(Debug, Clone)]
+pub struct ExifEntry {
+    /// rexif's name for the tag, or its number when rexif doesn't know it
+    pub tag: String,
+    pub tag_id: u16,
+    pub value: String,
+    /// "IFD0", "EXIF" or "GPS"; rexif skips the thumbnail's IFD1 and doesn't
+    /// descend into MakerNote or Interop IFDs, which would be named "IFD1",
+    /// "MakerNote" and "Interop"
+    pub ifd: &'static str,
+}
+
+/// every tag in the file, in file order; blobs such as the MakerNote are shown as
+/// "<N bytes>"
+pub fn dump(path: &Path) -> Result<Vec<ExifEntry>, ExifError> {
+    let (entries, error) = read_entries(path);
+    if let Some(e) = error {
+        return Err(e);
+    }
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| ExifEntry {
+            tag: match entry.tag {
+                rexif::ExifTag::UnknownToMe => format!("0x{:04x}", entry.ifd.tag),
+                tag => tag.to_string(),
+            },
+            tag_id: entry.ifd.tag,
+            value: match entry.value {
+                rexif::TagValue::U8(ref b) | rexif::TagValue::Undefined(ref b, _)
+                    if b.len() > MAX_DUMPED_BYTES =>
+                {
+                    format!("<{} bytes>", b.len())
+                }
+                _ => entry.value_more_readable.to_string(),
+            },
+            ifd: match entry.kind {
+                rexif::IfdKind::Ifd0 => "IFD0",
+                rexif::IfdKind::Ifd1 => "IFD1",
+                rexif::IfdKind::Exif => "EXIF",
+                rexif::IfdKind::Gps => "GPS",
+                rexif::IfdKind::Makernote => "MakerNote",
+                rexif::IfdKind::Interoperability => "Interop",
+            },
+        })
+        .collect())
+}
+
+/// one file's result from `extract_many`
+#[derive(Debug)]
+pub struct Extracted {
+    pub path: PathBuf,
+    pub info: ExifInfo,
+    pub error: Option<ExifError>,
+    /// from the image header, `None` when it couldn't be read
+    pub dimensions: Option<Dimensions>,
+    /// `discovery::mean_luminance`, only computed with `config::brightness`
+    pub luminance: Option<f32>,
+}
+
+/// `extract_partial` plus the header dimensions for many files in parallel, in the
+/// order given
+pub fn extract_many<P: AsRef<Path> + Sync>(paths: &[P]) -> Vec<Extracted> {
+    extract_many_with_progress(paths, |_, _| {})
+}
+
+/// like `extract_many`, calling `progress(done, total)` after each file
+pub fn extract_many_with_progress<P, F>(paths: &[P], progress: F) -> Vec<Extracted>
+where
+    P: AsRef<Path> + Sync,
+    F: FnMut(usize, usize) + Send,
+{
+    let total = paths.len();
+    let progress = Mutex::new((0, progress));
+    let brightness = config::brightness();
+
+    paths
+        .par_iter()
+        .map(|path| {
+            let path = path.as_ref();
+            let (info, error) = extract_partial(path);
+            let dimensions = discovery::probe_dimensions(path);
+            let luminance = brightness
+                .then(|| discovery::mean_luminance(path))
+                .flatten();
+
+            let mut guard = progress.lock().unwrap_or_else(|e| e.into_inner());
+            let (done, report) = &mut *guard;
+            *done += 1;
+            report(*done, total);
+
+            Extracted {
+                path: path.to_path_buf(),
+                info,
+                error,
+                dimensions,
+                luminance,
+            }
+        })
+        .collect()
+}
+
+/// lossy: a file whose EXIF can't be read gives whatever the sidecar and IPTC provide
+pub fn extract(path: &Path) -> ExifInfo {
+    extract_partial(path).0
+}
+
+pub fn try_extract(path: &Path) -> Result<ExifInfo, ExifError> {
+    match extract_partial(path) {
+        (info, None) => Ok(info),
+        (_, Some(e)) => Err(e),
+    }
+}
+
+/// embedded EXIF merged with an XMP sidecar, embedded values taking precedence,
+/// along with the error if the embedded EXIF couldn't be read
+pub fn extract_partial(path: &Path) -> (ExifInfo, Option<ExifError>) {
+    let mut info = ExifInfo::default();
     let mut gps = GpsData::default();
+    let mut datetimes = Datetimes::default();
+    let mut xp_title = None;
+    let mut lens_make = None;
+    let mut isos = Isos::default();
+    let mut apex_aperture = None;
+    let mut apex_shutter = None;
+    let mut lens_spec = None;
 
-    for entry in &exif.entries {
+    let (entries, error) = read_entries(path);
+
+    for entry in &entries {
         match entry.tag {
-            rexif::ExifTag::DateTimeOriginal => {
-                if let rexif::TagValue::Ascii(ref s) = entry.value {
-                    info.datetime_raw = Some(s.clone());
-                    info.datetime = Some(format_datetime(s));
-                    info.hour = parse_hour_from_datetime(s);
+            rexif::ExifTag::DateTimeOriginal => datetimes.original = ascii(&entry.value),
+            rexif::ExifTag::DateTimeDigitized => datetimes.digitized = ascii(&entry.value),
+            rexif::ExifTag::DateTime => datetimes.modified = ascii(&entry.value),
+            rexif::ExifTag::UnknownToMe => match entry.ifd.tag {
+                TAG_OFFSET_TIME_ORIGINAL => datetimes.offset_original = ascii(&entry.value),
+                TAG_OFFSET_TIME_DIGITIZED => datetimes.offset_digitized = ascii(&entry.value),
+                TAG_OFFSET_TIME => datetimes.offset_modified = ascii(&entry.value),
+                TAG_RATING => info.rating = entry.value.to_i64(0).map(clamp_rating),
+                TAG_ARTIST => info.artist = ascii(&entry.value),
+                TAG_XP_TITLE => xp_title = utf16le(&entry.value),
+                TAG_RECOMMENDED_EXPOSURE_INDEX => {
+                    isos.recommended_exposure_index = positive(&entry.value)
                 }
-            }
+                TAG_ISO_SPEED => isos.iso_speed = positive(&entry.value),
+                _ => {}
+            },
             rexif::ExifTag::Make => {
                 if let rexif::TagValue::Ascii(ref s) = entry.value {
                     info.camera = Some(s.trim().to_string());
@@ -64,11 +597,19 @@ pub fn extract(path: &Path) -> ExifInfo {
                     });
                 }
             }
+            rexif::ExifTag::Copyright => info.copyright = ascii(&entry.value),
+            rexif::ExifTag::ImageDescription => info.description = ascii(&entry.value),
             rexif::ExifTag::LensModel => {
                 if let rexif::TagValue::Ascii(ref s) = entry.value {
                     info.lens = Some(s.trim().to_string());
                 }
             }
+            rexif::ExifTag::LensMake => lens_make = ascii(&entry.value),
+            rexif::ExifTag::LensSpecification => {
+                if let rexif::TagValue::URational(ref vals) = entry.value {
+                    lens_spec = Some(vals.clone());
+                }
+            }
             rexif::ExifTag::ExposureTime => {
                 info.exposure = Some(entry.value_more_readable.to_string());
             }
@@ -80,13 +621,9 @@ pub fn extract(path: &Path) -> ExifInfo {
                     }
                 }
             }
-            rexif::ExifTag::ISOSpeedRatings => {
-                if let rexif::TagValue::U16(ref vals) = entry.value {
-                    if let Some(&iso) = vals.first() {
-                        info.iso = Some(format!("ISO {}", iso));
-                    }
-                }
-            }
+            rexif::ExifTag::ApertureValue => apex_aperture = entry.value.to_f64(0),
+            rexif::ExifTag::ShutterSpeedValue => apex_shutter = entry.value.to_f64(0),
+            rexif::ExifTag::ISOSpeedRatings => isos.sensitivity = positive(&entry.value),
             rexif::ExifTag::FocalLength => {
                 if let rexif::TagValue::URational(ref vals) = entry.value {
                     if let Some(v) = vals.first() {
@@ -95,21 +632,317 @@ pub fn extract(path: &Path) -> ExifInfo {
                     }
                 }
             }
+            rexif::ExifTag::ExposureBiasValue => {
+                if let rexif::TagValue::IRational(ref vals) = entry.value {
+                    if let Some(v) = vals.first() {
+                        info.exposure_bias = format_exposure_bias(v.numerator, v.denominator);
+                    }
+                }
+            }
+            // bit 0 is "fired", the rest describes mode and return light
+            rexif::ExifTag::Flash if entry.value.to_i64(0).is_some_and(|v| v & 1 == 1) => {
+                info.flash = Some("Flash".to_string());
+            }
+            rexif::ExifTag::WhiteBalanceMode => {
+                info.white_balance = match entry.value.to_i64(0) {
+                    Some(0) => Some("Auto WB".to_string()),
+                    Some(1) => Some("Manual WB".to_string()),
+                    _ => None,
+                };
+            }
+            rexif::ExifTag::MeteringMode => {
+                info.metering_mode = entry
+                    .value
+                    .to_i64(0)
+                    .and_then(metering_mode_name)
+                    .map(String::from);
+            }
+            rexif::ExifTag::Orientation => {
+                if let Some(v) = entry.value.to_i64(0) {
+                    info.orientation = ExifOrientation::from_exif(v as u16);
+                }
+            }
             rexif::ExifTag::GPSLatitude => gps.parse_lat(&entry.value),
             rexif::ExifTag::GPSLatitudeRef => gps.parse_lat_ref(&entry.value),
             rexif::ExifTag::GPSLongitude => gps.parse_lon(&entry.value),
             rexif::ExifTag::GPSLongitudeRef => gps.parse_lon_ref(&entry.value),
+            rexif::ExifTag::GPSAltitude => gps.altitude = entry.value.to_f64(0),
+            rexif::ExifTag::GPSImgDirection => {
+                info.gps_direction = entry.value.to_f64(0).and_then(normalize_direction);
+            }
+            rexif::ExifTag::GPSImgDirectionRef => {
+                info.gps_direction_ref = ascii(&entry.value).map(|r| r.to_ascii_uppercase());
+            }
+            rexif::ExifTag::GPSAltitudeRef => gps.altitude_ref = entry.value.to_i64(0),
             _ => {}
         }
     }
 
-    if let Some((lat, lon)) = gps.to_decimal() {
+    info.description = info.description.or(xp_title);
+    info.iso = isos.best().map(|iso| format!("ISO {}", iso));
+    if info.aperture.is_none() {
+        info.aperture = apex_aperture.and_then(aperture_from_apex);
+    }
+    if info.exposure.is_none() {
+        info.exposure = apex_shutter.and_then(exposure_from_apex);
+    }
+    if info.lens.is_none() {
+        info.lens = describe_lens(lens_make, lens_spec.as_deref());
+    }
+
+    let sidecar = xmp::read_sidecar(path).unwrap_or_default();
+    datetimes.sidecar = sidecar.datetime;
+    if info.camera.is_none() {
+        info.camera = match (sidecar.make, sidecar.model) {
+            (Some(make), Some(model)) if !model.starts_with(&make) => {
+                Some(format!("{} {}", make, model))
+            }
+            (_, Some(model)) => Some(model),
+            (make, None) => make,
+        };
+    }
+    if info.rating.is_none() {
+        info.rating = sidecar.rating.map(|r| clamp_rating(r as i64));
+    }
+    info.keywords = merge_keywords(iptc::read_keywords(path), sidecar.keywords);
+
+    if let Some((source, raw, offset)) = datetimes.best() {
+        info.datetime_source = Some(source);
+        info.datetime_raw = Some(raw);
+        info.offset = offset.filter(|o| parse_offset_minutes(o).is_some());
+    }
+
+    if let Some(captured) = info.datetime_raw.as_deref().and_then(parse_datetime) {
+        // DateTimeOriginal is local time, except from cameras that write it in
+        // UTC and keep the local offset separately
+        let local = match info.offset.as_deref().and_then(parse_offset_minutes) {
+            Some(minutes) if config::exif_utc() => captured + Duration::minutes(minutes),
+            _ => captured,
+        };
+
+        info.captured_at = Some(local);
+        info.hour = Some(local.hour() as u8);
+        info.minute_of_day = Some((local.hour() * 60 + local.minute()) as u16);
+        info.month = Some(local.month() as u8);
+        info.datetime = Some(match info.offset {
+            Some(ref o) => format!("{} ({})", format_datetime(&local), o),
+            None => format_datetime(&local),
+        });
+    }
+
+    let coordinates = gps
+        .to_decimal()
+        .or(sidecar.gps_latitude.zip(sidecar.gps_longitude));
+    if let Some((lat, lon)) = coordinates {
         info.gps_latitude = Some(lat);
         info.gps_longitude = Some(lon);
-        info.location = Some(format_gps_coordinates(lat, lon));
     }
+    info.gps_altitude = gps.altitude_meters();
 
-    info
+    (info, error)
+}
+
+/// "Canon 24-70mm f/2.8-4" from LensMake and LensSpecification, for bodies
+/// that don't write LensModel
+fn describe_lens(make: Option<String>, spec: Option<&[rexif::URational]>) -> Option<String> {
+    let value = |i: usize| {
+        spec?
+            .get(i)
+            .filter(|r| r.denominator != 0 && r.numerator != 0)
+            .map(|r| r.numerator as f64 / r.denominator as f64)
+    };
+    let range = |min: Option<f64>, max: Option<f64>| match (min, max) {
+        (Some(min), Some(max)) if (max - min).abs() > 0.05 => {
+            Some(format!("{}-{}", trim_number(min), trim_number(max)))
+        }
+        (Some(v), _) | (None, Some(v)) => Some(trim_number(v)),
+        (None, None) => None,
+    };
+
+    // the make alone says nothing about the lens
+    let focal = range(value(0), value(1)).map(|f| format!("{}mm", f))?;
+    let aperture = range(value(2), value(3)).map(|a| format!("f/{}", a));
+
+    let parts: Vec<String> = [make, Some(focal), aperture]
+        .into_iter()
+        .flatten()
+        .collect();
+    Some(parts.join(" "))
+}
+
+/// one decimal at most, without a trailing ".0"
+fn trim_number(v: f64) -> String {
+    let rounded = format!("{:.1}", v);
+    rounded.strip_suffix(".0").unwrap_or(&rounded).to_string()
+}
+
+/// the XP* tags are NUL-terminated UTF-16LE stored as a byte array
+fn utf16le(value: &rexif::TagValue) -> Option<String> {
+    let bytes = match value {
+        rexif::TagValue::U8(b) | rexif::TagValue::Undefined(b, _) => b,
+        _ => return None,
+    };
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|&u| u != 0)
+        .collect();
+    let decoded = String::from_utf16_lossy(&units);
+    let trimmed = decoded.trim();
+    (!trimmed.is_empty()).then(|| trimmed.to_string())
+}
+
+fn merge_keywords(embedded: Vec<String>, sidecar: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    embedded
+        .into_iter()
+        .chain(sidecar)
+        .filter(|k| seen.insert(k.to_lowercase()))
+        .take(MAX_KEYWORDS)
+        .collect()
+}
+
+/// "+1.3 EV" / "-0.7 EV", rounded to a tenth; None for zero
+fn format_exposure_bias(numerator: i32, denominator: i32) -> Option<String> {
+    if denominator == 0 {
+        return None;
+    }
+    let ev = (numerator as f64 / denominator as f64 * 10.0).round() / 10.0;
+    if ev == 0.0 {
+        return None;
+    }
+    Some(format!("{:+.1} EV", ev))
+}
+
+fn metering_mode_name(mode: i64) -> Option<&'static str> {
+    match mode {
+        1 => Some("Average"),
+        2 => Some("Center-weighted"),
+        3 => Some("Spot"),
+        4 => Some("Multi-spot"),
+        5 => Some("Matrix"),
+        6 => Some("Partial"),
+        _ => None,
+    }
+}
+
+/// marked f-stops in third stops, which APEX values only approximate
+const NOMINAL_F_NUMBERS: [f64; 31] = [
+    1.0, 1.1, 1.2, 1.4, 1.6, 1.8, 2.0, 2.2, 2.5, 2.8, 3.2, 3.5, 4.0, 4.5, 5.0, 5.6, 6.3, 7.1, 8.0,
+    9.0, 10.0, 11.0, 13.0, 14.0, 16.0, 18.0, 20.0, 22.0, 25.0, 29.0, 32.0,
+];
+
+/// marked shutter speeds as fractions of a second
+const NOMINAL_SHUTTER_DENOMINATORS: [f64; 36] = [
+    2.0, 3.0, 4.0, 5.0, 6.0, 8.0, 10.0, 13.0, 15.0, 20.0, 25.0, 30.0, 40.0, 50.0, 60.0, 80.0,
+    100.0, 125.0, 160.0, 200.0, 250.0, 320.0, 400.0, 500.0, 640.0, 800.0, 1000.0, 1250.0, 1600.0,
+    2000.0, 2500.0, 3200.0, 4000.0, 5000.0, 6400.0, 8000.0,
+];
+
+/// the marked value within 4% of `v`, so APEX 5 reads as f/5.6 rather than f/5.7
+fn snap_to_nominal(v: f64, nominal: &[f64]) -> Option<f64> {
+    nominal.iter().copied().find(|n| (v - n).abs() / n < 0.04)
+}
+
+/// ApertureValue is APEX: f-number = 2^(AV/2)
+fn aperture_from_apex(av: f64) -> Option<String> {
+    let f = 2f64.powf(av / 2.0);
+    if !f.is_finite() || f <= 0.0 {
+        return None;
+    }
+    let f = snap_to_nominal(f, &NOMINAL_F_NUMBERS).unwrap_or((f * 10.0).round() / 10.0);
+    Some(format!("f/{:.1}", f))
+}
+
+/// ShutterSpeedValue is APEX: exposure time = 2^-TV seconds, written like ExposureTime
+fn exposure_from_apex(tv: f64) -> Option<String> {
+    let t = 2f64.powf(-tv);
+    if !t.is_finite() || t <= 0.0 {
+        return None;
+    }
+    if t >= 1.0 {
+        return Some(format!("{:.1} s", t));
+    }
+    let denominator = snap_to_nominal(1.0 / t, &NOMINAL_SHUTTER_DENOMINATORS).unwrap_or(1.0 / t);
+    Some(format!("1/{:.0} s", denominator))
+}
+
+/// Windows writes -1 for "rejected", which counts as zero stars here
+fn clamp_rating(rating: i64) -> u8 {
+    rating.clamp(0, 5) as u8
+}
+
+/// the first value of an integer tag of any width, when above zero
+fn positive(value: &rexif::TagValue) -> Option<i64> {
+    value.to_i64(0).filter(|&v| v > 0)
+}
+
+/// trimmed, including the NUL padding some cameras leave in fixed-size fields
+fn ascii(value: &rexif::TagValue) -> Option<String> {
+    match value {
+        rexif::TagValue::Ascii(s) => {
+            let trimmed = s.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+            (!trimmed.is_empty()).then(|| trimmed.to_string())
+        }
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct Datetimes {
+    original: Option<String>,
+    sidecar: Option<String>,
+    digitized: Option<String>,
+    modified: Option<String>,
+    offset_original: Option<String>,
+    offset_digitized: Option<String>,
+    offset_modified: Option<String>,
+}
+
+impl Datetimes {
+    /// the preferred datetime that parses, with the offset belonging to it
+    fn best(self) -> Option<(DatetimeSource, String, Option<String>)> {
+        let offset_modified = self.offset_modified;
+        [
+            (
+                DatetimeSource::Original,
+                self.original,
+                self.offset_original.or(offset_modified.clone()),
+            ),
+            (DatetimeSource::Sidecar, self.sidecar, None),
+            (
+                DatetimeSource::Digitized,
+                self.digitized,
+                self.offset_digitized.or(offset_modified.clone()),
+            ),
+            (DatetimeSource::Modified, self.modified, offset_modified),
+        ]
+        .into_iter()
+        .find_map(|(source, raw, offset)| {
+            let raw = raw.filter(|r| parse_datetime(r).is_some())?;
+            Some((source, raw, offset))
+        })
+    }
+}
+
+#[derive(Default)]
+struct Isos {
+    /// ISOSpeedRatings, called PhotographicSensitivity since EXIF 2.3
+    sensitivity: Option<i64>,
+    recommended_exposure_index: Option<i64>,
+    iso_speed: Option<i64>,
+}
+
+impl Isos {
+    /// PhotographicSensitivity is a U16 that saturates at 65535 for higher ISOs,
+    /// the newer U32 tags carry the real value then
+    fn best(&self) -> Option<i64> {
+        let newer = self.recommended_exposure_index.or(self.iso_speed);
+        match self.sensitivity {
+            Some(iso) if iso < u16::MAX as i64 => Some(iso),
+            clipped => newer.or(clipped),
+        }
+    }
 }
 
 #[derive(Default)]
@@ -118,6 +951,8 @@ struct GpsData {
     lat_ref: Option<String>,
     lon: Option<(f64, f64, f64)>,
     lon_ref: Option<String>,
+    altitude: Option<f64>,
+    altitude_ref: Option<i64>,
 }
 
 impl GpsData {
@@ -157,6 +992,15 @@ impl GpsData {
         }
     }
 
+    /// a missing ref is read as above sea level, 1 means below
+    fn altitude_meters(&self) -> Option<f64> {
+        let altitude = self.altitude.filter(|a| a.is_finite())?;
+        match self.altitude_ref {
+            Some(1) => Some(-altitude),
+            _ => Some(altitude),
+        }
+    }
+
     fn to_decimal(&self) -> Option<(f64, f64)> {
         let (lat_d, lat_m, lat_s) = self.lat?;
         let lat_ref = self.lat_ref.as_ref()?;
@@ -177,70 +1021,797 @@ impl GpsData {
     }
 }
 
-/// format: "YYYY:MM:DD HH:MM:SS"
-fn parse_hour_from_datetime(datetime: &str) -> Option<u8> {
-    if datetime.len() >= 13 {
-        let hour_str = &datetime[11..13];
-        if let Ok(hour) = hour_str.parse::<u8>() {
-            if hour <= 23 {
-                return Some(hour);
-            }
-        }
+/// "YYYY:MM:DD HH:MM:SS" as the standard says, also accepting "-" or "/" between
+/// the date parts, missing seconds, and trailing fractions or zones; the all-zero
+/// placeholder and other impossible dates give None
+fn parse_datetime(raw: &str) -> Option<NaiveDateTime> {
+    let (date, time) = raw.trim().split_once([' ', 'T'])?;
+
+    let mut date_parts = date.split([':', '-', '/']);
+    let year = number(date_parts.next()?, 4..=4)?;
+    let month = number(date_parts.next()?, 1..=2)?;
+    let day = number(date_parts.next()?, 1..=2)?;
+    if date_parts.next().is_some() {
+        return None;
+    }
+
+    let time = time.trim_start();
+    let clock_end = time
+        .find(|c: char| !c.is_ascii_digit() && c != ':')
+        .unwrap_or(time.len());
+    let (clock, rest) = time.split_at(clock_end);
+    if !rest.is_empty() && !rest.starts_with(['.', '+', '-', 'Z', ' ']) {
+        return None;
     }
-    None
+
+    let mut clock_parts = clock.split(':');
+    let hour = number(clock_parts.next()?, 1..=2)?;
+    let minute = number(clock_parts.next()?, 2..=2)?;
+    let second = match clock_parts.next() {
+        Some(s) => number(s, 2..=2)?,
+        None => 0,
+    };
+    if clock_parts.next().is_some() || year == 0 {
+        return None;
+    }
+
+    NaiveDate::from_ymd_opt(year as i32, month, day)?.and_hms_opt(hour, minute, second)
 }
 
-fn format_datetime(s: &str) -> String {
-    if s.len() < 19 {
-        return s.to_string();
+/// an all-digit field of an allowed length
+fn number(s: &str, digits: RangeInclusive<usize>) -> Option<u32> {
+    if !digits.contains(&s.len()) || !s.chars().all(|c| c.is_ascii_digit()) {
+        return None;
     }
+    s.parse().ok()
+}
 
-    let month_name = match &s[5..7] {
-        "01" => "January",
-        "02" => "February",
-        "03" => "March",
-        "04" => "April",
-        "05" => "May",
-        "06" => "June",
-        "07" => "July",
-        "08" => "August",
-        "09" => "September",
-        "10" => "October",
-        "11" => "November",
-        "12" => "December",
-        m => m,
+/// "+02:00" / "-05:30" to minutes east of UTC
+pub fn parse_offset_minutes(offset: &str) -> Option<i64> {
+    let sign = match offset.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
     };
+    let (hours, minutes) = offset.get(1..)?.split_once(':')?;
+    let (hours, minutes): (i64, i64) = (hours.parse().ok()?, minutes.parse().ok()?);
+    if hours > 14 || minutes > 59 {
+        return None;
+    }
+    Some(sign * (hours * 60 + minutes))
+}
+
+/// "May 3, 2021 at 19:12"
+fn format_datetime(dt: &NaiveDateTime) -> String {
+    dt.format("%B %-d, %Y at %H:%M").to_string()
+}
+
+/// 360 is the same as 0; anything outside 0..=360 is a broken tag
+fn normalize_direction(degrees: f64) -> Option<f64> {
+    if !(0.0..=360.0).contains(&degrees) {
+        return None;
+    }
+    Some(degrees % 360.0)
+}
+
+/// one of the 16 compass points, e.g. "NNE"
+pub fn compass_point(degrees: f64) -> &'static str {
+    const POINTS: [&str; 16] = [
+        "N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE", "S", "SSW", "SW", "WSW", "W", "WNW",
+        "NW", "NNW",
+    ];
+    let index = (degrees.rem_euclid(360.0) / 22.5).round() as usize % POINTS.len();
+    POINTS[index]
+}
 
-    let day: u32 = s[8..10].parse().unwrap_or(0);
-    format!(
-        "{} {}, {} at {}:{}",
-        month_name,
-        day,
-        &s[0..4],
-        &s[11..13],
-        &s[14..16]
-    )
-}
-
-fn format_gps_coordinates(lat: f64, lon: f64) -> String {
-    let (lat_dir, lon_dir) = (
-        if lat >= 0.0 { "N" } else { "S" },
-        if lon >= 0.0 { "E" } else { "W" },
-    );
-    let (lat_abs, lon_abs) = (lat.abs(), lon.abs());
-
-    let format_dms = |v: f64| {
-        let deg = v.floor();
-        let min = ((v - deg) * 60.0).floor();
-        let sec = ((v - deg) * 60.0 - min) * 60.0;
-        (deg, min, sec)
+/// "60°23'12.4\"N 5°19'26.9\"E", rounded to a tenth of a second
+fn format_dms(lat: f64, lon: f64) -> String {
+    let dms = |v: f64, positive: &str, negative: &str| {
+        let tenths = (v.abs() * 36_000.0).round() as u64;
+        format!(
+            "{}°{}'{:.1}\"{}",
+            tenths / 36_000,
+            tenths % 36_000 / 600,
+            (tenths % 600) as f64 / 10.0,
+            if v >= 0.0 { positive } else { negative }
+        )
     };
+    format!("{} {}", dms(lat, "N", "S"), dms(lon, "E", "W"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// one IFD entry: tag, TIFF type, count and the little-endian value bytes
+    type Field = (u16, u16, u32, Vec<u8>);
+
+    const BYTE: u16 = 1;
+    const ASCII: u16 = 2;
+    const SHORT: u16 = 3;
+    const LONG: u16 = 4;
+    const RATIONAL: u16 = 5;
+    const UNDEFINED: u16 = 7;
+    const SRATIONAL: u16 = 10;
+
+    fn ascii_field(tag: u16, s: &str) -> Field {
+        let mut bytes = s.as_bytes().to_vec();
+        bytes.push(0);
+        (tag, ASCII, bytes.len() as u32, bytes)
+    }
+
+    fn short_field(tag: u16, v: u16) -> Field {
+        (tag, SHORT, 1, v.to_le_bytes().to_vec())
+    }
+
+    fn long_field(tag: u16, v: u32) -> Field {
+        (tag, LONG, 1, v.to_le_bytes().to_vec())
+    }
 
-    let (lat_d, lat_m, lat_s) = format_dms(lat_abs);
-    let (lon_d, lon_m, lon_s) = format_dms(lon_abs);
+    fn rational_field(tag: u16, values: &[(u32, u32)]) -> Field {
+        let bytes = values
+            .iter()
+            .flat_map(|&(n, d)| [n.to_le_bytes(), d.to_le_bytes()].concat())
+            .collect();
+        (tag, RATIONAL, values.len() as u32, bytes)
+    }
+
+    /// the serialized size of an IFD, its out-of-line values included
+    fn ifd_len(fields: &[Field]) -> usize {
+        let data: usize = fields
+            .iter()
+            .filter(|f| f.3.len() > 4)
+            .map(|f| f.3.len().next_multiple_of(2))
+            .sum();
+        2 + 12 * fields.len() + 4 + data
+    }
+
+    fn write_ifd(out: &mut Vec<u8>, mut fields: Vec<Field>, next: u32) {
+        fields.sort_by_key(|f| f.0);
+        let mut data_at = out.len() + 2 + 12 * fields.len() + 4;
+        let mut data = Vec::new();
+
+        out.extend((fields.len() as u16).to_le_bytes());
+        for (tag, kind, count, value) in fields {
+            out.extend(tag.to_le_bytes());
+            out.extend(kind.to_le_bytes());
+            out.extend(count.to_le_bytes());
+            if value.len() <= 4 {
+                let mut inline = value;
+                inline.resize(4, 0);
+                out.extend(inline);
+            } else {
+                out.extend((data_at as u32).to_le_bytes());
+                data_at += value.len().next_multiple_of(2);
+                data.extend(&value);
+                data.resize(data.len().next_multiple_of(2), 0);
+            }
+        }
+        out.extend(next.to_le_bytes());
+        out.extend(data);
+    }
+
+    /// a little-endian TIFF block as a camera writes it: IFD0 pointing to the EXIF
+    /// and GPS IFDs when they have entries, then IFD1 (the thumbnail) when it does
+    fn tiff(mut ifd0: Vec<Field>, exif: Vec<Field>, gps: Vec<Field>, ifd1: Vec<Field>) -> Vec<u8> {
+        let pointers = [!exif.is_empty(), !gps.is_empty()]
+            .iter()
+            .filter(|&&p| p)
+            .count();
+        let ifd0_at = 8;
+        let exif_at = ifd0_at + ifd_len(&ifd0) + 12 * pointers;
+        let gps_at = exif_at + if exif.is_empty() { 0 } else { ifd_len(&exif) };
+        let ifd1_at = gps_at + if gps.is_empty() { 0 } else { ifd_len(&gps) };
+
+        if !exif.is_empty() {
+            ifd0.push((0x8769, LONG, 1, (exif_at as u32).to_le_bytes().to_vec()));
+        }
+        if !gps.is_empty() {
+            ifd0.push((0x8825, LONG, 1, (gps_at as u32).to_le_bytes().to_vec()));
+        }
+
+        let mut out = b"II*\0".to_vec();
+        out.extend((ifd0_at as u32).to_le_bytes());
+        let next = if ifd1.is_empty() { 0 } else { ifd1_at as u32 };
+        write_ifd(&mut out, ifd0, next);
+        for ifd in [exif, gps, ifd1] {
+            if !ifd.is_empty() {
+                write_ifd(&mut out, ifd, 0);
+            }
+        }
+        out
+    }
+
+    /// SOI, an APP1 segment carrying `tiff` and EOI, without any image data
+    fn jpeg(tiff: &[u8]) -> Vec<u8> {
+        let mut out = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        out.extend(((2 + 6 + tiff.len()) as u16).to_be_bytes());
+        out.extend(b"Exif\0\0");
+        out.extend(tiff);
+        out.extend([0xFF, 0xD9]);
+        out
+    }
+
+    /// the signature, a 1x1 IHDR, an eXIf chunk carrying `tiff` and IEND; the CRCs
+    /// are left zero since the chunk walker doesn't check them
+    fn png(tiff: &[u8]) -> Vec<u8> {
+        let chunk = |kind: &[u8], data: &[u8]| {
+            let mut out = (data.len() as u32).to_be_bytes().to_vec();
+            out.extend(kind);
+            out.extend(data);
+            out.extend([0; 4]);
+            out
+        };
+        let ihdr = [0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0];
+        [
+            PNG_SIGNATURE.to_vec(),
+            chunk(b"IHDR", &ihdr),
+            chunk(b"eXIf", tiff),
+            chunk(b"IEND", &[]),
+        ]
+        .concat()
+    }
+
+    /// the photo of the summer beach most tests read: taken 21 July 2019 at
+    /// 14:03:55 at 60°23'12.4"N 5°19'26.9"E
+    fn beach() -> Vec<u8> {
+        tiff(
+            vec![ascii_field(0x010F, "Canon"), ascii_field(0x0110, "EOS R6")],
+            vec![ascii_field(0x9003, "2019:07:21 14:03:55")],
+            vec![
+                ascii_field(0x0001, "N"),
+                rational_field(0x0002, &[(60, 1), (23, 1), (124, 10)]),
+                ascii_field(0x0003, "E"),
+                rational_field(0x0004, &[(5, 1), (19, 1), (269, 10)]),
+            ],
+            vec![],
+        )
+    }
+
+    #[test]
+    fn png_exif_chunks_are_read() {
+        let path = fixture("beach.png", &png(&beach()));
+        let info = extract(&path);
+        let without = fixture("plain.png", &png(&[])[..33]);
+        let plain = try_extract(&without);
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(&without);
+
+        assert_eq!(info.datetime_raw.as_deref(), Some("2019:07:21 14:03:55"));
+        assert_eq!(info.hour, Some(14));
+        assert_eq!(info.camera.as_deref(), Some("Canon EOS R6"));
+        let (lat, lon) = (info.gps_latitude.unwrap(), info.gps_longitude.unwrap());
+        assert!((lat - 60.386_778).abs() < 1e-5, "{}", lat);
+        assert!((lon - 5.324_139).abs() < 1e-5, "{}", lon);
+        assert_eq!(
+            GpsFormat::Dms.format(lat, lon),
+            "60°23'12.4\"N 5°19'26.9\"E"
+        );
+
+        // the signature and IHDR alone: nothing to read and nothing wrong
+        assert!(
+            matches!(plain, Ok(ref info) if info.datetime_raw.is_none()),
+            "{:?}",
+            plain
+        );
+    }
+
+    fn fixture(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "wallpaper_slideshow_exif_{}_{}",
+            std::process::id(),
+            name
+        ));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn datetimes_as_cameras_and_editors_write_them() {
+        // every one of these is 21 July 2019 at 14:03
+        let variants = [
+            "2019:07:21 14:03:55",
+            "2019-07-21 14:03:55",
+            "2019/07/21 14:03:55",
+            "2019/07/21 14:03",
+            "2019-07-21 14:03",
+            "2019:7:21 14:03:00",
+            "  2019:07:21 14:03:55  ",
+            "2019:07:21  14:03:55",
+            "2019-07-21T14:03:55",
+            "2019:07:21 14:03:55.123",
+            "2019:07:21 14:03:55+02:00",
+            "2019-07-21T14:03:55Z",
+        ];
+        for raw in variants {
+            let parsed = parse_datetime(raw).unwrap_or_else(|| panic!("{:?} didn't parse", raw));
+            assert_eq!(
+                (parsed.hour(), parsed.minute()),
+                (14, 3),
+                "{:?} gave {}",
+                raw,
+                parsed
+            );
+            assert_eq!(
+                format_datetime(&parsed),
+                "July 21, 2019 at 14:03",
+                "{:?}",
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn garbage_datetimes_are_rejected() {
+        let garbage = [
+            "",
+            "0000:00:00 00:00:00",
+            "    :  :     :  :  ",
+            "2019:07:21",
+            "2019:13:21 14:03:55",
+            "2019:07:32 14:03:55",
+            "2019:07:21 24:00:00",
+            "2019:07:21 14:60:00",
+            "2019:07:21 14:3",
+            "2019.07.21 14:03:55",
+            "19:07:21 14:03:55",
+            "2019:07:21:01 14:03:55",
+            "2019:07:21 14:03:55:00",
+            "2019:07:21 14:03:55pm",
+            "yesterday 14:03",
+        ];
+        for raw in garbage {
+            assert_eq!(parse_datetime(raw), None, "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(parse_offset_minutes("+02:00"), Some(120));
+        assert_eq!(parse_offset_minutes("-05:30"), Some(-330));
+        assert_eq!(parse_offset_minutes("+00:00"), Some(0));
+        assert_eq!(parse_offset_minutes("02:00"), None);
+        assert_eq!(parse_offset_minutes("+15:00"), None);
+        assert_eq!(parse_offset_minutes("+02:60"), None);
+        assert_eq!(parse_offset_minutes("   :  "), None);
+    }
+
+    #[test]
+    fn exposure_bias_in_tenths_of_a_stop() {
+        assert_eq!(format_exposure_bias(-2, 3).as_deref(), Some("-0.7 EV"));
+        assert_eq!(format_exposure_bias(1, 3).as_deref(), Some("+0.3 EV"));
+        assert_eq!(format_exposure_bias(2, -2).as_deref(), Some("-1.0 EV"));
+        assert_eq!(format_exposure_bias(0, 3), None);
+        // a thirtieth of a stop is nothing worth showing
+        assert_eq!(format_exposure_bias(1, 30), None);
+        assert_eq!(format_exposure_bias(1, 0), None);
+
+        // stored as a signed rational
+        let mut bias = (-2i32).to_le_bytes().to_vec();
+        bias.extend(3i32.to_le_bytes());
+        let path = fixture(
+            "bias.jpg",
+            &jpeg(&tiff(
+                vec![],
+                vec![(0x9204, SRATIONAL, 1, bias)],
+                vec![],
+                vec![],
+            )),
+        );
+        let info = extract(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(info.exposure_bias.as_deref(), Some("-0.7 EV"));
+    }
+
+    #[test]
+    fn altitude_below_sea_level_is_negative() {
+        let altitude = |altitude, altitude_ref| {
+            GpsData {
+                altitude,
+                altitude_ref,
+                ..GpsData::default()
+            }
+            .altitude_meters()
+        };
+        assert_eq!(altitude(Some(1250.5), Some(0)), Some(1250.5));
+        assert_eq!(altitude(Some(28.0), Some(1)), Some(-28.0));
+        assert_eq!(altitude(Some(1250.5), None), Some(1250.5));
+        assert_eq!(altitude(None, Some(1)), None);
+        assert_eq!(altitude(Some(f64::NAN), Some(0)), None);
 
-    format!(
-        "{:.0}deg{:.0}'{:.1}\"{} {:.0}deg{:.0}'{:.1}\"{}",
-        lat_d, lat_m, lat_s, lat_dir, lon_d, lon_m, lon_s, lon_dir
-    )
+        // the Dead Sea shore, as a phone writes it
+        let path = fixture(
+            "dead_sea.jpg",
+            &jpeg(&tiff(
+                vec![],
+                vec![],
+                vec![
+                    (0x0005, BYTE, 1, vec![1]),
+                    rational_field(0x0006, &[(4305, 10)]),
+                ],
+                vec![],
+            )),
+        );
+        let info = extract(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(info.gps_altitude, Some(-430.5));
+    }
+
+    #[test]
+    fn orientations() {
+        assert_eq!(
+            ExifOrientation::from_exif(3),
+            Some(ExifOrientation::Rotate180)
+        );
+        assert_eq!(
+            ExifOrientation::from_exif(6),
+            Some(ExifOrientation::Rotate90Cw)
+        );
+        assert_eq!(
+            ExifOrientation::from_exif(8),
+            Some(ExifOrientation::Rotate90Ccw)
+        );
+        for value in 1..=8 {
+            assert_eq!(
+                ExifOrientation::from_exif(value).map(|o| o.as_exif()),
+                Some(value)
+            );
+        }
+        for value in [0, 9, 255, u16::MAX] {
+            assert_eq!(ExifOrientation::from_exif(value), None, "{}", value);
+        }
+
+        let path = fixture(
+            "portrait.jpg",
+            &jpeg(&tiff(vec![short_field(0x0112, 6)], vec![], vec![], vec![])),
+        );
+        let info = extract(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(info.orientation, Some(ExifOrientation::Rotate90Cw));
+    }
+
+    #[test]
+    fn datetimes_fall_back_tag_by_tag() {
+        let some = |s: &str| Some(s.to_string());
+        let all = || Datetimes {
+            original: some("2019:07:21 14:03:55"),
+            sidecar: some("2019:07:21 14:03:00"),
+            digitized: some("2019:07:21 18:30:00"),
+            modified: some("2020:01:02 09:00:00"),
+            offset_original: some("+02:00"),
+            offset_digitized: some("+01:00"),
+            offset_modified: some("+00:00"),
+        };
+        assert_eq!(
+            all().best(),
+            Some((
+                DatetimeSource::Original,
+                "2019:07:21 14:03:55".into(),
+                some("+02:00")
+            ))
+        );
+        // the all-zero placeholder counts as missing
+        let placeholder = Datetimes {
+            original: some("0000:00:00 00:00:00"),
+            ..all()
+        };
+        assert_eq!(
+            placeholder.best(),
+            Some((DatetimeSource::Sidecar, "2019:07:21 14:03:00".into(), None))
+        );
+        let digitized = Datetimes {
+            original: None,
+            sidecar: None,
+            ..all()
+        };
+        assert_eq!(
+            digitized.best(),
+            Some((
+                DatetimeSource::Digitized,
+                "2019:07:21 18:30:00".into(),
+                some("+01:00")
+            ))
+        );
+        // without its own offset a tag borrows OffsetTime, which goes with DateTime
+        let borrowed = Datetimes {
+            original: None,
+            sidecar: None,
+            offset_digitized: None,
+            ..all()
+        };
+        assert_eq!(
+            borrowed.best(),
+            Some((
+                DatetimeSource::Digitized,
+                "2019:07:21 18:30:00".into(),
+                some("+00:00")
+            ))
+        );
+        let modified = Datetimes {
+            modified: some("2020:01:02 09:00:00"),
+            ..Datetimes::default()
+        };
+        assert_eq!(
+            modified.best(),
+            Some((DatetimeSource::Modified, "2020:01:02 09:00:00".into(), None))
+        );
+        assert_eq!(Datetimes::default().best(), None);
+    }
+
+    #[test]
+    fn seasons_change_on_the_first_of_the_month() {
+        use Hemisphere::{Northern, Southern};
+        use Season::{Autumn, Spring, Summer, Winter};
+
+        let boundaries = [
+            (2, Winter, Summer),
+            (3, Spring, Autumn),
+            (5, Spring, Autumn),
+            (6, Summer, Winter),
+            (8, Summer, Winter),
+            (9, Autumn, Spring),
+            (11, Autumn, Spring),
+            (12, Winter, Summer),
+            (1, Winter, Summer),
+        ];
+        for (month, northern, southern) in boundaries {
+            assert_eq!(
+                Season::from_month(month, Northern),
+                Some(northern),
+                "{}",
+                month
+            );
+            assert_eq!(
+                Season::from_month(month, Southern),
+                Some(southern),
+                "{}",
+                month
+            );
+        }
+        for month in [0, 13] {
+            assert_eq!(Season::from_month(month, Northern), None);
+            assert_eq!(Season::from_month(month, Southern), None);
+        }
+    }
+
+    #[test]
+    fn coordinates_in_degrees_minutes_and_seconds() {
+        assert_eq!(
+            format_dms(-33.8688, 151.2093),
+            "33°52'7.7\"S 151°12'33.5\"E"
+        );
+        assert_eq!(format_dms(0.0, 0.0), "0°0'0.0\"N 0°0'0.0\"E");
+        assert_eq!(format_dms(0.0001, -0.0001), "0°0'0.4\"N 0°0'0.4\"W");
+        assert_eq!(format_dms(-90.0, 180.0), "90°0'0.0\"S 180°0'0.0\"E");
+        assert_eq!(format_dms(0.0, -180.0), "0°0'0.0\"N 180°0'0.0\"W");
+
+        // 59.99" rounds up into the next minute and degree, never to 60.0"
+        let almost = 10.0 + 59.0 / 60.0 + 59.99 / 3600.0;
+        assert_eq!(format_dms(almost, -almost), "11°0'0.0\"N 11°0'0.0\"W");
+        assert_eq!(format_dms(0.0, 179.999_999), "0°0'0.0\"N 180°0'0.0\"E");
+
+        assert_eq!(
+            GpsFormat::Decimal.format(-33.8688, 151.2093),
+            "-33.86880, 151.20930"
+        );
+        assert_eq!(
+            GpsFormat::Both.format(-33.8688, -0.0001),
+            "33°52'7.7\"S 0°0'0.4\"W (-33.86880, -0.00010)"
+        );
+    }
+
+    #[test]
+    fn lenses_from_each_tag_layout() {
+        let lens = |name: &str, exif: Vec<Field>| {
+            let path = fixture(name, &jpeg(&tiff(vec![], exif, vec![], vec![])));
+            let info = extract(&path);
+            let _ = fs::remove_file(&path);
+            info.lens
+        };
+        let spec = |values| rational_field(0xA432, values);
+
+        assert_eq!(
+            lens(
+                "model.jpg",
+                vec![ascii_field(0xA434, "RF24-70mm F2.8 L IS USM")]
+            )
+            .as_deref(),
+            Some("RF24-70mm F2.8 L IS USM")
+        );
+        // LensModel already names the lens, the make isn't repeated
+        assert_eq!(
+            lens(
+                "make_model.jpg",
+                vec![
+                    ascii_field(0xA433, "Canon"),
+                    ascii_field(0xA434, "RF24-70mm F2.8 L IS USM"),
+                ]
+            )
+            .as_deref(),
+            Some("RF24-70mm F2.8 L IS USM")
+        );
+        assert_eq!(
+            lens(
+                "make_spec.jpg",
+                vec![
+                    ascii_field(0xA433, "Canon"),
+                    spec(&[(24, 1), (70, 1), (28, 10), (4, 1)]),
+                ]
+            )
+            .as_deref(),
+            Some("Canon 24-70mm f/2.8-4")
+        );
+        assert_eq!(
+            lens(
+                "prime.jpg",
+                vec![spec(&[(50, 1), (50, 1), (18, 10), (18, 10)])]
+            )
+            .as_deref(),
+            Some("50mm f/1.8")
+        );
+        // 0/0 is how the standard writes an unknown value
+        assert_eq!(
+            lens(
+                "no_aperture.jpg",
+                vec![spec(&[(24, 1), (70, 1), (0, 0), (0, 0)])]
+            )
+            .as_deref(),
+            Some("24-70mm")
+        );
+
+        assert_eq!(describe_lens(Some("Canon".to_string()), None), None);
+        assert_eq!(describe_lens(None, Some(&[])), None);
+    }
+
+    #[test]
+    fn isos_past_the_sixteen_bit_limit() {
+        let isos = |sensitivity, recommended_exposure_index, iso_speed| {
+            Isos {
+                sensitivity,
+                recommended_exposure_index,
+                iso_speed,
+            }
+            .best()
+        };
+        assert_eq!(isos(Some(800), Some(1000), None), Some(800));
+        assert_eq!(isos(Some(65535), Some(102400), Some(100000)), Some(102400));
+        assert_eq!(isos(Some(65535), None, Some(204800)), Some(204800));
+        assert_eq!(isos(Some(65535), None, None), Some(65535));
+        assert_eq!(isos(None, None, None), None);
+
+        let iso = |name: &str, exif: Vec<Field>| {
+            let path = fixture(name, &jpeg(&tiff(vec![], exif, vec![], vec![])));
+            let info = extract(&path);
+            let _ = fs::remove_file(&path);
+            info.iso
+        };
+        assert_eq!(
+            iso(
+                "saturated.jpg",
+                vec![short_field(0x8827, 65535), long_field(0x8832, 102400)]
+            )
+            .as_deref(),
+            Some("ISO 102400")
+        );
+        // a body that writes only the EXIF 2.3 tags
+        assert_eq!(
+            iso("newer_only.jpg", vec![long_field(0x8833, 204800)]).as_deref(),
+            Some("ISO 204800")
+        );
+    }
+
+    #[test]
+    fn dump_lists_every_tag_in_file_order() {
+        let path = fixture(
+            "dump.jpg",
+            &jpeg(&tiff(
+                vec![ascii_field(0x010F, "Canon"), short_field(TAG_RATING, 4)],
+                vec![
+                    ascii_field(0x9003, "2019:07:21 14:03:55"),
+                    (0x927C, UNDEFINED, 64, vec![0xAB; 64]),
+                ],
+                vec![
+                    ascii_field(0x0001, "N"),
+                    rational_field(0x0002, &[(60, 1), (23, 1), (124, 10)]),
+                ],
+                // where the thumbnail JPEG starts and how long it is
+                vec![long_field(0x0201, 0), long_field(0x0202, 0)],
+            )),
+        );
+        let entries = dump(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        let listed: Vec<(&str, u16)> = entries.iter().map(|e| (e.ifd, e.tag_id)).collect();
+        // rexif reads only IFD0 and the EXIF and GPS IFDs it points to, so the
+        // thumbnail's IFD1 is skipped
+        assert_eq!(
+            listed,
+            [
+                ("IFD0", 0x010F),
+                ("IFD0", TAG_RATING),
+                ("IFD0", 0x8769),
+                ("IFD0", 0x8825),
+                ("EXIF", 0x9003),
+                ("EXIF", 0x927C),
+                ("GPS", 0x0001),
+                ("GPS", 0x0002),
+            ]
+        );
+
+        let value = |tag_id: u16| {
+            let entry = entries.iter().find(|e| e.tag_id == tag_id).unwrap();
+            (entry.tag.as_str(), entry.value.as_str())
+        };
+        assert_eq!(value(0x010F), ("Manufacturer", "Canon"));
+        assert_eq!(value(TAG_RATING).0, "0x4746");
+        assert_eq!(value(0x927C), ("Maker note", "<64 bytes>"));
+        assert_eq!(value(0x0002).1, "60°23'12.40\" N");
+    }
+
+    #[test]
+    fn a_broken_file_fails_alone() {
+        let good = jpeg(&beach());
+        let mut junk_tiff = vec![0xFF, 0xD8, 0xFF, 0xE1, 0x00, 0x40];
+        junk_tiff.extend(b"Exif\0\0");
+        junk_tiff.extend((0..0x40).map(|i: u8| i.wrapping_mul(37)));
+        let mut past_eof = beach();
+        past_eof[4..8].copy_from_slice(&0x7FFF_FFFFu32.to_le_bytes());
+
+        let paths = [
+            fixture("good.jpg", &good),
+            fixture("truncated.jpg", &good[..40]),
+            fixture("junk.jpg", &junk_tiff),
+            fixture("past_eof.jpg", &jpeg(&past_eof)),
+            fixture("good_again.jpg", &good),
+        ];
+        let extracted = extract_many(&paths);
+        for path in &paths {
+            let _ = fs::remove_file(path);
+        }
+
+        assert_eq!(extracted.len(), paths.len());
+        for broken in &extracted[1..4] {
+            assert!(
+                matches!(broken.error, Some(ExifError::Parse(_))),
+                "{:?}: {:?}",
+                broken.path,
+                broken.error
+            );
+            assert_eq!(broken.info.datetime_raw, None);
+        }
+        for good in [&extracted[0], &extracted[4]] {
+            assert!(good.error.is_none(), "{:?}", good.error);
+            assert_eq!(
+                good.info.datetime_raw.as_deref(),
+                Some("2019:07:21 14:03:55")
+            );
+            assert_eq!(good.info.camera.as_deref(), Some("Canon EOS R6"));
+        }
+    }
+
+    #[test]
+    fn apex_apertures_snap_to_marked_f_stops() {
+        assert_eq!(aperture_from_apex(0.0).as_deref(), Some("f/1.0"));
+        // 2^2.5 is 5.66, marked as f/5.6
+        assert_eq!(aperture_from_apex(5.0).as_deref(), Some("f/5.6"));
+        // 2^3.5 is 11.31, marked as f/11
+        assert_eq!(aperture_from_apex(7.0).as_deref(), Some("f/11.0"));
+        // beyond f/32 there is nothing to snap to
+        assert_eq!(aperture_from_apex(12.0).as_deref(), Some("f/64.0"));
+        assert_eq!(aperture_from_apex(f64::NAN), None);
+    }
+
+    #[test]
+    fn apex_shutter_speeds_snap_to_marked_speeds() {
+        assert_eq!(exposure_from_apex(0.0).as_deref(), Some("1.0 s"));
+        assert_eq!(exposure_from_apex(-1.0).as_deref(), Some("2.0 s"));
+        // 1/128 is marked as 1/125 and 1/256 as 1/250, but 1/64 is too far from 1/60
+        assert_eq!(exposure_from_apex(7.0).as_deref(), Some("1/125 s"));
+        assert_eq!(exposure_from_apex(8.0).as_deref(), Some("1/250 s"));
+        assert_eq!(exposure_from_apex(6.0).as_deref(), Some("1/64 s"));
+        // 1/16384 is faster than any marked speed
+        assert_eq!(exposure_from_apex(14.0).as_deref(), Some("1/16384 s"));
+        assert_eq!(exposure_from_apex(f64::INFINITY), None);
+    }
 }
@@ -1,45 +1,171 @@
-use std::collections::HashSet;
+use chrono::{Local, TimeZone};
+use glob::Pattern;
+use rayon::prelude::*;
+use rusqlite::params;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
 use std::fs::{self, File, OpenOptions};
-use std::io::{BufRead, BufReader, Write};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use crate::cache::Cache;
 use crate::config;
-use crate::discovery;
+use crate::discovery::{self, ImageFile};
+use crate::exif;
+
+/// where shown wallpapers are recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryBackend {
+    /// one line per wallpaper in `config::history_log`
+    File,
+    /// the `history` table of the cache database, see `SqliteHistory`
+    Sqlite,
+}
 
 pub fn load_recent() -> HashSet<String> {
     load_recent_with_size(config::HISTORY_SIZE)
 }
 
 pub fn load_recent_with_size(limit: usize) -> HashSet<String> {
-    let path = config::history_log();
-    let path = Path::new(&path);
+    load_recent_ordered(limit).into_iter().collect()
+}
+
+/// every entry still in the history, not just the recent ones
+pub fn load_all() -> HashSet<String> {
+    load_recent_with_size(usize::MAX)
+}
+
+/// the last `limit` entries, newest first
+pub fn load_recent_ordered(limit: usize) -> Vec<String> {
+    load_recent_on(None, limit)
+}
 
-    if !path.exists() {
-        return HashSet::new();
+/// the last `limit` entries shown on `monitor`, newest first, counting those
+/// logged without one as shown everywhere; every entry for None
+pub fn load_recent_on(monitor: Option<&str>, limit: usize) -> Vec<String> {
+    if config::history_backend() == HistoryBackend::Sqlite {
+        return SqliteHistory::open()
+            .and_then(|history| history.load_recent(monitor, limit))
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to read history: {}", e);
+                Vec::new()
+            });
     }
 
-    let file = match File::open(path) {
-        Ok(f) => f,
-        Err(_) => return HashSet::new(),
+    let Some((lines, _)) = read_log_lines(Path::new(&config::history_log())) else {
+        return Vec::new();
     };
-
-    let reader = BufReader::new(file);
-    reader
-        .lines()
-        .map_while(Result::ok)
-        .collect::<Vec<_>>()
+    lines
         .into_iter()
         .rev()
+        .filter(|(_, shown_on)| {
+            monitor.is_none() || shown_on.is_none() || shown_on.as_deref() == monitor
+        })
+        .map(|(entry, _)| entry)
         .take(limit)
         .collect()
 }
 
-pub fn log(basename: &str) {
+/// how much less likely `path` is to be picked for having been shown lately,
+/// from `recent` newest first: the newest entry weighs 1/(n+1) and the weight
+/// climbs linearly to 1 for anything older than the window
+pub fn recency_weight(recent: &[String], path: &Path) -> f64 {
+    shown_ago(recent, path).map_or(1.0, |i| (i + 1) as f64 / (recent.len() + 1) as f64)
+}
+
+/// how many entries of `recent` (newest first) are newer than the newest one
+/// for `path`, None if it isn't there
+pub fn shown_ago(recent: &[String], path: &Path) -> Option<usize> {
+    let entry = entry_for(path);
+    let basename = path.file_name().and_then(|s| s.to_str());
+    recent
+        .iter()
+        .position(|r| *r == entry || Some(r.as_str()) == basename)
+}
+
+/// `boost` for a wallpaper among `favorites`, else 1; a weight rather than a
+/// filter so the rest still get their turn
+pub fn favorite_weight(favorites: &PathList, path: &Path, boost: f64) -> f64 {
+    if favorites.contains(path) {
+        boost
+    } else {
+        1.0
+    }
+}
+
+/// how much less likely a wallpaper shown `count` times is to be picked when
+/// the least shown candidate was shown `least` times: 1/(1 + count - least).
+/// Counting from the least shown rather than from zero keeps a new or never
+/// shown image from outweighing the rest by more than the gap it has to close
+pub fn fairness_weight(count: usize, least: usize) -> f64 {
+    1.0 / (1 + count.saturating_sub(least)) as f64
+}
+
+/// the history line for `path`: relative to the wallpaper root, or absolute
+/// for files outside it
+pub fn entry_for(path: &Path) -> String {
+    let root = config::wallpaper_dir();
+    path.strip_prefix(&root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// whether `path` is among the `recent` history lines; older logs hold bare
+/// basenames, which match a file of that name in any directory
+pub fn is_recent(recent: &HashSet<String>, path: &Path) -> bool {
+    recent.contains(&entry_for(path))
+        || path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .is_some_and(|name| recent.contains(name))
+}
+
+/// the file an entry names, searching the tree only for a legacy basename
+/// that isn't at the root
+pub fn resolve_entry(entry: &str) -> Option<PathBuf> {
+    let path = Path::new(&config::wallpaper_dir()).join(entry);
+    if path.is_file() {
+        Some(path)
+    } else if Path::new(entry).components().count() == 1 {
+        discovery::find_by_basename(entry)
+    } else {
+        None
+    }
+}
+
+/// record `wallpaper` as shown, on `monitor` if it went to a single output
+pub fn log(wallpaper: &Path, monitor: Option<&str>) {
+    if config::history_backend() == HistoryBackend::Sqlite {
+        if let Err(e) = SqliteHistory::open().and_then(|history| history.log(wallpaper, monitor)) {
+            eprintln!("Failed to write history: {}", e);
+        }
+        return;
+    }
+
     let path = config::history_log();
     if let Some(parent) = Path::new(&path).parent() {
         let _ = fs::create_dir_all(parent);
     }
 
+    let mut lock = match lock_log(Path::new(&path)) {
+        Ok(Some(lock)) => lock,
+        Ok(None) => {
+            eprintln!(
+                "History log is locked by another run, not recording {}",
+                wallpaper.display()
+            );
+            return;
+        }
+        Err(e) => {
+            eprintln!("Failed to lock history log: {}", e);
+            return;
+        }
+    };
+
     let mut file = match OpenOptions::new().create(true).append(true).open(&path) {
         Ok(f) => f,
         Err(e) => {
@@ -48,20 +174,699 @@ pub fn log(basename: &str) {
         }
     };
 
-    let _ = writeln!(file, "{}", basename);
+    // one write, so the line can't be split up
+    let _ = file.write_all(format!("{}\n", log_line(&entry_for(wallpaper), monitor)).as_bytes());
+    drop(file);
+
+    let rotated = count_append(&mut lock).and_then(|due| {
+        if due {
+            rotate(Path::new(&path), config::history_max_lines())
+        } else {
+            Ok(())
+        }
+    });
+    if let Err(e) = rotated {
+        eprintln!("Failed to rotate history log: {}", e);
+    }
+}
+
+/// how long `log` waits for another run to finish writing before giving up
+const LOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// an exclusive lock on `<log>.lock`, held until the file is dropped; a
+/// sidecar because `rotate` replaces the log itself, also holding the count
+/// for `count_append`. None on timeout
+fn lock_log(path: &Path) -> io::Result<Option<File>> {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(PathBuf::from(lock_path))?;
+
+    let start = Instant::now();
+    loop {
+        match lock.try_lock() {
+            Ok(()) => return Ok(Some(lock)),
+            Err(fs::TryLockError::WouldBlock) if start.elapsed() < LOCK_TIMEOUT => {
+                thread::sleep(Duration::from_millis(5));
+            }
+            Err(fs::TryLockError::WouldBlock) => return Ok(None),
+            Err(fs::TryLockError::Error(e)) => return Err(e),
+        }
+    }
+}
+
+/// the log may grow this far past its limit before it's trimmed, so only
+/// every this-many-th append reads it
+const ROTATE_SLACK: usize = 100;
+
+/// count an append in the held `lock` file, true on every `ROTATE_SLACK`th
+/// when the log is due to be checked, so the others don't read it
+fn count_append(lock: &mut File) -> io::Result<bool> {
+    let mut count = String::new();
+    lock.seek(SeekFrom::Start(0))?;
+    lock.read_to_string(&mut count)?;
+    let count = count.trim().parse::<usize>().unwrap_or(0) + 1;
+    let due = count >= ROTATE_SLACK;
+    lock.set_len(0)?;
+    lock.seek(SeekFrom::Start(0))?;
+    write!(lock, "{}", if due { 0 } else { count })?;
+    Ok(due)
+}
+
+/// rewrite the log with only its newest `keep` lines if it has more; the copy
+/// is renamed over the original so a crash leaves one or the other intact
+fn rotate(path: &Path, keep: usize) -> io::Result<()> {
+    let Some((lines, _)) = read_log_lines(path) else {
+        return Ok(());
+    };
+    if lines.len() <= keep {
+        return Ok(());
+    }
+    let kept: Vec<String> = lines[lines.len() - keep..]
+        .iter()
+        .map(|(entry, monitor)| log_line(entry, monitor.as_deref()))
+        .collect();
+    replace_file(path, &kept)
+}
+
+/// write `lines` to a copy of `path` and rename it over the original, so a
+/// crash leaves one or the other intact
+fn replace_file(path: &Path, lines: &[impl AsRef<str>]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    for line in lines {
+        writeln!(writer, "{}", line.as_ref())?;
+    }
+    writer.into_inner()?.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+/// which history entries still name a file, from one walk of the wallpaper
+/// directory rather than a search per entry
+struct ExistingEntries {
+    relative: HashSet<String>,
+    basenames: HashSet<String>,
+}
+
+impl ExistingEntries {
+    fn scan() -> Self {
+        let mut existing = Self {
+            relative: HashSet::new(),
+            basenames: HashSet::new(),
+        };
+        for file in discovery::Walk::new(Path::new(&config::wallpaper_dir()))
+            .filter(|e| e.file_type().is_file())
+        {
+            existing.relative.insert(entry_for(file.path()));
+            if let Some(name) = file.file_name().to_str() {
+                existing.basenames.insert(name.to_string());
+            }
+        }
+        existing
+    }
+
+    fn contains(&self, entry: &str) -> bool {
+        let path = Path::new(entry);
+        if path.is_absolute() {
+            path.is_file()
+        } else if path.components().count() == 1 {
+            self.relative.contains(entry) || self.basenames.contains(entry)
+        } else {
+            self.relative.contains(entry)
+        }
+    }
+}
+
+/// drop history entries whose file is gone, returning how many were removed
+pub fn prune_missing() -> Result<usize, Box<dyn Error>> {
+    let existing = ExistingEntries::scan();
+    if config::history_backend() == HistoryBackend::Sqlite {
+        return Ok(SqliteHistory::open()?.prune(|entry| existing.contains(entry))?);
+    }
+
+    let path = config::history_log();
+    let path = Path::new(&path);
+    let _lock = lock_log(path)?.ok_or("History log is locked by another run")?;
+    let Some((lines, skipped)) = read_log_lines(path) else {
+        return Ok(0);
+    };
+    let kept: Vec<String> = lines
+        .iter()
+        .filter(|(entry, _)| existing.contains(entry))
+        .map(|(entry, monitor)| log_line(entry, monitor.as_deref()))
+        .collect();
+    let pruned = lines.len() - kept.len() + skipped;
+    if pruned > 0 {
+        replace_file(path, &kept)?;
+    }
+    Ok(pruned)
+}
+
+/// wallpapers listed one per line in a file like the blocklist or favorites:
+/// a path relative to the wallpaper root, an absolute path, a basename, or a
+/// glob over any of those. Lines starting with # are comments
+#[derive(Debug, Clone, Default)]
+pub struct PathList {
+    patterns: Vec<Pattern>,
+}
+
+impl PathList {
+    pub fn load(path: &Path) -> Self {
+        Self::from_lines(read_list(path))
+    }
+
+    pub fn from_lines(lines: impl IntoIterator<Item = String>) -> Self {
+        let patterns = lines
+            .into_iter()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                Pattern::new(&line).unwrap_or_else(|_| {
+                    Pattern::new(&Pattern::escape(&line)).expect("escaped pattern")
+                })
+            })
+            .collect();
+        Self { patterns }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        let entry = entry_for(path);
+        let absolute = path.to_string_lossy();
+        let basename = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
+        self.patterns.iter().any(|pattern| {
+            [entry.as_str(), &absolute, basename]
+                .iter()
+                .any(|name| pattern.as_str() == *name || pattern.matches(name))
+        })
+    }
+}
+
+/// wallpapers never to be selected again, from `config::blocklist`
+pub fn load_blocklist() -> PathList {
+    PathList::load(Path::new(&config::blocklist()))
+}
+
+/// add `entry` to the blocklist; false if it was already there
+pub fn block(entry: &str) -> io::Result<bool> {
+    add_to_list(Path::new(&config::blocklist()), entry)
+}
+
+/// false if `entry` wasn't blocked
+pub fn unblock(entry: &str) -> io::Result<bool> {
+    remove_from_list(Path::new(&config::blocklist()), entry)
+}
+
+/// wallpapers picked more often, from `config::favorites`
+pub fn load_favorites() -> PathList {
+    PathList::load(Path::new(&config::favorites()))
+}
+
+/// add `entry` to the favorites; false if it was already there
+pub fn favorite(entry: &str) -> io::Result<bool> {
+    add_to_list(Path::new(&config::favorites()), entry)
+}
+
+/// false if `entry` wasn't a favorite
+pub fn unfavorite(entry: &str) -> io::Result<bool> {
+    remove_from_list(Path::new(&config::favorites()), entry)
+}
+
+fn add_to_list(path: &Path, entry: &str) -> io::Result<bool> {
+    if read_list(path).iter().any(|line| line.trim() == entry) {
+        return Ok(false);
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(format!("{}\n", entry).as_bytes())?;
+    Ok(true)
+}
+
+/// leaves other lines, comments included, as they were
+fn remove_from_list(path: &Path, entry: &str) -> io::Result<bool> {
+    let lines = read_list(path);
+    let kept: Vec<&String> = lines.iter().filter(|line| line.trim() != entry).collect();
+    if kept.len() == lines.len() {
+        return Ok(false);
+    }
+    replace_file(path, &kept)?;
+    Ok(true)
+}
+
+/// a list file's lines, empty when it doesn't exist
+fn read_list(path: &Path) -> Vec<String> {
+    File::open(path)
+        .map(|file| BufReader::new(file).lines().map_while(Result::ok).collect())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    JsonLines,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" | "jsonl" => Some(Self::JsonLines),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// one line of `export`
+#[derive(Debug, Clone, Serialize)]
+struct ExportedShow {
+    /// RFC 3339, empty for entries from the flat log
+    shown_at: Option<String>,
+    entry: String,
+    path: Option<PathBuf>,
+    missing: bool,
+    /// capture time as ISO 8601, with the offset when EXIF has one
+    datetime: Option<String>,
+    hour: Option<u8>,
+    month: Option<u8>,
+    rating: Option<u8>,
+    camera: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+}
+
+/// the EXIF fields `export` adds to each entry for a file
+#[derive(Debug, Clone, Default)]
+struct ExportedExif {
+    datetime: Option<String>,
+    hour: Option<u8>,
+    month: Option<u8>,
+    rating: Option<u8>,
+    camera: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+}
+
+/// write every history entry, oldest first, with its file's EXIF; returns how
+/// many were written. Hour, month and rating come from the cache where it's
+/// fresh, so they match what selection saw; the cache doesn't keep the
+/// datetime, camera or GPS, so those are read from each file once. Entries
+/// whose file is gone are kept and marked `missing`
+pub fn export(writer: impl Write, format: ExportFormat) -> Result<usize, Box<dyn Error>> {
+    let entries = timed_entries()?;
+
+    let mut distinct: Vec<&str> = entries.iter().map(|(e, _)| e.as_str()).collect();
+    distinct.sort_unstable();
+    distinct.dedup();
+    let resolved: HashMap<&str, PathBuf> = distinct
+        .into_iter()
+        .filter_map(|entry| Some((entry, resolve_entry(entry)?)))
+        .collect();
+
+    let cache = Cache::open_default().ok();
+    let paths: Vec<String> = resolved
+        .values()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    let cached = match &cache {
+        Some(cache) => cache.load_for_paths(&paths)?,
+        None => HashMap::new(),
+    };
+    let exif: HashMap<&PathBuf, ExportedExif> = resolved
+        .par_iter()
+        .map(|(_, path)| {
+            let info = exif::extract(path);
+            let mut exported = ExportedExif {
+                datetime: info.captured_at.map(|t| {
+                    let offset = info.offset.as_deref().unwrap_or("");
+                    format!("{}{}", t.format("%Y-%m-%dT%H:%M:%S"), offset)
+                }),
+                hour: info.hour,
+                month: info.month,
+                rating: info.rating,
+                camera: info.camera,
+                latitude: info.gps_latitude,
+                longitude: info.gps_longitude,
+            };
+            let fresh = ImageFile::from_path(path).ok().and_then(|mut img| {
+                img.hash_if_enabled();
+                let entry = cached.get(path.to_string_lossy().as_ref())?;
+                entry.is_fresh(&img).then_some(entry)
+            });
+            if let Some(entry) = fresh {
+                exported.hour = entry.hour;
+                exported.month = entry.month;
+                exported.rating = entry.rating;
+            }
+            (path, exported)
+        })
+        .collect();
+
+    let rows = entries.iter().map(|(entry, shown_at)| {
+        let path = resolved.get(entry.as_str());
+        let info = path.and_then(|p| exif.get(p)).cloned().unwrap_or_default();
+        ExportedShow {
+            shown_at: shown_at
+                .and_then(|t| Local.timestamp_opt(t, 0).single())
+                .map(|t| t.to_rfc3339()),
+            entry: entry.clone(),
+            path: path.cloned(),
+            missing: path.is_none(),
+            datetime: info.datetime,
+            hour: info.hour,
+            month: info.month,
+            rating: info.rating,
+            camera: info.camera,
+            latitude: info.latitude,
+            longitude: info.longitude,
+        }
+    });
+
+    let mut written = 0;
+    match format {
+        ExportFormat::JsonLines => {
+            let mut writer = BufWriter::new(writer);
+            for row in rows {
+                serde_json::to_writer(&mut writer, &row)?;
+                writeln!(writer)?;
+                written += 1;
+            }
+            writer.flush()?;
+        }
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            for row in rows {
+                writer.serialize(row)?;
+                written += 1;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(written)
+}
+
+/// every entry, oldest first, with when it was shown where that's known
+fn timed_entries() -> Result<Vec<(String, Option<i64>)>, rusqlite::Error> {
+    match config::history_backend() {
+        HistoryBackend::File => Ok(read_log(Path::new(&config::history_log()))
+            .map(|(lines, _)| lines.into_iter().map(|line| (line, None)).collect())
+            .unwrap_or_default()),
+        HistoryBackend::Sqlite => SqliteHistory::open()?.timed_entries(),
+    }
+}
+
+/// how often a history entry was shown
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ShownStats {
+    pub count: usize,
+    /// unix seconds; the flat log doesn't record times
+    pub last_shown: Option<i64>,
+}
+
+impl ShownStats {
+    fn add(&mut self, other: ShownStats) {
+        self.count += other.count;
+        self.last_shown = self.last_shown.max(other.last_shown);
+    }
+}
+
+/// counts per entry over the whole history
+pub fn stats() -> HashMap<String, ShownStats> {
+    let result = match config::history_backend() {
+        HistoryBackend::File => Ok(read_log(Path::new(&config::history_log()))
+            .map(|(lines, _)| stats_from_entries(lines.into_iter().map(|line| (line, None))))
+            .unwrap_or_default()),
+        HistoryBackend::Sqlite => SqliteHistory::open().and_then(|history| history.stats()),
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("Failed to read history: {}", e);
+        HashMap::new()
+    })
+}
+
+/// aggregate (entry, shown at) pairs
+pub fn stats_from_entries(
+    entries: impl IntoIterator<Item = (String, Option<i64>)>,
+) -> HashMap<String, ShownStats> {
+    let mut stats: HashMap<String, ShownStats> = HashMap::new();
+    for (entry, shown_at) in entries {
+        stats.entry(entry).or_default().add(ShownStats {
+            count: 1,
+            last_shown: shown_at,
+        });
+    }
+    stats
+}
+
+/// the stats for `path`, counting older basename-only lines as well
+pub fn stats_for(stats: &HashMap<String, ShownStats>, path: &Path) -> ShownStats {
+    let entry = entry_for(path);
+    let mut total = stats.get(&entry).copied().unwrap_or_default();
+    if let Some(name) = path.file_name().and_then(|s| s.to_str()) {
+        if name != entry {
+            total.add(stats.get(name).copied().unwrap_or_default());
+        }
+    }
+    total
+}
+
+/// history in the cache database, with the same operations as the flat log;
+/// an existing log is imported the first time the table is opened empty
+pub struct SqliteHistory {
+    cache: Cache,
+}
+
+impl SqliteHistory {
+    pub fn open() -> Result<Self, rusqlite::Error> {
+        let history = Self {
+            cache: Cache::open_default()?,
+        };
+        history.import_log()?;
+        Ok(history)
+    }
+
+    /// the last `limit` wallpapers shown, newest first; see `load_recent_on`
+    pub fn load_recent(
+        &self,
+        monitor: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>, rusqlite::Error> {
+        let mut stmt = self.cache.connection().prepare(
+            "SELECT path FROM history
+             WHERE ?1 IS NULL OR monitor IS NULL OR monitor = ?1
+             ORDER BY id DESC LIMIT ?2",
+        )?;
+        let paths = stmt.query_map(params![monitor, limit as i64], |row| row.get(0))?;
+        paths.collect()
+    }
+
+    pub fn log(&self, wallpaper: &Path, monitor: Option<&str>) -> Result<(), rusqlite::Error> {
+        self.cache.connection().execute(
+            "INSERT INTO history (shown_at, path, monitor) VALUES (?1, ?2, ?3)",
+            params![Local::now().timestamp(), entry_for(wallpaper), monitor],
+        )?;
+        Ok(())
+    }
+
+    /// every entry, oldest first, for browsing; None when nothing was shown yet
+    pub fn load(&self) -> Result<Option<WallpaperHistory>, rusqlite::Error> {
+        let mut stmt = self
+            .cache
+            .connection()
+            .prepare("SELECT path FROM history ORDER BY id")?;
+        let entries = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(WallpaperHistory::from_entries(entries))
+    }
+
+    /// delete entries `keep` rejects, returning how many rows went
+    pub fn prune(&self, keep: impl Fn(&str) -> bool) -> Result<usize, rusqlite::Error> {
+        let conn = self.cache.connection();
+        let paths = conn
+            .prepare("SELECT DISTINCT path FROM history")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let tx = conn.unchecked_transaction()?;
+        let mut pruned = 0;
+        for path in paths.iter().filter(|p| !keep(p)) {
+            pruned += tx.execute("DELETE FROM history WHERE path = ?1", [path])?;
+        }
+        tx.commit()?;
+        Ok(pruned)
+    }
+
+    /// every entry, oldest first, with when it was shown; None for imported lines
+    pub fn timed_entries(&self) -> Result<Vec<(String, Option<i64>)>, rusqlite::Error> {
+        let mut stmt = self
+            .cache
+            .connection()
+            .prepare("SELECT path, shown_at FROM history ORDER BY id")?;
+        let entries = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        entries.collect()
+    }
+
+    pub fn stats(&self) -> Result<HashMap<String, ShownStats>, rusqlite::Error> {
+        let mut stmt = self
+            .cache
+            .connection()
+            .prepare("SELECT path, COUNT(*), MAX(shown_at) FROM history GROUP BY path")?;
+        let stats = stmt.query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                ShownStats {
+                    count: row.get::<_, i64>(1)? as usize,
+                    last_shown: row.get(2)?,
+                },
+            ))
+        })?;
+        stats.collect()
+    }
+
+    pub fn connection(&self) -> &rusqlite::Connection {
+        self.cache.connection()
+    }
+
+    /// copy the flat log into the table once, recorded in the cache's settings
+    /// so pruning the table empty doesn't bring the log back; a table that
+    /// already has entries was imported before that was recorded
+    fn import_log(&self) -> Result<(), rusqlite::Error> {
+        if self.cache.is_read_only() {
+            return Ok(());
+        }
+        let conn = self.cache.connection();
+        let (imported, has_entries): (bool, bool) = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM settings WHERE key = 'history_imported'),
+                    EXISTS (SELECT 1 FROM history)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        if imported {
+            return Ok(());
+        }
+        let path = config::history_log();
+        let lines = if has_entries {
+            Vec::new()
+        } else {
+            read_log_lines(Path::new(&path))
+                .map(|(lines, _)| lines)
+                .unwrap_or_default()
+        };
+
+        let tx = conn.unchecked_transaction()?;
+        {
+            let mut stmt = tx.prepare("INSERT INTO history (path, monitor) VALUES (?1, ?2)")?;
+            for (entry, monitor) in &lines {
+                stmt.execute(params![entry, monitor])?;
+            }
+        }
+        tx.execute(
+            "INSERT OR IGNORE INTO settings (key, value) VALUES ('history_imported', '1')",
+            [],
+        )?;
+        tx.commit()?;
+        if lines.is_empty() {
+            return Ok(());
+        }
+        println!(
+            "Imported {} entries from {} into the history table",
+            lines.len(),
+            path
+        );
+        Ok(())
+    }
+}
+
+/// the log's usable lines and how many were skipped as blank or garbled;
+/// None when there's no log or nothing usable in it
+fn read_log(path: &Path) -> Option<(Vec<String>, usize)> {
+    let (lines, skipped) = read_log_lines(path)?;
+    Some((lines.into_iter().map(|(entry, _)| entry).collect(), skipped))
+}
+
+/// a history entry and the monitor it was shown on, if just one
+type LogLine = (String, Option<String>);
+
+/// like `read_log`, keeping the monitor of each entry
+fn read_log_lines(path: &Path) -> Option<(Vec<LogLine>, usize)> {
+    let file = File::open(path).ok()?;
+    let mut lines = Vec::new();
+    let mut skipped = 0;
+    for line in BufReader::new(file).split(b'\n') {
+        let parsed = String::from_utf8(line.ok()?).ok().and_then(|line| {
+            let (entry, monitor) = match line.split_once('\t') {
+                Some((entry, monitor)) => (entry.to_string(), Some(monitor.to_string())),
+                None => (line, None),
+            };
+            let garbled = |s: &str| s.is_empty() || s.contains(char::is_control);
+            (!garbled(&entry) && !monitor.as_deref().is_some_and(garbled))
+                .then_some((entry, monitor))
+        });
+        match parsed {
+            Some(line) => lines.push(line),
+            None => skipped += 1,
+        }
+    }
+    (!lines.is_empty()).then_some((lines, skipped))
+}
+
+/// a log line: the entry, then a tab and the monitor when it went to just one
+fn log_line(entry: &str, monitor: Option<&str>) -> String {
+    match monitor {
+        Some(monitor) => format!("{}\t{}", entry, monitor),
+        None => entry.to_string(),
+    }
 }
 
 pub struct WallpaperHistory {
     entries: Vec<String>,
     current_index: usize,
+    skipped: usize,
+    missing: usize,
 }
 
 impl WallpaperHistory {
     pub fn load() -> Option<Self> {
-        let path = config::history_log();
-        let file = File::open(&path).ok()?;
-        let entries: Vec<String> = BufReader::new(file).lines().map_while(Result::ok).collect();
+        match config::history_backend() {
+            HistoryBackend::File => {
+                let (entries, skipped) = read_log(Path::new(&config::history_log()))?;
+                let mut history = Self::from_entries(entries)?;
+                history.skipped = skipped;
+                Some(history)
+            }
+            HistoryBackend::Sqlite => SqliteHistory::open().ok()?.load().ok()?,
+        }
+    }
+
+    /// like `load`, leaving out entries whose file no longer exists so browsing
+    /// never lands on one; `missing` says how many
+    pub fn load_existing() -> Option<Self> {
+        let loaded = Self::load()?;
+        let existing = ExistingEntries::scan();
+        let (entries, missing): (Vec<String>, Vec<String>) = loaded
+            .entries
+            .into_iter()
+            .partition(|e| existing.contains(e));
+        let mut history = Self::from_entries(entries)?;
+        history.skipped = loaded.skipped;
+        history.missing = missing.len();
+        Some(history)
+    }
 
+    /// positioned on the newest of `entries`, which are oldest first
+    pub fn from_entries(entries: Vec<String>) -> Option<Self> {
         if entries.is_empty() {
             return None;
         }
@@ -69,13 +874,33 @@ impl WallpaperHistory {
         Some(Self {
             current_index: entries.len() - 1,
             entries,
+            skipped: 0,
+            missing: 0,
         })
     }
 
-    pub fn current_basename(&self) -> &str {
+    /// the entries up to the current one, newest first
+    pub fn up_to_current(&self) -> Vec<String> {
+        self.entries[..=self.current_index]
+            .iter()
+            .rev()
+            .cloned()
+            .collect()
+    }
+
+    /// the current line as logged, see `entry_for`
+    pub fn current_entry(&self) -> &str {
         &self.entries[self.current_index]
     }
 
+    pub fn current_basename(&self) -> &str {
+        let entry = self.current_entry();
+        Path::new(entry)
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or(entry)
+    }
+
     pub fn go_previous(&mut self) -> bool {
         if self.current_index > 0 {
             self.current_index -= 1;
@@ -94,11 +919,210 @@ impl WallpaperHistory {
         }
     }
 
+    /// false, leaving the position alone, when `index` is out of range
+    pub fn jump_to(&mut self, index: usize) -> bool {
+        if index < self.entries.len() {
+            self.current_index = index;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn jump_first(&mut self) -> bool {
+        self.jump_to(0)
+    }
+
+    pub fn jump_last(&mut self) -> bool {
+        self.jump_to(self.entries.len().saturating_sub(1))
+    }
+
+    /// jump to the most recent entry for a file of this name
+    pub fn jump_to_basename(&mut self, basename: &str) -> bool {
+        let found = self.entries.iter().rposition(|entry| {
+            entry == basename
+                || Path::new(entry).file_name().and_then(|s| s.to_str()) == Some(basename)
+        });
+        found.is_some_and(|index| self.jump_to(index))
+    }
+
+    /// log lines left out by `load` because they couldn't be read
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// indices of entries whose file name contains `query` ignoring case,
+    /// newest first
+    pub fn search(&self, query: &str) -> Vec<usize> {
+        let query = query.to_lowercase();
+        (0..self.entries.len())
+            .rev()
+            .filter(|&i| {
+                let entry = &self.entries[i];
+                let name = Path::new(entry)
+                    .file_name()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or(entry);
+                name.to_lowercase().contains(&query)
+            })
+            .collect()
+    }
+
+    /// entries left out by `load_existing` because their file is gone
+    pub fn missing(&self) -> usize {
+        self.missing
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 0 is the oldest entry
+    pub fn current_index(&self) -> usize {
+        self.current_index
+    }
+
+    /// oldest first, as logged
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    pub fn entry(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
     pub fn position_str(&self) -> String {
         format!("{}/{}", self.current_index + 1, self.entries.len())
     }
 
     pub fn current_path(&self) -> Option<PathBuf> {
-        discovery::find_by_basename(self.current_basename())
+        resolve_entry(self.current_entry())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// oldest first, with beach.jpg shown twice
+    fn history() -> WallpaperHistory {
+        WallpaperHistory::from_entries(
+            ["2019/beach.jpg", "forest.jpg", "2021/beach.jpg", "city.jpg"]
+                .iter()
+                .map(|e| e.to_string())
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn jumps_stay_within_the_entries() {
+        let mut history = history();
+        let last = history.len() - 1;
+
+        assert!(history.jump_to(0));
+        assert_eq!(history.current_index(), 0);
+        assert!(!history.go_previous());
+        assert_eq!(history.current_index(), 0);
+
+        assert!(history.jump_to(last));
+        assert_eq!(history.current_entry(), "city.jpg");
+        assert!(!history.go_next());
+
+        // one past the end leaves the position alone
+        assert!(history.jump_to(1));
+        assert!(!history.jump_to(history.len()));
+        assert!(!history.jump_to(usize::MAX));
+        assert_eq!(history.current_index(), 1);
+
+        assert!(history.jump_first());
+        assert_eq!(history.current_index(), 0);
+        assert!(history.jump_last());
+        assert_eq!(history.current_index(), last);
+    }
+
+    #[test]
+    fn jump_to_basename_finds_the_newest() {
+        let mut history = history();
+        assert!(history.jump_to_basename("beach.jpg"));
+        assert_eq!(history.current_index(), 2);
+        assert_eq!(history.current_entry(), "2021/beach.jpg");
+
+        assert!(history.jump_to_basename("forest.jpg"));
+        assert_eq!(history.current_index(), 1);
+
+        assert!(!history.jump_to_basename("desert.jpg"));
+        assert_eq!(history.current_index(), 1);
+    }
+
+    #[test]
+    fn a_history_from_entries_starts_at_the_newest() {
+        assert!(WallpaperHistory::from_entries(Vec::new()).is_none());
+
+        let mut history = history();
+        assert_eq!(history.len(), 4);
+        assert!(!history.is_empty());
+        assert_eq!(
+            history.entries(),
+            ["2019/beach.jpg", "forest.jpg", "2021/beach.jpg", "city.jpg"]
+        );
+        assert_eq!(history.current_index(), 3);
+        assert_eq!(history.current_entry(), "city.jpg");
+        assert_eq!(history.position_str(), "4/4");
+        assert_eq!(history.entry(0), Some("2019/beach.jpg"));
+        assert_eq!(history.entry(4), None);
+        assert_eq!((history.skipped(), history.missing()), (0, 0));
+
+        assert!(history.go_previous());
+        assert_eq!(history.current_entry(), "2021/beach.jpg");
+        assert_eq!(history.current_basename(), "beach.jpg");
+        assert_eq!(history.position_str(), "3/4");
+        assert_eq!(
+            history.up_to_current(),
+            ["2021/beach.jpg", "forest.jpg", "2019/beach.jpg"]
+        );
+        assert_eq!(history.search("BEACH"), [2, 0]);
+        assert!(history.search("desert").is_empty());
+    }
+
+    #[test]
+    fn stats_add_up_relative_and_legacy_basename_lines() {
+        let root = Path::new(&config::wallpaper_dir()).to_path_buf();
+        let log = [
+            ("2021/beach.jpg", Some(100)),
+            ("beach.jpg", Some(200)),
+            ("forest.jpg", None),
+            ("2021/beach.jpg", Some(300)),
+            // from the flat log, without a time
+            ("beach.jpg", None),
+        ];
+        let stats = stats_from_entries(log.iter().map(|&(e, t)| (e.to_string(), t)));
+        assert_eq!(stats.len(), 3);
+        assert_eq!(
+            stats["beach.jpg"],
+            ShownStats {
+                count: 2,
+                last_shown: Some(200)
+            }
+        );
+
+        let beach = stats_for(&stats, &root.join("2021/beach.jpg"));
+        assert_eq!(
+            beach,
+            ShownStats {
+                count: 4,
+                last_shown: Some(300)
+            }
+        );
+        // at the root the entry is the basename, counted once
+        assert_eq!(stats_for(&stats, &root.join("forest.jpg")).count, 1);
+        assert_eq!(
+            stats_for(&stats, &root.join("never.jpg")),
+            ShownStats::default()
+        );
     }
 }
@@ -0,0 +1,39 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use crate::{backend, config};
+
+/// shell commands to run after every wallpaper change, from a file with one per
+/// line. Lines starting with # are comments
+pub fn load(path: &Path) -> io::Result<Vec<String>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// run the hooks from `config::post_hooks` in order with `wallpaper` in
+/// $WALLPAPER, each within `config::command_timeout`; one failing only warns.
+/// No file means no hooks
+pub fn run_post_hooks(wallpaper: &Path) {
+    let file = config::post_hooks();
+    let hooks = match load(Path::new(&file)) {
+        Ok(hooks) => hooks,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+        Err(e) => {
+            eprintln!("Failed to read post hooks {}: {}", file, e);
+            return;
+        }
+    };
+    for hook in hooks {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(&hook).env("WALLPAPER", wallpaper);
+        if let Err(e) = backend::run("post hook", &mut command) {
+            eprintln!("Post hook `{}` failed: {}", hook, e);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+const PHOTOSHOP_HEADER: &[u8] = b"Photoshop 3.0\0";
+const RESOURCE_IPTC: u16 = 0x0404;
+const RECORD_APPLICATION: u8 = 2;
+const DATASET_KEYWORDS: u8 = 25;
+
+/// IPTC keywords from the Photoshop IRB in a JPEG's APP13 segment
+pub fn read_keywords(path: &Path) -> Vec<String> {
+    let Ok(file) = File::open(path) else {
+        return Vec::new();
+    };
+    find_iptc(&mut BufReader::new(file))
+        .map(|iptc| parse_keywords(&iptc))
+        .unwrap_or_default()
+}
+
+/// walk the JPEG segments up to the image data, returning the IPTC-NAA resource
+fn find_iptc(r: &mut impl Read) -> Option<Vec<u8>> {
+    let mut marker = [0u8; 2];
+    r.read_exact(&mut marker).ok()?;
+    if marker != [0xFF, 0xD8] {
+        return None;
+    }
+
+    loop {
+        r.read_exact(&mut marker).ok()?;
+        if marker[0] != 0xFF {
+            return None;
+        }
+        // fill bytes may pad a marker
+        while marker[1] == 0xFF {
+            r.read_exact(&mut marker[1..]).ok()?;
+        }
+        // start of scan or end of image, no metadata after this
+        if marker[1] == 0xDA || marker[1] == 0xD9 {
+            return None;
+        }
+
+        let mut len = [0u8; 2];
+        r.read_exact(&mut len).ok()?;
+        let len = (u16::from_be_bytes(len) as usize).checked_sub(2)?;
+        let mut data = vec![0; len];
+        r.read_exact(&mut data).ok()?;
+
+        if marker[1] == 0xED {
+            if let Some(iptc) = photoshop_resource(&data, RESOURCE_IPTC) {
+                return Some(iptc.to_vec());
+            }
+        }
+    }
+}
+
+/// a resource block from a Photoshop 3.0 APP13 payload
+fn photoshop_resource(data: &[u8], id: u16) -> Option<&[u8]> {
+    let mut rest = data.strip_prefix(PHOTOSHOP_HEADER)?;
+
+    while rest.len() >= 4 && &rest[..4] == b"8BIM" {
+        let resource_id = u16::from_be_bytes([*rest.get(4)?, *rest.get(5)?]);
+        // pascal string name, padded so length byte plus name is even
+        let name_len = *rest.get(6)? as usize;
+        let name_total = (1 + name_len).next_multiple_of(2);
+        let size_at = 6 + name_total;
+        let size = u32::from_be_bytes(rest.get(size_at..size_at + 4)?.try_into().ok()?) as usize;
+        let body_at = size_at + 4;
+        let body = rest.get(body_at..body_at + size)?;
+
+        if resource_id == id {
+            return Some(body);
+        }
+        rest = rest.get(body_at + size.next_multiple_of(2)..)?;
+    }
+    None
+}
+
+fn parse_keywords(iptc: &[u8]) -> Vec<String> {
+    let mut keywords = Vec::new();
+    let mut i = 0;
+
+    while i + 5 <= iptc.len() && iptc[i] == 0x1C {
+        let (record, dataset) = (iptc[i + 1], iptc[i + 2]);
+        let size = u16::from_be_bytes([iptc[i + 3], iptc[i + 4]]) as usize;
+        // extended datasets are never used for keywords
+        if size & 0x8000 != 0 {
+            break;
+        }
+        let Some(value) = iptc.get(i + 5..i + 5 + size) else {
+            break;
+        };
+
+        if record == RECORD_APPLICATION && dataset == DATASET_KEYWORDS {
+            let keyword = decode(value);
+            if !keyword.trim().is_empty() {
+                keywords.push(keyword.trim().to_string());
+            }
+        }
+        i += 5 + size;
+    }
+    keywords
+}
+
+/// UTF-8 where valid, otherwise the Latin-1 older tools write
+fn decode(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => s.to_string(),
+        Err(_) => bytes.iter().map(|&b| b as char).collect(),
+    }
+}
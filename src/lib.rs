@@ -1,10 +1,26 @@
+pub mod backend;
 pub mod cache;
+pub mod color;
 pub mod config;
 pub mod discovery;
 pub mod exif;
 pub mod history;
+pub mod hooks;
+pub mod iptc;
+pub mod places;
+pub mod playlist;
+pub mod schedule;
+pub mod selection;
+pub mod solar;
+pub mod state;
+pub mod theming;
+pub mod watch;
+pub mod xmp;
 
 pub use config::{DEFAULT_CACHE_DB, DEFAULT_HISTORY_LOG, DEFAULT_WALLPAPER_DIR, HISTORY_SIZE};
-pub use discovery::ImageFile;
-pub use exif::ExifInfo;
+pub use discovery::{ImageFile, ImageFilter};
+pub use exif::{
+    DatetimeSource, ExifInfo, ExifOrientation, GpsFormat, Hemisphere, Season, SeasonMatch,
+    SeasonMode,
+};
 pub use history::WallpaperHistory;
@@ -1,104 +1,1754 @@
-use chrono::{Local, Timelike};
-use rand::prelude::*;
-use rayon::prelude::*;
+use chrono::{Datelike, Local, NaiveDateTime, TimeZone, Timelike};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use wallpaper_slideshow::{cache, discovery, exif, history, ImageFile};
+use wallpaper_slideshow::backend::{self, ApplyError};
+use wallpaper_slideshow::cache::{self, Cache, CacheRecord};
+use wallpaper_slideshow::discovery::Dimensions;
+use wallpaper_slideshow::places::Point;
+use wallpaper_slideshow::playlist::Playlist;
+use wallpaper_slideshow::selection::{
+    self, Candidate, Contender, Selection, SelectionContext, SelectionMode, SelectionReport,
+    SelectionStrategy, Tiebreak, Timing,
+};
+use wallpaper_slideshow::solar::Light;
+use wallpaper_slideshow::watch::{self, Change, DirWatcher};
+use wallpaper_slideshow::{
+    config, discovery, exif, history, schedule, ImageFile, ImageFilter, Season, SeasonMatch,
+    SeasonMode, WallpaperHistory, DEFAULT_CACHE_DB, DEFAULT_HISTORY_LOG, DEFAULT_WALLPAPER_DIR,
+};
 
-const TIME_WINDOW: i32 = 1;
+/// print parsing progress every this many images
+const PROGRESS_INTERVAL: usize = 500;
+/// failed files listed by -v
+const FAILURE_EXAMPLES: usize = 5;
 
 fn main() {
-    setup_environment();
+    let args: Vec<String> = env::args().collect();
+
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        print_help();
+        return;
+    }
+
+    if args.iter().any(|a| a == "--watch") {
+        if let Err(e) = run_watch() {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.iter().any(|a| a == "--cache-stats") {
+        let json = args.iter().any(|a| a == "--json");
+        if let Err(e) = print_cache_stats(json) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.iter().any(|a| a == "--history-stats") {
+        let json = args.iter().any(|a| a == "--json");
+        if let Err(e) = print_history_stats(json) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.iter().any(|a| a == "--prune-cache") {
+        if let Err(e) = prune_cache() {
+            if cache::is_locked(&e) {
+                eprintln!("The cache is in use by another instance, try again later");
+            } else {
+                eprintln!("Error: {}", e);
+            }
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(file) = flag_value(&args, "--export-cache") {
+        if let Err(e) = export_cache(file) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(file) = flag_value(&args, "--import-cache") {
+        if let Err(e) = import_cache(file) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.iter().any(|a| a == "--warm-cache") {
+        let jobs = match flag_value(&args, "--jobs").map(str::parse::<usize>) {
+            Some(Ok(n)) if n > 0 => Some(n),
+            Some(_) => {
+                eprintln!("--jobs expects a positive number");
+                std::process::exit(1);
+            }
+            None => None,
+        };
+        if let Err(e) = warm_cache(jobs) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.iter().any(|a| a == "--export-history") {
+        let format = match flag_value(&args, "--export-history") {
+            None => Some(history::ExportFormat::JsonLines),
+            Some(f) => history::ExportFormat::parse(f),
+        };
+        let Some(format) = format else {
+            eprintln!("Usage: --export-history [json|csv]");
+            std::process::exit(1);
+        };
+        match history::export(io::stdout().lock(), format) {
+            Ok(written) => eprintln!("Exported {} history entries", written),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if args.iter().any(|a| a == "--prune-history") {
+        match history::prune_missing() {
+            Ok(pruned) => println!("Removed {} history entries for missing files", pruned),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if args.iter().any(|a| a == "--prev") {
+        // N is optional, so another flag after it isn't one
+        let steps = match flag_value(&args, "--prev").filter(|n| !n.starts_with("--")) {
+            None => 1,
+            Some(n) => match n.parse::<usize>() {
+                Ok(steps) if steps > 0 => steps,
+                _ => {
+                    eprintln!(
+                        "Usage: --prev [N], a number of steps of 1 or more, not {}",
+                        n
+                    );
+                    std::process::exit(1);
+                }
+            },
+        };
+        if let Err(e) = apply_previous(steps) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.iter().any(|a| a == "--set") {
+        let Some(target) = flag_value(&args, "--set") else {
+            eprintln!("Usage: wallpaper_slideshow --set PATH");
+            std::process::exit(1);
+        };
+        if let Err(e) = apply_file(target) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(i) = args.iter().position(|a| a == "--set-hour") {
+        let (Some(target), Some(hour)) = (
+            args.get(i + 1),
+            args.get(i + 2)
+                .and_then(|h| h.parse::<u8>().ok())
+                .filter(|&h| h < 24),
+        ) else {
+            eprintln!("Usage: --set-hour <path or basename> <hour 0-23>");
+            std::process::exit(1);
+        };
+        if let Err(e) = set_hour(target, hour) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(target) = flag_value(&args, "--clear-hour") {
+        if let Err(e) = clear_hour(target) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.iter().any(|a| a == "--prune-thumbnails") {
+        if let Err(e) = prune_thumbnails() {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let verbose = args.iter().any(|a| a == "-v" || a == "--verbose");
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    let explain = args
+        .iter()
+        .any(|a| a == "--explain" || a.starts_with("--explain="));
+    let explain_json = args.iter().any(|a| a == "--explain=json");
+    let write_cache = !args.iter().any(|a| a == "--no-cache-write");
+    let monitor = flag_value(&args, "--monitor").map(String::from);
+    let seed = match flag_value(&args, "--seed").map(str::parse::<u64>) {
+        Some(Ok(seed)) => Some(seed),
+        Some(Err(_)) => {
+            eprintln!("--seed expects a non-negative number");
+            std::process::exit(1);
+        }
+        None => config::seed(),
+    };
+    let mut rng = match seed {
+        Some(seed) => {
+            println!("Using seed {}", seed);
+            StdRng::seed_from_u64(seed)
+        }
+        None => StdRng::from_os_rng(),
+    };
+
+    let run = RunOptions {
+        verbose,
+        dry_run,
+        explain,
+        explain_json,
+        write_cache,
+        monitor,
+    };
+    let playlist = flag_value(&args, "--playlist")
+        .map(String::from)
+        .or_else(config::playlist)
+        .map(PathBuf::from);
+
+    if args.iter().any(|a| a == "--daemon") {
+        let interval = duration_flag(&args, "--interval", config::DEFAULT_DAEMON_INTERVAL);
+        let jitter = duration_flag(&args, "--jitter", Duration::ZERO);
+        run_daemon(&run, playlist.as_deref(), interval, jitter, &mut rng);
+        return;
+    }
+
+    if let Some(file) = &playlist {
+        if let Err(e) = play_next(file, dry_run) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if !dry_run {
+        setup_environment();
+    }
+    let all_images = discovery::find_images();
+    if !select_and_apply(&run, &all_images, &mut None, &mut rng) {
+        std::process::exit(1);
+    }
+}
+
+/// `--daemon` looks for new and removed wallpapers this often, or on SIGHUP
+const RESCAN_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// how often a sleeping daemon checks for signals
+const SIGNAL_POLL: Duration = Duration::from_millis(250);
+
+static TERMINATE: AtomicBool = AtomicBool::new(false);
+static RESCAN: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    if signal == libc::SIGHUP {
+        RESCAN.store(true, Ordering::Relaxed);
+    } else {
+        TERMINATE.store(true, Ordering::Relaxed);
+    }
+}
+
+/// the duration given with `flag`, exiting on one that doesn't parse
+fn duration_flag(args: &[String], flag: &str, default: Duration) -> Duration {
+    match flag_value(args, flag).map(config::parse_duration) {
+        None => default,
+        Some(Some(duration)) if flag != "--interval" || !duration.is_zero() => duration,
+        Some(_) => {
+            eprintln!("{} expects a duration like 90s, 15m or 1h", flag);
+            std::process::exit(1);
+        }
+    }
+}
 
+/// select and apply a wallpaper every `interval` plus up to `jitter` until
+/// SIGTERM or SIGINT, keeping the image list and the cache between cycles
+fn run_daemon(
+    run: &RunOptions,
+    playlist: Option<&Path>,
+    interval: Duration,
+    jitter: Duration,
+    rng: &mut StdRng,
+) {
+    // SAFETY: the handler only stores to atomics
+    unsafe {
+        let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::signal(libc::SIGTERM, handler);
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGHUP, handler);
+    }
+    if !run.dry_run {
+        setup_environment();
+    }
+    println!(
+        "Changing the wallpaper every {}s{}",
+        interval.as_secs(),
+        if jitter.is_zero() {
+            String::new()
+        } else {
+            format!(" plus up to {}s", jitter.as_secs())
+        }
+    );
+
+    let mut images = discovery::find_images();
+    let mut scanned = Instant::now();
+    let mut cache = None;
+    let mut failed = false;
+
+    while !TERMINATE.load(Ordering::Relaxed) {
+        if RESCAN.swap(false, Ordering::Relaxed) || scanned.elapsed() >= RESCAN_INTERVAL {
+            images = discovery::find_images();
+            scanned = Instant::now();
+        }
+
+        let unavailable = (failed && !run.dry_run)
+            .then(backend::available)
+            .and_then(Result::err);
+        if let Some(e) = unavailable {
+            println!("{}, skipping this cycle", e);
+        } else {
+            failed = !match playlist {
+                Some(file) => play_next(file, run.dry_run).unwrap_or_else(|e| {
+                    eprintln!("Error: {}", e);
+                    false
+                }),
+                None => select_and_apply(run, &images, &mut cache, rng),
+            };
+        }
+
+        let wait = interval + jitter.mul_f64(rng.random::<f64>());
+        let until = Instant::now() + wait;
+        while Instant::now() < until && !TERMINATE.load(Ordering::Relaxed) {
+            thread::sleep(SIGNAL_POLL.min(until - Instant::now()));
+        }
+    }
+    println!("Stopping");
+}
+
+/// flags of a selection run
+struct RunOptions {
+    verbose: bool,
+    dry_run: bool,
+    explain: bool,
+    explain_json: bool,
+    write_cache: bool,
+    /// the only output to change, instead of each of them
+    monitor: Option<String>,
+}
+
+/// pick a wallpaper from `all_images` for each monitor and apply it, opening
+/// `cache` if it isn't yet; false if nothing was found or applying it failed.
+/// With a single output it goes to every output, as hyprpaper's "," does
+fn select_and_apply(
+    run: &RunOptions,
+    all_images: &[ImageFile],
+    cache: &mut Option<Cache>,
+    rng: &mut StdRng,
+) -> bool {
+    let outputs = monitors();
+    let named = |name: &str| outputs.iter().find(|m| m.name == name);
+    let targets: Vec<(Option<&str>, Option<&Monitor>)> = match &run.monitor {
+        Some(name) if !outputs.is_empty() && named(name).is_none() => {
+            let names: Vec<&str> = outputs.iter().map(|m| m.name.as_str()).collect();
+            eprintln!("No monitor named {} (found {})", name, names.join(", "));
+            return false;
+        }
+        Some(name) => vec![(Some(name.as_str()), named(name))],
+        None if outputs.len() > 1 => outputs
+            .iter()
+            .map(|m| (Some(m.name.as_str()), Some(m)))
+            .collect(),
+        None => vec![(None, outputs.first())],
+    };
+
+    let mut picks: Vec<(Option<&str>, PathBuf)> = Vec::new();
+    for &(monitor, output) in &targets {
+        if let Some(monitor) = monitor {
+            println!("Monitor {}:", monitor);
+        }
+        let taken: Vec<&Path> = picks.iter().map(|(_, path)| path.as_path()).collect();
+        let display = output.and_then(Monitor::size);
+        if let Some(path) =
+            select_wallpaper(run, all_images, cache, rng, (monitor, display), &taken)
+        {
+            picks.push((monitor, path));
+        }
+    }
+
+    if run.dry_run {
+        println!("Dry run, not applying or logging it");
+        return picks.len() == targets.len();
+    }
+    if run.verbose {
+        let (name, why) = backend::choose();
+        println!("Backend: {} ({})", name, why);
+    }
+    let backend = match backend::retry(backend::available) {
+        Ok(backend) => backend,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return false;
+        }
+    };
+    let picks: Vec<(Option<&str>, &Path)> = picks
+        .iter()
+        .map(|(monitor, path)| (*monitor, path.as_path()))
+        .collect();
+    let failed = backend::apply_all(backend.as_ref(), &picks);
+    for (path, e) in &failed {
+        eprintln!("Failed to apply {}: {}", path.display(), e);
+    }
+    failed.is_empty() && picks.len() == targets.len()
+}
+
+/// pick a wallpaper from `all_images` for `monitor`, or for every output, and
+/// none of `taken` unless nothing else is left; `display` is the size of the
+/// screen it's for, if known
+fn select_wallpaper(
+    run: &RunOptions,
+    all_images: &[ImageFile],
+    cache: &mut Option<Cache>,
+    rng: &mut StdRng,
+    (monitor, display): (Option<&str>, Option<Dimensions>),
+    taken: &[&Path],
+) -> Option<PathBuf> {
     let current_hour = Local::now().hour() as i32;
     println!("Current hour: {}", current_hour);
 
-    let recent = history::load_recent();
-    let all_images = discovery::find_images();
+    let recent = history::load_recent_on(monitor, config::HISTORY_SIZE);
+    let soft_recency = config::soft_recency();
     println!("Found {} total images", all_images.len());
 
-    let available: Vec<_> = all_images
+    let mut stages = vec![("found", all_images.len())];
+
+    let scheduled = scheduled_images(all_images);
+    if let Some(scheduled) = &scheduled {
+        stages.push(("scheduled for today", scheduled.len()));
+    }
+
+    let mut filter = ImageFilter::new().exclude_blocked(&history::load_blocklist());
+    if !soft_recency {
+        filter = filter.exclude_recent(&recent.iter().cloned().collect());
+    }
+    let mut filter_input = scheduled.as_deref().unwrap_or(all_images);
+    let sized;
+    if let Some(display) = display.filter(|_| config::aspect_filter()) {
+        filter = filter.aspect_ratio(display, config::aspect_tolerance());
+        sized = with_cached_dimensions(filter_input, cache);
+        filter_input = &sized;
+    }
+    let (mut pool, mut relaxed) = filter.apply_relaxed(filter_input);
+    for name in &relaxed {
+        println!("No images left after the {} filter, relaxing it", name);
+    }
+    stages.push(("left by the filters", pool.len()));
+    if !taken.is_empty() {
+        let on_other = |img: &ImageFile| taken.contains(&img.path.as_path());
+        if pool.iter().all(on_other) {
+            println!("Only images already on another monitor are left, allowing them");
+        } else {
+            pool.retain(|img| !on_other(img));
+            stages.push(("not on another monitor", pool.len()));
+        }
+    }
+
+    println!("Processing {} available images", pool.len());
+
+    let mut candidates =
+        get_candidates_with_cache(cache, &pool, all_images, run.verbose, run.write_cache);
+    // directory order varies between file systems, and a seed should pick the
+    // same image wherever the files are
+    candidates.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut below_rating = None;
+    if let Some(min) = config::min_rating() {
+        let before = candidates.len();
+        below_rating = keep_rated(&mut candidates, min, config::include_unrated());
+        if below_rating.is_none() {
+            relaxed.push(RATING_FILTER);
+        }
+        below_rating = below_rating.or(Some(before));
+        stages.push(("rated high enough", candidates.len()));
+    }
+    let burst_seconds = config::burst_seconds();
+    if burst_seconds > 0 {
+        let shown = recent_capture_times(cache, &recent);
+        let dropped = selection::drop_burst_frames(&mut candidates, &shown, burst_seconds);
+        if dropped > 0 {
+            println!(
+                "Dropped {} images taken within {} seconds of a recently shown one",
+                dropped, burst_seconds
+            );
+            stages.push(("not a recent burst", candidates.len()));
+        }
+    }
+    let timing = Timing::new(
+        config::strategy(),
+        Local::now(),
+        config::location(),
+        config::window_minutes(),
+    );
+    let brightness = config::brightness();
+    let (night_max, day_min) = (config::night_max_luminance(), config::day_min_luminance());
+    let favorites = history::load_favorites();
+    let shown_counts =
+        config::fairness().then(|| selection::shown_counts(&candidates, &history::stats()));
+    let least_shown = shown_counts
+        .iter()
+        .flat_map(HashMap::values)
+        .min()
+        .copied()
+        .unwrap_or(0);
+    let weight = |c: &Candidate| {
+        let favorite = history::favorite_weight(&favorites, &c.path, config::favorite_weight());
+        let recency = if soft_recency {
+            history::recency_weight(&recent, &c.path)
+        } else {
+            1.0
+        };
+        let fairness = shown_counts.as_ref().map_or(1.0, |counts| {
+            history::fairness_weight(counts.get(&c.path).copied().unwrap_or(0), least_shown)
+        });
+        let brightness = if brightness {
+            selection::brightness_weight(c.luminance, timing.is_night(), night_max, day_min)
+        } else {
+            1.0
+        };
+        favorite * recency * fairness * brightness
+    };
+    let today = Local::now().date_naive();
+    let hemisphere = config::hemisphere();
+    let season_mode = config::season_mode();
+    let season_of = |c: &Candidate| SeasonMatch::of(today, c.month, hemisphere);
+    if season_mode == SeasonMode::Strict {
+        let before = candidates.len();
+        candidates.retain(|c| season_of(c) != SeasonMatch::Other);
+        println!(
+            "Dropped {} images from other seasons",
+            before - candidates.len()
+        );
+        stages.push(("in season", candidates.len()));
+    }
+    let in_season =
+        |c: &Candidate| season_mode != SeasonMode::Off && season_of(c) == SeasonMatch::Same;
+    if config::place_of_the_day() {
+        match open_cache(cache) {
+            Ok(cache) => keep_place_of_the_day(&mut candidates, &timing, cache),
+            Err(e) => eprintln!("Failed to open the cache for locations: {}", e),
+        }
+        stages.push(("at today's place", candidates.len()));
+    }
+    if config::prefer_unseen() {
+        let before = candidates.len();
+        let dropped = selection::keep_unseen(&mut candidates, &history::load_all(), &timing);
+        if dropped > 0 {
+            println!(
+                "Preferring {} of {} images never shown",
+                candidates.len(),
+                before
+            );
+        }
+        stages.push(("never shown", candidates.len()));
+    }
+    let options = selection::Options::from_config();
+    let scoring = options.mode == SelectionMode::Score;
+    if options.tiebreak == Tiebreak::LeastRecent || scoring {
+        let shown = history::load_recent_on(monitor, usize::MAX);
+        for candidate in &mut candidates {
+            candidate.last_shown = history::shown_ago(&shown, &candidate.path);
+        }
+    }
+    if scoring {
+        for candidate in &mut candidates {
+            candidate.favorite = favorites.contains(&candidate.path);
+        }
+    }
+    let context = SelectionContext {
+        candidates: &candidates,
+        timing: &timing,
+        today,
+        recent: &recent,
+        options: &options,
+        weight: &weight,
+        preferred: &in_season,
+    };
+    let selection = selection::strategy_from_config().select(&context, rng);
+    let selected = selection.as_ref().map(|s| &candidates[s.index]);
+    if let Some(Selection { report, .. }) = &selection {
+        println!("{}", report.summary());
+        for note in &report.notes {
+            println!("{}", note);
+        }
+    }
+
+    if run.explain {
+        let explanation = Explanation {
+            stages: stages.iter().map(Stage::from).collect(),
+            excluded: filter
+                .rejections(filter_input)
+                .iter()
+                .chain(below_rating.map(|n| (RATING_FILTER, n)).iter())
+                .map(Stage::from)
+                .collect(),
+            relaxed: &relaxed,
+            contenders: selection
+                .iter()
+                .flat_map(|s| &s.report.contenders)
+                .map(|contender| ExplainedContender {
+                    path: &candidates[contender.index].path,
+                    contender,
+                })
+                .collect(),
+            selected: selected.map(|c| c.path.as_path()),
+            strategy: selection.as_ref().map(|s| s.strategy),
+            selection: selection.as_ref().map(|s| &s.report),
+        };
+        if let Err(e) = explanation.print(run.explain_json) {
+            eprintln!("Failed to explain the selection: {}", e);
+        }
+    }
+
+    let Some(candidate) = selected else {
+        eprintln!("No suitable wallpaper found");
+        return None;
+    };
+    println!(
+        "Selected: {} (Hour: {}, Season: {})",
+        candidate.path.display(),
+        candidate.describe_hour(),
+        candidate.season().map_or("N/A", Season::name)
+    );
+    Some(candidate.path.clone())
+}
+
+#[derive(Serialize)]
+struct CacheReport {
+    #[serde(flatten)]
+    stats: cache::CacheStats,
+    /// images in the wallpaper directory a run would take from the cache
+    hits: usize,
+    /// images a run would have to parse
+    misses: usize,
+}
+
+fn print_cache_stats(json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let cache = Cache::open_default()?;
+    let keys = cache.load_keys()?;
+    let all_images = discovery::find_images();
+    let brightness = config::brightness();
+    let hits = all_images
+        .iter()
+        .filter(|img| !needs_parse(&keys, img, brightness))
+        .count();
+    let report = CacheReport {
+        stats: cache.stats()?,
+        hits,
+        misses: all_images.len() - hits,
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    let stats = &report.stats;
+    let last_updated = stats
+        .last_updated
+        .and_then(|t| Local.timestamp_opt(t, 0).single())
+        .map_or("never".to_string(), |t| {
+            t.format("%Y-%m-%d %H:%M").to_string()
+        });
+    println!("Cache:        {}", config::cache_db());
+    println!(
+        "Entries:      {} ({} with an hour, {} without)",
+        stats.entries, stats.with_hour, stats.without_hour
+    );
+    println!("Failed:       {}", stats.failed);
+    println!("Thumbnails:   {}", stats.thumbnails);
+    println!(
+        "Size:         {:.1} MiB",
+        stats.db_bytes as f64 / (1024.0 * 1024.0)
+    );
+    println!("Last updated: {}", last_updated);
+    println!(
+        "Current run:  {} cached, {} to parse",
+        report.hits, report.misses
+    );
+    Ok(())
+}
+
+/// images listed under "most shown" by --history-stats
+const MOST_SHOWN: usize = 10;
+
+#[derive(Serialize)]
+struct HistoryReport {
+    images: usize,
+    shown: usize,
+    most_shown: Vec<ShownImage>,
+    never_shown: Vec<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct ShownImage {
+    path: PathBuf,
+    #[serde(flatten)]
+    stats: history::ShownStats,
+}
+
+fn print_history_stats(json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let stats = history::stats();
+    let all: Vec<ShownImage> = discovery::find_images()
+        .into_iter()
+        .map(|img| ShownImage {
+            stats: history::stats_for(&stats, &img.path),
+            path: img.path,
+        })
+        .collect();
+    let images = all.len();
+    let (mut shown, never): (Vec<_>, Vec<_>) = all.into_iter().partition(|s| s.stats.count > 0);
+    shown.sort_by(|a, b| {
+        (b.stats.count, b.stats.last_shown).cmp(&(a.stats.count, a.stats.last_shown))
+    });
+    let report = HistoryReport {
+        images,
+        shown: shown.len(),
+        most_shown: shown.into_iter().take(MOST_SHOWN).collect(),
+        never_shown: never.into_iter().map(|s| s.path).collect(),
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!(
+        "{} of {} images shown at least once",
+        report.shown, report.images
+    );
+    if !report.most_shown.is_empty() {
+        println!("\nMost shown:");
+        for image in &report.most_shown {
+            let last = image
+                .stats
+                .last_shown
+                .and_then(|t| Local.timestamp_opt(t, 0).single())
+                .map(|t| format!("  last {}", t.format("%Y-%m-%d %H:%M")))
+                .unwrap_or_default();
+            println!("{:>6}  {}{}", image.stats.count, image.path.display(), last);
+        }
+    }
+    if !report.never_shown.is_empty() {
+        println!("\nNever shown ({}):", report.never_shown.len());
+        for path in &report.never_shown {
+            println!("        {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// parse every image missing from the cache without picking a wallpaper; entries are
+/// written batch by batch so an interrupted run picks up where it stopped
+fn warm_cache(jobs: Option<usize>) -> Result<(), Box<dyn std::error::Error>> {
+    let cache = Cache::open_default()?;
+    let keys = cache.load_keys()?;
+    let all_images = discovery::find_images();
+    let brightness = config::brightness();
+    // entries cached before minutes were get them here rather than on every run
+    let to_parse: Vec<&ImageFile> = all_images
         .iter()
         .filter(|img| {
-            let basename = img.path.file_name().and_then(|s| s.to_str()).unwrap_or("");
-            !recent.contains(basename)
+            needs_parse(&keys, img, brightness)
+                || keys
+                    .get(img.path.to_string_lossy().as_ref())
+                    .is_some_and(|key| !key.has_minute)
         })
-        .cloned()
         .collect();
+    println!(
+        "{} of {} images already cached, parsing {}",
+        all_images.len() - to_parse.len(),
+        all_images.len(),
+        to_parse.len()
+    );
+
+    let threads = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.unwrap_or(0))
+        .build()?;
+    let start = Instant::now();
+    let total = to_parse.len();
+    let mut done = 0;
+    let mut failed = 0;
+
+    for batch in to_parse.chunks(PROGRESS_INTERVAL) {
+        let entries = threads.install(|| parse_entries_with_progress(batch, |_, _| {}));
+        cache.upsert(&entries)?;
+        failed += entries.iter().filter(|r| r.error.is_some()).count();
+        done += batch.len();
+
+        let rate = done as f64 / start.elapsed().as_secs_f64().max(0.001);
+        let eta = ((total - done) as f64 / rate) as u64;
+        println!(
+            "Parsed {}/{} images, {:.0} files/s, ETA {}:{:02}",
+            done,
+            total,
+            rate,
+            eta / 60,
+            eta % 60
+        );
+    }
+
+    println!(
+        "Cached {} images in {:.1}s, {} failed to parse",
+        done,
+        start.elapsed().as_secs_f64(),
+        failed
+    );
+    Ok(())
+}
+
+/// show the wallpaper from `steps` entries before the latest again, logging it
+/// as the newest entry
+fn apply_previous(steps: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let mut history = WallpaperHistory::load().ok_or("No wallpaper history yet")?;
+    let index = history
+        .len()
+        .checked_sub(steps + 1)
+        .filter(|_| steps > 0)
+        .ok_or_else(|| {
+            format!(
+                "Can't go back {} from a history of {} wallpapers",
+                steps,
+                history.len()
+            )
+        })?;
+    history.jump_to(index);
+    let path = history
+        .current_path()
+        .ok_or_else(|| format!("Could not find: {}", history.current_entry()))?;
 
-    let pool = if available.is_empty() {
-        println!("All images used recently, resetting pool");
-        all_images.clone()
+    println!("Going back to {}", path.display());
+    setup_environment();
+    apply_wallpaper(&path)?;
+    Ok(())
+}
+
+/// apply the playlist entry after the last one shown
+fn play_next(file: &Path, dry_run: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    let playlist = Playlist::load(file)
+        .map_err(|e| format!("Failed to read playlist {}: {}", file.display(), e))?;
+    for line in playlist.missing() {
+        eprintln!("Skipping missing playlist entry: {}", line);
+    }
+    let (index, path) = playlist
+        .next(&history::load_recent_ordered(usize::MAX))
+        .ok_or_else(|| format!("No existing wallpapers in playlist {}", file.display()))?;
+
+    println!(
+        "Playlist {}/{}: {}",
+        index + 1,
+        playlist.len(),
+        path.display()
+    );
+    if dry_run {
+        println!("Dry run, not applying or logging it");
+        return Ok(true);
+    }
+    setup_environment();
+    apply_wallpaper(&path)?;
+    Ok(true)
+}
+
+/// apply `target` without selecting, logging it like a selected wallpaper. A
+/// bare file name not in the working directory is looked up in the wallpapers
+fn apply_file(target: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let given = Path::new(target);
+    let path = if !given.exists() && given.components().count() == 1 {
+        discovery::find_by_basename(target)
+            .ok_or_else(|| format!("No wallpaper named {}", target))?
     } else {
-        available
+        std::path::absolute(given)?
     };
+    if !path.is_file() {
+        return Err(format!("Not a file: {}", path.display()).into());
+    }
+    if !discovery::is_candidate(&path) {
+        return Err(format!("Not a supported image: {}", path.display()).into());
+    }
 
-    println!("Processing {} available images", pool.len());
+    println!("Setting {}", path.display());
+    setup_environment();
+    apply_wallpaper(&path)?;
+    Ok(())
+}
+
+/// overrides given as a path are stored absolute, bare names match any directory
+fn override_key(target: &str) -> io::Result<String> {
+    if target.contains(std::path::MAIN_SEPARATOR) {
+        Ok(std::path::absolute(target)?.to_string_lossy().to_string())
+    } else {
+        Ok(target.to_string())
+    }
+}
+
+fn set_hour(target: &str, hour: u8) -> Result<(), Box<dyn std::error::Error>> {
+    let key = override_key(target)?;
+    Cache::open_default()?.set_override(&key, hour)?;
+    println!("{} will be treated as a {}:00 image", key, hour);
+    Ok(())
+}
+
+fn clear_hour(target: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let key = override_key(target)?;
+    if Cache::open_default()?.remove_override(&key)? {
+        println!("Removed the hour override for {}", key);
+    } else {
+        println!("No hour override for {}", key);
+    }
+    Ok(())
+}
 
-    let candidates = get_candidates_with_cache(&pool, &all_images);
-    let selected = select_wallpaper(&candidates, current_hour);
+/// an image count after a step of the selection
+#[derive(Serialize)]
+struct Stage {
+    name: &'static str,
+    images: usize,
+}
+
+impl From<&(&'static str, usize)> for Stage {
+    fn from(&(name, images): &(&'static str, usize)) -> Self {
+        Self { name, images }
+    }
+}
+
+#[derive(Serialize)]
+struct ExplainedContender<'a> {
+    path: &'a Path,
+    #[serde(flatten)]
+    contender: &'a Contender,
+}
+
+/// everything `--explain` shows about a selection
+#[derive(Serialize)]
+struct Explanation<'a> {
+    stages: Vec<Stage>,
+    /// images each filter rejects on its own, before any is relaxed
+    excluded: Vec<Stage>,
+    relaxed: &'a [&'static str],
+    selection: Option<&'a SelectionReport>,
+    contenders: Vec<ExplainedContender<'a>>,
+    selected: Option<&'a Path>,
+    /// `SelectionStrategy::name` of the one that picked it
+    strategy: Option<&'static str>,
+}
+
+impl Explanation<'_> {
+    fn print(&self, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+        if json {
+            // one line, so it can be told apart from the progress output
+            println!("{}", serde_json::to_string(self)?);
+            return Ok(());
+        }
+
+        println!("\nImages at each step:");
+        for stage in &self.stages {
+            println!("  {:<22} {}", stage.name, stage.images);
+        }
+        for stage in self.excluded.iter().filter(|s| s.images > 0) {
+            let relaxed = if self.relaxed.contains(&stage.name) {
+                ", relaxed"
+            } else {
+                ""
+            };
+            println!("    {} excluded {}{}", stage.name, stage.images, relaxed);
+        }
 
-    if let Some((path, hour)) = selected {
+        let Some(selection) = self.selection else {
+            println!("\nNothing to select from\n");
+            return Ok(());
+        };
         println!(
-            "Selected: {} (Hour: {})",
-            path.display(),
-            hour.map(|h| h.to_string()).unwrap_or_else(|| "N/A".into())
+            "\n{} of {} candidates have a capture time, {} within {}",
+            selection.with_time, selection.candidates, selection.in_window, selection.window
         );
+        let distances: Vec<String> = selection
+            .distances
+            .iter()
+            .enumerate()
+            .filter(|(_, &n)| n > 0)
+            .map(|(d, n)| format!("{}: {}", d, n))
+            .collect();
+        if !distances.is_empty() {
+            println!(
+                "By distance in {}: {}",
+                selection.distance_unit,
+                distances.join(", ")
+            );
+        }
+        println!("Rule: {}", selection.summary());
+        if let Some(tiebreak) = selection.tiebreak {
+            println!("Tiebreak: {}", tiebreak.name());
+        }
+        if let Some(strategy) = self.strategy {
+            println!("Strategy: {}", strategy);
+        }
+        for note in &selection.notes {
+            println!("{}", note);
+        }
+        if !selection.factors.is_empty() {
+            println!("Score of the pick:");
+            for factor in &selection.factors {
+                println!(
+                    "  {:<9} {:.2} -> {:+.3}",
+                    factor.name, factor.value, factor.contribution
+                );
+            }
+        }
 
-        if let Some(basename) = path.file_name().and_then(|s| s.to_str()) {
-            history::log(basename);
+        if !self.contenders.is_empty() {
+            println!("\nLikeliest of the pool drawn from:");
+            for c in &self.contenders {
+                let distance = c
+                    .contender
+                    .distance
+                    .map_or("-".to_string(), |d| d.to_string());
+                println!(
+                    "  {:>5.1}%  distance {:>3}  weight {:.3}  {}",
+                    c.contender.chance * 100.0,
+                    distance,
+                    c.contender.weight,
+                    c.path.display()
+                );
+            }
         }
+        println!();
+        Ok(())
+    }
+}
 
-        apply_wallpaper(&path.to_string_lossy());
-    } else {
-        eprintln!("No suitable wallpaper found");
+/// keep only candidates from today's place if any of them match the time; the
+/// places are found over every cached wallpaper with GPS so they don't change
+/// as the recent history does
+fn keep_place_of_the_day(candidates: &mut Vec<Candidate>, timing: &Timing, cache: &Cache) {
+    let root = PathBuf::from(config::wallpaper_dir());
+    let locations: Vec<(String, Point)> = match cache.load_locations() {
+        Ok(locations) => locations
+            .into_iter()
+            .filter(|(path, _)| Path::new(path).starts_with(&root))
+            .collect(),
+        Err(e) => {
+            eprintln!("Failed to load locations: {}", e);
+            return;
+        }
+    };
+    let today = Local::now().date_naive();
+    let Some(place) = selection::place_of_the_day(&locations, config::place_size_km(), today)
+    else {
+        return;
+    };
+    if !selection::keep_at_place(candidates, &place, timing) {
+        println!("Nothing from today's place matches the time, using every place");
+        return;
     }
+    println!(
+        "Place {} of {}: {} images around {:.3}, {:.3}",
+        place.index + 1,
+        place.count,
+        candidates.len(),
+        place.center.0,
+        place.center.1
+    );
+}
+
+/// how the rating filter is named in `--explain`
+const RATING_FILTER: &str = "minimum rating";
+
+/// `selection::keep_rated`, saying what it dropped
+fn keep_rated(candidates: &mut Vec<Candidate>, min: u8, include_unrated: bool) -> Option<usize> {
+    let before = candidates.len();
+    let Some(dropped) = selection::keep_rated(candidates, min, include_unrated) else {
+        eprintln!(
+            "No images are rated {} or more, ignoring WALLPAPER_MIN_RATING",
+            min
+        );
+        return None;
+    };
+    if dropped > 0 {
+        println!(
+            "Dropped {} of {} images rated below {}",
+            dropped, before, min
+        );
+    }
+    Some(dropped)
+}
+
+/// when each of the `recent` history entries that has a cached capture time
+/// was taken
+fn recent_capture_times(
+    cache: &mut Option<Cache>,
+    recent: &[String],
+) -> Vec<(PathBuf, NaiveDateTime)> {
+    let paths: Vec<PathBuf> = recent
+        .iter()
+        .filter_map(|e| history::resolve_entry(e))
+        .collect();
+    let keys: Vec<String> = paths
+        .iter()
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect();
+    let entries = match open_cache(cache).and_then(|c| c.load_for_paths(&keys)) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to load capture times: {}", e);
+            return Vec::new();
+        }
+    };
+    paths
+        .into_iter()
+        .zip(&keys)
+        .filter_map(|(path, key)| Some((path, entries.get(key)?.captured_at?)))
+        .collect()
+}
+
+/// `images` with the width and height cached for their current version, left
+/// unknown for the rest
+fn with_cached_dimensions(images: &[ImageFile], cache: &mut Option<Cache>) -> Vec<ImageFile> {
+    let cached = match open_cache(cache).and_then(|c| c.load_dimensions()) {
+        Ok(cached) => cached,
+        Err(e) => {
+            eprintln!("Failed to load image dimensions: {}", e);
+            HashMap::new()
+        }
+    };
+    images
+        .iter()
+        .map(|img| {
+            let mut img = img.clone();
+            if let Some(&(mtime, Some((width, height)))) =
+                cached.get(img.path.to_string_lossy().as_ref())
+            {
+                if mtime == img.cache_mtime() {
+                    img.width = Some(width);
+                    img.height = Some(height);
+                }
+            }
+            img
+        })
+        .collect()
+}
+
+/// the images today's entries in the schedule allow, or None to use them all
+fn scheduled_images(all: &[ImageFile]) -> Option<Vec<ImageFile>> {
+    let today = Local::now().weekday();
+    let active = schedule::load().active(today)?;
+    let Some(scheduled) = selection::scheduled(all, &active) else {
+        eprintln!(
+            "No images match the schedule for {}, using all of them",
+            today
+        );
+        return None;
+    };
+    println!("{} images scheduled for {}", scheduled.len(), today);
+    Some(scheduled)
+}
+
+/// the argument following `flag`
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+/// write the cache as JSON lines to `file`, or stdout for "-"
+fn export_cache(file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let cache = Cache::open_default()?;
+    let written = if file == "-" {
+        cache.export(io::stdout().lock())?
+    } else {
+        cache.export(BufWriter::new(File::create(file)?))?
+    };
+    eprintln!("Exported {} cache entries", written);
+    Ok(())
 }
 
-struct Candidate {
-    path: std::path::PathBuf,
-    hour: Option<u8>,
+/// read JSON lines written by --export-cache from `file`, or stdin for "-"
+fn import_cache(file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let cache = Cache::open_default()?;
+    let (imported, skipped) = if file == "-" {
+        cache.import(io::stdin().lock())?
+    } else {
+        cache.import(BufReader::new(File::open(file)?))?
+    };
+    println!(
+        "Imported {} cache entries, skipped {} that don't match a file here",
+        imported, skipped
+    );
+    Ok(())
 }
 
-fn get_candidates_with_cache(pool: &[ImageFile], all: &[ImageFile]) -> Vec<Candidate> {
-    match try_cached_candidates(pool, all) {
+fn prune_cache() -> Result<(), rusqlite::Error> {
+    let cache = Cache::open_default()?;
+    let keep: HashSet<String> = discovery::find_images()
+        .iter()
+        .map(|img| img.path.to_string_lossy().to_string())
+        .collect();
+    let report = cache.prune(&keep)?;
+    println!(
+        "Removed {} entries for missing files and {} expired entries, reclaimed {:.1} MiB",
+        report.missing,
+        report.expired,
+        report.bytes_reclaimed as f64 / (1024.0 * 1024.0)
+    );
+    Ok(())
+}
+
+/// shrink the thumbnail cache to its budget, keeping the current wallpaper's
+fn prune_thumbnails() -> Result<(), rusqlite::Error> {
+    let cache = Cache::open_default()?;
+    let current = WallpaperHistory::load()
+        .and_then(|h| h.current_path())
+        .map(|p| p.to_string_lossy().into_owned());
+    let removed = cache.prune_thumbnails(config::thumbnail_budget(), current.as_deref())?;
+    println!("Removed {} thumbnails", removed);
+    Ok(())
+}
+
+fn print_help() {
+    println!(
+        r#"wallpaper_slideshow {}
+Pick a wallpaper matching the current time of day and apply it
+
+USAGE:
+    wallpaper_slideshow [OPTIONS]
+
+OPTIONS:
+    -h, --help    Print help information
+    -v, --verbose List files whose EXIF failed to parse
+    --watch       Keep running and add new or changed wallpapers to the EXIF cache
+                  as they appear, removing deleted ones
+    --cache-stats Print how many images are cached, failed or still need parsing;
+                  add --json for machine-readable output
+    --history-stats
+                  List the most shown wallpapers and those never shown; add --json
+                  for machine-readable output
+    --prune-cache Remove cache entries for files that no longer exist, then compact
+                  the database
+    --export-cache FILE
+                  Write the EXIF cache as JSON lines with paths relative to
+                  WALLPAPER_DIR, "-" for stdout
+    --import-cache FILE
+                  Load entries written by --export-cache, skipping files whose
+                  mtime or size differ here
+    --warm-cache  Parse EXIF for every image not yet cached, or cached without the
+                  minute it was taken, then exit; safe to interrupt and run again
+    --jobs N      Parse with at most N threads in --warm-cache
+    --export-history [json|csv]
+                  Write the history with each wallpaper's EXIF to stdout as JSON
+                  lines (the default) or CSV
+    --prune-history
+                  Remove history entries whose files no longer exist
+    --dry-run     Select a wallpaper and print why, without applying it or adding it
+                  to the history
+    --no-cache-write
+                  Don't add or remove EXIF cache entries, e.g. with --dry-run
+    --playlist FILE
+                  Show the wallpapers listed in FILE in order, one per run, instead
+                  of selecting by time; overrides WALLPAPER_PLAYLIST
+    --explain[=json]
+                  Show how the wallpaper was picked: images left after each step, how
+                  far candidates are from now, the rule used and the likeliest picks;
+                  =json prints it as one JSON line
+    --daemon      Keep running and change the wallpaper every --interval; SIGHUP
+                  looks for new wallpapers right away, SIGTERM stops
+    --interval DURATION
+                  Time between changes with --daemon, e.g. 90s, 15m or 1h
+                  (default 20m)
+    --jitter DURATION
+                  Wait up to this much longer each time, so several machines don't
+                  change in lockstep
+    --monitor NAME
+                  Change only the wallpaper on output NAME; by default each output
+                  of several gets its own, unlike the others and its recent ones
+    --seed N      Seed the random choices so the same images at the same time pick
+                  the same wallpaper; overrides WALLPAPER_SEED
+    --set PATH    Apply PATH instead of selecting one; a bare file name is also
+                  looked up in WALLPAPER_DIR
+    --prev [N]    Show the wallpaper from N steps back in history again (default 1)
+    --set-hour PATH HOUR
+                  Treat an image as taken at HOUR (0-23) regardless of its EXIF; a
+                  bare file name matches that name in any directory
+    --clear-hour PATH
+                  Remove an hour set with --set-hour
+    --prune-thumbnails
+                  Evict least recently used thumbnails until the cache fits its budget
+
+ENVIRONMENT VARIABLES:
+    WALLPAPER_DIR           Directory containing wallpaper images
+                            Default: {}
+    WALLPAPER_HISTORY_LOG   Path to wallpaper history log file
+                            Default: {}
+    WALLPAPER_HISTORY_MAX_LINES
+                            Trim the history log to this many newest entries
+                            Default: {}
+    WALLPAPER_HISTORY_BACKEND
+                            file, or sqlite to keep history in the cache database
+                            (an existing log is imported once)
+                            Default: file
+    WALLPAPER_BLOCKLIST     File listing wallpapers never to select, one path, file
+                            name or glob per line
+                            Default: {}
+    WALLPAPER_FAVORITES     File listing favorite wallpapers, in the blocklist's format
+                            Default: {}
+    WALLPAPER_FAVORITE_WEIGHT
+                            How many times as likely a favorite is to be picked
+                            Default: {}
+    WALLPAPER_RECENCY       hard drops the last {} wallpapers from the pool; soft keeps
+                            them, less likely the more recently they were shown
+                            Default: hard
+    WALLPAPER_SEASON        boost prefers images from the current season within the
+                            time window, strict never picks ones from another season,
+                            off ignores seasons
+                            Default: boost
+    WALLPAPER_STRATEGY      hour matches capture hours within an hour of now; solar
+                            matches the part of the day (dawn, midday, dusk, ...)
+                            from sunrise and sunset at the location below; light
+                            matches how high the sun was where a photo was taken,
+                            by its GPS, against how high it is here now
+                            Default: hour
+    WALLPAPER_LATITUDE, WALLPAPER_LONGITUDE
+                            Where to compute sunrise and sunset for solar and light
+                            matching, in degrees north and east
+    WALLPAPER_SELECTION     window picks randomly among images within the window, else
+                            the closest; weighted draws from all images, likelier the
+                            closer they are; score draws from the best scoring images
+                            by the weights below
+                            Default: window
+    WALLPAPER_SCORE_TIME, WALLPAPER_SCORE_SEASON, WALLPAPER_SCORE_FAVORITE,
+    WALLPAPER_SCORE_UNSEEN, WALLPAPER_SCORE_RECENT
+                            How much closeness to the time, being from this season, a
+                            favorite or never shown add to the score, and having been
+                            shown recently takes off it
+                            Default: {}, {}, {}, {}, {}
+    WALLPAPER_SCORE_TOP_K   How many of the best scoring images the pick is drawn from
+                            Default: {}
+    WALLPAPER_SELECTION_TAU Hours (or phases) over which weighted selection falls off
+                            by a factor of e
+                            Default: {}
+    WALLPAPER_BACKEND       What applies the wallpaper: hyprpaper, swww, swaybg, gnome,
+                            plasma or x11, or auto to pick one for the running desktop
+                            (shown with --verbose)
+                            Default: {}
+    WALLPAPER_HYPRPAPER_PRELOAD
+                            Preload each wallpaper into hyprpaper and unload the one it
+                            replaces, instead of only setting it (0 or 1)
+                            Default: 1
+    WALLPAPER_HYPRPAPER_UNLOAD_ALL_EVERY
+                            Also unload everything hyprpaper isn't showing after this many
+                            wallpapers, 0 for never
+                            Default: {}
+    WALLPAPER_SWWW_TRANSITION_TYPE, WALLPAPER_SWWW_TRANSITION_DURATION
+                            swww's --transition-type and --transition-duration (in
+                            seconds)
+                            Default: swww's own
+    WALLPAPER_SWAYBG_MODE   How swaybg fits the image: fill, fit, center, tile or stretch
+                            Default: fill
+    WALLPAPER_GNOME_PICTURE_OPTIONS
+                            How GNOME fits the image: none, wallpaper, centered, scaled,
+                            stretched, zoom or spanned
+                            Default: zoom
+    WALLPAPER_X11_TOOL      What the x11 backend runs: xwallpaper or feh
+                            Default: xwallpaper if installed, else feh
+    WALLPAPER_APPLY_RETRY   How long to keep trying while the backend isn't reachable
+                            yet, e.g. right after login, like 30s or 2m; 0 to give up
+                            right away
+                            Default: {}s
+    WALLPAPER_COMMAND_TIMEOUT
+                            How long a command applying the wallpaper or the theme may
+                            run before it's killed, like 10s or 1m
+                            Default: {}s
+    WALLPAPER_POST_HOOKS    File of shell commands, one per line, run after each wallpaper
+                            change with its path in $WALLPAPER, e.g.
+                            thaimeleon "$WALLPAPER" -w ~/.config/yolk/chameleon.rhai
+                            Default: {}
+    WALLPAPER_PLAYLIST      File of wallpapers, one per line, to show in order instead of
+                            selecting by time, continuing after the last one shown
+    WALLPAPER_SCHEDULE      File of "days = pattern" lines narrowing the images by weekday,
+                            e.g. "weekend = nature/**" or "mon-fri = minimal"; days are
+                            names, ranges, "weekdays" or "weekend"
+                            Default: {}
+    WALLPAPER_FAIRNESS      Make images shown more often over the whole history less likely,
+                            evening out how often each is shown (1 to enable)
+                            Default: 0
+    WALLPAPER_PLACE_OF_THE_DAY
+                            Group images by GPS into places and pick from one place a
+                            day while it has images matching the time (1 to enable)
+                            Default: 0
+    WALLPAPER_PLACE_SIZE_KM How close photos have to be to count as one place
+                            Default: {}
+    WALLPAPER_ANNIVERSARY   Pick from photos taken on today's date in any year, or
+                            failing that near it, before going by the time (1 to enable)
+                            Default: 0
+    WALLPAPER_ANNIVERSARY_DAYS
+                            How many days either side of today still count
+                            Default: {}
+    WALLPAPER_MIN_RATING    Only select images rated at least this many stars (1-5)
+    WALLPAPER_UNRATED       Whether images without a rating pass WALLPAPER_MIN_RATING:
+                            include or exclude
+                            Default: include
+    WALLPAPER_BURST_SECONDS Skip photos taken within this many seconds of a recently shown
+                            one, near duplicates from the same burst (0 to disable)
+                            Default: {}
+    WALLPAPER_PREFER_UNSEEN Pick from images never shown before while any match the time
+                            (1 to enable)
+                            Default: 0
+    WALLPAPER_ASPECT_FILTER Skip images whose aspect ratio is far from the monitor's, going
+                            by the dimensions cached for them (1 to enable)
+                            Default: 0
+    WALLPAPER_ASPECT_TOLERANCE
+                            How far off the aspect ratio may be, as a fraction
+                            Default: {}
+    WALLPAPER_WINDOW_MINUTES
+                            How many minutes either side of now a photo may have been
+                            taken to match
+                            Default: {}
+    WALLPAPER_MAX_WINDOW    With nothing in the time window, pick from the closest images
+                            up to this many hours (phases with solar) away before taking
+                            the single closest one
+                            Default: {}
+    WALLPAPER_TIEBREAK      Which of several images within the time window to pick:
+                            random, least-recent (shown longest ago) or newest (by mtime)
+                            Default: random
+    WALLPAPER_SEED          Seed for the random choices, like --seed
+    WALLPAPER_CACHE_DB      Path to the EXIF cache database
+                            Default: {}
+    WALLPAPER_FOLLOW_LINKS  Follow symlinks while scanning (0 to disable)
+                            Default: 1
+    WALLPAPER_SETTLE_SECONDS
+                            Ignore images modified within this many seconds
+                            Default: 0
+    WALLPAPER_EXIF_UTC      The camera writes DateTimeOriginal in UTC, so add its
+                            OffsetTimeOriginal to get the local hour (1 to enable);
+                            cached photos with an offset are reparsed when it changes
+                            Default: 0
+    WALLPAPER_MTIME_FALLBACK
+                            Use the file's modification hour for images without
+                            an EXIF date (1 to enable)
+                            Default: 0
+    WALLPAPER_CONTENT_HASH  Decide whether an image changed from a hash of its first 64 KiB
+                            rather than its mtime, for sync tools that reset mtimes
+                            (1 to enable)
+                            Default: 0
+    WALLPAPER_EXIF_MAX_BYTES
+                            Only parse EXIF from this many leading bytes of each file
+                            Default: unset, the whole file
+    WALLPAPER_HEMISPHERE    north or south, decides which months are winter
+                            Default: north
+    WALLPAPER_CACHE_MAX_AGE_DAYS
+                            Make --prune-cache also drop entries older than this
+                            Default: unset, keep them
+    WALLPAPER_THUMBNAIL_BUDGET_MB
+                            Total size of thumbnails kept in the cache for wallpaper-info
+                            Default: {}
+    WALLPAPER_BRIGHTNESS    Measure each image's brightness while caching it and make
+                            bright images less likely at night and dark ones by day;
+                            decodes every image once (1 to enable)
+                            Default: 0
+    WALLPAPER_NIGHT_MAX_LUMINANCE
+                            Mean luminance (0 to 1) above which an image is too bright
+                            for the night
+                            Default: {}
+    WALLPAPER_DAY_MIN_LUMINANCE
+                            Mean luminance (0 to 1) below which an image is too dark
+                            for the day
+                            Default: {}
+    WALLPAPER_THEMING       Write the applied wallpaper's colours as pywal's colors.json
+                            and colors.sh, and render templates, before the theme hooks
+                            run (1 to enable)
+                            Default: 0
+    WALLPAPER_THEME_DIR     Where colors.json, colors.sh and relative template outputs go
+                            Default: $XDG_CACHE_HOME/wallpaper_slideshow
+    WALLPAPER_THEME_TEMPLATES
+                            File of "template = output" lines; in a template {{color0}} to
+                            {{color15}}, {{background}}, {{foreground}}, {{cursor}}, {{accent}} and
+                            {{secondary}} become #rrggbb, with .strip rrggbb and with .rgb
+                            r,g,b, and {{wallpaper}} the image's path
+                            Default: {}
+    XDG_CACHE_HOME          The wallpaper applied last is recorded as JSON in
+                            wallpaper_slideshow/current under it, with a
+                            wallpaper_slideshow/current_wallpaper symlink to the image
+                            Default: ~/.cache
+"#,
+        env!("CARGO_PKG_VERSION"),
+        DEFAULT_WALLPAPER_DIR,
+        DEFAULT_HISTORY_LOG,
+        config::DEFAULT_HISTORY_MAX_LINES,
+        config::DEFAULT_BLOCKLIST,
+        config::DEFAULT_FAVORITES,
+        config::DEFAULT_FAVORITE_WEIGHT,
+        config::HISTORY_SIZE,
+        config::DEFAULT_SCORE_WEIGHTS.time,
+        config::DEFAULT_SCORE_WEIGHTS.season,
+        config::DEFAULT_SCORE_WEIGHTS.favorite,
+        config::DEFAULT_SCORE_WEIGHTS.unseen,
+        config::DEFAULT_SCORE_WEIGHTS.recent,
+        config::DEFAULT_SCORE_TOP_K,
+        config::DEFAULT_SELECTION_TAU,
+        config::DEFAULT_BACKEND,
+        config::DEFAULT_HYPRPAPER_UNLOAD_ALL_EVERY,
+        config::DEFAULT_APPLY_RETRY.as_secs(),
+        config::DEFAULT_COMMAND_TIMEOUT.as_secs(),
+        config::DEFAULT_POST_HOOKS,
+        config::DEFAULT_SCHEDULE,
+        config::DEFAULT_PLACE_SIZE_KM,
+        config::DEFAULT_ANNIVERSARY_DAYS,
+        config::DEFAULT_BURST_SECONDS,
+        config::DEFAULT_ASPECT_TOLERANCE,
+        config::DEFAULT_WINDOW_MINUTES,
+        config::DEFAULT_MAX_WINDOW,
+        DEFAULT_CACHE_DB,
+        config::DEFAULT_THUMBNAIL_BUDGET_MB,
+        config::DEFAULT_NIGHT_MAX_LUMINANCE,
+        config::DEFAULT_DAY_MIN_LUMINANCE,
+        config::DEFAULT_THEME_TEMPLATES
+    );
+}
+
+fn run_watch() -> Result<(), Box<dyn std::error::Error>> {
+    let root = config::wallpaper_dir();
+
+    // set up the watches before scanning so nothing slips in between
+    let mut watcher = DirWatcher::new(&root)?;
+    let images = discovery::find_images_in(&root);
+    get_candidates_with_cache(&mut None, &images, &images, false, true);
+
+    let cache = Cache::open_default()?;
+    let mut pool: HashMap<PathBuf, ImageFile> = images
+        .into_iter()
+        .map(|img| (img.path.clone(), img))
+        .collect();
+
+    println!(
+        "Watching {} directories under {} ({} images)",
+        watcher.watched_dirs(),
+        root,
+        pool.len()
+    );
+
+    loop {
+        let mut added = Vec::new();
+        let mut removed = Vec::new();
+
+        for change in watcher.poll()? {
+            match change {
+                Change::Added(img) => added.push(img),
+                Change::Removed(path) => pool.retain(|p, _| {
+                    let keep = !p.starts_with(&path);
+                    if !keep {
+                        removed.push(p.to_string_lossy().to_string());
+                    }
+                    keep
+                }),
+            }
+        }
+
+        if !added.is_empty() {
+            let new_entries = parse_entries(&added.iter().collect::<Vec<_>>());
+
+            match cache.upsert(&new_entries) {
+                Ok(()) => {
+                    for CacheRecord {
+                        path, info, error, ..
+                    } in &new_entries
+                    {
+                        if let Some(e) = error {
+                            eprintln!("Failed to parse {}: {}", path, e);
+                        }
+                        println!(
+                            "Added: {} (Hour: {})",
+                            path,
+                            info.hour
+                                .map(|h| h.to_string())
+                                .unwrap_or_else(|| "N/A".into())
+                        );
+                    }
+                }
+                Err(e) => eprintln!("Failed to insert cache entries: {}", e),
+            }
+
+            pool.extend(added.into_iter().map(|img| (img.path.clone(), img)));
+        }
+
+        if !removed.is_empty() {
+            match cache.remove(&removed) {
+                Ok(_) => println!("Removed {} images", removed.len()),
+                Err(e) => eprintln!("Failed to remove cache entries: {}", e),
+            }
+        }
+
+        thread::sleep(watch::POLL_INTERVAL);
+    }
+}
+
+/// candidates for `pool`, parsing what the cache is missing; `cache` is opened
+/// if it isn't yet and kept open for the next call
+fn get_candidates_with_cache(
+    cache: &mut Option<Cache>,
+    pool: &[ImageFile],
+    all: &[ImageFile],
+    verbose: bool,
+    write_cache: bool,
+) -> Vec<Candidate> {
+    let mut result =
+        open_cache(cache).and_then(|c| try_cached_candidates(c, pool, all, verbose, write_cache));
+    // damage past the header only shows up once the entries are read
+    if let Err(e) = &result {
+        if write_cache && cache::is_corrupt(e) {
+            *cache = None;
+            if cache::move_aside(Path::new(&config::cache_db())).is_ok() {
+                result = open_cache(cache)
+                    .and_then(|c| try_cached_candidates(c, pool, all, verbose, write_cache));
+            }
+        }
+    }
+
+    match result {
         Ok(c) => c,
         Err(e) => {
             eprintln!("Cache error, falling back to direct EXIF parsing: {}", e);
-            pool.par_iter()
-                .map(|img| Candidate {
-                    path: img.path.clone(),
-                    hour: exif::extract(&img.path).hour,
-                })
+            let mtime_fallback = config::mtime_fallback();
+            let paths: Vec<&Path> = pool.iter().map(|img| img.path.as_path()).collect();
+            pool.iter()
+                .zip(exif::extract_many(&paths))
+                .map(|(img, e)| parsed_candidate(img, &e.info, mtime_fallback))
                 .collect()
         }
     }
 }
 
+/// a candidate from freshly parsed EXIF
+fn parsed_candidate(img: &ImageFile, info: &exif::ExifInfo, mtime_fallback: bool) -> Candidate {
+    Candidate::new(img, (info.minute_of_day, info.month), mtime_fallback)
+        .with_captured_on(info.captured_at.map(|t| t.date()))
+        .with_captured_at(info.captured_at)
+        .with_light(photo_light(info))
+        .with_rating(info.rating)
+}
+
+/// see `CachedEntry::light`
+fn photo_light(info: &exif::ExifInfo) -> Option<Light> {
+    let offset = info
+        .offset
+        .as_deref()
+        .and_then(exif::parse_offset_minutes)
+        .map(|m| m as i32);
+    let location = info.gps_latitude.zip(info.gps_longitude)?;
+    Some(Light::of_photo(info.captured_at?, offset, location))
+}
+
+/// report a failed write; a corrupt database is moved aside so the next run
+/// starts over instead of failing the same way
+fn cache_write_failed(what: &str, e: &rusqlite::Error) {
+    eprintln!("Failed to {}: {}", what, e);
+    if cache::is_corrupt(e) {
+        if let Err(e) = cache::move_aside(Path::new(&config::cache_db())) {
+            eprintln!("Failed to move the corrupt cache aside: {}", e);
+        }
+    }
+}
+
+/// whether `img` has no usable cache entry; with `brightness` an entry cached
+/// before it was turned on counts as unusable, as does one whose image couldn't
+/// be decoded, which is rare enough not to be worth remembering
+fn needs_parse(keys: &HashMap<String, cache::CacheKey>, img: &ImageFile, brightness: bool) -> bool {
+    !keys
+        .get(img.path.to_string_lossy().as_ref())
+        .is_some_and(|key| key.is_fresh(img) && (!brightness || key.failed || key.has_luminance))
+}
+
+/// parse EXIF for the given images, reporting progress on large batches
+fn parse_entries(images: &[&ImageFile]) -> Vec<CacheRecord> {
+    parse_entries_with_progress(images, |done, total| {
+        if total >= PROGRESS_INTERVAL && (done % PROGRESS_INTERVAL == 0 || done == total) {
+            println!("Parsed {}/{} images", done, total);
+        }
+    })
+}
+
+fn parse_entries_with_progress(
+    images: &[&ImageFile],
+    progress: impl FnMut(usize, usize) + Send,
+) -> Vec<CacheRecord> {
+    let paths: Vec<&Path> = images.iter().map(|img| img.path.as_path()).collect();
+    let extracted = exif::extract_many_with_progress(&paths, progress);
+
+    images
+        .iter()
+        .zip(extracted)
+        .map(|(img, e)| CacheRecord::new(img, e))
+        .collect()
+}
+
+fn open_cache(cache: &mut Option<Cache>) -> Result<&Cache, rusqlite::Error> {
+    if cache.is_none() {
+        *cache = Some(Cache::open_default()?);
+    }
+    Ok(cache.as_ref().expect("opened above"))
+}
+
 fn try_cached_candidates(
+    cache: &Cache,
     pool: &[ImageFile],
     all: &[ImageFile],
+    verbose: bool,
+    write_cache: bool,
 ) -> Result<Vec<Candidate>, rusqlite::Error> {
-    let conn = cache::open()?;
-    let cached = cache::load_all(&conn)?;
-    println!("Loaded {} entries from cache", cached.len());
+    let keys = cache.load_keys()?;
+    println!("Loaded {} entries from cache", keys.len());
 
     let current_paths: HashSet<String> = all
         .iter()
         .map(|img| img.path.to_string_lossy().to_string())
         .collect();
 
+    let brightness = config::brightness();
     let to_parse: Vec<_> = all
         .iter()
-        .filter(|img| {
-            let path_str = img.path.to_string_lossy();
-            match cached.get(path_str.as_ref()) {
-                Some(entry) => entry.mtime != img.mtime,
-                None => true,
-            }
-        })
+        .filter(|img| needs_parse(&keys, img, brightness))
         .collect();
 
     println!(
@@ -106,97 +1756,99 @@ fn try_cached_candidates(
         all.len() - to_parse.len(),
         to_parse.len()
     );
+    if verbose {
+        let given_up = keys
+            .values()
+            .filter(|k| k.failed && !k.should_retry())
+            .count();
+        if given_up > 0 {
+            println!(
+                "Not retrying {} images that failed to parse {} times",
+                given_up,
+                cache::MAX_PARSE_ATTEMPTS
+            );
+        }
+    }
 
-    let new_entries: Vec<(String, i64, Option<u8>)> = to_parse
-        .par_iter()
-        .map(|img| {
-            let hour = exif::extract(&img.path).hour;
-            (img.path.to_string_lossy().to_string(), img.mtime, hour)
-        })
-        .collect();
+    let new_entries = parse_entries(&to_parse);
 
-    if !new_entries.is_empty() {
-        cache::insert(&conn, &new_entries)?;
-        println!("Inserted {} new cache entries", new_entries.len());
+    // the parsed entries are used either way, a failed write only means they are
+    // parsed again next run
+    if !write_cache {
+        if !new_entries.is_empty() {
+            println!("Not writing {} new cache entries", new_entries.len());
+        }
+    } else if cache.is_read_only() {
+        if verbose {
+            println!(
+                "Cache is read-only, {} new entries not persisted",
+                new_entries.len()
+            );
+        }
+    } else if !new_entries.is_empty() {
+        match cache.upsert(&new_entries) {
+            Ok(()) => println!("Inserted {} new cache entries", new_entries.len()),
+            Err(e) => cache_write_failed("update the cache", &e),
+        }
     }
 
-    cache::cleanup_stale(&conn, &current_paths, &cached)?;
+    if write_cache {
+        match cache.cleanup_stale(&current_paths) {
+            Ok(0) => {}
+            Ok(removed) => println!("Removed {} stale cache entries", removed),
+            Err(e) => cache_write_failed("remove stale cache entries", &e),
+        }
+    }
 
-    let new_map: HashMap<&str, Option<u8>> = new_entries
+    let failures: Vec<_> = new_entries
         .iter()
-        .map(|(path, _, hour)| (path.as_str(), *hour))
+        .filter_map(|r| Some((&r.path, r.error.as_ref()?)))
         .collect();
+    if !failures.is_empty() {
+        println!("Failed to parse EXIF of {} images", failures.len());
+        if verbose {
+            for (path, error) in failures.iter().take(FAILURE_EXAMPLES) {
+                println!("  {}: {}", path, error);
+            }
+        }
+    }
+
+    let new_map: HashMap<&str, &CacheRecord> =
+        new_entries.iter().map(|r| (r.path.as_str(), r)).collect();
 
+    // only the pool needs its hours, everything else just needed its key
+    let pool_paths: Vec<String> = pool
+        .iter()
+        .map(|img| img.path.to_string_lossy().to_string())
+        .filter(|path| !new_map.contains_key(path.as_str()))
+        .collect();
+    let cached = cache.load_for_paths(&pool_paths)?;
+
+    let overrides = cache.get_overrides()?;
+    let mtime_fallback = config::mtime_fallback();
     let candidates = pool
         .iter()
         .map(|img| {
             let path_str = img.path.to_string_lossy();
-            let hour = new_map
-                .get(path_str.as_ref())
-                .copied()
-                .flatten()
-                .or_else(|| cached.get(path_str.as_ref()).and_then(|e| e.hour));
-
-            Candidate {
-                path: img.path.clone(),
-                hour,
-            }
+            let candidate = if let Some(record) = new_map.get(path_str.as_ref()) {
+                parsed_candidate(img, &record.info, mtime_fallback).with_luminance(record.luminance)
+            } else if let Some(entry) = cached.get(path_str.as_ref()) {
+                Candidate::new(img, (entry.minute(), entry.month), mtime_fallback)
+                    .with_luminance(entry.luminance)
+                    .with_captured_on(entry.captured_on)
+                    .with_captured_at(entry.captured_at)
+                    .with_light(entry.light())
+                    .with_rating(entry.rating)
+            } else {
+                Candidate::new(img, (None, None), mtime_fallback)
+            };
+            candidate.with_override(cache::lookup_override(&overrides, &img.path))
         })
         .collect();
 
     Ok(candidates)
 }
 
-fn select_wallpaper(
-    candidates: &[Candidate],
-    current_hour: i32,
-) -> Option<(std::path::PathBuf, Option<u8>)> {
-    let mut best_match: Option<&Candidate> = None;
-    let mut best_diff = 24;
-    let mut time_window_matches: Vec<&Candidate> = Vec::new();
-
-    for candidate in candidates {
-        if let Some(image_hour) = candidate.hour {
-            let diff = time_diff(current_hour, image_hour as i32);
-
-            if diff <= TIME_WINDOW {
-                time_window_matches.push(candidate);
-            }
-
-            if diff < best_diff {
-                best_diff = diff;
-                best_match = Some(candidate);
-            }
-        }
-    }
-
-    let selected = if !time_window_matches.is_empty() {
-        println!(
-            "Found {} images within {} hour window",
-            time_window_matches.len(),
-            TIME_WINDOW
-        );
-        time_window_matches.choose(&mut rand::rng()).copied()
-    } else if let Some(best) = best_match {
-        println!("Using best time match (diff: {} hours)", best_diff);
-        Some(best)
-    } else {
-        println!("Choosing random image");
-        candidates.choose(&mut rand::rng())
-    };
-
-    selected.map(|c| (c.path.clone(), c.hour))
-}
-
-/// wrap hours around 24
-fn time_diff(current: i32, image: i32) -> i32 {
-    let mut diff = (current - image + 24) % 24;
-    if diff > 12 {
-        diff = 24 - diff;
-    }
-    diff
-}
-
 fn setup_environment() {
     let uid = unsafe { libc::getuid() };
     let runtime_dir = format!("/run/user/{}", uid);
@@ -216,27 +1868,56 @@ fn setup_environment() {
     }
 }
 
-fn apply_wallpaper(path: &str) {
-    let reload_arg = format!(",{}", path);
-    if let Err(e) = Command::new("hyprctl")
-        .args(["hyprpaper", "wallpaper", &reload_arg])
-        .status()
-    {
-        eprintln!("Failed to run hyprctl: {}", e);
+/// show `path` on every output with the configured backend, waiting for it
+/// if it isn't up yet; see `backend::apply_all`
+fn apply_wallpaper(path: &Path) -> Result<(), ApplyError> {
+    let backend = backend::retry(backend::available)?;
+    match backend::apply_all(backend.as_ref(), &[(None, path)]).pop() {
+        Some((_, e)) => Err(e),
+        None => Ok(()),
     }
+}
 
-    let home = env::var("HOME").unwrap_or_else(|_| "/home/simon".to_string());
-    let thaimeleon = format!("{}/.cargo/bin/thaimeleon", home);
-    let config = format!("{}/.config/yolk/chameleon.rhai", home);
+/// an output as `hyprctl monitors -j` describes it
+#[derive(Deserialize)]
+struct Monitor {
+    name: String,
+    #[serde(default)]
+    width: u32,
+    #[serde(default)]
+    height: u32,
+    /// wl_output transform; odd ones turn the screen by 90 degrees
+    #[serde(default)]
+    transform: u32,
+}
 
-    if let Err(e) = Command::new(&thaimeleon)
-        .args([path, "-w", &config])
-        .status()
-    {
-        eprintln!("Failed to run thaimeleon: {}", e);
+impl Monitor {
+    /// width and height as the wallpaper is shown, None if not reported
+    fn size(&self) -> Option<Dimensions> {
+        let size = if self.transform % 2 == 1 {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        };
+        (size.0 > 0 && size.1 > 0).then_some(size)
     }
+}
 
-    if let Err(e) = Command::new("/usr/bin/yolk").arg("sync").status() {
-        eprintln!("Failed to run yolk: {}", e);
+/// Hyprland's outputs, empty if hyprctl can't tell
+fn monitors() -> Vec<Monitor> {
+    let output = match backend::run_with_timeout(
+        "hyprctl",
+        Command::new("hyprctl").args(["monitors", "-j"]),
+        config::command_timeout(),
+    ) {
+        Ok(output) if output.status.success() => output.stdout,
+        _ => return Vec::new(),
+    };
+    match serde_json::from_slice::<Vec<Monitor>>(&output) {
+        Ok(monitors) => monitors,
+        Err(e) => {
+            eprintln!("Failed to read the monitor list: {}", e);
+            Vec::new()
+        }
     }
 }
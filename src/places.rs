@@ -0,0 +1,63 @@
+use chrono::{Datelike, NaiveDate};
+use std::collections::HashMap;
+
+/// (latitude, longitude) in degrees
+pub type Point = (f64, f64);
+
+/// kilometres per degree of latitude, and of longitude at the equator
+const KM_PER_DEGREE: f64 = 111.2;
+
+/// group (latitude, longitude) points into places: points share a grid cell of
+/// about `cell_km` square, and neighbouring occupied cells join into one place,
+/// so a trip along a coast stays together. Returns each point's place, numbered
+/// from 0 in order of their first point
+pub fn cluster(points: &[Point], cell_km: f64) -> Vec<usize> {
+    let cells: Vec<(i64, i64)> = points
+        .iter()
+        .map(|&(lat, lon)| {
+            // longitude degrees shrink toward the poles
+            let x = lon * lat.to_radians().cos() * KM_PER_DEGREE / cell_km;
+            let y = lat * KM_PER_DEGREE / cell_km;
+            (x.floor() as i64, y.floor() as i64)
+        })
+        .collect();
+
+    let mut index: HashMap<(i64, i64), usize> = HashMap::new();
+    for &cell in &cells {
+        let next = index.len();
+        index.entry(cell).or_insert(next);
+    }
+
+    let mut parent: Vec<usize> = (0..index.len()).collect();
+    for (&(x, y), &i) in &index {
+        for (dx, dy) in [(1, -1), (1, 0), (1, 1), (0, 1)] {
+            if let Some(&j) = index.get(&(x + dx, y + dy)) {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+    }
+
+    let mut places: HashMap<usize, usize> = HashMap::new();
+    cells
+        .iter()
+        .map(|cell| {
+            let r = root(&mut parent, index[cell]);
+            let next = places.len();
+            *places.entry(r).or_insert(next)
+        })
+        .collect()
+}
+
+fn root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+/// which of `places` to show on `date`, going through them one a day
+pub fn of_the_day(date: NaiveDate, places: usize) -> Option<usize> {
+    (places > 0).then(|| date.num_days_from_ce() as usize % places)
+}
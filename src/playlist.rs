@@ -0,0 +1,95 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{config, history};
+
+/// wallpapers shown in a fixed order instead of by time of day, read from a
+/// file with one path per line: relative to the wallpaper root or absolute.
+/// Lines starting with # are comments
+#[derive(Debug, Clone, Default)]
+pub struct Playlist {
+    /// history entries, see `history::entry_for`
+    entries: Vec<String>,
+    /// lines naming files that don't exist
+    missing: Vec<String>,
+}
+
+impl Playlist {
+    /// files that don't exist are skipped, see `missing`
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let root = config::wallpaper_dir();
+        let mut entries = Vec::new();
+        let mut missing = Vec::new();
+        for line in fs::read_to_string(path)?.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let file = Path::new(&root).join(line);
+            if file.is_file() {
+                entries.push(history::entry_for(&file));
+            } else {
+                missing.push(line.to_string());
+            }
+        }
+        Ok(Self { entries, missing })
+    }
+
+    pub fn missing(&self) -> &[String] {
+        &self.missing
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// where `entry` first appears, counting from 0
+    pub fn position(&self, entry: &str) -> Option<usize> {
+        self.entries.iter().position(|e| e == entry)
+    }
+
+    /// where the newest of `history` (newest first) that is in the playlist
+    /// was played from. An entry listed more than once is told apart by the
+    /// playlist entries shown before it: the appearance whose predecessors
+    /// match the longest run of them wins, the first on a tie
+    pub fn locate(&self, history: &[String]) -> Option<usize> {
+        let len = self.entries.len();
+        let played: Vec<&String> = history
+            .iter()
+            .filter(|entry| self.position(entry).is_some())
+            .take(len)
+            .collect();
+        let newest = *played.first()?;
+        let run = |i: usize| {
+            played
+                .iter()
+                .enumerate()
+                .take_while(|&(back, entry)| self.entries[(i + len - back) % len] == **entry)
+                .count()
+        };
+        (0..len)
+            .filter(|&i| self.entries[i] == *newest)
+            // max_by_key keeps the last of equals, so go from the end
+            .rev()
+            .max_by_key(|&i| run(i))
+    }
+
+    /// the index and file to show after `locate`, wrapping at the end; the
+    /// first one if none of `history` is in the playlist
+    pub fn next(&self, history: &[String]) -> Option<(usize, PathBuf)> {
+        let index = self
+            .locate(history)
+            .map_or(0, |i| (i + 1) % self.entries.len());
+        let entry = self.entries.get(index)?;
+        Some((index, Path::new(&config::wallpaper_dir()).join(entry)))
+    }
+}
+
+/// the playlist from `config::playlist`, or None when none is set
+pub fn load() -> Option<io::Result<Playlist>> {
+    config::playlist().map(|path| Playlist::load(Path::new(&path)))
+}
@@ -0,0 +1,119 @@
+use chrono::Weekday;
+use std::fs;
+use std::path::Path;
+
+use crate::config;
+use crate::history::PathList;
+
+const WEEK: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// wallpapers to pick from on given days, from a file of `days = pattern`
+/// lines such as `weekend = nature/**` or `mon,wed-fri = minimal/**`. A
+/// pattern is what the blocklist takes, or a subdirectory of the wallpaper
+/// root. Lines starting with # are comments
+#[derive(Debug, Clone, Default)]
+pub struct Schedule {
+    rules: Vec<(Vec<Weekday>, String)>,
+}
+
+impl Schedule {
+    pub fn load(path: &Path) -> Self {
+        let Ok(content) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+        Self::parse(&content)
+    }
+
+    /// lines that don't parse are skipped with a warning
+    pub fn parse(content: &str) -> Self {
+        let mut rules = Vec::new();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with('[') {
+                continue;
+            }
+            let rule = line.split_once('=').and_then(|(days, pattern)| {
+                let pattern = pattern.trim().trim_matches('"');
+                (!pattern.is_empty()).then_some((parse_days(days)?, pattern.to_string()))
+            });
+            match rule {
+                Some(rule) => rules.push(rule),
+                None => eprintln!("Ignoring schedule line: {}", line),
+            }
+        }
+        Self { rules }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// the patterns of every rule covering `day`, or None if none do
+    pub fn active(&self, day: Weekday) -> Option<PathList> {
+        let root = config::wallpaper_dir();
+        let patterns: Vec<String> = self
+            .rules
+            .iter()
+            .filter(|(days, _)| days.contains(&day))
+            .map(|(_, pattern)| directory_glob(&root, pattern))
+            .collect();
+        (!patterns.is_empty()).then(|| PathList::from_lines(patterns))
+    }
+}
+
+/// the schedule from `config::schedule`
+pub fn load() -> Schedule {
+    Schedule::load(Path::new(&config::schedule()))
+}
+
+/// a comma separated list of days, names or ranges of them: "mon,tue",
+/// "weekend", "weekdays", "mon-fri", "sunday"
+pub fn parse_days(s: &str) -> Option<Vec<Weekday>> {
+    let mut days = Vec::new();
+    for part in s.split(',').map(str::trim) {
+        let part = part.to_ascii_lowercase();
+        let span: Vec<Weekday> = match part.as_str() {
+            "weekend" | "weekends" => vec![Weekday::Sat, Weekday::Sun],
+            "weekday" | "weekdays" => WEEK[..5].to_vec(),
+            "daily" | "all" | "*" => WEEK.to_vec(),
+            _ => match part.split_once('-') {
+                Some((from, to)) => range(from.trim().parse().ok()?, to.trim().parse().ok()?),
+                None => vec![part.parse().ok()?],
+            },
+        };
+        for day in span {
+            if !days.contains(&day) {
+                days.push(day);
+            }
+        }
+    }
+    Some(days)
+}
+
+/// `from` to `to` inclusive, wrapping past Sunday
+fn range(from: Weekday, to: Weekday) -> Vec<Weekday> {
+    let mut days = vec![from];
+    let mut day = from;
+    while day != to {
+        day = day.succ();
+        days.push(day);
+    }
+    days
+}
+
+/// a bare subdirectory of the wallpaper root stands for everything under it
+fn directory_glob(root: &str, pattern: &str) -> String {
+    let is_glob = pattern.contains(['*', '?', '[']);
+    if !is_glob && Path::new(root).join(pattern).is_dir() {
+        format!("{}/**", pattern.trim_end_matches('/'))
+    } else {
+        pattern.to_string()
+    }
+}
@@ -0,0 +1,1252 @@
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+use rand::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use crate::history::{self, PathList, ShownStats};
+use crate::places::{self, Point};
+use crate::solar::{self, Light, Phase, SunTimes};
+use crate::{config, ImageFile, Season};
+
+/// minutes in a day, over which time distances wrap
+pub const MINUTES_PER_DAY: i32 = 24 * 60;
+
+/// how a candidate's capture time is compared with now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// clock time to the minute, within `config::window_minutes`
+    Hour,
+    /// phases of the day from sunrise and sunset, the same phase matching
+    Solar,
+    /// the sun's elevation where and when a photo was taken against here and
+    /// now, the same `Light` matching
+    Light,
+}
+
+impl Strategy {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "hour" => Some(Self::Hour),
+            "solar" => Some(Self::Solar),
+            "light" => Some(Self::Light),
+            _ => None,
+        }
+    }
+}
+
+/// how the pick is made once candidates have a distance from now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionMode {
+    /// random within the window, else the single closest, see `select`
+    Window,
+    /// one weighted draw over everything, see `select_weighted`
+    Weighted,
+    /// a draw among the best scoring by several factors, see `select_scored`
+    Score,
+}
+
+impl SelectionMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "window" => Some(Self::Window),
+            "weighted" => Some(Self::Weighted),
+            "score" => Some(Self::Score),
+            _ => None,
+        }
+    }
+}
+
+/// which of several candidates within the window `select` takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tiebreak {
+    /// any, by weight
+    Random,
+    /// the one shown longest ago, never shown ones first
+    LeastRecent,
+    /// the one with the newest mtime, so new photos come up soon
+    Newest,
+}
+
+impl Tiebreak {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "random" => Some(Self::Random),
+            "least-recent" => Some(Self::LeastRecent),
+            "newest" => Some(Self::Newest),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Random => "random",
+            Self::LeastRecent => "least recently shown",
+            Self::Newest => "newest file",
+        }
+    }
+
+    /// higher wins; equal keys are drawn from by weight
+    pub fn key(self, candidate: &Candidate) -> i64 {
+        match self {
+            Self::Random => 0,
+            Self::LeastRecent => candidate.last_shown.map_or(i64::MAX, |ago| ago as i64),
+            Self::Newest => candidate.mtime,
+        }
+    }
+}
+
+/// weight of a candidate without a capture hour in `select_weighted`, about
+/// that of one 4.5 hours off with the default tau
+pub const HOURLESS_WEIGHT: f64 = 0.05;
+
+/// how many times as likely `preferred` candidates are in `select_weighted`
+pub const PREFERRED_BOOST: f64 = 2.0;
+
+/// clock hours counted as night in `Strategy::Hour`, from the first up to
+/// but not including the second
+pub const NIGHT_HOURS: (i32, i32) = (21, 6);
+
+/// weight of an image too bright for the night or too dark for the day
+pub const BRIGHTNESS_PENALTY: f64 = 0.2;
+
+/// now, as a strategy sees it
+#[derive(Debug, Clone)]
+pub enum Timing {
+    Hour {
+        /// minutes since midnight
+        current: i32,
+        /// minutes either side of `current` that count as a match
+        window: i32,
+    },
+    Solar {
+        now: Phase,
+        /// sun times on the 15th of each month this year, for candidates
+        /// whose capture day isn't cached
+        by_month: Box<[SunTimes; 12]>,
+        today: SunTimes,
+    },
+    Light {
+        now: Light,
+        /// where photos without their own `Light` are taken to be from
+        location: (f64, f64),
+    },
+}
+
+/// when a candidate was taken, as far as comparing it with now goes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TakenAt {
+    /// minutes after midnight
+    pub minute: Option<u16>,
+    pub month: Option<u8>,
+    /// from its own location and capture time, if it has both
+    pub light: Option<Light>,
+}
+
+impl Timing {
+    /// `Strategy::Solar` and `Strategy::Light` need a location and fall back to
+    /// the clock without one; `window` is in minutes, only for the clock
+    pub fn new(
+        strategy: Strategy,
+        now: DateTime<Local>,
+        location: Option<(f64, f64)>,
+        window: i32,
+    ) -> Self {
+        let current = (now.hour() * 60 + now.minute()) as i32;
+        match (strategy, location) {
+            (Strategy::Solar, Some((lat, lon))) => {
+                let today = solar::sun_times(now.date_naive(), lat, lon);
+                let by_month = Box::new(std::array::from_fn(|i| {
+                    NaiveDate::from_ymd_opt(now.year(), i as u32 + 1, 15)
+                        .map_or(today, |date| solar::sun_times(date, lat, lon))
+                }));
+                let hour = now.hour() as f64 + now.minute() as f64 / 60.0;
+                Self::Solar {
+                    now: Phase::at(hour, today),
+                    by_month,
+                    today,
+                }
+            }
+            (Strategy::Light, Some((lat, lon))) => Self::Light {
+                now: Light::at(now.with_timezone(&Utc), lat, lon),
+                location: (lat, lon),
+            },
+            (Strategy::Solar | Strategy::Light, None) => {
+                eprintln!(
+                    "Solar matching needs WALLPAPER_LATITUDE and WALLPAPER_LONGITUDE, \
+                     matching hours instead"
+                );
+                Self::Hour { current, window }
+            }
+            (Strategy::Hour, _) => Self::Hour { current, window },
+        }
+    }
+
+    /// how far a photo taken at `taken` is from now; minutes for `Hour`,
+    /// phases for `Solar` and `Light` levels for `Light`, where a photo without
+    /// its own light is taken to be from here, at its time of day in its month
+    pub fn distance(&self, taken: TakenAt) -> Option<i32> {
+        let TakenAt {
+            minute,
+            month,
+            light,
+        } = taken;
+        if let (Self::Light { now, .. }, Some(light)) = (self, light) {
+            return Some(now.distance(light));
+        }
+        let minute = minute?;
+        match self {
+            Self::Hour { current, .. } => Some(minute_distance(*current, minute as i32)),
+            Self::Solar {
+                now,
+                by_month,
+                today,
+            } => {
+                let sun = month
+                    .and_then(|m| by_month.get(m.checked_sub(1)? as usize))
+                    .unwrap_or(today);
+                Some(now.distance(Phase::at(minute as f64 / 60.0, *sun)))
+            }
+            Self::Light {
+                now,
+                location: (lat, lon),
+            } => {
+                let today = Local::now().date_naive();
+                let date = month
+                    .and_then(|m| NaiveDate::from_ymd_opt(today.year(), m as u32, 15))
+                    .unwrap_or(today);
+                let time = date.and_hms_opt(minute as u32 / 60, minute as u32 % 60, 0)?;
+                Some(now.distance(Light::at_local(time, *lat, *lon)))
+            }
+        }
+    }
+
+    /// whether a photo taken at `taken` is within the window
+    pub fn matches(&self, taken: TakenAt) -> bool {
+        self.distance(taken).is_some_and(|d| d <= self.window())
+    }
+
+    /// the largest distance that counts as a match
+    pub fn window(&self) -> i32 {
+        match self {
+            Self::Hour { window, .. } => *window,
+            Self::Solar { .. } | Self::Light { .. } => 0,
+        }
+    }
+
+    /// distance units in an hour for `Hour` and in a phase or level otherwise,
+    /// the units tau and the widest window are given in
+    pub fn per_unit(&self) -> i32 {
+        match self {
+            Self::Hour { .. } => 60,
+            Self::Solar { .. } | Self::Light { .. } => 1,
+        }
+    }
+
+    /// the largest `distance` there is: half a day, half the phases, or from
+    /// night to day
+    pub fn max_distance(&self) -> i32 {
+        match self {
+            Self::Hour { .. } => MINUTES_PER_DAY / 2,
+            Self::Solar { .. } | Self::Light { .. } => 3,
+        }
+    }
+
+    /// whether it's night now, by the clock for `Hour` and the sun for `Solar`
+    pub fn is_night(&self) -> bool {
+        match self {
+            Self::Hour { current, .. } => {
+                let hour = current / 60;
+                hour >= NIGHT_HOURS.0 || hour < NIGHT_HOURS.1
+            }
+            Self::Solar { now, .. } => *now == Phase::Night,
+            Self::Light { now, .. } => *now == Light::Night,
+        }
+    }
+
+    fn describe_window(&self) -> String {
+        match self {
+            Self::Hour { window, .. } => format!("{} minute window", window),
+            Self::Solar { now, .. } => format!("the current {} phase", now.name()),
+            Self::Light { now, .. } => format!("the current light ({})", now.name()),
+        }
+    }
+
+    fn unit(&self) -> &'static str {
+        match self {
+            Self::Hour { .. } => "minutes",
+            Self::Solar { .. } => "phases",
+            Self::Light { .. } => "light levels",
+        }
+    }
+
+    /// what `per_unit` distance units make up
+    fn coarse_unit(&self) -> &'static str {
+        match self {
+            Self::Hour { .. } => "hours",
+            Self::Solar { .. } => "phases",
+            Self::Light { .. } => "light levels",
+        }
+    }
+}
+
+/// `BRIGHTNESS_PENALTY` for an image brighter than `night_max` at night or
+/// darker than `day_min` by day, else 1; unmeasured images aren't penalized
+pub fn brightness_weight(luminance: Option<f32>, night: bool, night_max: f32, day_min: f32) -> f64 {
+    match luminance {
+        Some(l) if night && l > night_max => BRIGHTNESS_PENALTY,
+        Some(l) if !night && l < day_min => BRIGHTNESS_PENALTY,
+        _ => 1.0,
+    }
+}
+
+/// stands in for the minute of a photo whose cached time only has the hour
+pub fn middle_of_hour(hour: u8) -> u16 {
+    hour as u16 * 60 + 30
+}
+
+/// minutes between two times of day, wrapping around midnight
+pub fn minute_distance(current: i32, image: i32) -> i32 {
+    let diff = (current - image).rem_euclid(MINUTES_PER_DAY);
+    diff.min(MINUTES_PER_DAY - diff)
+}
+
+/// whether a photo taken at `taken` is within `seconds` of any of `shown`, as
+/// frames of one burst are
+pub fn is_burst_of(taken: NaiveDateTime, shown: &[NaiveDateTime], seconds: i64) -> bool {
+    shown
+        .iter()
+        .any(|&t| (taken - t).num_seconds().abs() <= seconds)
+}
+
+/// days from `today` to the nearest anniversary of `taken` in any year, so
+/// Dec 30 is two days from Jan 1; Feb 29 has its anniversary on Feb 28 when
+/// there is no leap day
+pub fn days_from_anniversary(taken: NaiveDate, today: NaiveDate) -> u32 {
+    [today.year() - 1, today.year(), today.year() + 1]
+        .into_iter()
+        .filter_map(|year| {
+            NaiveDate::from_ymd_opt(year, taken.month(), taken.day())
+                .or_else(|| NaiveDate::from_ymd_opt(year, 2, 28))
+        })
+        .map(|date| (date - today).num_days().unsigned_abs() as u32)
+        .min()
+        .unwrap_or(u32::MAX)
+}
+
+/// where a candidate's hour came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HourSource {
+    Exif,
+    Mtime,
+    Override,
+}
+
+/// an image that can be selected, with what selection needs to know about it
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub path: PathBuf,
+    pub hour: Option<u8>,
+    /// minutes after midnight, set whenever `hour` is
+    pub minute: Option<u16>,
+    pub month: Option<u8>,
+    pub hour_source: Option<HourSource>,
+    /// mean luminance from 0 to 1, when measured
+    pub luminance: Option<f32>,
+    /// the local capture date, only from EXIF
+    pub captured_on: Option<NaiveDate>,
+    /// the local capture time to the second, only from EXIF
+    pub captured_at: Option<NaiveDateTime>,
+    /// from its GPS position and capture time
+    pub light: Option<Light>,
+    /// 0-5 stars, None when unrated
+    pub rating: Option<u8>,
+    pub mtime: i64,
+    /// how many wallpapers were shown since this one was, None if it never
+    /// was; only filled in for `Tiebreak::LeastRecent` and `SelectionMode::Score`
+    pub last_shown: Option<usize>,
+    /// only filled in for `SelectionMode::Score`
+    pub favorite: bool,
+}
+
+impl Candidate {
+    /// `exif` is the minute after midnight and the month; falls back to the
+    /// file's mtime for them only when `mtime_fallback` is set
+    pub fn new(img: &ImageFile, exif: (Option<u16>, Option<u8>), mtime_fallback: bool) -> Self {
+        let (minute, month, hour_source) = match exif {
+            (Some(minute), month) => (Some(minute), month, Some(HourSource::Exif)),
+            (None, _) if mtime_fallback => match Local.timestamp_opt(img.mtime, 0).single() {
+                Some(dt) => (
+                    Some((dt.hour() * 60 + dt.minute()) as u16),
+                    Some(dt.month() as u8),
+                    Some(HourSource::Mtime),
+                ),
+                None => (None, None, None),
+            },
+            (None, month) => (None, month, None),
+        };
+
+        Candidate {
+            path: img.path.clone(),
+            hour: minute.map(|m| (m / 60) as u8),
+            minute,
+            month,
+            hour_source,
+            luminance: None,
+            captured_on: None,
+            captured_at: None,
+            light: None,
+            rating: None,
+            mtime: img.mtime,
+            last_shown: None,
+            favorite: false,
+        }
+    }
+
+    /// an hour set with --set-hour wins over anything read from the file
+    pub fn with_override(mut self, hour: Option<u8>) -> Self {
+        if let Some(hour) = hour {
+            self.hour = Some(hour);
+            self.minute = Some(middle_of_hour(hour));
+            self.hour_source = Some(HourSource::Override);
+        }
+        self
+    }
+
+    pub fn with_luminance(mut self, luminance: Option<f32>) -> Self {
+        self.luminance = luminance;
+        self
+    }
+
+    pub fn with_rating(mut self, rating: Option<u8>) -> Self {
+        self.rating = rating;
+        self
+    }
+
+    pub fn with_last_shown(mut self, ago: Option<usize>) -> Self {
+        self.last_shown = ago;
+        self
+    }
+
+    pub fn with_captured_on(mut self, date: Option<NaiveDate>) -> Self {
+        self.captured_on = date;
+        self
+    }
+
+    pub fn with_captured_at(mut self, time: Option<NaiveDateTime>) -> Self {
+        self.captured_at = time;
+        self
+    }
+
+    pub fn with_light(mut self, light: Option<Light>) -> Self {
+        self.light = light;
+        self
+    }
+
+    pub fn taken_at(&self) -> TakenAt {
+        TakenAt {
+            minute: self.minute,
+            month: self.month,
+            light: self.light,
+        }
+    }
+
+    pub fn season(&self) -> Option<Season> {
+        Season::from_month(self.month?, config::hemisphere())
+    }
+
+    pub fn describe_hour(&self) -> String {
+        match (self.hour, self.hour_source) {
+            (Some(h), Some(HourSource::Mtime)) => format!("{} from mtime", h),
+            (Some(h), Some(HourSource::Override)) => format!("{} from override", h),
+            (Some(h), _) => h.to_string(),
+            (None, _) => "N/A".into(),
+        }
+    }
+}
+
+/// the images `active` allows, see `schedule::Schedule::active`; None, to use
+/// them all, when it allows none of them
+pub fn scheduled(all: &[ImageFile], active: &PathList) -> Option<Vec<ImageFile>> {
+    let scheduled: Vec<ImageFile> = all
+        .iter()
+        .filter(|img| active.contains(&img.path))
+        .cloned()
+        .collect();
+    (!scheduled.is_empty()).then_some(scheduled)
+}
+
+/// drop candidates rated below `min` stars, and unrated ones unless
+/// `include_unrated`, returning how many were dropped; None, dropping none,
+/// if that would drop them all
+pub fn keep_rated(
+    candidates: &mut Vec<Candidate>,
+    min: u8,
+    include_unrated: bool,
+) -> Option<usize> {
+    let rated = |c: &Candidate| c.rating.map_or(include_unrated, |r| r >= min);
+    if !candidates.iter().any(rated) {
+        return None;
+    }
+    let before = candidates.len();
+    candidates.retain(rated);
+    Some(before - candidates.len())
+}
+
+/// drop candidates taken within `seconds` of a different photo in `shown`,
+/// near duplicates from the same burst, returning how many were dropped; none
+/// are if that would drop them all. Photos without a capture time are kept
+pub fn drop_burst_frames(
+    candidates: &mut Vec<Candidate>,
+    shown: &[(PathBuf, NaiveDateTime)],
+    seconds: i64,
+) -> usize {
+    let in_burst = |c: &Candidate| {
+        let Some(taken) = c.captured_at else {
+            return false;
+        };
+        let others: Vec<NaiveDateTime> = shown
+            .iter()
+            .filter(|(path, _)| *path != c.path)
+            .map(|&(_, t)| t)
+            .collect();
+        is_burst_of(taken, &others, seconds)
+    };
+    let dropped = candidates.iter().filter(|c| in_burst(c)).count();
+    if dropped == candidates.len() {
+        return 0;
+    }
+    candidates.retain(|c| !in_burst(c));
+    dropped
+}
+
+/// drop candidates among the `shown` history entries if any never shown one
+/// matches the time, so the rest of the collection gets its turn, returning
+/// how many were dropped; once everything has been shown this drops none
+pub fn keep_unseen(
+    candidates: &mut Vec<Candidate>,
+    shown: &HashSet<String>,
+    timing: &Timing,
+) -> usize {
+    let unseen = |c: &Candidate| !history::is_recent(shown, &c.path);
+    if !candidates
+        .iter()
+        .any(|c| unseen(c) && timing.matches(c.taken_at()))
+    {
+        return 0;
+    }
+    let before = candidates.len();
+    candidates.retain(unseen);
+    before - candidates.len()
+}
+
+/// how many times each candidate appears in `stats`, see `history::stats`
+pub fn shown_counts(
+    candidates: &[Candidate],
+    stats: &HashMap<String, ShownStats>,
+) -> HashMap<PathBuf, usize> {
+    candidates
+        .iter()
+        .map(|c| (c.path.clone(), history::stats_for(stats, &c.path).count))
+        .collect()
+}
+
+/// the photos taken around one of the places in the collection
+#[derive(Debug, Clone, PartialEq)]
+pub struct Place {
+    /// numbered from 0, of `count`
+    pub index: usize,
+    pub count: usize,
+    pub paths: HashSet<PathBuf>,
+    /// the mean position of its photos
+    pub center: Point,
+}
+
+/// today's place among the photos at `locations`, grouped by `places::cluster`
+/// into cells of `cell_km`; None when there are none
+pub fn place_of_the_day(
+    locations: &[(String, Point)],
+    cell_km: f64,
+    today: NaiveDate,
+) -> Option<Place> {
+    let points: Vec<Point> = locations.iter().map(|(_, point)| *point).collect();
+    let places = places::cluster(&points, cell_km);
+    let count = places.iter().max().map_or(0, |&p| p + 1);
+    let index = places::of_the_day(today, count)?;
+
+    let here: Vec<&(String, Point)> = locations
+        .iter()
+        .zip(&places)
+        .filter(|(_, &place)| place == index)
+        .map(|(location, _)| location)
+        .collect();
+    let n = here.len() as f64;
+    let center = here.iter().fold((0.0, 0.0), |(lat, lon), (_, (y, x))| {
+        (lat + y / n, lon + x / n)
+    });
+    Some(Place {
+        index,
+        count,
+        paths: here.iter().map(|(path, _)| PathBuf::from(path)).collect(),
+        center,
+    })
+}
+
+/// keep only candidates taken at `place` if any of them match the time;
+/// false, keeping them all, if none do
+pub fn keep_at_place(candidates: &mut Vec<Candidate>, place: &Place, timing: &Timing) -> bool {
+    let at_place = |c: &Candidate| place.paths.contains(&c.path);
+    if !candidates
+        .iter()
+        .any(|c| at_place(c) && timing.matches(c.taken_at()))
+    {
+        return false;
+    }
+    candidates.retain(at_place);
+    true
+}
+
+/// how `select_candidate` picks
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    pub mode: SelectionMode,
+    /// see `select_weighted`
+    pub tau: f64,
+    /// only for `SelectionMode::Window`, which has ties to break
+    pub tiebreak: Tiebreak,
+    /// see `select`
+    pub max_window: i32,
+    /// see `select_scored`
+    pub scores: ScoreWeights,
+    pub top_k: usize,
+}
+
+impl Options {
+    pub fn from_config() -> Self {
+        Self {
+            mode: config::selection_mode(),
+            tau: config::selection_tau(),
+            tiebreak: config::tiebreak(),
+            max_window: config::max_window(),
+            scores: config::score_weights(),
+            top_k: config::score_top_k(),
+        }
+    }
+}
+
+/// `select` or `select_weighted` over candidates by their hour and month
+pub fn select_candidate<'a>(
+    candidates: &'a [Candidate],
+    timing: &Timing,
+    options: &Options,
+    weight: impl Fn(&Candidate) -> f64,
+    preferred: impl Fn(&Candidate) -> bool,
+    rng: &mut impl Rng,
+) -> (Option<&'a Candidate>, SelectionReport) {
+    let time_of = Candidate::taken_at;
+    match options.mode {
+        SelectionMode::Window => {
+            let tiebreak = options.tiebreak;
+            let (chosen, mut report) = select(
+                candidates,
+                timing,
+                options.max_window,
+                time_of,
+                weight,
+                preferred,
+                |c| tiebreak.key(c),
+                rng,
+            );
+            if matches!(
+                report.branch,
+                Some(Branch::Preferred | Branch::Window | Branch::Widened)
+            ) {
+                report.tiebreak = Some(tiebreak);
+            }
+            (chosen, report)
+        }
+        SelectionMode::Weighted => select_weighted(
+            candidates,
+            timing,
+            time_of,
+            weight,
+            preferred,
+            options.tau,
+            rng,
+        ),
+        SelectionMode::Score => select_scored(
+            candidates,
+            timing,
+            &options.scores,
+            options.top_k,
+            preferred,
+            rng,
+        ),
+    }
+}
+
+/// how much each factor of `select_scored` counts
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ScoreWeights {
+    /// how close the capture time is to now
+    pub time: f64,
+    /// whether it's `preferred`, from this season unless seasons are off
+    pub season: f64,
+    pub favorite: f64,
+    /// never shown before
+    pub unseen: f64,
+    /// subtracted, more the more recently it was shown
+    pub recent: f64,
+}
+
+/// one part of a candidate's score
+#[derive(Debug, Clone, Serialize)]
+pub struct Factor {
+    pub name: &'static str,
+    /// 0 to 1
+    pub value: f64,
+    /// `value` times its weight, negative for "recent"
+    pub contribution: f64,
+}
+
+impl ScoreWeights {
+    /// the factors of `candidate`'s score, which is the sum of their
+    /// contributions
+    pub fn factors(&self, candidate: &Candidate, timing: &Timing, preferred: bool) -> [Factor; 5] {
+        let closeness = timing
+            .distance(candidate.taken_at())
+            .map_or(0.0, |d| 1.0 - d as f64 / timing.max_distance() as f64);
+        let recency = candidate.last_shown.map_or(0.0, |ago| {
+            (1.0 - ago as f64 / config::HISTORY_SIZE as f64).max(0.0)
+        });
+        let flag = |set: bool| if set { 1.0 } else { 0.0 };
+        let factor = |name, value: f64, weight: f64| Factor {
+            name,
+            value,
+            contribution: value * weight,
+        };
+        [
+            factor("time", closeness, self.time),
+            factor("season", flag(preferred), self.season),
+            factor("favorite", flag(candidate.favorite), self.favorite),
+            factor("unseen", flag(candidate.last_shown.is_none()), self.unseen),
+            factor("recent", recency, -self.recent),
+        ]
+    }
+
+    pub fn score(&self, candidate: &Candidate, timing: &Timing, preferred: bool) -> f64 {
+        self.factors(candidate, timing, preferred)
+            .iter()
+            .map(|f| f.contribution)
+            .sum()
+    }
+}
+
+/// score every candidate by `weights` and draw one of the `top_k` best, likelier
+/// the higher its score; evenly if none scores above 0
+pub fn select_scored<'a>(
+    candidates: &'a [Candidate],
+    timing: &Timing,
+    weights: &ScoreWeights,
+    top_k: usize,
+    preferred: impl Fn(&Candidate) -> bool,
+    rng: &mut impl Rng,
+) -> (Option<&'a Candidate>, SelectionReport) {
+    let time_of = Candidate::taken_at;
+    let mut report = SelectionReport::new(candidates, timing, time_of);
+    let mut pool: Vec<(usize, f64)> = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (i, weights.score(c, timing, preferred(c))))
+        .collect();
+    // stable, so equal scores keep the candidates' order
+    pool.sort_by(|a, b| b.1.total_cmp(&a.1));
+    pool.truncate(top_k.max(1));
+    let any_positive = pool.iter().any(|&(_, score)| score > 0.0);
+    for (_, score) in &mut pool {
+        *score = if any_positive { score.max(0.0) } else { 1.0 };
+    }
+
+    report.preferred = candidates.iter().filter(|c| preferred(c)).count();
+    let chosen = pool.choose_weighted(rng, |(_, w)| *w).ok().map(|(i, _)| *i);
+    report.finish(Branch::Scored, pool, chosen);
+    fill_distances(&mut report, candidates, timing, time_of);
+    if let Some(i) = chosen {
+        let c = &candidates[i];
+        report.factors = weights.factors(c, timing, preferred(c)).to_vec();
+    }
+    (chosen.map(|i| &candidates[i]), report)
+}
+
+/// what a `SelectionStrategy` picks from
+#[derive(Clone, Copy)]
+pub struct SelectionContext<'a> {
+    pub candidates: &'a [Candidate],
+    pub timing: &'a Timing,
+    pub today: NaiveDate,
+    /// history entries, newest first
+    pub recent: &'a [String],
+    pub options: &'a Options,
+    /// how likely a candidate is drawn relative to the others, before its time
+    pub weight: &'a dyn Fn(&Candidate) -> f64,
+    /// tried first within the window, such as those from this season
+    pub preferred: &'a dyn Fn(&Candidate) -> bool,
+}
+
+/// a strategy's pick
+#[derive(Debug, Clone)]
+pub struct Selection {
+    /// into `SelectionContext::candidates`
+    pub index: usize,
+    /// `SelectionStrategy::name` of the one that picked it
+    pub strategy: &'static str,
+    pub report: SelectionReport,
+}
+
+/// a way of picking the wallpaper
+pub trait SelectionStrategy {
+    fn name(&self) -> &'static str;
+
+    /// None if it has nothing to pick, leaving it to the next strategy of a
+    /// `CompositeStrategy`
+    fn select(&self, ctx: &SelectionContext, rng: &mut dyn RngCore) -> Option<Selection>;
+}
+
+/// `select_candidate` by `SelectionContext::options`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimeOfDayStrategy;
+
+impl SelectionStrategy for TimeOfDayStrategy {
+    fn name(&self) -> &'static str {
+        "time of day"
+    }
+
+    fn select(&self, ctx: &SelectionContext, mut rng: &mut dyn RngCore) -> Option<Selection> {
+        let (_, report) = select_candidate(
+            ctx.candidates,
+            ctx.timing,
+            ctx.options,
+            ctx.weight,
+            ctx.preferred,
+            &mut rng,
+        );
+        Some(Selection {
+            index: report.chosen?,
+            strategy: self.name(),
+            report,
+        })
+    }
+}
+
+/// `then` over the candidates taken on today's date in an earlier year or,
+/// failing that, within `days` of it; nothing if there are none
+#[derive(Debug, Clone, Copy)]
+pub struct AnniversaryStrategy<S> {
+    pub days: u32,
+    pub then: S,
+}
+
+impl<S: SelectionStrategy> SelectionStrategy for AnniversaryStrategy<S> {
+    fn name(&self) -> &'static str {
+        "anniversary"
+    }
+
+    fn select(&self, ctx: &SelectionContext, rng: &mut dyn RngCore) -> Option<Selection> {
+        let days = |c: &Candidate| {
+            c.captured_on
+                .map(|taken| days_from_anniversary(taken, ctx.today))
+        };
+        let closest = ctx
+            .candidates
+            .iter()
+            .filter_map(days)
+            .min()
+            .filter(|&d| d <= self.days)?;
+        let within = if closest == 0 { 0 } else { self.days };
+        let indices: Vec<usize> = (0..ctx.candidates.len())
+            .filter(|&i| days(&ctx.candidates[i]).is_some_and(|d| d <= within))
+            .collect();
+        let matching: Vec<Candidate> = indices.iter().map(|&i| ctx.candidates[i].clone()).collect();
+
+        let mut selection = self.then.select(
+            &SelectionContext {
+                candidates: &matching,
+                ..*ctx
+            },
+            rng,
+        )?;
+        let report = &mut selection.report;
+        report.notes.push(if within == 0 {
+            format!("{} images were taken on this day", matching.len())
+        } else {
+            format!(
+                "{} images were taken within {} days of this day",
+                matching.len(),
+                within
+            )
+        });
+        if let Some(taken) = matching[selection.index].captured_on {
+            report
+                .notes
+                .push(format!("anniversary match: taken {}", taken));
+        }
+        report.remap(&indices);
+        selection.index = indices[selection.index];
+        selection.strategy = self.name();
+        Some(selection)
+    }
+}
+
+/// the first pick of `strategies`, in order
+#[derive(Default)]
+pub struct CompositeStrategy {
+    pub strategies: Vec<Box<dyn SelectionStrategy>>,
+}
+
+impl SelectionStrategy for CompositeStrategy {
+    fn name(&self) -> &'static str {
+        "composite"
+    }
+
+    fn select(&self, ctx: &SelectionContext, rng: &mut dyn RngCore) -> Option<Selection> {
+        self.strategies
+            .iter()
+            .find_map(|strategy| strategy.select(ctx, rng))
+    }
+}
+
+/// `select_scored` by `weights`, whatever `SelectionContext::options` say
+#[derive(Debug, Clone, Copy)]
+pub struct ScoringStrategy {
+    pub weights: ScoreWeights,
+    pub top_k: usize,
+}
+
+impl SelectionStrategy for ScoringStrategy {
+    fn name(&self) -> &'static str {
+        "score"
+    }
+
+    fn select(&self, ctx: &SelectionContext, mut rng: &mut dyn RngCore) -> Option<Selection> {
+        let (_, report) = select_scored(
+            ctx.candidates,
+            ctx.timing,
+            &self.weights,
+            self.top_k,
+            ctx.preferred,
+            &mut rng,
+        );
+        Some(Selection {
+            index: report.chosen?,
+            strategy: self.name(),
+            report,
+        })
+    }
+}
+
+/// the strategies config turns on, ending with `ScoringStrategy` for
+/// `SelectionMode::Score` and `TimeOfDayStrategy` otherwise
+pub fn strategy_from_config() -> CompositeStrategy {
+    let scoring = (config::selection_mode() == SelectionMode::Score).then(|| ScoringStrategy {
+        weights: config::score_weights(),
+        top_k: config::score_top_k(),
+    });
+    let mut strategies: Vec<Box<dyn SelectionStrategy>> = Vec::new();
+    if config::anniversary() {
+        let days = config::anniversary_days();
+        strategies.push(match scoring {
+            Some(then) => Box::new(AnniversaryStrategy { days, then }),
+            None => Box::new(AnniversaryStrategy {
+                days,
+                then: TimeOfDayStrategy,
+            }),
+        });
+    }
+    match scoring {
+        Some(scoring) => strategies.push(Box::new(scoring)),
+        None => strategies.push(Box::new(TimeOfDayStrategy)),
+    }
+    CompositeStrategy { strategies }
+}
+
+/// which rule picked the wallpaper
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Branch {
+    /// a `preferred` candidate within the window
+    Preferred,
+    /// any candidate within the window
+    Window,
+    /// nothing in the window, any of the closest ones within the widest
+    Widened,
+    /// nothing within the widest window, the closest one
+    BestMatch,
+    /// nothing with a time, any one
+    Random,
+    /// `select_weighted`
+    Weighted,
+    /// `select_scored`
+    Scored,
+}
+
+/// a candidate the pick was drawn from
+#[derive(Debug, Clone, Serialize)]
+pub struct Contender {
+    /// into the candidates given
+    pub index: usize,
+    pub distance: Option<i32>,
+    pub weight: f64,
+    /// its chance of being drawn, 0 to 1
+    pub chance: f64,
+}
+
+/// how a selection came about, for printing after it or explaining it
+#[derive(Debug, Clone, Serialize)]
+pub struct SelectionReport {
+    pub candidates: usize,
+    /// candidates with a capture time
+    pub with_time: usize,
+    /// how many candidates are at each distance from now in whole
+    /// `distance_unit`s, from 0
+    pub distances: Vec<usize>,
+    /// "hours" or "phases"
+    pub distance_unit: &'static str,
+    /// of the other distances: "minutes" or "phases"
+    pub unit: &'static str,
+    pub window: String,
+    pub in_window: usize,
+    pub preferred: usize,
+    pub branch: Option<Branch>,
+    /// how the pick among several within the window was made
+    pub tiebreak: Option<Tiebreak>,
+    /// the likeliest candidates of the drawn pool, likeliest first
+    pub contenders: Vec<Contender>,
+    /// into the candidates given
+    pub chosen: Option<usize>,
+    pub chosen_distance: Option<i32>,
+    /// what a `SelectionStrategy` adds about its pick
+    pub notes: Vec<String>,
+    /// of the chosen candidate's score, for `Branch::Scored`
+    pub factors: Vec<Factor>,
+}
+
+impl SelectionReport {
+    fn new<T>(candidates: &[T], timing: &Timing, time_of: impl Fn(&T) -> TakenAt) -> Self {
+        let mut distances = Vec::new();
+        let mut in_window = 0;
+        for candidate in candidates {
+            if let Some(d) = timing.distance(time_of(candidate)) {
+                let bucket = (d / timing.per_unit()) as usize;
+                if distances.len() <= bucket {
+                    distances.resize(bucket + 1, 0);
+                }
+                distances[bucket] += 1;
+                if d <= timing.window() {
+                    in_window += 1;
+                }
+            }
+        }
+        Self {
+            candidates: candidates.len(),
+            with_time: distances.iter().sum(),
+            distances,
+            distance_unit: timing.coarse_unit(),
+            unit: timing.unit(),
+            window: timing.describe_window(),
+            in_window,
+            preferred: 0,
+            branch: None,
+            tiebreak: None,
+            contenders: Vec::new(),
+            chosen: None,
+            chosen_distance: None,
+            notes: Vec::new(),
+            factors: Vec::new(),
+        }
+    }
+
+    /// point the indices at `indices[i]`, for a report on some of the candidates
+    fn remap(&mut self, indices: &[usize]) {
+        for contender in &mut self.contenders {
+            contender.index = indices[contender.index];
+        }
+        self.chosen = self.chosen.map(|i| indices[i]);
+    }
+
+    /// record the pick of `chosen` from `pool` (index, weight)
+    fn finish(&mut self, branch: Branch, mut pool: Vec<(usize, f64)>, chosen: Option<usize>) {
+        let total: f64 = pool.iter().map(|(_, w)| w).sum();
+        pool.sort_by(|a, b| b.1.total_cmp(&a.1));
+        self.contenders = pool
+            .into_iter()
+            .take(CONTENDERS)
+            .map(|(index, weight)| Contender {
+                index,
+                distance: None,
+                weight,
+                chance: if total > 0.0 { weight / total } else { 0.0 },
+            })
+            .collect();
+        self.branch = Some(branch);
+        self.chosen = chosen;
+    }
+
+    /// the line printed after every selection
+    pub fn summary(&self) -> String {
+        match self.branch {
+            Some(Branch::Preferred) => format!(
+                "Found {} images within {}, {} from this season",
+                self.in_window, self.window, self.preferred
+            ),
+            Some(Branch::Window) => {
+                format!("Found {} images within {}", self.in_window, self.window)
+            }
+            Some(Branch::Widened) => format!(
+                "Nothing within {}, widened to {} {}",
+                self.window,
+                self.chosen_distance.unwrap_or_default(),
+                self.unit
+            ),
+            Some(Branch::BestMatch) => format!(
+                "Using best time match (diff: {} {})",
+                self.chosen_distance.unwrap_or_default(),
+                self.unit
+            ),
+            Some(Branch::Weighted) => match self.chosen_distance {
+                Some(d) => format!("Drew an image {} {} from now", d, self.unit),
+                None => "Drew an image without a capture time".to_string(),
+            },
+            Some(Branch::Scored) => format!(
+                "Drew an image scoring {:.2}",
+                self.factors.iter().map(|f| f.contribution).sum::<f64>()
+            ),
+            Some(Branch::Random) | None => "Choosing random image".to_string(),
+        }
+    }
+}
+
+/// contenders kept in a `SelectionReport`
+pub const CONTENDERS: usize = 10;
+
+/// pick one of `candidates` by how close `time_of` puts them to
+/// now: one within the window, trying `preferred` ones first, else one of
+/// those closest if they are within `max_window` hours (phases with
+/// `Timing::Solar`), else the closest, else any.
+/// Only those with the highest `tie_key` are drawn from; random picks are
+/// weighted by `weight`
+#[allow(clippy::too_many_arguments)]
+pub fn select<'a, T>(
+    candidates: &'a [T],
+    timing: &Timing,
+    max_window: i32,
+    time_of: impl Fn(&T) -> TakenAt,
+    weight: impl Fn(&T) -> f64,
+    preferred: impl Fn(&T) -> bool,
+    tie_key: impl Fn(&T) -> i64,
+    rng: &mut impl Rng,
+) -> (Option<&'a T>, SelectionReport) {
+    let mut report = SelectionReport::new(candidates, timing, &time_of);
+    let mut best: Option<(usize, i32)> = None;
+    let mut window_matches: Vec<usize> = Vec::new();
+
+    for (i, candidate) in candidates.iter().enumerate() {
+        if let Some(diff) = timing.distance(time_of(candidate)) {
+            if diff <= timing.window() {
+                window_matches.push(i);
+            }
+            if best.is_none_or(|(_, best_diff)| diff < best_diff) {
+                best = Some((i, diff));
+            }
+        }
+    }
+
+    let preferred_matches: Vec<usize> = window_matches
+        .iter()
+        .copied()
+        .filter(|&i| preferred(&candidates[i]))
+        .collect();
+    report.preferred = preferred_matches.len();
+
+    let (branch, mut pool) = if !preferred_matches.is_empty() {
+        (Branch::Preferred, preferred_matches)
+    } else if !window_matches.is_empty() {
+        (Branch::Window, window_matches)
+    } else if let Some((_, diff)) = best.filter(|&(_, diff)| diff <= max_window * timing.per_unit())
+    {
+        // nothing is closer, so widening stops at the first distance with any
+        let closest = candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| timing.distance(time_of(c)) == Some(diff));
+        (Branch::Widened, closest.map(|(i, _)| i).collect())
+    } else if let Some((i, _)) = best {
+        (Branch::BestMatch, vec![i])
+    } else {
+        (Branch::Random, (0..candidates.len()).collect())
+    };
+
+    if matches!(branch, Branch::Preferred | Branch::Window | Branch::Widened) {
+        if let Some(top) = pool.iter().map(|&i| tie_key(&candidates[i])).max() {
+            pool.retain(|&i| tie_key(&candidates[i]) == top);
+        }
+    }
+
+    let chosen = if branch == Branch::BestMatch {
+        pool.first().copied()
+    } else {
+        pool.choose_weighted(rng, |&i| weight(&candidates[i]))
+            .ok()
+            .copied()
+    };
+    let weighted = pool.iter().map(|&i| (i, weight(&candidates[i]))).collect();
+    report.finish(branch, weighted, chosen);
+    fill_distances(&mut report, candidates, timing, &time_of);
+    (chosen.map(|i| &candidates[i]), report)
+}
+
+/// pick one of `candidates` in a single draw weighted by exp(-distance/tau),
+/// with the distance in hours or phases, so closer is likelier but nothing near the window edge drops to zero;
+/// candidates without an hour weigh `HOURLESS_WEIGHT`. `weight` and
+/// `preferred` scale that further
+pub fn select_weighted<'a, T>(
+    candidates: &'a [T],
+    timing: &Timing,
+    time_of: impl Fn(&T) -> TakenAt,
+    weight: impl Fn(&T) -> f64,
+    preferred: impl Fn(&T) -> bool,
+    tau: f64,
+    rng: &mut impl Rng,
+) -> (Option<&'a T>, SelectionReport) {
+    let mut report = SelectionReport::new(candidates, timing, &time_of);
+    let closeness = |c: &T| {
+        let time = timing.distance(time_of(c)).map_or(HOURLESS_WEIGHT, |d| {
+            (-(d as f64 / timing.per_unit() as f64) / tau).exp()
+        });
+        let boost = if preferred(c) { PREFERRED_BOOST } else { 1.0 };
+        time * boost * weight(c)
+    };
+    let pool: Vec<(usize, f64)> = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (i, closeness(c)))
+        .collect();
+    report.preferred = candidates.iter().filter(|c| preferred(c)).count();
+    let chosen = pool.choose_weighted(rng, |(_, w)| *w).ok().map(|(i, _)| *i);
+    report.finish(Branch::Weighted, pool, chosen);
+    fill_distances(&mut report, candidates, timing, &time_of);
+    (chosen.map(|i| &candidates[i]), report)
+}
+
+fn fill_distances<T>(
+    report: &mut SelectionReport,
+    candidates: &[T],
+    timing: &Timing,
+    time_of: impl Fn(&T) -> TakenAt,
+) {
+    let distance = |i: usize| timing.distance(time_of(&candidates[i]));
+    for contender in &mut report.contenders {
+        contender.distance = distance(contender.index);
+    }
+    report.chosen_distance = report.chosen.and_then(distance);
+}
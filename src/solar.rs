@@ -0,0 +1,233 @@
+use chrono::{
+    DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, Offset, TimeZone, Timelike, Utc,
+};
+use std::f64::consts::PI;
+
+/// sunrise and sunset in hours of the day, e.g. 6.5 for 06:30
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SunTimes {
+    Normal {
+        sunrise: f64,
+        sunset: f64,
+    },
+    /// the sun doesn't set
+    PolarDay,
+    /// the sun doesn't rise
+    PolarNight,
+}
+
+/// sun times in UTC from NOAA's approximation, within a minute or two away
+/// from the poles; `latitude` north and `longitude` east are positive
+pub fn sun_times_utc(date: NaiveDate, latitude: f64, longitude: f64) -> SunTimes {
+    let (eqtime, declination) = sun_position(2.0 * PI / 365.0 * (date.ordinal0() as f64));
+
+    // the sun's center 0.833° below the horizon, for refraction and its radius
+    let lat = latitude.to_radians();
+    let cos_hour_angle = 90.833_f64.to_radians().cos() / (lat.cos() * declination.cos())
+        - lat.tan() * declination.tan();
+    if cos_hour_angle > 1.0 {
+        return SunTimes::PolarNight;
+    }
+    if cos_hour_angle < -1.0 {
+        return SunTimes::PolarDay;
+    }
+
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+    let minutes = |angle: f64| 720.0 - 4.0 * (longitude + angle) - eqtime;
+    SunTimes::Normal {
+        sunrise: (minutes(hour_angle) / 60.0).rem_euclid(24.0),
+        sunset: (minutes(-hour_angle) / 60.0).rem_euclid(24.0),
+    }
+}
+
+/// the equation of time in minutes and the sun's declination in radians at
+/// `gamma`, the fraction of the year as an angle
+fn sun_position(gamma: f64) -> (f64, f64) {
+    let eqtime = 229.18
+        * (0.000075 + 0.001868 * gamma.cos()
+            - 0.032077 * gamma.sin()
+            - 0.014615 * (2.0 * gamma).cos()
+            - 0.040849 * (2.0 * gamma).sin());
+    let declination = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos()
+        + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos()
+        + 0.00148 * (3.0 * gamma).sin();
+    (eqtime, declination)
+}
+
+/// the sun's elevation above the horizon in degrees at `time`, from the same
+/// approximation as `sun_times_utc` and without refraction
+pub fn elevation(time: DateTime<Utc>, latitude: f64, longitude: f64) -> f64 {
+    let hour = time.hour() as f64 + time.minute() as f64 / 60.0 + time.second() as f64 / 3600.0;
+    let gamma = 2.0 * PI / 365.0 * (time.ordinal0() as f64 + (hour - 12.0) / 24.0);
+    let (eqtime, declination) = sun_position(gamma);
+
+    let solar_minutes = hour * 60.0 + eqtime + 4.0 * longitude;
+    let hour_angle = (solar_minutes / 4.0 - 180.0).to_radians();
+    let lat = latitude.to_radians();
+    let cos_zenith =
+        lat.sin() * declination.sin() + lat.cos() * declination.cos() * hour_angle.cos();
+    90.0 - cos_zenith.clamp(-1.0, 1.0).acos().to_degrees()
+}
+
+/// sun times on `date` in the local time zone
+pub fn sun_times(date: NaiveDate, latitude: f64, longitude: f64) -> SunTimes {
+    let offset = date
+        .and_hms_opt(12, 0, 0)
+        .and_then(|noon| Local.from_local_datetime(&noon).earliest())
+        .map_or(0, |t| t.offset().fix().local_minus_utc()) as f64
+        / 3600.0;
+    match sun_times_utc(date, latitude, longitude) {
+        SunTimes::Normal { sunrise, sunset } => SunTimes::Normal {
+            sunrise: (sunrise + offset).rem_euclid(24.0),
+            sunset: (sunset + offset).rem_euclid(24.0),
+        },
+        polar => polar,
+    }
+}
+
+/// parts of the day by where the sun is rather than the clock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Night,
+    /// the hour either side of sunrise
+    Dawn,
+    Morning,
+    /// the three hours around solar noon
+    Midday,
+    Evening,
+    /// the hour either side of sunset
+    Dusk,
+}
+
+impl Phase {
+    const ORDER: [Phase; 6] = [
+        Phase::Night,
+        Phase::Dawn,
+        Phase::Morning,
+        Phase::Midday,
+        Phase::Evening,
+        Phase::Dusk,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Night => "night",
+            Self::Dawn => "dawn",
+            Self::Morning => "morning",
+            Self::Midday => "midday",
+            Self::Evening => "evening",
+            Self::Dusk => "dusk",
+        }
+    }
+
+    /// steps between the two going around the day, 0 to 3
+    pub fn distance(self, other: Phase) -> i32 {
+        let index = |p: Phase| Self::ORDER.iter().position(|&o| o == p).unwrap_or(0) as i32;
+        let diff = (index(self) - index(other)).abs();
+        diff.min(Self::ORDER.len() as i32 - diff)
+    }
+
+    /// the phase at `hour` (local, fractional) on a day with these sun times
+    pub fn at(hour: f64, sun: SunTimes) -> Phase {
+        let (sunrise, sunset) = match sun {
+            SunTimes::Normal { sunrise, sunset } => (sunrise, sunset),
+            SunTimes::PolarNight => return Phase::Night,
+            SunTimes::PolarDay => {
+                return match hour {
+                    h if (h - 12.0).abs() < 1.5 => Phase::Midday,
+                    h if h < 12.0 => Phase::Morning,
+                    _ => Phase::Evening,
+                }
+            }
+        };
+
+        // measured from sunrise so days crossing midnight UTC need no care
+        let day = (sunset - sunrise).rem_euclid(24.0);
+        let since_sunrise = (hour - sunrise).rem_euclid(24.0);
+        if since_sunrise >= day {
+            if since_sunrise - day < 1.0 {
+                Phase::Dusk
+            } else if 24.0 - since_sunrise < 1.0 {
+                Phase::Dawn
+            } else {
+                Phase::Night
+            }
+        } else if since_sunrise < 1.0 {
+            Phase::Dawn
+        } else if day - since_sunrise < 1.0 {
+            Phase::Dusk
+        } else if (since_sunrise - day / 2.0).abs() < 1.5 {
+            Phase::Midday
+        } else if since_sunrise < day / 2.0 {
+            Phase::Morning
+        } else {
+            Phase::Evening
+        }
+    }
+}
+
+/// how light it is by the sun's elevation: coarser than `Phase`, but the same
+/// for a photo wherever and whenever it was taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Light {
+    /// the sun more than 6° below the horizon
+    Night,
+    /// up to 6° below, civil twilight
+    Twilight,
+    /// up to 6° above
+    GoldenHour,
+    Day,
+}
+
+impl Light {
+    pub fn from_elevation(degrees: f64) -> Self {
+        match degrees {
+            d if d < -6.0 => Self::Night,
+            d if d < 0.0 => Self::Twilight,
+            d if d < 6.0 => Self::GoldenHour,
+            _ => Self::Day,
+        }
+    }
+
+    pub fn at(time: DateTime<Utc>, latitude: f64, longitude: f64) -> Self {
+        Self::from_elevation(elevation(time, latitude, longitude))
+    }
+
+    /// the light at `local`, a time on this machine's clock
+    pub fn at_local(local: NaiveDateTime, latitude: f64, longitude: f64) -> Self {
+        let utc = Local
+            .from_local_datetime(&local)
+            .earliest()
+            .map_or_else(|| local.and_utc(), |t| t.with_timezone(&Utc));
+        Self::at(utc, latitude, longitude)
+    }
+
+    /// the light a photo was taken in, from its local capture time and the UTC
+    /// offset it was written with; without one the offset is guessed from the
+    /// longitude, which is off by the time zone's distance from solar time
+    pub fn of_photo(
+        taken: NaiveDateTime,
+        offset_minutes: Option<i32>,
+        (latitude, longitude): (f64, f64),
+    ) -> Self {
+        let offset = offset_minutes.map_or((longitude * 4.0).round() as i64, i64::from);
+        let utc = (taken - Duration::minutes(offset)).and_utc();
+        Self::at(utc, latitude, longitude)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Night => "night",
+            Self::Twilight => "twilight",
+            Self::GoldenHour => "golden hour",
+            Self::Day => "day",
+        }
+    }
+
+    /// steps between the two, 0 to 3; night and day are furthest apart
+    pub fn distance(self, other: Light) -> i32 {
+        (self as i32 - other as i32).abs()
+    }
+}
@@ -0,0 +1,71 @@
+use chrono::{DateTime, FixedOffset, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+
+/// the wallpaper applied last, as other tools read it from `current_file`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Current {
+    /// absolute
+    pub path: PathBuf,
+    pub shown_at: DateTime<FixedOffset>,
+    /// the output it went to, None for every output
+    pub monitor: Option<String>,
+}
+
+/// `Current` as written: the time as RFC 3339
+#[derive(Serialize, Deserialize)]
+struct CurrentFile {
+    path: PathBuf,
+    shown_at: String,
+    monitor: Option<String>,
+}
+
+/// JSON with the path, when it was applied and to which monitor
+pub fn current_file() -> PathBuf {
+    Path::new(&config::state_dir()).join("current")
+}
+
+/// a symlink to the image itself
+pub fn current_link() -> PathBuf {
+    Path::new(&config::state_dir()).join("current_wallpaper")
+}
+
+/// the wallpaper applied last, if any was recorded
+pub fn current() -> Option<Current> {
+    let file: CurrentFile = serde_json::from_slice(&fs::read(current_file()).ok()?).ok()?;
+    Some(Current {
+        path: file.path,
+        shown_at: DateTime::parse_from_rfc3339(&file.shown_at).ok()?,
+        monitor: file.monitor,
+    })
+}
+
+/// record `path` as applied to `monitor` just now, replacing `current_file`
+/// and `current_link` in one rename each so readers never see them half done
+pub fn set_current(path: &Path, monitor: Option<&str>) -> io::Result<()> {
+    let path = std::path::absolute(path)?;
+    let file = current_file();
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let content = CurrentFile {
+        path: path.clone(),
+        shown_at: Local::now().to_rfc3339(),
+        monitor: monitor.map(str::to_string),
+    };
+    let temp = file.with_extension("tmp");
+    fs::write(&temp, serde_json::to_vec_pretty(&content)?)?;
+    fs::rename(&temp, &file)?;
+
+    let link = current_link();
+    let temp = link.with_extension("tmp");
+    let _ = fs::remove_file(&temp);
+    symlink(&path, &temp)?;
+    fs::rename(&temp, &link)
+}
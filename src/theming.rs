@@ -0,0 +1,214 @@
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::color::{self, ColorPalette, Rgb};
+use crate::config;
+
+/// the colours of a wallpaper, for terminals, bars and the like
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub wallpaper: PathBuf,
+    pub palette: ColorPalette,
+    /// see `color::terminal_colors`
+    pub colors: [Rgb; 16],
+}
+
+impl Theme {
+    pub fn from_image(path: &Path) -> Result<Self, image::ImageError> {
+        let image = image::open(path)?;
+        let palette = color::extract_palette(&image);
+        let colors = color::terminal_colors(&image, &palette);
+        Ok(Self {
+            wallpaper: std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+            palette,
+            colors,
+        })
+    }
+
+    /// pywal's special colours
+    fn special(&self) -> [(&'static str, Rgb); 3] {
+        [
+            ("background", self.colors[0]),
+            ("foreground", self.colors[15]),
+            ("cursor", self.colors[15]),
+        ]
+    }
+
+    /// every colour a template can name: color0 to color15, the special ones,
+    /// then accent and secondary
+    pub fn variables(&self) -> Vec<(String, Rgb)> {
+        let mut variables: Vec<(String, Rgb)> = self
+            .colors
+            .iter()
+            .enumerate()
+            .map(|(i, &rgb)| (format!("color{}", i), rgb))
+            .collect();
+        variables.extend(self.special().map(|(name, rgb)| (name.to_string(), rgb)));
+        variables.push(("accent".to_string(), self.palette.accent));
+        variables.push(("secondary".to_string(), self.palette.secondary));
+        variables
+    }
+
+    /// `template` with `{name}` replaced by "#rrggbb", `{name.strip}` by
+    /// "rrggbb" and `{name.rgb}` by "r,g,b" for each of `variables`, and
+    /// `{wallpaper}` by the image's path. Anything else in braces is kept
+    pub fn render(&self, template: &str) -> String {
+        let variables = self.variables();
+        let mut out = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let Some(len) = rest[start..].find('}') else {
+                break;
+            };
+            out.push_str(&rest[..start]);
+            let name = &rest[start + 1..start + len];
+            let (base, format) = name.split_once('.').unwrap_or((name, ""));
+            let value = if name == "wallpaper" {
+                Some(self.wallpaper.display().to_string())
+            } else {
+                variables
+                    .iter()
+                    .find(|(n, _)| n == base)
+                    .and_then(|(_, rgb)| match format {
+                        "" => Some(rgb.hex()),
+                        "strip" => Some(rgb.hex()[1..].to_string()),
+                        "rgb" => Some(format!("{},{},{}", rgb.r, rgb.g, rgb.b)),
+                        _ => None,
+                    })
+            };
+            match value {
+                Some(value) => out.push_str(&value),
+                None => out.push_str(&rest[start..=start + len]),
+            }
+            rest = &rest[start + len + 1..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// pywal's colors.json, written by hand to keep color0 to color15 in order
+    pub fn to_json(&self) -> String {
+        let members = |pairs: Vec<(String, Rgb)>| {
+            let lines: Vec<String> = pairs
+                .iter()
+                .map(|(name, rgb)| format!("        \"{}\": \"{}\"", name, rgb.hex()))
+                .collect();
+            lines.join(",\n")
+        };
+        let special = self.special().map(|(name, rgb)| (name.to_string(), rgb));
+        let colors = self.variables().into_iter().take(16).collect();
+        let wallpaper = serde_json::Value::from(self.wallpaper.display().to_string());
+        let mut json = String::from("{\n");
+        json.push_str(&format!("    \"wallpaper\": {},\n", wallpaper));
+        json.push_str("    \"alpha\": \"100\",\n");
+        json.push_str(&format!(
+            "    \"special\": {{\n{}\n    }},\n",
+            members(special.to_vec())
+        ));
+        json.push_str(&format!(
+            "    \"colors\": {{\n{}\n    }}\n",
+            members(colors)
+        ));
+        json.push_str("}\n");
+        json
+    }
+
+    /// pywal's colors.sh, shell variables to source
+    pub fn to_shell(&self) -> String {
+        let mut out = format!(
+            "wallpaper='{}'\n\n",
+            self.wallpaper.display().to_string().replace('\'', "'\\''")
+        );
+        for (name, rgb) in self.special() {
+            out.push_str(&format!("{}='{}'\n", name, rgb.hex()));
+        }
+        out.push('\n');
+        let mut names = Vec::new();
+        for (i, rgb) in self.colors.iter().enumerate() {
+            out.push_str(&format!("color{}='{}'\n", i, rgb.hex()));
+            names.push(format!("color{}", i));
+        }
+        out.push_str(&format!("\nexport {}\n", names.join(" ")));
+        out
+    }
+}
+
+/// template files and where to render them, from a file of
+/// `template = output` lines; relative paths are relative to that file for
+/// templates and to `config::theme_dir` for outputs. Lines starting with #
+/// are comments
+pub fn templates(path: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let base = path.parent().unwrap_or(Path::new(""));
+    let theme_dir = config::theme_dir();
+    let mut templates = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with('[') {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((template, output))
+                if !template.trim().is_empty() && !output.trim().is_empty() =>
+            {
+                let unquote = |s: &str| s.trim().trim_matches('"').to_string();
+                templates.push((
+                    base.join(unquote(template)),
+                    Path::new(&theme_dir).join(unquote(output)),
+                ));
+            }
+            _ => eprintln!("Ignoring template line: {}", line),
+        }
+    }
+    templates
+}
+
+/// work out the theme of `wallpaper` and write colors.json, colors.sh and
+/// every template from `config::theme_templates` to their outputs, returning
+/// the files written. A template that can't be read or written is skipped
+/// with a warning
+pub fn generate(wallpaper: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let theme = Theme::from_image(wallpaper)?;
+    let dir = PathBuf::from(config::theme_dir());
+    fs::create_dir_all(&dir)?;
+
+    let mut written = Vec::new();
+    for (name, content) in [
+        ("colors.json", theme.to_json()),
+        ("colors.sh", theme.to_shell()),
+    ] {
+        let path = dir.join(name);
+        write_atomically(&path, &content)?;
+        written.push(path);
+    }
+
+    for (template, output) in templates(Path::new(&config::theme_templates())) {
+        let rendered = match fs::read_to_string(&template) {
+            Ok(content) => theme.render(&content),
+            Err(e) => {
+                eprintln!("Failed to read template {}: {}", template.display(), e);
+                continue;
+            }
+        };
+        if let Some(parent) = output.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        match write_atomically(&output, &rendered) {
+            Ok(()) => written.push(output),
+            Err(e) => eprintln!("Failed to write {}: {}", output.display(), e),
+        }
+    }
+    Ok(written)
+}
+
+/// write to a temporary file next to `path` and rename it over `path`, so
+/// whatever reads it never sees half of it
+fn write_atomically(path: &Path, content: &str) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, content)?;
+    fs::rename(&temp, path)
+}
@@ -0,0 +1,169 @@
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::discovery::{self, ImageFile, Walk};
+use crate::xmp;
+
+/// how long a new file's size must stay unchanged before it is picked up
+pub const SETTLE_TIME: Duration = Duration::from_secs(2);
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone)]
+pub enum Change {
+    Added(ImageFile),
+    /// a removed file, or a removed directory covering everything below it
+    Removed(PathBuf),
+}
+
+struct Pending {
+    size: u64,
+    since: Instant,
+}
+
+pub struct DirWatcher {
+    inotify: Inotify,
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    pending: HashMap<PathBuf, Pending>,
+    buffer: Vec<u8>,
+}
+
+impl DirWatcher {
+    pub fn new(root: &str) -> io::Result<Self> {
+        let mut watcher = Self {
+            inotify: Inotify::init()?,
+            dirs: HashMap::new(),
+            pending: HashMap::new(),
+            buffer: vec![0; 64 * 1024],
+        };
+        watcher.watch_tree(Path::new(root), false)?;
+        Ok(watcher)
+    }
+
+    pub fn watched_dirs(&self) -> usize {
+        self.dirs.len()
+    }
+
+    /// drain queued events without blocking and return the changes that are ready
+    pub fn poll(&mut self) -> io::Result<Vec<Change>> {
+        let mut changes = Vec::new();
+        let mut new_dirs = Vec::new();
+
+        loop {
+            let events = match self.inotify.read_events(&mut self.buffer) {
+                Ok(events) => events,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            };
+
+            for event in events {
+                if event.mask.contains(EventMask::Q_OVERFLOW) {
+                    eprintln!("inotify queue overflowed, some changes may have been missed");
+                    continue;
+                }
+                if event.mask.contains(EventMask::IGNORED) {
+                    self.dirs.remove(&event.wd);
+                    continue;
+                }
+
+                let (Some(dir), Some(name)) = (self.dirs.get(&event.wd), event.name) else {
+                    continue;
+                };
+                let path = dir.join(name);
+
+                if event
+                    .mask
+                    .intersects(EventMask::DELETE | EventMask::MOVED_FROM)
+                {
+                    self.pending.retain(|p, _| !p.starts_with(&path));
+                    if let Some(image) = xmp::image_for_sidecar(&path) {
+                        // the image falls back to its embedded metadata
+                        track(&mut self.pending, image);
+                    } else {
+                        changes.push(Change::Removed(path));
+                    }
+                } else if event.mask.contains(EventMask::ISDIR) {
+                    new_dirs.push(path);
+                } else if discovery::is_candidate(&path) {
+                    track(&mut self.pending, path);
+                } else if let Some(image) = xmp::image_for_sidecar(&path) {
+                    // a new or edited sidecar changes the image's metadata
+                    track(&mut self.pending, image);
+                }
+            }
+        }
+
+        for dir in new_dirs {
+            if let Err(e) = self.watch_tree(&dir, true) {
+                eprintln!("Failed to watch {}: {}", dir.display(), e);
+            }
+        }
+
+        changes.extend(self.take_settled().into_iter().map(Change::Added));
+        Ok(changes)
+    }
+
+    fn watch_tree(&mut self, root: &Path, track_files: bool) -> io::Result<()> {
+        let mask = WatchMask::CREATE
+            | WatchMask::CLOSE_WRITE
+            | WatchMask::MOVED_TO
+            | WatchMask::MOVED_FROM
+            | WatchMask::DELETE;
+
+        for entry in Walk::new(root) {
+            if entry.file_type().is_dir() {
+                let wd = self.inotify.watches().add(entry.path(), mask)?;
+                self.dirs.insert(wd, entry.path().to_path_buf());
+            } else if track_files && discovery::is_candidate(entry.path()) {
+                // files moved in together with a new directory produce no events of their own
+                track(&mut self.pending, entry.path().to_path_buf());
+            }
+        }
+        Ok(())
+    }
+
+    fn take_settled(&mut self) -> Vec<ImageFile> {
+        let mut settled = Vec::new();
+
+        self.pending.retain(|path, pending| {
+            let Ok(metadata) = fs::metadata(path) else {
+                return false;
+            };
+
+            if metadata.len() != pending.size {
+                pending.size = metadata.len();
+                pending.since = Instant::now();
+                return true;
+            }
+
+            if pending.since.elapsed() < SETTLE_TIME {
+                return true;
+            }
+
+            match ImageFile::from_path(path) {
+                Ok(mut img) => {
+                    img.hash_if_enabled();
+                    settled.push(img);
+                }
+                Err(e) => eprintln!("Failed to stat {}: {}", path.display(), e),
+            }
+            false
+        });
+
+        settled
+    }
+}
+
+fn track(pending: &mut HashMap<PathBuf, Pending>, path: PathBuf) {
+    let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    pending.insert(
+        path,
+        Pending {
+            size,
+            since: Instant::now(),
+        },
+    );
+}
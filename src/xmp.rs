@@ -0,0 +1,169 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// values read from an XMP packet, in EXIF formats where one exists
+#[derive(Debug, Default, Clone)]
+pub struct XmpData {
+    /// "YYYY:MM:DD HH:MM:SS"
+    pub datetime: Option<String>,
+    pub gps_latitude: Option<f64>,
+    pub gps_longitude: Option<f64>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub rating: Option<i32>,
+    pub keywords: Vec<String>,
+}
+
+/// `foo.jpg.xmp` (darktable, digiKam) or `foo.xmp` (Lightroom), whichever exists
+pub fn find_sidecar(image: &Path) -> Option<PathBuf> {
+    let mut appended = image.as_os_str().to_owned();
+    appended.push(".xmp");
+
+    [PathBuf::from(appended), image.with_extension("xmp")]
+        .into_iter()
+        .find(|p| p.is_file())
+}
+
+/// the image a sidecar belongs to, if it exists
+pub fn image_for_sidecar(sidecar: &Path) -> Option<PathBuf> {
+    let ext = sidecar.extension().and_then(|s| s.to_str())?;
+    if !ext.eq_ignore_ascii_case("xmp") {
+        return None;
+    }
+
+    let stripped = sidecar.with_extension("");
+    if stripped.extension().is_some() {
+        return Some(stripped).filter(|p| p.is_file());
+    }
+
+    ["jpg", "jpeg", "JPG", "JPEG"]
+        .iter()
+        .map(|ext| sidecar.with_extension(ext))
+        .find(|p| p.is_file())
+}
+
+pub fn read_sidecar(image: &Path) -> Option<XmpData> {
+    let xml = fs::read_to_string(find_sidecar(image)?).ok()?;
+    Some(parse(&xml))
+}
+
+pub fn parse(xml: &str) -> XmpData {
+    XmpData {
+        datetime: value(xml, "exif:DateTimeOriginal")
+            .or_else(|| value(xml, "xmp:CreateDate"))
+            .and_then(|v| to_exif_datetime(&v)),
+        gps_latitude: value(xml, "exif:GPSLatitude").and_then(|v| parse_coordinate(&v)),
+        gps_longitude: value(xml, "exif:GPSLongitude").and_then(|v| parse_coordinate(&v)),
+        make: value(xml, "tiff:Make"),
+        model: value(xml, "tiff:Model"),
+        rating: value(xml, "xmp:Rating").and_then(|v| {
+            v.parse::<i32>()
+                .ok()
+                .or_else(|| v.parse::<f64>().ok().map(|f| f.round() as i32))
+        }),
+        keywords: subjects(xml),
+    }
+}
+
+/// a simple property written either as `name="value"` or `<name>value</name>`
+fn value(xml: &str, name: &str) -> Option<String> {
+    xml.match_indices(name).find_map(|(i, _)| {
+        let before = xml[..i].chars().next_back();
+        let rest = &xml[i + name.len()..];
+
+        if before.is_some_and(char::is_whitespace) {
+            let quoted = rest.trim_start().strip_prefix('=')?.trim_start();
+            let quote = quoted.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            let end = quoted[1..].find(quote)?;
+            non_empty(unescape(&quoted[1..1 + end]))
+        } else if before == Some('<') {
+            let body = rest.strip_prefix('>')?;
+            non_empty(unescape(&body[..body.find('<')?]))
+        } else {
+            None
+        }
+    })
+}
+
+/// the `rdf:li` items of `dc:subject`
+fn subjects(xml: &str) -> Vec<String> {
+    let Some(start) = xml.find("<dc:subject") else {
+        return Vec::new();
+    };
+    let body = &xml[start..];
+    let body = &body[..body.find("</dc:subject>").unwrap_or(body.len())];
+
+    body.split("<rdf:li")
+        .skip(1)
+        .filter_map(|item| {
+            let content = &item[item.find('>')? + 1..];
+            non_empty(unescape(&content[..content.find('<')?]))
+        })
+        .collect()
+}
+
+fn non_empty(s: String) -> Option<String> {
+    let trimmed = s.trim();
+    (!trimmed.is_empty()).then(|| trimmed.to_string())
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// "2023-07-21T19:32:10+02:00" to "2023:07:21 19:32:10"; the zone is local time already
+fn to_exif_datetime(v: &str) -> Option<String> {
+    let (date, time) = v.split_once('T')?;
+    let mut date_parts = date.split('-');
+    let (year, month, day) = (date_parts.next()?, date_parts.next()?, date_parts.next()?);
+
+    let time = time
+        .split(['+', '-', 'Z'])
+        .next()
+        .unwrap_or(time)
+        .split('.')
+        .next()
+        .unwrap_or(time);
+    let mut time_parts = time.split(':');
+    let (hour, minute) = (time_parts.next()?, time_parts.next()?);
+    let second = time_parts.next().unwrap_or("00");
+
+    let all_digits = [year, month, day, hour, minute, second]
+        .iter()
+        .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()));
+    if !all_digits {
+        return None;
+    }
+
+    Some(format!(
+        "{}:{}:{} {}:{}:{}",
+        year, month, day, hour, minute, second
+    ))
+}
+
+/// "60,23.2067N" or "60,23,12.4N" to signed decimal degrees
+fn parse_coordinate(v: &str) -> Option<f64> {
+    let v = v.trim();
+    let direction = v.chars().next_back()?;
+    let sign = match direction.to_ascii_uppercase() {
+        'N' | 'E' => 1.0,
+        'S' | 'W' => -1.0,
+        _ => return None,
+    };
+
+    let parts: Vec<f64> = v[..v.len() - 1]
+        .split(',')
+        .map(|p| p.trim().parse().ok())
+        .collect::<Option<_>>()?;
+
+    let degrees = match parts.as_slice() {
+        [d, m] => d + m / 60.0,
+        [d, m, s] => d + m / 60.0 + s / 3600.0,
+        _ => return None,
+    };
+    Some(sign * degrees)
+}
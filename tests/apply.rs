@@ -0,0 +1,122 @@
+//! the apply path end to end, with a backend that only records what it's asked
+
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use wallpaper_slideshow::backend::{self, ApplyError, WallpaperBackend};
+use wallpaper_slideshow::{history, state};
+
+/// succeeds on every output except `broken`, remembering each call
+struct MockBackend {
+    broken: Vec<&'static str>,
+    calls: RefCell<Vec<(PathBuf, Option<String>)>>,
+}
+
+impl MockBackend {
+    fn new(broken: &[&'static str]) -> Self {
+        Self {
+            broken: broken.to_vec(),
+            calls: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl WallpaperBackend for MockBackend {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn apply(&self, path: &Path, monitor: Option<&str>) -> Result<(), ApplyError> {
+        self.calls
+            .borrow_mut()
+            .push((path.to_path_buf(), monitor.map(str::to_string)));
+        match monitor {
+            Some(name) if self.broken.contains(&name) => {
+                Err(ApplyError::Rejected("mock", format!("{} is broken", name)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn is_available(&self) -> bool {
+        true
+    }
+}
+
+// one test, as the configuration is read from the process environment
+#[test]
+fn only_successful_applies_are_recorded() {
+    let dir = env::temp_dir().join(format!("wallpaper_slideshow_apply_{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let hooked = dir.join("hooked");
+    fs::write(
+        dir.join("hooks"),
+        format!("# comment\necho \"$WALLPAPER\" >> {}\n", hooked.display()),
+    )
+    .unwrap();
+    env::set_var("WALLPAPER_DIR", &dir);
+    env::set_var("WALLPAPER_HISTORY_LOG", dir.join("history.log"));
+    env::set_var("XDG_CACHE_HOME", dir.join("cache"));
+    env::set_var("WALLPAPER_POST_HOOKS", dir.join("hooks"));
+    env::set_var("WALLPAPER_APPLY_RETRY", "0");
+    env::remove_var("WALLPAPER_HISTORY_BACKEND");
+    env::remove_var("WALLPAPER_THEMING");
+
+    let (a, b, c) = (dir.join("a.jpg"), dir.join("b.jpg"), dir.join("c.jpg"));
+
+    // the first output fails, so the second becomes current
+    let mock = MockBackend::new(&["DP-1"]);
+    let failed = backend::apply_all(
+        &mock,
+        &[(Some("DP-1"), a.as_path()), (Some("HDMI-A-1"), b.as_path())],
+    );
+    assert_eq!(mock.calls.borrow().len(), 2);
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].0, a);
+    assert!(matches!(failed[0].1, ApplyError::Rejected("mock", _)));
+    assert_eq!(history::load_recent_ordered(10), vec!["b.jpg"]);
+    let current = state::current().unwrap();
+    assert_eq!(current.path, b);
+    assert_eq!(current.monitor.as_deref(), Some("HDMI-A-1"));
+    assert_eq!(
+        fs::read_to_string(&hooked).unwrap(),
+        format!("{}\n", b.display())
+    );
+
+    // nothing worked: nothing is logged and the previous wallpaper stays current
+    let mock = MockBackend::new(&["DP-1", "HDMI-A-1"]);
+    let failed = backend::apply_all(
+        &mock,
+        &[(Some("DP-1"), c.as_path()), (Some("HDMI-A-1"), a.as_path())],
+    );
+    assert_eq!(failed.len(), 2);
+    assert_eq!(history::load_recent_ordered(10), vec!["b.jpg"]);
+    assert_eq!(state::current().unwrap().path, b);
+    assert_eq!(
+        fs::read_to_string(&hooked).unwrap(),
+        format!("{}\n", b.display())
+    );
+
+    // everything works: all are logged, the first is current
+    let mock = MockBackend::new(&[]);
+    let failed = backend::apply_all(
+        &mock,
+        &[(Some("DP-1"), c.as_path()), (Some("HDMI-A-1"), a.as_path())],
+    );
+    assert!(failed.is_empty());
+    assert_eq!(
+        history::load_recent_ordered(10),
+        vec!["a.jpg", "c.jpg", "b.jpg"]
+    );
+    assert_eq!(state::current().unwrap().path, c);
+    assert_eq!(
+        fs::read_to_string(&hooked).unwrap(),
+        format!("{}\n{}\n", b.display(), c.display())
+    );
+
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,204 @@
+//! helpers the backends share, run against real processes and fabricated desktops
+
+use std::fs;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use wallpaper_slideshow::backend::{self, ApplyError, DesktopSnapshot};
+
+/// whether `pid` has exited, counting a zombie nobody reaped yet
+fn exited(pid: &str) -> bool {
+    match fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        Ok(stat) => stat
+            .rsplit_once(") ")
+            .is_some_and(|(_, rest)| rest.starts_with('Z')),
+        Err(_) => true,
+    }
+}
+
+#[test]
+fn timeout_kills_the_whole_process_group() {
+    let pid_file =
+        std::env::temp_dir().join(format!("wallpaper_slideshow_bg_{}", std::process::id()));
+    let script = format!("sleep 30 & echo $! > {}; sleep 30", pid_file.display());
+    let started = Instant::now();
+    let result = backend::run_with_timeout(
+        "sleep",
+        Command::new("sh").arg("-c").arg(&script),
+        Duration::from_millis(300),
+    );
+    assert!(matches!(result, Err(ApplyError::TimedOut("sleep", _))));
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    // the sleep it started in the background went with it
+    let pid = fs::read_to_string(&pid_file).unwrap();
+    let _ = fs::remove_file(&pid_file);
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while !exited(pid.trim()) {
+        assert!(
+            Instant::now() < deadline,
+            "background sleep {} survived",
+            pid.trim()
+        );
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn failure_keeps_stderr() {
+    let output = backend::run_with_timeout(
+        "sh",
+        Command::new("sh")
+            .arg("-c")
+            .arg("echo out; echo broken >&2; exit 3"),
+        Duration::from_secs(5),
+    )
+    .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(output.stdout, b"out\n");
+    assert_eq!(output.stderr, b"broken\n");
+
+    match backend::run(
+        "sh",
+        Command::new("sh").arg("-c").arg("echo broken >&2; exit 3"),
+    ) {
+        Err(ApplyError::Failed("sh", status, message)) => {
+            assert_eq!(status.code(), Some(3));
+            assert_eq!(message, "broken");
+        }
+        other => panic!("expected a failure, got {:?}", other),
+    }
+    // stdout stands in when stderr is empty
+    match backend::run("sh", Command::new("sh").arg("-c").arg("echo why; exit 1")) {
+        Err(ApplyError::Failed(_, _, message)) => assert_eq!(message, "why"),
+        other => panic!("expected a failure, got {:?}", other),
+    }
+}
+
+#[test]
+fn background_child_holding_the_pipes_does_not_block() {
+    let started = Instant::now();
+    let output = backend::run_with_timeout(
+        "sh",
+        Command::new("sh").arg("-c").arg("sleep 3 & exit 0"),
+        Duration::from_millis(500),
+    )
+    .unwrap();
+    assert!(output.status.success());
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+/// a desktop with `vars` set and the wayland socket and daemons as given
+fn desktop(
+    vars: &[(&str, &str)],
+    wayland_socket: bool,
+    hyprpaper: bool,
+    swww: bool,
+) -> DesktopSnapshot {
+    DesktopSnapshot {
+        vars: vars
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+        wayland_socket,
+        hyprpaper_running: hyprpaper,
+        swww_running: swww,
+    }
+}
+
+#[test]
+fn detect_picks_a_backend_per_desktop() {
+    let hyprland = [
+        ("HYPRLAND_INSTANCE_SIGNATURE", "abc"),
+        ("WAYLAND_DISPLAY", "wayland-1"),
+    ];
+    let sway = [
+        ("SWAYSOCK", "/run/user/1000/sway-ipc.sock"),
+        ("WAYLAND_DISPLAY", "wayland-1"),
+    ];
+    let cases = [
+        (
+            "GNOME",
+            desktop(
+                &[
+                    ("XDG_CURRENT_DESKTOP", "ubuntu:GNOME"),
+                    ("WAYLAND_DISPLAY", "wayland-0"),
+                ],
+                true,
+                false,
+                false,
+            ),
+            "gnome",
+        ),
+        (
+            "KDE",
+            desktop(
+                &[("XDG_CURRENT_DESKTOP", "KDE"), ("DISPLAY", ":0")],
+                false,
+                false,
+                false,
+            ),
+            "plasma",
+        ),
+        (
+            "Hyprland with hyprpaper",
+            desktop(&hyprland, true, true, false),
+            "hyprpaper",
+        ),
+        (
+            "Hyprland with both",
+            desktop(&hyprland, true, true, true),
+            "hyprpaper",
+        ),
+        (
+            "Hyprland with swww",
+            desktop(&hyprland, true, false, true),
+            "swww",
+        ),
+        (
+            "Hyprland with neither",
+            desktop(&hyprland, true, false, false),
+            "hyprpaper",
+        ),
+        (
+            "Hyprland by name",
+            desktop(&[("XDG_CURRENT_DESKTOP", "Hyprland")], true, false, false),
+            "hyprpaper",
+        ),
+        ("sway", desktop(&sway, true, false, false), "swaybg"),
+        ("sway with swww", desktop(&sway, true, false, true), "swww"),
+        (
+            "bare Wayland",
+            desktop(&[("WAYLAND_DISPLAY", "wayland-1")], true, false, false),
+            "swaybg",
+        ),
+        (
+            "bare Wayland with swww",
+            desktop(&[("WAYLAND_DISPLAY", "wayland-1")], true, false, true),
+            "swww",
+        ),
+        (
+            "X11",
+            desktop(&[("DISPLAY", ":0")], false, false, false),
+            "x11",
+        ),
+        // WAYLAND_DISPLAY without its socket is the fallback the slideshow sets
+        (
+            "X11 with a stale WAYLAND_DISPLAY",
+            desktop(
+                &[("DISPLAY", ":0"), ("WAYLAND_DISPLAY", "wayland-0")],
+                false,
+                false,
+                false,
+            ),
+            "x11",
+        ),
+        ("nothing", desktop(&[], false, false, false), "hyprpaper"),
+    ];
+    for (name, snapshot, expected) in cases {
+        let (backend, why) = backend::detect(&snapshot);
+        assert_eq!(backend, expected, "{}: {}", name, why);
+        assert!(!why.is_empty(), "{}", name);
+    }
+}
@@ -0,0 +1,66 @@
+//! one cache shared by two wallpaper directories, switching between them
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use wallpaper_slideshow::cache::{Cache, CacheRecord};
+use wallpaper_slideshow::discovery::ImageFile;
+use wallpaper_slideshow::exif::ExifInfo;
+
+fn record(path: &str) -> CacheRecord {
+    CacheRecord {
+        path: path.to_string(),
+        mtime: 100,
+        size: 1,
+        hash: None,
+        dimensions: None,
+        luminance: None,
+        info: ExifInfo::default(),
+        error: None,
+    }
+}
+
+fn paths(names: &[&str]) -> HashSet<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+// one test, since it points the process-wide wallpaper directory at each root
+#[test]
+fn alternating_roots_keep_each_others_entries() {
+    let dir = env::temp_dir().join(format!("wallpaper_slideshow_cache_roots_{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let cache = Cache::open(dir.join("cache.db")).unwrap();
+
+    let home = ["/photos/home/a.jpg", "/photos/home/b.jpg"];
+    let travel = ["/photos/travel/c.jpg", "/photos/travel/d.jpg"];
+    let records: Vec<CacheRecord> = home.iter().chain(&travel).map(|p| record(p)).collect();
+    cache.upsert(&records).unwrap();
+
+    // b.jpg was deleted while home was the wallpaper directory
+    env::set_var("WALLPAPER_DIR", "/photos/home");
+    assert_eq!(cache.cleanup_stale(&paths(&home[..1])).unwrap(), 1);
+
+    for _ in 0..3 {
+        env::set_var("WALLPAPER_DIR", "/photos/travel");
+        assert_eq!(cache.cleanup_stale(&paths(&travel)).unwrap(), 0);
+        env::set_var("WALLPAPER_DIR", "/photos/home");
+        assert_eq!(cache.cleanup_stale(&paths(&home[..1])).unwrap(), 0);
+    }
+
+    // every surviving entry is still fresh, so nothing is parsed again
+    let keys = cache.load_keys().unwrap();
+    let mut cached: Vec<&str> = keys.keys().map(String::as_str).collect();
+    cached.sort();
+    assert_eq!(cached, [home[0], travel[0], travel[1]]);
+    for (path, key) in &keys {
+        let img = ImageFile::new(PathBuf::from(path), 100, 1);
+        assert!(key.is_fresh(&img), "{} would be parsed again", path);
+    }
+
+    drop(cache);
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,156 @@
+//! which files discovery takes for wallpapers, and the filters that narrow them
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use wallpaper_slideshow::discovery::{is_candidate, ImageFilter, Orientation};
+use wallpaper_slideshow::history::PathList;
+use wallpaper_slideshow::ImageFile;
+
+#[test]
+fn partial_downloads_are_skipped() {
+    let cases = [
+        ("fjord.jpg", true),
+        ("fjord.JPEG", true),
+        ("fjord.png", false),
+        // hidden, but finished
+        (".cover.jpg", true),
+        ("summer/.fjord.jpg", true),
+        // rsync's temporary, renamed into place when done
+        (".fjord.jpg.a1B2c3", false),
+        (".fjord.jpeg.XyZ789", false),
+        (".~tmp~/fjord.jpg", false),
+        ("fjord.jpg.part", false),
+        ("fjord.jpg.crdownload", false),
+        ("fjord.part.jpg", true),
+    ];
+    for (path, expected) in cases {
+        assert_eq!(is_candidate(Path::new(path)), expected, "{}", path);
+    }
+}
+
+/// outside any wallpaper root, so history entries are the absolute paths
+fn image(name: &str, dims: Option<(u32, u32)>) -> ImageFile {
+    let mut img = ImageFile::new(PathBuf::from("/nowhere/photos").join(name), 0, 0);
+    img.width = dims.map(|(w, _)| w);
+    img.height = dims.map(|(_, h)| h);
+    img
+}
+
+fn images() -> Vec<ImageFile> {
+    vec![
+        image("wide.jpg", Some((3840, 2160))),
+        image("small.jpg", Some((1280, 720))),
+        image("tall.jpg", Some((1080, 1920))),
+        image("square.jpg", Some((2000, 2000))),
+        image("ultrawide.jpg", Some((5120, 2160))),
+        image("unknown.jpg", None),
+    ]
+}
+
+fn names(images: &[ImageFile]) -> Vec<&str> {
+    images
+        .iter()
+        .map(|img| img.path.file_name().unwrap().to_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn exclude_recent_matches_entries_and_legacy_basenames() {
+    let recent: HashSet<String> = ["/nowhere/photos/wide.jpg", "tall.jpg"]
+        .map(String::from)
+        .into();
+    let kept = ImageFilter::new().exclude_recent(&recent).apply(&images());
+    assert_eq!(
+        names(&kept),
+        ["small.jpg", "square.jpg", "ultrawide.jpg", "unknown.jpg"]
+    );
+}
+
+#[test]
+fn exclude_blocked_takes_names_and_globs() {
+    let blocklist = PathList::from_lines(
+        ["# comment", "small.jpg", "/nowhere/photos/*wide.jpg"].map(String::from),
+    );
+    let kept = ImageFilter::new()
+        .exclude_blocked(&blocklist)
+        .apply(&images());
+    assert_eq!(names(&kept), ["tall.jpg", "square.jpg", "unknown.jpg"]);
+}
+
+#[test]
+fn min_dimensions_keeps_unknown_sizes() {
+    let kept = ImageFilter::new()
+        .min_dimensions(1920, 1080)
+        .apply(&images());
+    assert_eq!(
+        names(&kept),
+        ["wide.jpg", "square.jpg", "ultrawide.jpg", "unknown.jpg"]
+    );
+}
+
+#[test]
+fn orientation_by_shape() {
+    let by = |orientation| ImageFilter::new().orientation(orientation).apply(&images());
+    assert_eq!(
+        names(&by(Orientation::Landscape)),
+        ["wide.jpg", "small.jpg", "ultrawide.jpg", "unknown.jpg"]
+    );
+    assert_eq!(
+        names(&by(Orientation::Portrait)),
+        ["tall.jpg", "unknown.jpg"]
+    );
+    assert_eq!(
+        names(&by(Orientation::Square)),
+        ["square.jpg", "unknown.jpg"]
+    );
+}
+
+#[test]
+fn aspect_ratio_within_tolerance() {
+    let kept = ImageFilter::new()
+        .aspect_ratio((2560, 1440), 0.1)
+        .apply(&images());
+    assert_eq!(names(&kept), ["wide.jpg", "small.jpg", "unknown.jpg"]);
+    // 64:27 is a third wider than 16:9
+    let kept = ImageFilter::new()
+        .aspect_ratio((2560, 1440), 0.34)
+        .apply(&images());
+    assert_eq!(
+        names(&kept),
+        ["wide.jpg", "small.jpg", "ultrawide.jpg", "unknown.jpg"]
+    );
+}
+
+#[test]
+fn predicates_combine_and_relax_from_the_last() {
+    let images = images();
+    let blocklist = PathList::from_lines(["unknown.jpg".to_string()]);
+    let filter = ImageFilter::new()
+        .exclude_blocked(&blocklist)
+        .min_dimensions(1920, 1080)
+        .orientation(Orientation::Portrait);
+    assert!(filter.apply(&images).is_empty());
+    assert_eq!(
+        filter.rejections(&images),
+        [
+            ("blocklist", 1),
+            ("minimum dimensions", 2),
+            ("orientation", 4)
+        ]
+    );
+
+    // orientation goes first, as the last added
+    let (kept, relaxed) = filter.apply_relaxed(&images);
+    assert_eq!(names(&kept), ["wide.jpg", "square.jpg", "ultrawide.jpg"]);
+    assert_eq!(relaxed, ["orientation"]);
+
+    // the blocklist stays even when nothing else is left
+    let everything = PathList::from_lines(["*.jpg".to_string()]);
+    let (kept, relaxed) = ImageFilter::new()
+        .exclude_blocked(&everything)
+        .min_dimensions(1920, 1080)
+        .apply_relaxed(&images);
+    assert!(kept.is_empty());
+    assert_eq!(relaxed, ["minimum dimensions"]);
+}
@@ -0,0 +1,147 @@
+//! `history::export` over a small history and cache, in both formats
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use chrono::DateTime;
+use serde_json::Value;
+use wallpaper_slideshow::cache::{Cache, CacheRecord};
+use wallpaper_slideshow::discovery::ImageFile;
+use wallpaper_slideshow::exif::{ExifInfo, Extracted};
+use wallpaper_slideshow::history::{self, ExportFormat, SqliteHistory};
+
+/// cache `path` as taken at `hour` in `month` and rated `rating`
+fn cache_entry(cache: &Cache, path: &Path, hour: u8, month: u8, rating: u8) {
+    let img = ImageFile::from_path(path).unwrap();
+    let extracted = Extracted {
+        path: path.to_path_buf(),
+        info: ExifInfo {
+            hour: Some(hour),
+            minute_of_day: Some(hour as u16 * 60),
+            month: Some(month),
+            rating: Some(rating),
+            ..ExifInfo::default()
+        },
+        error: None,
+        dimensions: None,
+        luminance: None,
+    };
+    cache.upsert(&[CacheRecord::new(&img, extracted)]).unwrap();
+}
+
+fn export(format: ExportFormat) -> String {
+    let mut out = Vec::new();
+    assert_eq!(history::export(&mut out, format).unwrap(), 4);
+    String::from_utf8(out).unwrap()
+}
+
+/// a CSV cell as the JSON value it stands for
+fn csv_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+// one test, since it points the process-wide settings at a temp dir
+#[test]
+fn export_round_trips_as_json_lines_and_csv() {
+    let dir = env::temp_dir().join(format!("wallpaper_slideshow_export_{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let root = dir.join("wallpapers");
+    fs::create_dir_all(root.join("2020")).unwrap();
+    env::set_var("WALLPAPER_DIR", &root);
+    env::set_var("WALLPAPER_CACHE_DB", dir.join("cache.db"));
+    env::set_var("WALLPAPER_HISTORY_LOG", dir.join("history.log"));
+    env::set_var("WALLPAPER_HISTORY_BACKEND", "sqlite");
+
+    // not images at all, so anything they export came from the cache
+    let beach = root.join("beach.jpg");
+    let forest = root.join("2020/forest.jpg");
+    fs::write(&beach, b"not a jpeg").unwrap();
+    fs::write(&forest, b"not a jpeg either").unwrap();
+    cache_entry(&Cache::open_default().unwrap(), &beach, 7, 6, 4);
+
+    let shown = SqliteHistory::open().unwrap();
+    shown.log(&beach, None).unwrap();
+    shown.log(&forest, Some("DP-1")).unwrap();
+    shown.log(&root.join("gone.jpg"), None).unwrap();
+    shown.log(&beach, None).unwrap();
+    drop(shown);
+
+    let json = export(ExportFormat::JsonLines);
+    let rows: Vec<Value> = json
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let entries: Vec<&str> = rows.iter().map(|r| r["entry"].as_str().unwrap()).collect();
+    assert_eq!(
+        entries,
+        ["beach.jpg", "2020/forest.jpg", "gone.jpg", "beach.jpg"]
+    );
+    for row in &rows {
+        let shown_at = row["shown_at"].as_str().unwrap();
+        assert!(
+            DateTime::parse_from_rfc3339(shown_at).is_ok(),
+            "{}",
+            shown_at
+        );
+    }
+
+    let beach_row = &rows[0];
+    assert_eq!(beach_row["path"], beach.to_str().unwrap());
+    assert_eq!(beach_row["missing"], false);
+    assert_eq!(
+        (
+            &beach_row["hour"],
+            &beach_row["month"],
+            &beach_row["rating"]
+        ),
+        (&Value::from(7), &Value::from(6), &Value::from(4))
+    );
+    assert_eq!(beach_row["camera"], Value::Null);
+
+    // not cached and no EXIF to read
+    assert_eq!(rows[1]["path"], forest.to_str().unwrap());
+    assert_eq!(rows[1]["missing"], false);
+    assert_eq!(rows[1]["hour"], Value::Null);
+
+    let gone = &rows[2];
+    assert_eq!(gone["missing"], true);
+    assert_eq!(gone["path"], Value::Null);
+
+    // the CSV holds the same rows, field for field
+    let csv = export(ExportFormat::Csv);
+    let mut reader = csv::Reader::from_reader(csv.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .unwrap()
+        .iter()
+        .map(str::to_string)
+        .collect();
+    let fields: Vec<&String> = rows[0].as_object().unwrap().keys().collect();
+    let mut sorted_headers: Vec<&String> = headers.iter().collect();
+    sorted_headers.sort();
+    let mut sorted_fields = fields.clone();
+    sorted_fields.sort();
+    assert_eq!(sorted_headers, sorted_fields);
+
+    let records: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+    assert_eq!(records.len(), rows.len());
+    for (record, row) in records.iter().zip(&rows) {
+        for (header, cell) in headers.iter().zip(record) {
+            assert_eq!(
+                cell,
+                csv_cell(&row[header]),
+                "{} of {}",
+                header,
+                row["entry"]
+            );
+        }
+    }
+
+    let _ = fs::remove_dir_all(&dir);
+}
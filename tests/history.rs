@@ -0,0 +1,108 @@
+//! the flat history log under concurrent runs
+
+use std::collections::HashSet;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::process;
+use std::thread;
+
+use wallpaper_slideshow::history::{self, WallpaperHistory};
+
+const WRITERS: usize = 8;
+const APPENDS: usize = 100;
+
+// one test, since it points the process-wide history settings at a temp dir
+#[test]
+fn concurrent_appends_leave_a_clean_complete_log() {
+    let dir = env::temp_dir().join(format!("wallpaper_slideshow_history_{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("history.log");
+    let root = dir.join("wallpapers");
+    env::set_var("WALLPAPER_DIR", &root);
+    env::set_var("WALLPAPER_HISTORY_LOG", &log);
+    env::set_var("WALLPAPER_HISTORY_BACKEND", "file");
+    env::set_var(
+        "WALLPAPER_HISTORY_MAX_LINES",
+        (WRITERS * APPENDS * 2).to_string(),
+    );
+
+    let writers: Vec<_> = (0..WRITERS)
+        .map(|writer| {
+            let root = root.clone();
+            thread::spawn(move || {
+                for n in 0..APPENDS {
+                    let monitor = format!("DP-{}", writer);
+                    history::log(&root.join(format!("w{}/{}.jpg", writer, n)), Some(&monitor));
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    let content = fs::read_to_string(&log).unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines.len(), WRITERS * APPENDS);
+    let expected: HashSet<String> = (0..WRITERS)
+        .flat_map(|writer| {
+            (0..APPENDS).map(move |n| format!("w{}/{}.jpg\tDP-{}", writer, n, writer))
+        })
+        .collect();
+    assert_eq!(
+        lines.iter().map(|l| l.to_string()).collect::<HashSet<_>>(),
+        expected
+    );
+
+    // each writer's own lines stay in the order it wrote them
+    for writer in 0..WRITERS {
+        let prefix = format!("w{}/", writer);
+        let order: Vec<usize> = lines
+            .iter()
+            .filter_map(|line| line.strip_prefix(&prefix)?.split_once(".jpg"))
+            .map(|(n, _)| n.parse().unwrap())
+            .collect();
+        assert_eq!(order, (0..APPENDS).collect::<Vec<_>>());
+    }
+
+    let history = WallpaperHistory::load().unwrap();
+    assert_eq!(history.len(), WRITERS * APPENDS);
+    assert_eq!(history.skipped(), 0);
+
+    // a blank line, a control character and bytes that aren't UTF-8 are left
+    // out and counted, the lines around them still load
+    let mut file = OpenOptions::new().append(true).open(&log).unwrap();
+    file.write_all(b"\nhalf\x07written.jpg\n\xff\xfe.jpg\n")
+        .unwrap();
+    drop(file);
+    history::log(&root.join("after.jpg"), None);
+
+    let history = WallpaperHistory::load().unwrap();
+    assert_eq!(history.len(), WRITERS * APPENDS + 1);
+    assert_eq!(history.skipped(), 3);
+    assert_eq!(history.current_entry(), "after.jpg");
+    assert!(!Path::new(&format!("{}.tmp", log.display())).exists());
+
+    // only every 100th append checks the length and trims the oldest lines,
+    // counting in the lock file
+    let lock = format!("{}.lock", log.display());
+    env::set_var("WALLPAPER_HISTORY_MAX_LINES", "50");
+    fs::write(&lock, "98").unwrap();
+    history::log(&root.join("99th.jpg"), None);
+    assert_eq!(fs::read_to_string(&lock).unwrap(), "99");
+    assert_eq!(
+        WallpaperHistory::load().unwrap().len(),
+        WRITERS * APPENDS + 2
+    );
+    history::log(&root.join("100th.jpg"), None);
+    assert_eq!(fs::read_to_string(&lock).unwrap(), "0");
+    let history = WallpaperHistory::load().unwrap();
+    assert_eq!(history.len(), 50);
+    assert_eq!(history.skipped(), 0);
+    assert_eq!(&history.entries()[48..], ["99th.jpg", "100th.jpg"]);
+
+    let _ = fs::remove_dir_all(&dir);
+}
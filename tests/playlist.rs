@@ -0,0 +1,55 @@
+//! playing a playlist in order from what history says was shown
+
+use std::env;
+use std::fs;
+use std::process;
+
+use wallpaper_slideshow::playlist::Playlist;
+
+// one test, as the wallpaper root is read from the process environment
+#[test]
+fn repeated_entries_keep_their_place() {
+    let dir = env::temp_dir().join(format!("wallpaper_slideshow_playlist_{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for name in ["a.jpg", "b.jpg", "c.jpg", "other.jpg"] {
+        fs::write(dir.join(name), b"").unwrap();
+    }
+    fs::write(
+        dir.join("playlist"),
+        "a.jpg\nb.jpg\n# again\na.jpg\nc.jpg\n",
+    )
+    .unwrap();
+    env::set_var("WALLPAPER_DIR", &dir);
+    let playlist = Playlist::load(&dir.join("playlist")).unwrap();
+    assert_eq!(playlist.len(), 4);
+
+    // newest first, as history is read
+    let mut history: Vec<String> = Vec::new();
+    let play = |history: &mut Vec<String>| {
+        let (index, path) = playlist.next(history).unwrap();
+        let entry = path.file_name().unwrap().to_string_lossy().to_string();
+        history.insert(0, entry.clone());
+        (index, entry)
+    };
+    let played: Vec<(usize, String)> = (0..9).map(|_| play(&mut history)).collect();
+    let expected = [
+        "a.jpg", "b.jpg", "a.jpg", "c.jpg", "a.jpg", "b.jpg", "a.jpg", "c.jpg", "a.jpg",
+    ];
+    for (i, (index, entry)) in played.iter().enumerate() {
+        assert_eq!(*index, i % 4);
+        assert_eq!(entry, expected[i]);
+    }
+
+    // something shown outside the playlist in between doesn't lose the place
+    history.insert(0, "other.jpg".to_string());
+    assert_eq!(play(&mut history), (1, "b.jpg".to_string()));
+    history.insert(0, "other.jpg".to_string());
+    assert_eq!(play(&mut history), (2, "a.jpg".to_string()));
+    assert_eq!(play(&mut history), (3, "c.jpg".to_string()));
+
+    assert_eq!(playlist.locate(&[]), None);
+    assert_eq!(playlist.next(&[]).unwrap().0, 0);
+
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,812 @@
+//! picking by time of day, and each shipped `SelectionStrategy` against the
+//! same few photos at noon
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use wallpaper_slideshow::history::{self, PathList};
+use wallpaper_slideshow::selection::{
+    self, AnniversaryStrategy, Branch, Candidate, CompositeStrategy, HourSource, Options, Place,
+    ScoreWeights, ScoringStrategy, SelectionContext, SelectionMode, SelectionStrategy, TakenAt,
+    Tiebreak, TimeOfDayStrategy, Timing,
+};
+use wallpaper_slideshow::{config, ImageFile};
+
+fn photo(name: &str, minute: u16, captured_on: Option<(i32, u32, u32)>) -> Candidate {
+    Candidate {
+        path: PathBuf::from(name),
+        hour: Some((minute / 60) as u8),
+        minute: Some(minute),
+        month: Some(6),
+        hour_source: Some(HourSource::Exif),
+        luminance: None,
+        captured_on: captured_on.and_then(|(y, m, d)| NaiveDate::from_ymd_opt(y, m, d)),
+        captured_at: None,
+        light: None,
+        rating: None,
+        mtime: 0,
+        last_shown: None,
+        favorite: false,
+    }
+}
+
+/// it's noon on 2026-06-01; two photos are from around noon, two from this day
+/// in earlier years and one from two days after it
+fn photos() -> Vec<Candidate> {
+    vec![
+        photo("noon.jpg", 12 * 60, Some((2020, 3, 10))),
+        photo("morning.jpg", 8 * 60, Some((2019, 6, 1))),
+        photo("night.jpg", 23 * 60, Some((2021, 6, 3))),
+        Candidate {
+            favorite: true,
+            ..photo("evening.jpg", 18 * 60, None)
+        },
+        photo("afternoon.jpg", 12 * 60 + 30, Some((2018, 6, 1))),
+    ]
+}
+
+const NOON: Timing = Timing::Hour {
+    current: 12 * 60,
+    window: 60,
+};
+
+fn today() -> NaiveDate {
+    NaiveDate::from_ymd_opt(2026, 6, 1).unwrap()
+}
+
+fn options(mode: SelectionMode) -> Options {
+    Options {
+        mode,
+        tau: 60.0,
+        tiebreak: Tiebreak::Random,
+        max_window: 3,
+        scores: favorites_only(),
+        top_k: 5,
+    }
+}
+
+fn favorites_only() -> ScoreWeights {
+    ScoreWeights {
+        time: 0.0,
+        season: 0.0,
+        favorite: 1.0,
+        unseen: 0.0,
+        recent: 0.0,
+    }
+}
+
+fn even(_: &Candidate) -> f64 {
+    1.0
+}
+
+fn none(_: &Candidate) -> bool {
+    false
+}
+
+fn context<'a>(candidates: &'a [Candidate], options: &'a Options) -> SelectionContext<'a> {
+    SelectionContext {
+        candidates,
+        timing: &NOON,
+        today: today(),
+        recent: &[],
+        options,
+        weight: &even,
+        preferred: &none,
+    }
+}
+
+fn rng() -> StdRng {
+    StdRng::seed_from_u64(7)
+}
+
+fn picked(candidates: &[Candidate], index: usize) -> &Path {
+    &candidates[index].path
+}
+
+#[test]
+fn minute_distance_wraps_around_midnight() {
+    let cases = [
+        ((12, 0), (12, 0), 0),
+        ((23, 0), (0, 0), 60),
+        ((0, 0), (23, 0), 60),
+        // the antipode is as far as it gets, either way round
+        ((0, 0), (12, 0), 12 * 60),
+        ((18, 0), (6, 0), 12 * 60),
+        ((1, 0), (13, 30), 11 * 60 + 30),
+    ];
+    for ((h1, m1), (h2, m2), expected) in cases {
+        let distance = selection::minute_distance(h1 * 60 + m1, h2 * 60 + m2);
+        assert_eq!(distance, expected, "{}:{:02} to {}:{:02}", h1, m1, h2, m2);
+    }
+}
+
+#[test]
+fn minutes_match_across_midnight() {
+    // 23:50 and 00:10 are 20 minutes apart, not 23 hours
+    assert_eq!(selection::minute_distance(23 * 60 + 50, 10), 20);
+    assert_eq!(selection::minute_distance(10, 23 * 60 + 50), 20);
+    assert_eq!(selection::minute_distance(23 * 60 + 59, 0), 1);
+
+    let midnight = Timing::Hour {
+        current: 23 * 60 + 50,
+        window: 30,
+    };
+    let at = |minute| TakenAt {
+        minute: Some(minute),
+        ..TakenAt::default()
+    };
+    assert_eq!(midnight.distance(at(10)), Some(20));
+    assert!(midnight.matches(at(10)));
+    assert!(!midnight.matches(at(23 * 60 + 10)));
+
+    // at 06:55, 07:05 is closer than 06:05
+    let sunrise = Timing::Hour {
+        current: 6 * 60 + 55,
+        window: 60,
+    };
+    assert_eq!(sunrise.distance(at(7 * 60 + 5)), Some(10));
+    assert_eq!(sunrise.distance(at(6 * 60 + 5)), Some(50));
+
+    // entries cached with only the hour stand in at its middle
+    assert_eq!(selection::middle_of_hour(23), 23 * 60 + 30);
+    assert_eq!(
+        midnight.distance(at(selection::middle_of_hour(0))),
+        Some(40)
+    );
+}
+
+/// `selection::select` at noon over photos taken at these times, if any, and
+/// preferring those at the `preferred` indices
+fn select_at_noon(
+    taken: &[Option<(u16, u16)>],
+    preferred: &[usize],
+    seed: u64,
+) -> (Option<usize>, Option<Branch>) {
+    let candidates: Vec<(usize, Option<u16>)> = taken
+        .iter()
+        .enumerate()
+        .map(|(i, time)| (i, time.map(|(h, m)| h * 60 + m)))
+        .collect();
+    let (chosen, report) = selection::select(
+        &candidates,
+        &NOON,
+        3,
+        |&(_, minute)| TakenAt {
+            minute,
+            ..TakenAt::default()
+        },
+        |_| 1.0,
+        |(i, _)| preferred.contains(i),
+        |_| 0,
+        &mut StdRng::seed_from_u64(seed),
+    );
+    assert_eq!(chosen.map(|c| c.0), report.chosen);
+    (report.chosen, report.branch)
+}
+
+#[test]
+fn select_tries_each_branch_in_turn() {
+    let noonish = [Some((12, 0)), Some((12, 40)), Some((18, 0))];
+    // a preferred one in the window beats the closer one that isn't
+    assert_eq!(
+        select_at_noon(&noonish, &[1], 0),
+        (Some(1), Some(Branch::Preferred))
+    );
+    // a preferred one outside the window doesn't count
+    for seed in 0..20 {
+        let (chosen, branch) = select_at_noon(&noonish, &[2], seed);
+        assert!(matches!(chosen, Some(0 | 1)));
+        assert_eq!(branch, Some(Branch::Window));
+    }
+
+    // nothing within the hour: any of the closest, up to max_window hours off
+    let afternoon = [Some((14, 30)), Some((15, 0)), Some((9, 30)), None];
+    for seed in 0..20 {
+        let (chosen, branch) = select_at_noon(&afternoon, &[], seed);
+        assert!(matches!(chosen, Some(0 | 2)));
+        assert_eq!(branch, Some(Branch::Widened));
+    }
+
+    // nothing within max_window either: the single closest, every time
+    let evening = [Some((19, 0)), Some((17, 0)), Some((23, 0)), None];
+    for seed in 0..5 {
+        assert_eq!(
+            select_at_noon(&evening, &[], seed),
+            (Some(1), Some(Branch::BestMatch))
+        );
+    }
+
+    // no times at all: any
+    let (chosen, branch) = select_at_noon(&[None, None], &[], 0);
+    assert!(chosen.is_some());
+    assert_eq!(branch, Some(Branch::Random));
+
+    assert_eq!(select_at_noon(&[], &[], 0).0, None);
+}
+
+#[test]
+fn time_of_day_picks_within_the_window() {
+    let photos = photos();
+    let options = options(SelectionMode::Window);
+    for seed in 0..20 {
+        let selection = TimeOfDayStrategy
+            .select(
+                &context(&photos, &options),
+                &mut StdRng::seed_from_u64(seed),
+            )
+            .unwrap();
+        assert!(
+            matches!(selection.index, 0 | 4),
+            "picked {}",
+            selection.index
+        );
+        assert_eq!(selection.strategy, "time of day");
+        assert_eq!(selection.report.branch, Some(Branch::Window));
+        assert_eq!(selection.report.chosen, Some(selection.index));
+    }
+
+    // nothing within the window or max_window: the closest
+    let far = [photos[1].clone(), photos[2].clone()];
+    let selection = TimeOfDayStrategy
+        .select(&context(&far, &options), &mut rng())
+        .unwrap();
+    assert_eq!(picked(&far, selection.index), Path::new("morning.jpg"));
+    assert_eq!(selection.report.branch, Some(Branch::BestMatch));
+
+    assert!(TimeOfDayStrategy
+        .select(&context(&[], &options), &mut rng())
+        .is_none());
+}
+
+#[test]
+fn anniversary_prefers_this_very_day() {
+    let photos = photos();
+    let options = options(SelectionMode::Window);
+    let strategy = AnniversaryStrategy {
+        days: 3,
+        then: TimeOfDayStrategy,
+    };
+    let selection = strategy
+        .select(&context(&photos, &options), &mut rng())
+        .unwrap();
+    // of morning and afternoon, taken on 1 June, afternoon is closer to noon;
+    // the indices are translated back from those two to all the photos
+    assert_eq!(selection.index, 4);
+    assert_eq!(selection.strategy, "anniversary");
+    assert_eq!(selection.report.chosen, Some(4));
+    assert!(selection
+        .report
+        .contenders
+        .iter()
+        .all(|c| matches!(c.index, 1 | 4)));
+    assert!(selection
+        .report
+        .notes
+        .contains(&"2 images were taken on this day".to_string()));
+    assert!(selection
+        .report
+        .notes
+        .contains(&"anniversary match: taken 2018-06-01".to_string()));
+}
+
+#[test]
+fn anniversary_widens_to_nearby_days() {
+    let photos = photos();
+    let options = options(SelectionMode::Window);
+    // noon, night and evening: only night, two days off, is near the date
+    let others = [photos[0].clone(), photos[2].clone(), photos[3].clone()];
+    let strategy = |days| AnniversaryStrategy {
+        days,
+        then: TimeOfDayStrategy,
+    };
+
+    let selection = strategy(3)
+        .select(&context(&others, &options), &mut rng())
+        .unwrap();
+    assert_eq!(selection.index, 1);
+    assert_eq!(selection.report.chosen, Some(1));
+    assert!(selection
+        .report
+        .notes
+        .contains(&"1 images were taken within 3 days of this day".to_string()));
+
+    assert!(strategy(1)
+        .select(&context(&others, &options), &mut rng())
+        .is_none());
+}
+
+#[test]
+fn scoring_ranks_by_its_own_weights() {
+    let photos = photos();
+    // the options say window, which scoring ignores
+    let options = options(SelectionMode::Window);
+    let strategy = ScoringStrategy {
+        weights: favorites_only(),
+        top_k: 1,
+    };
+    let selection = strategy
+        .select(&context(&photos, &options), &mut rng())
+        .unwrap();
+    assert_eq!(picked(&photos, selection.index), Path::new("evening.jpg"));
+    assert_eq!(selection.strategy, "score");
+    assert_eq!(selection.report.branch, Some(Branch::Scored));
+    let favorite = selection
+        .report
+        .factors
+        .iter()
+        .find(|f| f.name == "favorite")
+        .unwrap();
+    assert_eq!(favorite.contribution, 1.0);
+
+    assert!(strategy
+        .select(&context(&[], &options), &mut rng())
+        .is_none());
+}
+
+#[test]
+fn composite_takes_the_first_that_picks() {
+    let photos = photos();
+    let options = options(SelectionMode::Window);
+    let composite = CompositeStrategy {
+        strategies: vec![
+            Box::new(AnniversaryStrategy {
+                days: 0,
+                then: TimeOfDayStrategy,
+            }),
+            Box::new(ScoringStrategy {
+                weights: favorites_only(),
+                top_k: 1,
+            }),
+        ],
+    };
+
+    let selection = composite
+        .select(&context(&photos, &options), &mut rng())
+        .unwrap();
+    assert_eq!(selection.strategy, "anniversary");
+    assert_eq!(selection.index, 4);
+
+    // no anniversary among these, so scoring picks
+    let others = [photos[0].clone(), photos[3].clone()];
+    let selection = composite
+        .select(&context(&others, &options), &mut rng())
+        .unwrap();
+    assert_eq!(selection.strategy, "score");
+    assert_eq!(picked(&others, selection.index), Path::new("evening.jpg"));
+
+    assert!(CompositeStrategy::default()
+        .select(&context(&photos, &options), &mut rng())
+        .is_none());
+}
+
+/// how often each photo taken at these minutes, if any, is the one drawn by
+/// `select_weighted` at noon
+fn weighted_draws(taken: &[Option<u16>], tau: f64, draws: usize) -> Vec<usize> {
+    let candidates: Vec<(usize, Option<u16>)> = taken.iter().copied().enumerate().collect();
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut counts = vec![0; taken.len()];
+    for _ in 0..draws {
+        let (chosen, report) = selection::select_weighted(
+            &candidates,
+            &NOON,
+            |&(_, minute)| TakenAt {
+                minute,
+                ..TakenAt::default()
+            },
+            |_| 1.0,
+            |_| false,
+            tau,
+            &mut rng,
+        );
+        assert_eq!(report.branch, Some(Branch::Weighted));
+        counts[chosen.unwrap().0] += 1;
+    }
+    counts
+}
+
+#[test]
+fn weighted_falls_off_with_distance() {
+    // an hour further off is e^-1 as likely with tau at one hour
+    let counts = weighted_draws(
+        &[Some(12 * 60), Some(13 * 60), Some(14 * 60), Some(9 * 60)],
+        1.0,
+        20_000,
+    );
+    for pair in counts.windows(2) {
+        let ratio = pair[1] as f64 / pair[0] as f64;
+        assert!((0.30..0.44).contains(&ratio), "{:?}", counts);
+    }
+
+    // a wider tau flattens it out
+    let flat = weighted_draws(&[Some(12 * 60), Some(15 * 60)], 6.0, 20_000);
+    let ratio = flat[1] as f64 / flat[0] as f64;
+    assert!((0.55..0.67).contains(&ratio), "{:?}", flat);
+}
+
+#[test]
+fn weighted_gives_hourless_photos_a_floor() {
+    let counts = weighted_draws(&[Some(12 * 60), None], 1.0, 20_000);
+    let share = counts[1] as f64 / 20_000.0;
+    let expected = selection::HOURLESS_WEIGHT / (1.0 + selection::HOURLESS_WEIGHT);
+    assert!(
+        (share - expected).abs() < 0.01,
+        "{} hourless of {:?}",
+        share,
+        counts
+    );
+}
+
+#[test]
+fn weighted_has_no_cliff_at_the_window_edge() {
+    // five minutes either side of the hour's edge; the window never draws the
+    // one just outside while it's there
+    let edge = [Some(12 * 60 + 55), Some(13 * 60 + 5)];
+    for seed in 0..20 {
+        let window = select_at_noon(&[Some((12, 55)), Some((13, 5))], &[], seed);
+        assert_eq!(window, (Some(0), Some(Branch::Window)));
+    }
+    let counts = weighted_draws(&edge, 1.0, 20_000);
+    let ratio = counts[1] as f64 / counts[0] as f64;
+    assert!((0.78..0.92).contains(&ratio), "{:?}", counts);
+
+    // and with nothing close, the best match isn't the only one ever drawn
+    let counts = weighted_draws(&[Some(17 * 60), Some(18 * 60)], 1.0, 2_000);
+    assert!(counts.iter().all(|&n| n > 0), "{:?}", counts);
+}
+
+/// the photos `select_candidate` picks in this mode, one draw after another
+/// from a generator seeded with `seed`
+fn seeded_picks(mode: SelectionMode, seed: u64) -> Vec<String> {
+    let photos = photos();
+    let options = options(mode);
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..6)
+        .map(|_| {
+            let (chosen, _) =
+                selection::select_candidate(&photos, &NOON, &options, even, none, &mut rng);
+            chosen.unwrap().path.display().to_string()
+        })
+        .collect()
+}
+
+#[test]
+fn a_seed_pins_the_picks() {
+    assert_eq!(
+        seeded_picks(SelectionMode::Window, 42),
+        [
+            "afternoon.jpg",
+            "afternoon.jpg",
+            "afternoon.jpg",
+            "noon.jpg",
+            "noon.jpg",
+            "noon.jpg"
+        ]
+    );
+    assert_eq!(
+        seeded_picks(SelectionMode::Weighted, 42),
+        [
+            "night.jpg",
+            "night.jpg",
+            "evening.jpg",
+            "morning.jpg",
+            "noon.jpg",
+            "night.jpg"
+        ]
+    );
+    assert_eq!(seeded_picks(SelectionMode::Score, 42), ["evening.jpg"; 6]);
+
+    // the same seed again gives the same run, another seed a different one
+    let weighted = seeded_picks(SelectionMode::Weighted, 42);
+    assert_eq!(seeded_picks(SelectionMode::Weighted, 42), weighted);
+    assert_ne!(seeded_picks(SelectionMode::Weighted, 43), weighted);
+}
+
+fn names(candidates: &[Candidate]) -> Vec<&str> {
+    candidates
+        .iter()
+        .map(|c| c.path.to_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn scheduled_keeps_what_the_day_allows() {
+    let all: Vec<ImageFile> = photos()
+        .iter()
+        .map(|c| ImageFile::new(c.path.clone(), 0, 0))
+        .collect();
+    let names_of = |images: Vec<ImageFile>| -> Vec<PathBuf> {
+        images.into_iter().map(|img| img.path).collect()
+    };
+
+    let noonish = PathList::from_lines(["noon.jpg".to_string(), "after*".to_string()]);
+    assert_eq!(
+        selection::scheduled(&all, &noonish).map(names_of),
+        Some(vec![
+            PathBuf::from("noon.jpg"),
+            PathBuf::from("afternoon.jpg")
+        ])
+    );
+    // nothing allowed is the same as no schedule
+    let elsewhere = PathList::from_lines(["beach/*".to_string()]);
+    assert!(selection::scheduled(&all, &elsewhere).is_none());
+}
+
+#[test]
+fn keep_rated_drops_low_and_unrated_unless_that_is_everything() {
+    let rated = |ratings: [Option<u8>; 5]| -> Vec<Candidate> {
+        photos()
+            .into_iter()
+            .zip(ratings)
+            .map(|(c, rating)| c.with_rating(rating))
+            .collect()
+    };
+    let ratings = [Some(5), Some(2), None, Some(3), None];
+
+    let mut candidates = rated(ratings);
+    assert_eq!(selection::keep_rated(&mut candidates, 3, false), Some(3));
+    assert_eq!(names(&candidates), ["noon.jpg", "evening.jpg"]);
+
+    let mut candidates = rated(ratings);
+    assert_eq!(selection::keep_rated(&mut candidates, 3, true), Some(1));
+    assert_eq!(
+        names(&candidates),
+        ["noon.jpg", "night.jpg", "evening.jpg", "afternoon.jpg"]
+    );
+
+    // nobody rated high enough: the filter steps aside
+    let mut candidates = rated(ratings);
+    assert_eq!(selection::keep_rated(&mut candidates, 6, false), None);
+    assert_eq!(candidates.len(), 5);
+}
+
+#[test]
+fn burst_frames_of_a_shown_photo_are_dropped() {
+    let at = |h, m, s| {
+        NaiveDate::from_ymd_opt(2026, 5, 31)
+            .unwrap()
+            .and_hms_opt(h, m, s)
+    };
+    let times = [at(12, 0, 0), at(8, 0, 0), None, at(18, 0, 0), at(12, 0, 2)];
+    let candidates: Vec<Candidate> = photos()
+        .into_iter()
+        .zip(times)
+        .map(|(c, time)| c.with_captured_at(time))
+        .collect();
+    let shown: Vec<(PathBuf, NaiveDateTime)> =
+        vec![(PathBuf::from("noon.jpg"), at(12, 0, 0).unwrap())];
+
+    // two seconds after the shown one, but not the shown one itself
+    let mut kept = candidates.clone();
+    assert_eq!(selection::drop_burst_frames(&mut kept, &shown, 5), 1);
+    assert_eq!(
+        names(&kept),
+        ["noon.jpg", "morning.jpg", "night.jpg", "evening.jpg"]
+    );
+
+    // too far apart to be a burst
+    let mut kept = candidates.clone();
+    assert_eq!(selection::drop_burst_frames(&mut kept, &shown, 1), 0);
+    assert_eq!(kept.len(), 5);
+
+    // never drops the last of them
+    let mut kept = vec![candidates[4].clone()];
+    assert_eq!(selection::drop_burst_frames(&mut kept, &shown, 5), 0);
+    assert_eq!(names(&kept), ["afternoon.jpg"]);
+}
+
+#[test]
+fn keep_unseen_only_when_an_unseen_one_fits_the_time() {
+    let shown =
+        |entries: &[&str]| -> HashSet<String> { entries.iter().map(|e| e.to_string()).collect() };
+
+    // afternoon.jpg is unseen and within the hour, so noon.jpg has had its turn
+    let mut candidates = photos();
+    let dropped = selection::keep_unseen(&mut candidates, &shown(&["noon.jpg"]), &NOON);
+    assert_eq!(dropped, 1);
+    assert_eq!(
+        names(&candidates),
+        ["morning.jpg", "night.jpg", "evening.jpg", "afternoon.jpg"]
+    );
+
+    // the unseen ones are all from other times of day: nothing changes
+    let mut candidates = photos();
+    let noonish = shown(&["noon.jpg", "afternoon.jpg"]);
+    assert_eq!(selection::keep_unseen(&mut candidates, &noonish, &NOON), 0);
+    assert_eq!(candidates.len(), 5);
+
+    // a legacy basename entry counts as shown for a file in a subdirectory
+    let mut candidates = vec![
+        Candidate {
+            path: PathBuf::from("2020/noon.jpg"),
+            ..photos()[0].clone()
+        },
+        photos()[4].clone(),
+    ];
+    assert_eq!(
+        selection::keep_unseen(&mut candidates, &shown(&["noon.jpg"]), &NOON),
+        1
+    );
+    assert_eq!(names(&candidates), ["afternoon.jpg"]);
+}
+
+#[test]
+fn shown_counts_add_up_the_history() {
+    let stats = history::stats_from_entries(
+        ["noon.jpg", "evening.jpg", "noon.jpg"]
+            .into_iter()
+            .map(|entry| (entry.to_string(), None)),
+    );
+    let counts = selection::shown_counts(&photos(), &stats);
+    assert_eq!(counts.len(), 5);
+    assert_eq!(counts[Path::new("noon.jpg")], 2);
+    assert_eq!(counts[Path::new("evening.jpg")], 1);
+    assert_eq!(counts[Path::new("morning.jpg")], 0);
+}
+
+#[test]
+fn place_of_the_day_goes_through_the_places() {
+    let locations: Vec<(String, (f64, f64))> = vec![
+        ("paris/a.jpg".to_string(), (48.85, 2.35)),
+        ("tokyo/c.jpg".to_string(), (35.68, 139.69)),
+        ("paris/b.jpg".to_string(), (48.86, 2.34)),
+    ];
+    let day = NaiveDate::from_ymd_opt(2026, 6, 1).unwrap();
+    // places are numbered by their first photo, one a day in turn
+    let (paris_day, tokyo_day) = if day.num_days_from_ce() % 2 == 0 {
+        (day, day.succ_opt().unwrap())
+    } else {
+        (day.succ_opt().unwrap(), day)
+    };
+
+    let paris = selection::place_of_the_day(&locations, 5.0, paris_day).unwrap();
+    assert_eq!((paris.index, paris.count), (0, 2));
+    assert_eq!(
+        paris.paths,
+        HashSet::from([PathBuf::from("paris/a.jpg"), PathBuf::from("paris/b.jpg")])
+    );
+    assert!((paris.center.0 - 48.855).abs() < 1e-9);
+    assert!((paris.center.1 - 2.345).abs() < 1e-9);
+
+    let tokyo = selection::place_of_the_day(&locations, 5.0, tokyo_day).unwrap();
+    assert_eq!(tokyo.index, 1);
+    assert_eq!(tokyo.paths, HashSet::from([PathBuf::from("tokyo/c.jpg")]));
+
+    assert_eq!(selection::place_of_the_day(&[], 5.0, day), None);
+}
+
+#[test]
+fn keep_at_place_only_when_it_has_the_time() {
+    let place = |paths: &[&str]| Place {
+        index: 0,
+        count: 2,
+        paths: paths.iter().map(PathBuf::from).collect(),
+        center: (0.0, 0.0),
+    };
+
+    let mut candidates = photos();
+    assert!(selection::keep_at_place(
+        &mut candidates,
+        &place(&["noon.jpg", "morning.jpg"]),
+        &NOON
+    ));
+    assert_eq!(names(&candidates), ["noon.jpg", "morning.jpg"]);
+
+    // nothing there from around noon: every place stays in
+    let mut candidates = photos();
+    assert!(!selection::keep_at_place(
+        &mut candidates,
+        &place(&["night.jpg"]),
+        &NOON
+    ));
+    assert_eq!(candidates.len(), 5);
+}
+
+/// how often each of `candidates` is drawn by `selection::select` at noon with
+/// these weights, and the branch it drew from
+fn window_draws(
+    candidates: &[Candidate],
+    weight: impl Fn(&Candidate) -> f64,
+    draws: usize,
+) -> (Vec<usize>, Option<Branch>) {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut counts = vec![0; candidates.len()];
+    let mut branch = None;
+    for _ in 0..draws {
+        let (_, report) = selection::select(
+            candidates,
+            &NOON,
+            3,
+            Candidate::taken_at,
+            &weight,
+            none,
+            |_| 0,
+            &mut rng,
+        );
+        counts[report.chosen.unwrap()] += 1;
+        branch = report.branch;
+    }
+    (counts, branch)
+}
+
+fn untimed(name: &str) -> Candidate {
+    Candidate {
+        hour: None,
+        minute: None,
+        ..photo(name, 0, None)
+    }
+}
+
+#[test]
+fn favorites_are_drawn_boost_times_as_often() {
+    let favorites = PathList::from_lines(["a.jpg".to_string()]);
+    let boost = config::DEFAULT_FAVORITE_WEIGHT;
+    let weight = |c: &Candidate| history::favorite_weight(&favorites, &c.path, boost);
+
+    let in_window: Vec<Candidate> = ["a.jpg", "b.jpg", "c.jpg", "d.jpg"]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| photo(name, 12 * 60 + i as u16 * 10, None))
+        .collect();
+    let untimed: Vec<Candidate> = ["a.jpg", "b.jpg", "c.jpg", "d.jpg"]
+        .into_iter()
+        .map(untimed)
+        .collect();
+    for (candidates, expected) in [(in_window, Branch::Window), (untimed, Branch::Random)] {
+        let (counts, branch) = window_draws(&candidates, weight, 12_000);
+        assert_eq!(branch, Some(expected));
+        for &other in &counts[1..] {
+            // never starved, just less likely
+            assert!(other > 0, "{:?}", counts);
+            let ratio = counts[0] as f64 / other as f64;
+            assert!(
+                (boost * 0.85..boost * 1.15).contains(&ratio),
+                "{:?} in {:?}",
+                counts,
+                expected
+            );
+        }
+    }
+}
+
+#[test]
+fn recently_shown_are_unlikely_but_possible() {
+    // newest first: new.jpg was the last one shown, old.jpg five before
+    let recent: Vec<String> = ["new.jpg", "x.jpg", "y.jpg", "z.jpg", "old.jpg"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    assert_eq!(
+        history::recency_weight(&recent, Path::new("new.jpg")),
+        1.0 / 6.0
+    );
+    assert_eq!(
+        history::recency_weight(&recent, Path::new("old.jpg")),
+        5.0 / 6.0
+    );
+    assert_eq!(
+        history::recency_weight(&recent, Path::new("never.jpg")),
+        1.0
+    );
+
+    let candidates: Vec<Candidate> = ["new.jpg", "old.jpg", "never.jpg", "also_never.jpg"]
+        .iter()
+        .map(|name| photo(name, 12 * 60, None))
+        .collect();
+    let (counts, _) = window_draws(
+        &candidates,
+        |c| history::recency_weight(&recent, &c.path),
+        20_000,
+    );
+    let [new, old, never, also_never] = counts[..] else {
+        unreachable!()
+    };
+    assert!(new > 0, "{:?}", counts);
+    assert!(new * 4 < never, "{:?}", counts);
+    assert!(new < old && old < never, "{:?}", counts);
+    // past the window everything is alike
+    let ratio = never as f64 / also_never as f64;
+    assert!((0.92..1.08).contains(&ratio), "{:?}", counts);
+}
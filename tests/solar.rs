@@ -0,0 +1,259 @@
+//! the sun calculations against published sunrise and sunset times and the
+//! sun's height at noon
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+
+use wallpaper_slideshow::solar::{self, Light, Phase, SunTimes};
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+        .unwrap()
+}
+
+/// hours between two times of day, going the short way round midnight
+fn hours_apart(a: f64, b: f64) -> f64 {
+    let diff = (a - b).rem_euclid(24.0);
+    diff.min(24.0 - diff)
+}
+
+/// within five minutes, leaving room for the approximation and for rounding in
+/// the published times
+const TOLERANCE: f64 = 5.0 / 60.0;
+
+#[test]
+fn sun_times_match_published_ones() {
+    // (place, latitude, longitude, date, sunrise and sunset UTC as h:mm)
+    let cases = [
+        (
+            "London",
+            51.5074,
+            -0.1278,
+            date(2024, 6, 21),
+            (3, 43),
+            (20, 21),
+        ),
+        (
+            "London",
+            51.5074,
+            -0.1278,
+            date(2024, 12, 21),
+            (8, 4),
+            (15, 53),
+        ),
+        (
+            "New York",
+            40.7128,
+            -74.006,
+            date(2024, 12, 21),
+            (12, 16),
+            (21, 32),
+        ),
+        // sunrise is the evening before in UTC
+        (
+            "Sydney",
+            -33.8688,
+            151.2093,
+            date(2024, 12, 21),
+            (18, 41),
+            (9, 5),
+        ),
+        ("Null Island", 0.0, 0.0, date(2024, 3, 20), (6, 4), (18, 11)),
+    ];
+    for (place, lat, lon, day, (rise_h, rise_m), (set_h, set_m)) in cases {
+        let SunTimes::Normal { sunrise, sunset } = solar::sun_times_utc(day, lat, lon) else {
+            panic!("{} {}: the sun should rise and set", place, day);
+        };
+        let expected_rise = rise_h as f64 + rise_m as f64 / 60.0;
+        let expected_set = set_h as f64 + set_m as f64 / 60.0;
+        assert!(
+            hours_apart(sunrise, expected_rise) < TOLERANCE,
+            "{} {}: sunrise {:.3}, expected {:.3}",
+            place,
+            day,
+            sunrise,
+            expected_rise
+        );
+        assert!(
+            hours_apart(sunset, expected_set) < TOLERANCE,
+            "{} {}: sunset {:.3}, expected {:.3}",
+            place,
+            day,
+            sunset,
+            expected_set
+        );
+    }
+}
+
+#[test]
+fn polar_days_and_nights() {
+    let (lat, lon) = (69.6492, 18.9553);
+    assert_eq!(
+        solar::sun_times_utc(date(2024, 6, 21), lat, lon),
+        SunTimes::PolarDay
+    );
+    assert_eq!(
+        solar::sun_times_utc(date(2024, 12, 21), lat, lon),
+        SunTimes::PolarNight
+    );
+    // Antarctica the other way round
+    assert_eq!(
+        solar::sun_times_utc(date(2024, 6, 21), -77.85, 166.67),
+        SunTimes::PolarNight
+    );
+}
+
+#[test]
+fn phases_follow_the_sun() {
+    let sun = SunTimes::Normal {
+        sunrise: 6.0,
+        sunset: 20.0,
+    };
+    let cases = [
+        (2.0, Phase::Night),
+        (5.5, Phase::Dawn),
+        (6.5, Phase::Dawn),
+        (9.0, Phase::Morning),
+        (13.0, Phase::Midday),
+        (16.0, Phase::Evening),
+        (19.5, Phase::Dusk),
+        (20.5, Phase::Dusk),
+        (23.0, Phase::Night),
+    ];
+    for (hour, phase) in cases {
+        assert_eq!(Phase::at(hour, sun), phase, "at {}", hour);
+    }
+
+    // a day crossing midnight, as in UTC far from Greenwich
+    let wrapped = SunTimes::Normal {
+        sunrise: 18.0,
+        sunset: 8.0,
+    };
+    assert_eq!(Phase::at(1.0, wrapped), Phase::Midday);
+    assert_eq!(Phase::at(12.0, wrapped), Phase::Night);
+
+    assert_eq!(Phase::at(12.0, SunTimes::PolarNight), Phase::Night);
+    assert_eq!(Phase::at(12.0, SunTimes::PolarDay), Phase::Midday);
+    assert_eq!(Phase::at(2.0, SunTimes::PolarDay), Phase::Morning);
+}
+
+#[test]
+fn phase_distance_wraps_through_the_night() {
+    assert_eq!(Phase::Night.distance(Phase::Night), 0);
+    assert_eq!(Phase::Night.distance(Phase::Dusk), 1);
+    assert_eq!(Phase::Dawn.distance(Phase::Dusk), 2);
+    assert_eq!(Phase::Night.distance(Phase::Midday), 3);
+    assert_eq!(Phase::Midday.distance(Phase::Night), 3);
+}
+
+#[test]
+fn elevation_at_known_places_and_times() {
+    // at solar noon the sun stands 90° - latitude + declination high, the
+    // declination being ±23.44° at the solstices and 0 at the equinoxes
+    let cases = [
+        (
+            "Null Island, equinox noon",
+            utc(2024, 3, 20, 12, 7),
+            0.0,
+            0.0,
+            89.8,
+        ),
+        (
+            "London, midsummer noon",
+            utc(2024, 6, 21, 12, 2),
+            51.5074,
+            -0.1278,
+            61.9,
+        ),
+        (
+            "New York, midwinter noon",
+            utc(2024, 12, 21, 16, 54),
+            40.7128,
+            -74.006,
+            25.9,
+        ),
+        (
+            "Sydney, midsummer noon",
+            utc(2024, 12, 21, 1, 53),
+            -33.8688,
+            151.2093,
+            79.6,
+        ),
+        // the midnight sun skims above the horizon, the polar night's noon below it
+        (
+            "Tromsø, midsummer midnight",
+            utc(2024, 6, 21, 22, 44),
+            69.6492,
+            18.9553,
+            3.1,
+        ),
+        (
+            "Tromsø, midwinter noon",
+            utc(2024, 12, 21, 10, 44),
+            69.6492,
+            18.9553,
+            -3.1,
+        ),
+        (
+            "London, midwinter midnight",
+            utc(2024, 12, 21, 0, 0),
+            51.5074,
+            -0.1278,
+            -61.9,
+        ),
+    ];
+    for (what, time, lat, lon, expected) in cases {
+        let elevation = solar::elevation(time, lat, lon);
+        assert!(
+            (elevation - expected).abs() < 0.5,
+            "{}: {:.2}°, expected {:.1}°",
+            what,
+            elevation,
+            expected
+        );
+    }
+}
+
+#[test]
+fn light_by_elevation() {
+    let cases = [
+        (-20.0, Light::Night),
+        (-6.1, Light::Night),
+        (-3.0, Light::Twilight),
+        (0.0, Light::GoldenHour),
+        (5.9, Light::GoldenHour),
+        (6.0, Light::Day),
+        (60.0, Light::Day),
+    ];
+    for (degrees, light) in cases {
+        assert_eq!(Light::from_elevation(degrees), light, "at {}°", degrees);
+    }
+    assert_eq!(Light::Night.distance(Light::Day), 3);
+    assert_eq!(Light::Day.distance(Light::GoldenHour), 1);
+}
+
+#[test]
+fn same_clock_time_different_light() {
+    let tromso = (69.6492, 18.9553);
+    let here = (51.5074, -0.1278);
+    // 22:00 in Tromsø at midsummer, written with its +02:00 offset, is day;
+    // 22:00 in London at midwinter is night
+    let summer_night = date(2024, 6, 21).and_hms_opt(22, 0, 0).unwrap();
+    let winter_night = date(2024, 12, 21).and_hms_opt(22, 0, 0).unwrap();
+    assert_eq!(Light::of_photo(summer_night, Some(120), tromso), Light::Day);
+    assert_eq!(Light::of_photo(winter_night, Some(0), here), Light::Night);
+
+    // without an offset it's guessed from the longitude, here 76 minutes
+    // instead of 120, which puts 01:00 local still in the midnight sun
+    let late = date(2024, 6, 22).and_hms_opt(1, 0, 0).unwrap();
+    assert_eq!(Light::of_photo(late, Some(120), tromso), Light::GoldenHour);
+    assert_eq!(Light::of_photo(late, None, tromso), Light::GoldenHour);
+
+    assert_eq!(
+        Light::at(utc(2024, 12, 21, 10, 44), tromso.0, tromso.1),
+        Light::Twilight
+    );
+}
@@ -0,0 +1,47 @@
+//! the history table in the cache database, taking over from the flat log
+
+use std::env;
+use std::fs;
+use std::process;
+
+use wallpaper_slideshow::history::SqliteHistory;
+
+// one test, since it points the process-wide cache and log at a temp dir
+#[test]
+fn the_flat_log_is_imported_once() {
+    let dir = env::temp_dir().join(format!(
+        "wallpaper_slideshow_sqlite_history_{}",
+        process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("history.log");
+    fs::write(&log, "a.jpg\nb.jpg\tDP-1\na.jpg\n").unwrap();
+    env::set_var("WALLPAPER_CACHE_DB", dir.join("cache.db"));
+    env::set_var("WALLPAPER_HISTORY_LOG", &log);
+    env::set_var("WALLPAPER_HISTORY_BACKEND", "sqlite");
+
+    let history = SqliteHistory::open().unwrap();
+    let entries: Vec<String> = history
+        .timed_entries()
+        .unwrap()
+        .into_iter()
+        .map(|(entry, shown_at)| {
+            assert_eq!(shown_at, None);
+            entry
+        })
+        .collect();
+    assert_eq!(entries, ["a.jpg", "b.jpg", "a.jpg"]);
+    assert_eq!(
+        history.load_recent(Some("DP-2"), 10).unwrap(),
+        ["a.jpg", "a.jpg"]
+    );
+
+    // emptied by pruning, it stays empty with the log still there
+    assert_eq!(history.prune(|_| false).unwrap(), 3);
+    drop(history);
+    let history = SqliteHistory::open().unwrap();
+    assert!(history.timed_entries().unwrap().is_empty());
+
+    let _ = fs::remove_dir_all(&dir);
+}